@@ -0,0 +1,87 @@
+//! Time embedding and extracting 1 MiB and 16 MiB payloads, subpixel by subpixel as the writer
+//! and reader used to and 8 samples per byte as they do now
+//!
+//! ```text
+//! cargo bench --bench lsb
+//! ```
+//!
+//! Each case runs a few times and the fastest run is printed. The old implementations are kept
+//! here, with the reader's running `sum * 2 + bit`, to measure against rather than in the crate.
+
+use image::RgbaImage;
+use pngsecret::byte_to_8bits;
+use pngsecret::format::Framing;
+use pngsecret::testing::{CoverBuilder, Pattern};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 3;
+
+fn main() {
+    for mib in [1, 16] {
+        let payload: Vec<u8> = (0..mib << 20).map(|i| (i * 7 + i / 251) as u8).collect();
+        let framed = Framing::LengthPrefixed.frame(&payload);
+        // The smallest square RGBA cover the framed payload fits into
+        let side = ((framed.len() * 2) as f64).sqrt().ceil() as u32;
+        let cover = CoverBuilder::new(side, side)
+            .with_pattern(Pattern::Noise)
+            .build();
+
+        let old = fastest(|| old_embed(cover.clone(), &framed));
+        let new = fastest(|| pngsecret::embed(cover.clone(), &payload).unwrap());
+        report("embed", mib, old, new);
+
+        let stego = pngsecret::embed(cover.clone(), &payload).unwrap();
+        assert_eq!(old_embed(cover, &framed), stego);
+        assert_eq!(old_extract(stego.clone(), framed.len()), framed);
+        // Both take their image by value, the copy is timed alike
+        let old = fastest(|| old_extract(stego.clone(), framed.len()));
+        let new = fastest(|| pngsecret::extract(stego.clone()).unwrap());
+        report("extract", mib, old, new);
+    }
+}
+
+/// The fastest of [`RUNS`] runs of `run`
+fn fastest<T>(mut run: impl FnMut() -> T) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            black_box(run());
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn report(step: &str, mib: usize, old: Duration, new: Duration) {
+    println!(
+        "{:<7} {:>2} MiB  old {:>10.2?}  new {:>10.2?}  {:.1}x",
+        step,
+        mib,
+        old,
+        new,
+        old.as_secs_f64() / new.as_secs_f64()
+    );
+}
+
+/// Embed `framed` one subpixel at a time, a bit array per byte
+fn old_embed(mut img: RgbaImage, framed: &[u8]) -> RgbaImage {
+    for (sample, bit) in img.iter_mut().zip(framed.iter().flat_map(byte_to_8bits)) {
+        *sample = (*sample & !1) | bit;
+    }
+    img
+}
+
+/// Read `bytes` framed bytes back one subpixel at a time
+fn old_extract(img: RgbaImage, bytes: usize) -> Vec<u8> {
+    let mut message = Vec::with_capacity(bytes);
+    let mut sum = 0u8;
+    for (i, sample) in img.iter().take(bytes * 8).enumerate() {
+        sum = sum.wrapping_mul(2) + sample % 2;
+        if i % 8 == 7 {
+            message.push(sum);
+            sum = 0;
+        }
+    }
+    message
+}
@@ -0,0 +1,42 @@
+//! Time embedding a payload that fills a synthetic 8000x8000 RGBA cover, without any file I/O
+//!
+//! ```text
+//! cargo run --release --example embed_timing -- [side]
+//! ```
+//!
+//! Prints the time per step and a progress line for every report of the writer, the same
+//! reports `pngsecret -e` draws its progress bar from.
+
+use pngsecret::format::Framing;
+use pngsecret::testing::{CoverBuilder, Pattern};
+use pngsecret::{NaiveEncoder, PngSecretWriter};
+use std::time::Instant;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let side: u32 = match std::env::args().nth(1) {
+        Some(side) => side.parse()?,
+        None => 8000,
+    };
+    let start = Instant::now();
+    let cover = CoverBuilder::new(side, side)
+        .with_pattern(Pattern::Noise)
+        .build();
+    println!("generated {0}x{0} cover in {1:?}", side, start.elapsed());
+
+    let mut writer = PngSecretWriter::new(
+        cover,
+        Box::new(NaiveEncoder::with_framing(Framing::LengthPrefixed)),
+    );
+    let payload: Vec<u8> = (0..writer.capacity()).map(|i| i as u8).collect();
+    writer.encoder.encode(&payload);
+    let start = Instant::now();
+    writer.embed_observed(None, &mut |done, total| {
+        println!("{:>10} / {} bytes after {:?}", done, total, start.elapsed())
+    })?;
+    println!(
+        "embedded {} bytes in {:?}",
+        writer.encoder.text().len(),
+        start.elapsed()
+    );
+    Ok(())
+}
@@ -0,0 +1,31 @@
+//! Stamp a release screenshot with the build it came from and read the stamp back
+//!
+//! ```text
+//! cargo run --example stamp_provenance -- screenshot.png stamped.png
+//! ```
+//!
+//! The commit and build id come from `GITHUB_SHA` and `GITHUB_RUN_ID` when they are set. The
+//! `pngsecret provenance` subcommand does the same with more options.
+
+use pngsecret::provenance::{read_provenance, stamp_provenance, Provenance};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args_os().skip(1);
+    let (Some(input), Some(output)) = (args.next(), args.next()) else {
+        return Err("usage: stamp_provenance <screenshot> <stamped.png>".into());
+    };
+    let provenance = Provenance {
+        commit: std::env::var("GITHUB_SHA").unwrap_or_else(|_| "unknown".to_string()),
+        build_id: std::env::var("GITHUB_RUN_ID").unwrap_or_else(|_| "local".to_string()),
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    };
+
+    let stamped = stamp_provenance(&std::fs::read(input)?, &provenance, None)?;
+    std::fs::write(&output, &stamped)?;
+
+    let read = read_provenance(&std::fs::read(&output)?).ok_or("the stamp didn't survive")?;
+    assert_eq!(read, provenance);
+    println!("{:?} carries {:?}", output, read);
+    Ok(())
+}
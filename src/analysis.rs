@@ -0,0 +1,257 @@
+//! Closed-form detectability estimate, for gates that can't afford steganalysis on every run
+//!
+//! [`estimate_detectability`] scores embedding parameters against [`CoverStats`] in constant
+//! time. The stats are computed once per cover and are part of the `doctor` JSON. The estimate
+//! rests on three assumptions:
+//!
+//! 1. LSB replacement changes about half of the carrying subpixels it touches, so the evidence
+//!    grows with the utilization of the slot.
+//! 2. Sequential embedding fills the start of the slot completely. A detector looking at that
+//!    region sees full density there, and its evidence from a fraction `u` of the samples grows
+//!    like `sqrt(u)`. Permuted blocks spread the same changes at density `u`.
+//! 3. Cover LSBs that already look like noise mask the replacement, an LSB plane with entropy 1
+//!    halves the score. Constant planes such as an opaque alpha channel mask nothing.
+//!
+//! The score is not a probability. It orders parameter sets for one cover and is calibrated to
+//! rise with the chi-square p-value of [`crate::sweep::chi_square_p`], see the tests.
+
+use pngsecret::carrier::Carrier;
+use serde::Serialize;
+use std::fmt;
+
+use crate::format::{self, Framing};
+use crate::order::{Slot, SubpixelOrder};
+
+/// Share of the score an LSB plane of entropy 1 removes
+const MASKING: f64 = 0.5;
+
+/// What the estimate needs to know about a cover
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CoverStats {
+    pub width: u32,
+    pub height: u32,
+    /// Binary entropy of the red, green and blue LSBs
+    pub rgb_lsb_entropy: f64,
+    pub alpha_lsb_entropy: f64,
+}
+
+/// Binary entropy of a plane of `total` bits with `ones` set
+pub fn binary_entropy(ones: usize, total: usize) -> f64 {
+    if total == 0 || ones == 0 || ones == total {
+        return 0.0;
+    }
+    let p = ones as f64 / total as f64;
+    -(p * p.log2() + (1.0 - p) * (1.0 - p).log2())
+}
+
+impl CoverStats {
+    pub fn of(img: &impl Carrier) -> Self {
+        let channels = img.channels() as usize;
+        // Two and four channels end with alpha
+        let alpha = channels.is_multiple_of(2);
+        let (mut rgb_ones, mut alpha_ones) = (0, 0);
+        for (i, sample) in img.samples().iter().enumerate() {
+            match alpha && i % channels == channels - 1 {
+                true => alpha_ones += (*sample & 1) as usize,
+                false => rgb_ones += (*sample & 1) as usize,
+            }
+        }
+        let (width, height) = img.dimensions();
+        let pixels = width as usize * height as usize;
+        CoverStats {
+            width,
+            height,
+            rgb_lsb_entropy: binary_entropy(rgb_ones, pixels * (channels - alpha as usize)),
+            alpha_lsb_entropy: binary_entropy(alpha_ones, pixels * alpha as usize),
+        }
+    }
+
+    /// LSB entropy of the subpixels of `slot`
+    fn lsb_entropy(&self, slot: Slot) -> f64 {
+        match slot {
+            Slot::All => (3.0 * self.rgb_lsb_entropy + self.alpha_lsb_entropy) / 4.0,
+            Slot::Rgb => self.rgb_lsb_entropy,
+            Slot::Alpha => self.alpha_lsb_entropy,
+        }
+    }
+}
+
+/// The embedding parameters the estimate depends on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    pub payload_bytes: u64,
+    pub slot: Slot,
+    pub order: SubpixelOrder,
+    pub framing: Framing,
+}
+
+/// Estimated detectability from 0, untouched, to 1, evident
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Score(pub f64);
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.3}", self.0)
+    }
+}
+
+impl std::str::FromStr for Score {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<f64>() {
+            Ok(score) if (0.0..=1.0).contains(&score) => Ok(Score(score)),
+            _ => Err(format!("{:?} is not a score between 0 and 1", s)),
+        }
+    }
+}
+
+pub fn estimate_detectability(cover: &CoverStats, params: &Params) -> Score {
+    let subpixels = params
+        .slot
+        .subpixels(format::subpixel_count(cover.width, cover.height) as usize)
+        as u64;
+    // Scored as if every payload bit had a subpixel of its own, which deeper embedding only makes
+    // more visible
+    let capacity_bits = (subpixels * format::DEFAULT_BITS as u64) as f64;
+    let embedded_bits = ((params.payload_bytes + params.framing.overhead_bytes()) * 8) as f64;
+    let utilization = (embedded_bits / capacity_bits.max(1.0)).min(1.0);
+    let density = match params.order {
+        SubpixelOrder::Sequential => utilization.sqrt(),
+        SubpixelOrder::Blocks { .. } | SubpixelOrder::Shuffled { .. } => utilization,
+    };
+    Score(density * (1.0 - MASKING * cover.lsb_entropy(params.slot)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sweep::chi_square_p;
+    use crate::{NaiveEncoder, PngSecretWriter};
+    use image::{GrayAlphaImage, RgbImage, RgbaImage};
+    use rand::Rng;
+    use rand_chacha::rand_core::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    const PERMUTED: SubpixelOrder = SubpixelOrder::Blocks {
+        block_size: 64,
+        seed: 1,
+    };
+
+    fn stats(rgb: f64, alpha: f64) -> CoverStats {
+        CoverStats {
+            width: 64,
+            height: 64,
+            rgb_lsb_entropy: rgb,
+            alpha_lsb_entropy: alpha,
+        }
+    }
+
+    fn score(cover: &CoverStats, payload_bytes: u64, slot: Slot, order: SubpixelOrder) -> f64 {
+        let params = Params {
+            payload_bytes,
+            slot,
+            order,
+            framing: Framing::Terminated,
+        };
+        estimate_detectability(cover, &params).0
+    }
+
+    #[test]
+    fn scores_order_across_the_parameter_grid() {
+        let noisy = stats(1.0, 0.0);
+        let flat = stats(0.0, 0.0);
+        let payloads = [0, 100, 500, 1000, 1535];
+        for slot in [Slot::All, Slot::Rgb] {
+            for order in [SubpixelOrder::Sequential, PERMUTED] {
+                let scores: Vec<f64> = payloads
+                    .iter()
+                    .map(|bytes| score(&noisy, *bytes, slot, order))
+                    .collect();
+                assert!(scores.windows(2).all(|w| w[0] < w[1]), "{:?}", scores);
+                assert!(scores.iter().all(|s| (0.0..=1.0).contains(s)));
+            }
+            // Below full utilization, where both orders touch every subpixel
+            for bytes in &payloads[1..4] {
+                let sequential = score(&noisy, *bytes, slot, SubpixelOrder::Sequential);
+                assert!(sequential > score(&noisy, *bytes, slot, PERMUTED));
+                assert!(
+                    score(&flat, *bytes, slot, PERMUTED) > score(&noisy, *bytes, slot, PERMUTED)
+                );
+            }
+        }
+        // The same payload uses more of the smaller slot, and an opaque alpha plane masks nothing
+        assert!(
+            score(&noisy, 400, Slot::Alpha, PERMUTED) > score(&noisy, 400, Slot::Rgb, PERMUTED)
+        );
+        assert!(score(&noisy, 400, Slot::Rgb, PERMUTED) > score(&noisy, 400, Slot::All, PERMUTED));
+        assert_eq!(
+            score(&flat, 10_000, Slot::Alpha, SubpixelOrder::Sequential),
+            1.0
+        );
+    }
+
+    #[test]
+    fn cover_stats_split_rgb_and_alpha() {
+        let img = RgbaImage::from_fn(4, 4, |x, _| image::Rgba([x as u8, 0, 1, 255]));
+        let stats = CoverStats::of(&img);
+        assert_eq!(stats.alpha_lsb_entropy, 0.0);
+        // Six of twelve RGB LSBs per row are set
+        assert!((stats.rgb_lsb_entropy - binary_entropy(6, 12)).abs() < 1e-12);
+        // Covers without alpha have none to mask with, gray ones a single color sample
+        let rgb = RgbImage::from_fn(4, 4, |x, _| image::Rgb([x as u8, 0, 1]));
+        assert_eq!(CoverStats::of(&rgb), stats);
+        let gray = GrayAlphaImage::from_fn(4, 4, |x, _| image::LumaA([x as u8, 1]));
+        let stats = CoverStats::of(&gray);
+        assert_eq!(stats.rgb_lsb_entropy, 1.0);
+        assert_eq!(stats.alpha_lsb_entropy, 0.0);
+        assert_eq!("0.25".parse::<Score>(), Ok(Score(0.25)));
+        assert!("1.5".parse::<Score>().is_err());
+    }
+
+    /// Synthetic covers whose value pairs are far from equal, the case chi-square is built for
+    fn corpus() -> Vec<RgbaImage> {
+        vec![
+            RgbaImage::from_fn(64, 64, |x, y| {
+                let v = ((x + y) / 2 * 2) as u8;
+                image::Rgba([v, v, v, 255])
+            }),
+            RgbaImage::from_fn(64, 64, |x, y| {
+                image::Rgba([(x * 4) as u8 & !1, (y * 4) as u8 & !1, 128, 255])
+            }),
+        ]
+    }
+
+    #[test]
+    fn calibrated_against_chi_square() {
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        for cover in corpus() {
+            let stats = CoverStats::of(&cover);
+            let mut points = Vec::new();
+            for fraction in [0.0, 0.05, 0.25, 0.5, 0.9, 0.97, 1.0] {
+                let mut writer = PngSecretWriter::new(cover.clone(), Box::new(NaiveEncoder::new()))
+                    .with_order(PERMUTED)
+                    .with_slot(Slot::Rgb);
+                let bytes = (writer.capacity() as f64 * fraction) as usize;
+                let payload: Vec<u8> = (0..bytes).map(|_| rng.gen_range(1..=255)).collect();
+                writer.encoder.encode(&payload);
+                writer.embed().unwrap();
+                let estimate = score(&stats, bytes as u64, Slot::Rgb, PERMUTED);
+                points.push((estimate, chi_square_p(&writer.buffer)));
+            }
+            // Wherever chi-square clearly separates two embeddings, the estimate agrees
+            for (a, b) in points.iter().zip(points.iter().skip(1)) {
+                assert!(a.0 < b.0, "{:?}", points);
+            }
+            for a in &points {
+                for b in &points {
+                    if a.1 + 0.1 < b.1 {
+                        assert!(a.0 < b.0, "{:?}", points);
+                    }
+                }
+            }
+            // The global histogram only gives embedding away close to full utilization
+            assert!(points[0].1 < 0.1 && points[6].1 > 0.9, "{:?}", points);
+        }
+    }
+}
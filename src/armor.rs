@@ -0,0 +1,278 @@
+//! ASCII armor for stego PNGs, for text-only channels like chat
+//!
+//! The layout follows OpenPGP armor: a header line, the PNG as base64 in lines of
+//! [`LINE_LENGTH`] characters, a `=` line with the base64 CRC-24 of the PNG, and a footer line.
+//! Text around the block is ignored, so a pasted message with a greeting still dearmors.
+
+use base64ct::{Base64, Encoding};
+use std::fmt;
+
+pub const HEADER: &str = "-----BEGIN PNGSECRET IMAGE-----";
+pub const FOOTER: &str = "-----END PNGSECRET IMAGE-----";
+/// Base64 characters per line, every line but the last is exactly this long
+pub const LINE_LENGTH: usize = 64;
+
+const CRC24_INIT: u32 = 0xB7_04CE;
+const CRC24_POLY: u32 = 0x186_4CFB;
+
+/// Why a text isn't a valid armored image, with 1-based line numbers of the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArmorError {
+    MissingHeader,
+    /// The header on `line` is never closed by a footer
+    MissingFooter {
+        line: usize,
+    },
+    LineTooLong {
+        line: usize,
+        length: usize,
+    },
+    /// A line other than the last is shorter than [`LINE_LENGTH`]
+    LineTooShort {
+        line: usize,
+        length: usize,
+    },
+    InvalidBase64 {
+        line: usize,
+    },
+    /// The footer on `line` isn't preceded by a checksum line
+    MissingChecksum {
+        line: usize,
+    },
+    ChecksumMismatch {
+        line: usize,
+        expected: u32,
+        actual: u32,
+    },
+}
+
+impl fmt::Display for ArmorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArmorError::MissingHeader => write!(f, "no {:?} line found", HEADER),
+            ArmorError::MissingFooter { line } => {
+                write!(
+                    f,
+                    "the armor starting on line {} has no {:?} line",
+                    line, FOOTER
+                )
+            }
+            ArmorError::LineTooLong { line, length } => write!(
+                f,
+                "line {} is {} characters long, armor lines are at most {}",
+                line, length, LINE_LENGTH
+            ),
+            ArmorError::LineTooShort { line, length } => write!(
+                f,
+                "line {} is {} characters long, only the last line may be shorter than {}; \
+                 was the block rewrapped?",
+                line, length, LINE_LENGTH
+            ),
+            ArmorError::InvalidBase64 { line } => write!(f, "line {} isn't valid base64", line),
+            ArmorError::MissingChecksum { line } => {
+                write!(
+                    f,
+                    "the footer on line {} isn't preceded by a =checksum line",
+                    line
+                )
+            }
+            ArmorError::ChecksumMismatch {
+                line,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "the checksum on line {} is {:06X} but the data hashes to {:06X}, the block \
+                 was altered or truncated",
+                line, expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ArmorError {}
+
+/// CRC-24 of RFC 4880 section 6.1
+fn crc24(bytes: &[u8]) -> u32 {
+    let mut crc = CRC24_INIT;
+    for byte in bytes {
+        crc ^= (*byte as u32) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x100_0000 != 0 {
+                crc ^= CRC24_POLY;
+            }
+        }
+    }
+    crc & 0xFF_FFFF
+}
+
+fn checksum_line(crc: u32) -> String {
+    format!("={}", Base64::encode_string(&crc.to_be_bytes()[1..]))
+}
+
+/// Wrap `png` in armor, ending with a newline
+pub fn armor(png: &[u8]) -> String {
+    let encoded = Base64::encode_string(png);
+    let mut text = format!("{}\n", HEADER);
+    for line in encoded.as_bytes().chunks(LINE_LENGTH) {
+        text.push_str(std::str::from_utf8(line).expect("base64 is ASCII"));
+        text.push('\n');
+    }
+    text.push_str(&checksum_line(crc24(png)));
+    text.push('\n');
+    text.push_str(FOOTER);
+    text.push('\n');
+    text
+}
+
+/// The PNG inside the first armored block of `text`
+pub fn dearmor(text: &str) -> Result<Vec<u8>, ArmorError> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()));
+    let (header, _) = lines
+        .by_ref()
+        .find(|(_, line)| *line == HEADER)
+        .ok_or(ArmorError::MissingHeader)?;
+    let mut body = Vec::new();
+    let mut footer = None;
+    for (number, line) in lines.by_ref() {
+        if line == FOOTER {
+            footer = Some(number);
+            break;
+        }
+        if !line.is_empty() {
+            body.push((number, line));
+        }
+    }
+    let footer = footer.ok_or(ArmorError::MissingFooter { line: header })?;
+    let Some(&(checksum_number, checksum)) = body.last().filter(|(_, line)| line.starts_with('='))
+    else {
+        return Err(ArmorError::MissingChecksum { line: footer });
+    };
+    body.pop();
+
+    let mut encoded = String::new();
+    for (index, (number, line)) in body.iter().enumerate() {
+        if line.len() > LINE_LENGTH {
+            return Err(ArmorError::LineTooLong {
+                line: *number,
+                length: line.len(),
+            });
+        }
+        if line.len() < LINE_LENGTH && index + 1 < body.len() {
+            return Err(ArmorError::LineTooShort {
+                line: *number,
+                length: line.len(),
+            });
+        }
+        let valid = line
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/' || b == b'=');
+        if !valid {
+            return Err(ArmorError::InvalidBase64 { line: *number });
+        }
+        encoded.push_str(line);
+    }
+    let last_line = body.last().map_or(header, |(number, _)| *number);
+    let png =
+        Base64::decode_vec(&encoded).map_err(|_| ArmorError::InvalidBase64 { line: last_line })?;
+    let expected = Base64::decode_vec(&checksum[1..])
+        .ok()
+        .filter(|crc| crc.len() == 3)
+        .map(|crc| u32::from_be_bytes([0, crc[0], crc[1], crc[2]]))
+        .ok_or(ArmorError::InvalidBase64 {
+            line: checksum_number,
+        })?;
+    let actual = crc24(&png);
+    if expected != actual {
+        return Err(ArmorError::ChecksumMismatch {
+            line: checksum_number,
+            expected,
+            actual,
+        });
+    }
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc24_matches_openpgp() {
+        // RFC 4880 initial value for empty input, and a value computed with GnuPG
+        assert_eq!(crc24(b""), 0xB704CE);
+        assert_eq!(checksum_line(crc24(b"")), "=twTO");
+        assert_eq!(crc24(b"123456789"), 0x21CF02);
+    }
+
+    #[test]
+    fn roundtrip_with_surrounding_text() {
+        let png: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let armored = armor(&png);
+        assert!(armored.lines().all(|line| line.len() <= LINE_LENGTH));
+        let pasted = format!(
+            "Here you go:\r\n\n{}\nCheers",
+            armored.replace('\n', "\r\n")
+        );
+        assert_eq!(dearmor(&pasted).unwrap(), png);
+        assert_eq!(dearmor(&armor(b"")).unwrap(), b"");
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let armored = armor(&[7; 200]);
+        let lines: Vec<&str> = armored.lines().collect();
+        let edit = |index: usize, replacement: &str| {
+            let mut edited = lines.clone();
+            edited[index] = replacement;
+            edited.join("\n")
+        };
+
+        assert_eq!(dearmor("hello"), Err(ArmorError::MissingHeader));
+        assert_eq!(
+            dearmor(&lines[..4].join("\n")),
+            Err(ArmorError::MissingFooter { line: 1 })
+        );
+        let long = format!("{}AAAA", lines[2]);
+        assert_eq!(
+            dearmor(&edit(2, &long)),
+            Err(ArmorError::LineTooLong {
+                line: 3,
+                length: 68
+            })
+        );
+        assert_eq!(
+            dearmor(&edit(2, &lines[2][..60])),
+            Err(ArmorError::LineTooShort {
+                line: 3,
+                length: 60
+            })
+        );
+        let mut bad = lines[3].to_string();
+        bad.replace_range(0..1, "*");
+        assert_eq!(
+            dearmor(&edit(3, &bad)),
+            Err(ArmorError::InvalidBase64 { line: 4 })
+        );
+        let mut flipped = lines[2].to_string();
+        let replacement = if flipped.starts_with('A') { "B" } else { "A" };
+        flipped.replace_range(0..1, replacement);
+        assert!(matches!(
+            dearmor(&edit(2, &flipped)),
+            Err(ArmorError::ChecksumMismatch { line: 7, .. })
+        ));
+        let without_checksum: Vec<&str> = lines
+            .iter()
+            .copied()
+            .filter(|l| !l.starts_with('='))
+            .collect();
+        assert_eq!(
+            dearmor(&without_checksum.join("\n")),
+            Err(ArmorError::MissingChecksum { line: 7 })
+        );
+    }
+}
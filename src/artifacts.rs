@@ -0,0 +1,292 @@
+//! Cover structure that makes an embedded payload stand out
+//!
+//! Payload bits look like noise. Covers whose lowest bits follow a pattern, like ordered
+//! dithering, or that only use a handful of colors, like posterized and GIF-converted images,
+//! show the changed pixels plainly. So do screenshots and renders whose lowest bits are nearly
+//! all the same, where the payload is the only region with mixed bits. [`detect`] looks for all
+//! three before encoding.
+
+use image::RgbaImage;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Periods checked for a repeating LSB pattern, the sizes of common dither matrices
+const PERIODS: [u32; 3] = [2, 4, 8];
+/// Normalized autocorrelation above which the LSB plane counts as repeating
+const MIN_CORRELATION: f64 = 0.5;
+/// Channels whose LSB plane is this close to constant carry no pattern to measure
+const MIN_VARIANCE: f64 = 0.01;
+/// Most distinct colors a cover may have to count as posterized
+const POSTERIZED_COLORS: usize = 64;
+/// Smallest cover the checks are meaningful for, in pixels
+const MIN_PIXELS: u64 = 1024;
+/// Side of the centered window the LSB pattern is measured in, bounding the time on large covers
+const WINDOW: u32 = 512;
+/// Share of the color LSBs with the same value above which the LSB plane counts as flat
+const FLAT_LSB_SHARE: f64 = 0.9;
+
+/// Structure found in a cover
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Artifact {
+    /// The LSB plane repeats horizontally and vertically every `period` pixels
+    OrderedDither { period: u32 },
+    /// The cover uses only `colors` distinct colors
+    Posterized { colors: usize },
+    /// `percent` of the color LSBs are `bit`, as rendered images and screenshots have
+    FlatLsb { bit: u8, percent: u8 },
+}
+
+impl Artifact {
+    /// Stable name of the kind of artifact, for the warnings of the run summary
+    pub fn id(&self) -> &'static str {
+        match self {
+            Artifact::OrderedDither { .. } => "ordered_dither",
+            Artifact::FlatLsb { .. } => "flat_lsb",
+            Artifact::Posterized { .. } => "posterized",
+        }
+    }
+}
+
+impl fmt::Display for Artifact {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Artifact::OrderedDither { period, .. } => write!(
+                f,
+                "the lowest bits of the cover repeat every {} pixels, as with ordered dithering \
+                 or banding, so the payload will stand out against the pattern",
+                period
+            ),
+            Artifact::Posterized { colors } => write!(
+                f,
+                "the cover has only {} distinct colors, as posterized or palette-converted \
+                 images do, so changed pixels will stand out",
+                colors
+            ),
+            Artifact::FlatLsb { bit, percent } => write!(
+                f,
+                "{}% of the lowest color bits of the cover are {}, as in screenshots and renders, \
+                 so the payload will be the only region with mixed bits; --pad-to-capacity with \
+                 --embedding match fills the rest of the image with them",
+                percent, bit
+            ),
+        }
+    }
+}
+
+/// Look for structure in `img` that makes an embedded payload easy to spot
+pub fn detect(img: &RgbaImage) -> Vec<Artifact> {
+    if (img.width() as u64) * (img.height() as u64) < MIN_PIXELS {
+        return Vec::new();
+    }
+    let mut artifacts = Vec::new();
+    let (width, height) = (img.width().min(WINDOW), img.height().min(WINDOW));
+    let window = image::imageops::crop_imm(
+        img,
+        (img.width() - width) / 2,
+        (img.height() - height) / 2,
+        width,
+        height,
+    )
+    .to_image();
+    if let Some(artifact) = ordered_dither(&window) {
+        artifacts.push(artifact);
+    }
+    let colors = distinct_colors(img);
+    if colors <= POSTERIZED_COLORS {
+        artifacts.push(Artifact::Posterized { colors });
+    }
+    if let Some(artifact) = flat_lsb(img) {
+        artifacts.push(artifact);
+    }
+    artifacts
+}
+
+/// The value most color LSBs share, if nearly all do
+fn flat_lsb(img: &RgbaImage) -> Option<Artifact> {
+    let (mut ones, mut total) = (0u64, 0u64);
+    for pixel in img.pixels() {
+        ones += pixel.0[..3].iter().map(|c| (c & 1) as u64).sum::<u64>();
+        total += 3;
+    }
+    let share = ones as f64 / total as f64;
+    let (bit, share) = match share >= 0.5 {
+        true => (1, share),
+        false => (0, 1.0 - share),
+    };
+    (share >= FLAT_LSB_SHARE).then(|| Artifact::FlatLsb {
+        bit,
+        percent: (share * 100.0).floor() as u8,
+    })
+}
+
+/// Distinct RGB colors of the image, counting stops past [`POSTERIZED_COLORS`]
+fn distinct_colors(img: &RgbaImage) -> usize {
+    let mut colors = HashSet::new();
+    for pixel in img.pixels() {
+        let [r, g, b, _] = pixel.0;
+        colors.insert([r, g, b]);
+        if colors.len() > POSTERIZED_COLORS {
+            break;
+        }
+    }
+    colors.len()
+}
+
+/// Normalized autocorrelation of the LSB plane of `channel` at lag `(dx, dy)`, `None` if the
+/// plane is constant
+fn lsb_correlation(img: &RgbaImage, channel: usize, dx: u32, dy: u32) -> Option<f64> {
+    let bit = |x: u32, y: u32| (img.get_pixel(x, y).0[channel] & 1) as f64;
+    let (width, height) = img.dimensions();
+    let mean = img.pixels().map(|p| (p.0[channel] & 1) as f64).sum::<f64>()
+        / (width as u64 * height as u64) as f64;
+    let variance = mean * (1.0 - mean);
+    if variance < MIN_VARIANCE || dx >= width || dy >= height {
+        return None;
+    }
+    let (mut sum, mut count) = (0.0, 0u64);
+    for y in 0..height - dy {
+        for x in 0..width - dx {
+            sum += (bit(x, y) - mean) * (bit(x + dx, y + dy) - mean);
+            count += 1;
+        }
+    }
+    Some(sum / count as f64 / variance)
+}
+
+/// Average over the color channels of how strongly the LSB planes repeat every `period` pixels
+/// in both directions, `None` if all planes are constant
+///
+/// Smooth areas correlate at every lag, so only the excess over the correlation one pixel short of
+/// the period counts.
+fn periodicity(img: &RgbaImage, period: u32) -> Option<f64> {
+    let scores: Vec<f64> = (0..3)
+        .filter_map(|channel| {
+            let score = |dx: u32, dy: u32| {
+                let at_period = lsb_correlation(img, channel, dx * period, dy * period)?;
+                let off_period =
+                    lsb_correlation(img, channel, dx * (period - 1), dy * (period - 1))?;
+                Some(at_period.min(at_period - off_period))
+            };
+            Some(score(1, 0)?.min(score(0, 1)?))
+        })
+        .collect();
+    (!scores.is_empty()).then(|| scores.iter().sum::<f64>() / scores.len() as f64)
+}
+
+/// The period at which the LSB planes repeat best, if they clearly do
+///
+/// Larger dither matrices also repeat at their sub-periods, ties go to the shortest period.
+fn ordered_dither(img: &RgbaImage) -> Option<Artifact> {
+    let mut best: Option<(u32, f64)> = None;
+    for period in PERIODS {
+        match periodicity(img, period) {
+            Some(score) if score > MIN_CORRELATION && best.is_none_or(|(_, s)| score > s) => {
+                best = Some((period, score))
+            }
+            _ => {}
+        }
+    }
+    best.map(|(period, _)| Artifact::OrderedDither { period })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use pngsecret::testing::{CoverBuilder, Pattern};
+
+    const BAYER_4: [[u16; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+    fn noise(width: u32, height: u32) -> RgbaImage {
+        CoverBuilder::new(width, height)
+            .with_pattern(Pattern::Noise)
+            .build()
+    }
+
+    /// Flat 16x16 tiles at 1/16 level precision, dithered down to 8 bits with a 4x4 Bayer matrix
+    fn bayer_dithered(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let threshold = BAYER_4[(y % 4) as usize][(x % 4) as usize];
+            let (tx, ty) = ((x / 16) as u16, (y / 16) as u16);
+            let level = |fine: u16| ((fine + threshold) >> 4) as u8;
+            Rgba([
+                level(tx * 5 + ty * 7),
+                level(tx * 3 + ty * 11 + 400),
+                level(tx * 13 + ty * 2 + 1600),
+                255,
+            ])
+        })
+    }
+
+    #[test]
+    fn bayer_dithering_is_detected() {
+        let img = bayer_dithered(256, 256);
+        let artifacts = detect(&img);
+        assert!(
+            matches!(
+                artifacts.as_slice(),
+                [Artifact::OrderedDither { period: 4, .. }]
+            ),
+            "{:?}",
+            artifacts
+        );
+    }
+
+    #[test]
+    fn posterization_is_detected() {
+        let mut img = noise(64, 64);
+        for pixel in img.pixels_mut() {
+            for channel in &mut pixel.0[..3] {
+                *channel &= 0xc0;
+            }
+        }
+        assert_eq!(
+            detect(&img),
+            [
+                Artifact::Posterized { colors: 64 },
+                Artifact::FlatLsb {
+                    bit: 0,
+                    percent: 100
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn flat_lsb_planes_are_detected() {
+        // A render in steps of 2 and 4 levels, the LSBs all zero until a few are set
+        let mut img = RgbaImage::from_fn(64, 64, |x, y| {
+            Rgba([(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8, 255])
+        });
+        assert_eq!(
+            detect(&img),
+            [Artifact::FlatLsb {
+                bit: 0,
+                percent: 100
+            }]
+        );
+        for pixel in img.pixels_mut().take(400) {
+            pixel.0[0] |= 1;
+        }
+        assert_eq!(
+            detect(&img),
+            [Artifact::FlatLsb {
+                bit: 0,
+                percent: 96
+            }]
+        );
+        for pixel in img.pixels_mut() {
+            pixel.0[..3].iter_mut().for_each(|c| *c |= 1);
+        }
+        assert!(matches!(
+            detect(&img).as_slice(),
+            [Artifact::FlatLsb { bit: 1, .. }]
+        ));
+    }
+
+    #[test]
+    fn noise_and_tiny_covers_are_clean() {
+        assert_eq!(detect(&noise(64, 64)), []);
+        assert_eq!(detect(&RgbaImage::new(16, 16)), []);
+    }
+}
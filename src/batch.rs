@@ -0,0 +1,137 @@
+//! Encoding or decoding every PNG of a directory in one run, `--input <dir>`
+//!
+//! Each image goes through the regular single-image path with its own `--input` and `--output`,
+//! so a batch does what as many separate runs would. An image that fails is reported with its
+//! name and the others still run; the batch fails at the end if any of them did.
+
+use std::path::{Path, PathBuf};
+
+use crate::error::PngSecretError;
+use crate::output::{self, Channel};
+use crate::summary::{Outcome, Summary};
+use crate::{fsguard, paths, Opt};
+
+/// Extension of the files decode writes the messages to
+const MESSAGE_EXTENSION: &str = "payload";
+
+/// The PNG files directly inside `dir`, sorted by name
+pub fn list_pngs(dir: &Path) -> Result<Vec<PathBuf>, PngSecretError> {
+    let io = |e| PngSecretError::Io(format!("Couldn't list the images in {:?}", dir), e);
+    let mut pngs = Vec::new();
+    for entry in std::fs::read_dir(dir).map_err(io)? {
+        let path = entry.map_err(io)?.path();
+        let is_png = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+        if is_png && path.is_file() {
+            pngs.push(path);
+        }
+    }
+    pngs.sort();
+    Ok(pngs)
+}
+
+/// Where the result for `input` goes: under the same name for encode, with the extension
+/// replaced by `.payload` for decode
+pub fn output_path(output_dir: &Path, input: &Path, encode: bool) -> PathBuf {
+    let path = output_dir.join(input.file_name().unwrap_or_default());
+    match encode {
+        true => path,
+        false => paths::derive_output(&path, MESSAGE_EXTENSION),
+    }
+}
+
+/// Encode or decode every PNG in `input_dir` into the `--output` directory, creating it
+pub fn run(opt: &Opt, input_dir: &Path, summary: &mut Summary) -> Result<(), PngSecretError> {
+    // `--inspect` only reports, there are no results to put anywhere
+    let output_dir = match (opt.output.as_deref(), opt.inspect) {
+        (_, true) => None,
+        (Some(output_dir), false) => Some(output_dir),
+        (None, false) => {
+            return Err(PngSecretError::Usage(format!(
+                "{:?} is a directory, pass --output with the directory for the results",
+                input_dir
+            )))
+        }
+    };
+    for (given, flag) in [
+        (opt.armor, "--armor"),
+        (opt.dearmor, "--dearmor"),
+        (opt.receipt.is_some(), "--receipt"),
+        (opt.trace_indices.is_some(), "--trace-indices"),
+        (opt.json, "--json"),
+    ] {
+        if given {
+            return Err(PngSecretError::Usage(format!(
+                "{} works on a single image, not a directory",
+                flag
+            )));
+        }
+    }
+    if let Some(output_dir) = output_dir.filter(|dir| paths::collides(input_dir, dir)) {
+        return Err(PngSecretError::OutputIsInput(output_dir.to_path_buf()));
+    }
+    let inputs = list_pngs(input_dir)?;
+    if inputs.is_empty() {
+        return Err(PngSecretError::Usage(format!(
+            "{:?} has no PNG files",
+            input_dir
+        )));
+    }
+    if let (Some(output_dir), false) = (output_dir, opt.dry_run) {
+        fsguard::create_dir_all(output_dir)
+            .map_err(|e| PngSecretError::Io(format!("Couldn't create {:?}", output_dir), e))?;
+    }
+
+    let mut failed = 0;
+    for (i, input) in inputs.iter().enumerate() {
+        if !opt.silent {
+            output::line(
+                Channel::Diagnostics,
+                format_args!("[{}/{}] {}", i + 1, inputs.len(), input.display()),
+            );
+        }
+        let mut item = opt.clone();
+        item.input = vec![input.clone()];
+        item.output = output_dir.map(|output_dir| output_path(output_dir, input, opt.encode));
+        match crate::run(&item, summary) {
+            Ok(()) => summary.item(Outcome::Succeeded),
+            Err(e) => {
+                summary.item(Outcome::Failed);
+                failed += 1;
+                output::line(
+                    Channel::Diagnostics,
+                    format_args!("{}: {}", input.display(), e),
+                );
+            }
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        failed => Err(PngSecretError::BatchIncomplete {
+            failed,
+            total: inputs.len(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_pngs_are_listed_and_outputs_keep_their_names() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b.png", "a.PNG", "notes.txt", "png"] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+        std::fs::create_dir(dir.path().join("nested.png")).unwrap();
+        let listed = list_pngs(dir.path()).unwrap();
+        assert_eq!(listed, [dir.path().join("a.PNG"), dir.path().join("b.png")]);
+
+        let out = Path::new("out");
+        let input = &listed[1];
+        assert_eq!(output_path(out, input, true), out.join("b.png"));
+        assert_eq!(output_path(out, input, false), out.join("b.payload"));
+    }
+}
@@ -0,0 +1,72 @@
+//! What this binary is and supports, printed by `--version -v`
+//!
+//! Meant for debugging interop between builds, so everything that decides whether two binaries
+//! can read each other's images is listed here.
+
+use serde::Serialize;
+use std::fmt;
+
+use crate::format::Framing;
+use crate::{Backend, NaiveDecoder, NaiveEncoder};
+
+/// Program name and crate version, as in `--version` and receipts
+pub const TOOL: &str = concat!("pngsecret ", env!("CARGO_PKG_VERSION"));
+/// Cargo features compiled in
+const FEATURES: &[&str] = &[
+    #[cfg(feature = "failure-injection")]
+    "failure-injection",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    /// `git describe` of the source tree at build time, `unknown` outside a checkout
+    pub git_describe: &'static str,
+    pub formats: Vec<&'static str>,
+    pub codecs: Vec<&'static str>,
+    pub backends: Vec<&'static str>,
+    pub features: Vec<&'static str>,
+}
+
+pub fn collect() -> BuildInfo {
+    let mut codecs = vec![NaiveEncoder::ID, NaiveDecoder::ID];
+    codecs.dedup();
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_describe: env!("PNGSECRET_GIT_DESCRIBE"),
+        formats: Framing::ALL.iter().map(|framing| framing.name()).collect(),
+        codecs,
+        backends: Backend::ALL.iter().map(Backend::name).collect(),
+        features: FEATURES.to_vec(),
+    }
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let list = |items: &[&str]| match items {
+            [] => "none".to_string(),
+            items => items.join(", "),
+        };
+        writeln!(f, "pngsecret {} ({})", self.version, self.git_describe)?;
+        writeln!(f, "formats: {}", list(&self.formats))?;
+        writeln!(f, "codecs: {}", list(&self.codecs))?;
+        writeln!(f, "backends: {}", list(&self.backends))?;
+        writeln!(f, "features: {}", list(&self.features))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_every_codec_and_backend() {
+        let info = collect();
+        assert_eq!(info.codecs, ["naive"]);
+        assert_eq!(info.backends, ["pixel", "chunk"]);
+        for backend in &info.backends {
+            assert!(backend.parse::<Backend>().is_ok());
+        }
+        assert!(info.to_string().starts_with(&format!("{} (", TOOL)));
+    }
+}
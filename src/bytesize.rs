@@ -0,0 +1,226 @@
+//! Parsing and formatting of human readable byte sizes
+//!
+//! Every size printed by the tool goes through [`format`] so that summaries and error messages
+//! agree on units (binary, one decimal). Sizes given on the command line go through [`parse`].
+
+use std::fmt;
+
+const KIB: u64 = 1024;
+const BINARY_UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ByteSizeError {
+    Empty,
+    Negative,
+    InvalidNumber(String),
+    UnknownUnit(String),
+    /// A bare `K`/`M`/`G`/`T` suffix or a fractional value without `--si`
+    Ambiguous(String),
+    /// The value doesn't resolve to a whole number of bytes
+    Fractional(String),
+    Overflow,
+}
+
+impl fmt::Display for ByteSizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ByteSizeError::Empty => write!(f, "empty size"),
+            ByteSizeError::Negative => write!(f, "size cannot be negative"),
+            ByteSizeError::InvalidNumber(s) => write!(f, "invalid number {:?}", s),
+            ByteSizeError::UnknownUnit(s) => write!(f, "unknown unit {:?}", s),
+            ByteSizeError::Ambiguous(s) => write!(
+                f,
+                "ambiguous size {:?}, use an explicit unit like KiB/KB or pass --si",
+                s
+            ),
+            ByteSizeError::Fractional(s) => {
+                write!(f, "size {:?} is not a whole number of bytes", s)
+            }
+            ByteSizeError::Overflow => write!(f, "size is too large"),
+        }
+    }
+}
+
+impl std::error::Error for ByteSizeError {}
+
+/// Format a byte count with binary units and one decimal, e.g. `1023 B`, `1.0 KiB`
+pub fn format(bytes: u64) -> String {
+    if bytes < KIB {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= KIB as f64 && unit < BINARY_UNITS.len() - 1 {
+        value /= KIB as f64;
+        unit += 1;
+    }
+    // Avoid printing "1024.0 KiB" when rounding pushes the value over the boundary
+    if format!("{:.1}", value) == "1024.0" && unit < BINARY_UNITS.len() - 1 {
+        value /= KIB as f64;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, BINARY_UNITS[unit])
+}
+
+/// Parse a size like `512KiB`, `2MB`, `1_000_000` or `64 B`
+///
+/// Units are case-insensitive. Binary units (`KiB`, `MiB`, ...) are powers of 1024, decimal units
+/// (`KB`, `MB`, ...) are powers of 1000. Bare `K`/`M`/`G`/`T` suffixes and fractional values are
+/// rejected as ambiguous unless `si` is set, in which case bare suffixes are decimal.
+pub fn parse(input: &str, si: bool) -> Result<u64, ByteSizeError> {
+    let trimmed = input.trim();
+    if trimmed.is_empty() {
+        return Err(ByteSizeError::Empty);
+    }
+    if trimmed.starts_with('-') {
+        return Err(ByteSizeError::Negative);
+    }
+    let split = trimmed
+        .find(|c: char| !(c.is_ascii_digit() || c == '_' || c == '.'))
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    let number = number.replace('_', "");
+    let unit = unit.trim();
+    if number.is_empty() || number.starts_with('.') || number.ends_with('.') {
+        return Err(ByteSizeError::InvalidNumber(input.to_string()));
+    }
+
+    let multiplier = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kib" => KIB,
+        "mib" => KIB.pow(2),
+        "gib" => KIB.pow(3),
+        "tib" => KIB.pow(4),
+        "kb" => 1000,
+        "mb" => 1000u64.pow(2),
+        "gb" => 1000u64.pow(3),
+        "tb" => 1000u64.pow(4),
+        "k" | "m" | "g" | "t" if !si => {
+            return Err(ByteSizeError::Ambiguous(input.to_string()));
+        }
+        "k" => 1000,
+        "m" => 1000u64.pow(2),
+        "g" => 1000u64.pow(3),
+        "t" => 1000u64.pow(4),
+        _ => return Err(ByteSizeError::UnknownUnit(unit.to_string())),
+    };
+
+    match number.split_once('.') {
+        None => number
+            .parse::<u64>()
+            .map_err(|_| ByteSizeError::Overflow)?
+            .checked_mul(multiplier)
+            .ok_or(ByteSizeError::Overflow),
+        Some(_) if !si => Err(ByteSizeError::Ambiguous(input.to_string())),
+        Some((whole, frac)) => {
+            if frac.contains('.') {
+                return Err(ByteSizeError::InvalidNumber(input.to_string()));
+            }
+            let whole = whole.parse::<u64>().map_err(|_| ByteSizeError::Overflow)?;
+            let scale = 10u64
+                .checked_pow(frac.len() as u32)
+                .ok_or(ByteSizeError::Overflow)?;
+            let frac = frac.parse::<u64>().map_err(|_| ByteSizeError::Overflow)?;
+            let frac_bytes = frac
+                .checked_mul(multiplier)
+                .ok_or(ByteSizeError::Overflow)?;
+            if frac_bytes % scale != 0 {
+                return Err(ByteSizeError::Fractional(input.to_string()));
+            }
+            whole
+                .checked_mul(multiplier)
+                .and_then(|w| w.checked_add(frac_bytes / scale))
+                .ok_or(ByteSizeError::Overflow)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_boundaries() {
+        assert_eq!(format(0), "0 B");
+        assert_eq!(format(1023), "1023 B");
+        assert_eq!(format(1024), "1.0 KiB");
+        assert_eq!(format(1536), "1.5 KiB");
+        assert_eq!(format(1024 * 1024 - 1), "1.0 MiB");
+        assert_eq!(format(1024 * 1024), "1.0 MiB");
+        assert_eq!(format(5 * 1024 * 1024 * 1024), "5.0 GiB");
+        assert_eq!(format(u64::MAX), "16384.0 PiB");
+    }
+
+    #[test]
+    fn parse_plain_numbers() {
+        assert_eq!(parse("0", false), Ok(0));
+        assert_eq!(parse("1_000_000", false), Ok(1_000_000));
+        assert_eq!(parse(" 64 B ", false), Ok(64));
+    }
+
+    #[test]
+    fn parse_units_and_casing() {
+        assert_eq!(parse("512KiB", false), Ok(512 * 1024));
+        assert_eq!(parse("512kib", false), Ok(512 * 1024));
+        assert_eq!(parse("512KIB", false), Ok(512 * 1024));
+        assert_eq!(parse("2MB", false), Ok(2_000_000));
+        assert_eq!(parse("2mb", false), Ok(2_000_000));
+        assert_eq!(parse("3 GiB", false), Ok(3 * 1024 * 1024 * 1024));
+        assert_eq!(
+            parse("2XB", false),
+            Err(ByteSizeError::UnknownUnit("XB".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_ambiguous_requires_si() {
+        assert!(matches!(
+            parse("1.5K", false),
+            Err(ByteSizeError::Ambiguous(_))
+        ));
+        assert!(matches!(
+            parse("4M", false),
+            Err(ByteSizeError::Ambiguous(_))
+        ));
+        assert!(matches!(
+            parse("1.5KiB", false),
+            Err(ByteSizeError::Ambiguous(_))
+        ));
+        assert_eq!(parse("1.5K", true), Ok(1500));
+        assert_eq!(parse("4M", true), Ok(4_000_000));
+        assert_eq!(parse("1.5KiB", true), Ok(1536));
+        assert!(matches!(
+            parse("1.0001K", true),
+            Err(ByteSizeError::Fractional(_))
+        ));
+    }
+
+    #[test]
+    fn parse_rejects_invalid() {
+        assert_eq!(parse("", false), Err(ByteSizeError::Empty));
+        assert_eq!(parse("-5", false), Err(ByteSizeError::Negative));
+        assert_eq!(parse("-5KiB", true), Err(ByteSizeError::Negative));
+        assert!(matches!(
+            parse("KiB", false),
+            Err(ByteSizeError::InvalidNumber(_))
+        ));
+        assert!(matches!(
+            parse("1.", true),
+            Err(ByteSizeError::InvalidNumber(_))
+        ));
+        assert!(matches!(
+            parse("1.2.3", true),
+            Err(ByteSizeError::InvalidNumber(_))
+        ));
+    }
+
+    #[test]
+    fn parse_overflow() {
+        assert_eq!(parse("18446744073709551615", false), Ok(u64::MAX));
+        assert_eq!(
+            parse("18446744073709551616", false),
+            Err(ByteSizeError::Overflow)
+        );
+        assert_eq!(parse("16777216 TiB", false), Err(ByteSizeError::Overflow));
+    }
+}
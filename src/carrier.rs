@@ -0,0 +1,226 @@
+//! Images whose samples carry the payload, in the channels they were decoded with
+//!
+//! Payload bits go into every 8-bit sample the image has, so an RGB cover stays RGB and a gray
+//! one stays gray instead of gaining channels a before/after comparison would notice. The color
+//! samples form [`Slot::Rgb`](crate::order::Slot::Rgb) and the alpha samples
+//! [`Slot::Alpha`](crate::order::Slot::Alpha), which is empty in images without alpha.
+//!
+//! 16-bit images carry the payload in the low byte of every sample: [`low_bytes`] is the 8-bit
+//! image the payload is written into and [`with_low_bytes`] puts it back, so the cover keeps its
+//! depth and no sample moves further than in an 8-bit one.
+
+use image::{DynamicImage, ImageBuffer, Pixel};
+
+/// An 8-bit image the payload is written into
+pub trait Carrier {
+    fn dimensions(&self) -> (u32, u32);
+    /// Samples per pixel, the last one alpha for two and four
+    fn channels(&self) -> u8;
+    /// Every sample, pixel by pixel in row order
+    fn samples(&self) -> &[u8];
+    fn samples_mut(&mut self) -> &mut [u8];
+}
+
+impl<P: Pixel<Subpixel = u8>> Carrier for ImageBuffer<P, Vec<u8>> {
+    fn dimensions(&self) -> (u32, u32) {
+        ImageBuffer::dimensions(self)
+    }
+    fn channels(&self) -> u8 {
+        P::CHANNEL_COUNT
+    }
+    fn samples(&self) -> &[u8] {
+        self
+    }
+    fn samples_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+/// Panics for images with more than 8 bits per sample, [`to_8bit`] converts them first
+impl Carrier for DynamicImage {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width(), self.height())
+    }
+    fn channels(&self) -> u8 {
+        self.color().channel_count()
+    }
+    fn samples(&self) -> &[u8] {
+        match self {
+            DynamicImage::ImageLuma8(img) => img.samples(),
+            DynamicImage::ImageLumaA8(img) => img.samples(),
+            DynamicImage::ImageRgb8(img) => img.samples(),
+            DynamicImage::ImageRgba8(img) => img.samples(),
+            other => panic!("{:?} images can't carry a payload", other.color()),
+        }
+    }
+    fn samples_mut(&mut self) -> &mut [u8] {
+        match self {
+            DynamicImage::ImageLuma8(img) => img.samples_mut(),
+            DynamicImage::ImageLumaA8(img) => img.samples_mut(),
+            DynamicImage::ImageRgb8(img) => img.samples_mut(),
+            DynamicImage::ImageRgba8(img) => img.samples_mut(),
+            other => panic!("{:?} images can't carry a payload", other.color()),
+        }
+    }
+}
+
+/// `img` with 8 bits per sample and the same channels, unchanged if it already has 8
+pub fn to_8bit(img: DynamicImage) -> DynamicImage {
+    match img {
+        DynamicImage::ImageLuma8(_)
+        | DynamicImage::ImageLumaA8(_)
+        | DynamicImage::ImageRgb8(_)
+        | DynamicImage::ImageRgba8(_) => img,
+        other => match other.color().channel_count() {
+            1 => DynamicImage::ImageLuma8(other.into_luma8()),
+            2 => DynamicImage::ImageLumaA8(other.into_luma_alpha8()),
+            3 => DynamicImage::ImageRgb8(other.into_rgb8()),
+            _ => DynamicImage::ImageRgba8(other.into_rgba8()),
+        },
+    }
+}
+
+/// The 8-bit image of the low byte of every sample of a 16-bit `img`, `None` for other depths
+pub fn low_bytes(img: &DynamicImage) -> Option<DynamicImage> {
+    fn low<P: Pixel<Subpixel = u8>>(
+        (width, height): (u32, u32),
+        samples: &[u16],
+    ) -> ImageBuffer<P, Vec<u8>> {
+        let bytes = samples.iter().map(|&sample| sample as u8).collect();
+        ImageBuffer::from_raw(width, height, bytes).expect("one byte per sample")
+    }
+    let size = (img.width(), img.height());
+    Some(match img {
+        DynamicImage::ImageLuma16(img) => DynamicImage::ImageLuma8(low(size, img)),
+        DynamicImage::ImageLumaA16(img) => DynamicImage::ImageLumaA8(low(size, img)),
+        DynamicImage::ImageRgb16(img) => DynamicImage::ImageRgb8(low(size, img)),
+        DynamicImage::ImageRgba16(img) => DynamicImage::ImageRgba8(low(size, img)),
+        _ => return None,
+    })
+}
+
+/// The 16-bit `wide` with the low byte of every sample taken from `low`, as [`low_bytes`] made
+/// it
+///
+/// Panics unless `wide` has 16 bits per sample.
+pub fn with_low_bytes(mut wide: DynamicImage, low: &impl Carrier) -> DynamicImage {
+    let samples: &mut [u16] = match &mut wide {
+        DynamicImage::ImageLuma16(img) => img,
+        DynamicImage::ImageLumaA16(img) => img,
+        DynamicImage::ImageRgb16(img) => img,
+        DynamicImage::ImageRgba16(img) => img,
+        other => panic!("{:?} images have no low bytes", other.color()),
+    };
+    for (sample, &byte) in samples.iter_mut().zip(low.samples()) {
+        *sample = *sample & 0xFF00 | byte as u16;
+    }
+    wide
+}
+
+/// The samples the payload of `img` is read from, the low bytes of 16-bit images and the image
+/// at 8 bits otherwise
+pub fn payload_samples(img: DynamicImage) -> DynamicImage {
+    payload_carrier(img).0
+}
+
+/// The [`payload_samples`] of `img` and, for 16-bit images, `img` itself to put them back into
+/// with [`with_low_bytes`] once the payload changed
+pub fn payload_carrier(img: DynamicImage) -> (DynamicImage, Option<DynamicImage>) {
+    match low_bytes(&img) {
+        Some(low) => (low, Some(img)),
+        None => (to_8bit(img), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::Slot;
+    use crate::{capacity_bytes, embed, extract, NaiveEncoder, PngSecretWriter};
+    use image::{GrayAlphaImage, GrayImage, RgbImage, RgbaImage};
+
+    fn roundtrip<C: Carrier + Clone + PartialEq + std::fmt::Debug>(cover: C) {
+        let payload = b"stays in its own channels".to_vec();
+        let stego = embed(cover.clone(), &payload).unwrap();
+        assert_ne!(stego, cover);
+        let high_bits_kept = cover
+            .samples()
+            .iter()
+            .zip(stego.samples())
+            .all(|(before, after)| before >> 1 == after >> 1);
+        assert!(high_bits_kept);
+        assert_eq!(extract(stego), Ok(payload));
+    }
+
+    #[test]
+    fn every_8bit_color_type_carries_a_payload() {
+        roundtrip(RgbImage::from_fn(24, 12, |x, y| {
+            image::Rgb([x as u8, y as u8, 200])
+        }));
+        roundtrip(RgbaImage::from_fn(24, 12, |x, y| {
+            image::Rgba([x as u8, y as u8, 200, 255])
+        }));
+        roundtrip(GrayImage::from_fn(32, 12, |x, y| {
+            image::Luma([(x * y) as u8])
+        }));
+        roundtrip(GrayAlphaImage::from_fn(24, 12, |x, y| {
+            image::LumaA([x as u8, y as u8])
+        }));
+        roundtrip(DynamicImage::ImageLumaA8(GrayAlphaImage::new(24, 12)));
+    }
+
+    #[test]
+    fn capacity_counts_the_channels_there_are() {
+        // 16x8 pixels of 1 to 3 samples, 13 bytes of frame header or 1 terminator byte
+        let gray = GrayImage::new(16, 8);
+        assert_eq!(capacity_bytes(&gray, 1), 16 - 13);
+        assert_eq!(capacity_bytes(&RgbImage::new(16, 8), 1), 48 - 13);
+        let writer =
+            PngSecretWriter::new(GrayAlphaImage::new(16, 8), Box::new(NaiveEncoder::new()));
+        assert_eq!(writer.capacity_in(Slot::Rgb), 16 - 1);
+        assert_eq!(writer.capacity_in(Slot::Alpha), 16 - 1);
+        let writer = PngSecretWriter::new(gray, Box::new(NaiveEncoder::new()));
+        assert_eq!(writer.capacity_in(Slot::Rgb), 16 - 1);
+        assert_eq!(writer.capacity_in(Slot::Alpha), 0);
+    }
+
+    #[test]
+    fn deeper_images_keep_their_channels() {
+        let rgb16 = DynamicImage::ImageRgb16(image::ImageBuffer::from_pixel(
+            2,
+            2,
+            image::Rgb([0x1234, 0, 0xFFFF]),
+        ));
+        let rgb = to_8bit(rgb16);
+        assert_eq!(rgb.color(), image::ColorType::Rgb8);
+        assert_eq!(rgb.samples()[..3], [0x12, 0, 0xFF]);
+        let gray = DynamicImage::ImageLuma8(GrayImage::new(2, 2));
+        assert_eq!(to_8bit(gray.clone()), gray);
+    }
+
+    #[test]
+    fn sixteen_bit_images_carry_in_their_low_bytes() {
+        let cover = DynamicImage::ImageRgba16(image::ImageBuffer::from_fn(24, 12, |x, y| {
+            image::Rgba([x as u16 * 2731, 0xFFFF - y as u16, 0x00FF, 0xFF00])
+        }));
+        let low = low_bytes(&cover).unwrap();
+        assert_eq!(low.color(), image::ColorType::Rgba8);
+        let stego = with_low_bytes(cover.clone(), &embed(low, b"sixteen bits").unwrap());
+        assert_eq!(stego.color(), cover.color());
+        let moved_by_one = cover
+            .as_rgba16()
+            .unwrap()
+            .iter()
+            .zip(stego.as_rgba16().unwrap().iter())
+            .all(|(before, after)| before.abs_diff(*after) <= 1);
+        assert!(moved_by_one);
+        assert_eq!(
+            extract(payload_samples(stego)),
+            Ok(b"sixteen bits".to_vec())
+        );
+        assert_eq!(
+            low_bytes(&DynamicImage::ImageLuma8(GrayImage::new(2, 2))),
+            None
+        );
+    }
+}
@@ -0,0 +1,165 @@
+//! Unsigned integrity sidecars (`.psum`) of encode outputs, for archives where decoding every
+//! image again is too slow
+//!
+//! A sidecar is a small TOML file next to the stego file with the SHA-256 of the file and of the
+//! payload, hashed like [`crate::receipt`] does. Checking the file hash needs no credentials;
+//! checking the payload hash needs the image decoded.
+//! Unlike a receipt nothing is signed, so a sidecar only catches accidental changes.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::buildinfo;
+use crate::error::PngSecretError;
+use crate::fsguard;
+use crate::receipt::{self, sha256};
+
+/// Extension appended to the output file name, `out.enc.png` gets `out.enc.png.psum`
+pub const EXTENSION: &str = "psum";
+/// Version of the sidecar layout, bumped whenever fields change meaning
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Digest {
+    pub sha256: String,
+    pub bytes: u64,
+}
+
+impl Digest {
+    fn of(bytes: &[u8]) -> Self {
+        Digest {
+            sha256: sha256(bytes),
+            bytes: bytes.len() as u64,
+        }
+    }
+}
+
+/// How the payload bytes were turned into embedded bits, on top of the receipt parameters
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Codec {
+    pub bits: u8,
+    pub legacy: bool,
+    pub compressed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sidecar {
+    pub format_version: u32,
+    pub tool: String,
+    pub output: Digest,
+    /// The embedded payload, before compression
+    pub payload: Digest,
+    pub parameters: receipt::Parameters,
+    pub codec: Codec,
+}
+
+fn invalid(reason: impl Into<String>) -> PngSecretError {
+    PngSecretError::ChecksumMismatch(reason.into())
+}
+
+/// The sidecar path of the output file `output`
+pub fn sidecar_path(output: &Path) -> PathBuf {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".");
+    path.push(EXTENSION);
+    PathBuf::from(path)
+}
+
+/// The output file a sidecar at `path` was written for, `None` without the `.psum` extension
+pub fn output_path(path: &Path) -> Option<PathBuf> {
+    match path.extension() {
+        Some(extension) if extension == EXTENSION => Some(path.with_extension("")),
+        _ => None,
+    }
+}
+
+pub fn new(stego: &[u8], payload: &[u8], parameters: receipt::Parameters, codec: Codec) -> Sidecar {
+    Sidecar {
+        format_version: FORMAT_VERSION,
+        tool: buildinfo::TOOL.to_string(),
+        output: Digest::of(stego),
+        payload: Digest::of(payload),
+        parameters,
+        codec,
+    }
+}
+
+pub fn write(sidecar: &Sidecar, path: &Path) -> Result<(), PngSecretError> {
+    let text = toml::to_string(sidecar).expect("sidecar serializes");
+    fsguard::write_atomic(path, text.as_bytes()).map_err(|e| {
+        PngSecretError::Io(format!("Couldn't write the checksum sidecar {:?}", path), e)
+    })
+}
+
+/// Read the sidecar at `path` and check that `contents`, read from `file`, still match it
+pub fn verify(path: &Path, file: &Path, contents: &[u8]) -> Result<Sidecar, PngSecretError> {
+    let text = std::fs::read_to_string(path).map_err(|e| {
+        PngSecretError::Io(format!("Couldn't read the checksum sidecar {:?}", path), e)
+    })?;
+    let sidecar: Sidecar =
+        toml::from_str(&text).map_err(|e| invalid(format!("malformed sidecar: {}", e)))?;
+    if sidecar.format_version != FORMAT_VERSION {
+        return Err(invalid(format!(
+            "unsupported format version {}",
+            sidecar.format_version
+        )));
+    }
+    if Digest::of(contents) != sidecar.output {
+        return Err(invalid(format!(
+            "{} is not the file the sidecar was written for",
+            file.display()
+        )));
+    }
+    Ok(sidecar)
+}
+
+/// Check a payload decoded from the file against the sidecar
+pub fn verify_payload(sidecar: &Sidecar, payload: &[u8]) -> Result<(), PngSecretError> {
+    match Digest::of(payload) == sidecar.payload {
+        true => Ok(()),
+        false => Err(invalid("the decoded payload differs from the one embedded")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidecars_roundtrip_and_catch_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let stego = dir.path().join("out.enc.png");
+        let path = sidecar_path(&stego);
+        assert_eq!(path, dir.path().join("out.enc.png.psum"));
+        assert_eq!(output_path(&path), Some(stego.clone()));
+        assert_eq!(output_path(&stego), None);
+
+        let parameters = receipt::Parameters {
+            slot: "all".to_string(),
+            permute: "none".to_string(),
+            truncated: false,
+            chunk_notice: false,
+            alpha_payload: false,
+        };
+        let codec = Codec {
+            bits: 1,
+            legacy: false,
+            compressed: true,
+        };
+        let sidecar = new(b"stego bytes", b"payload", parameters, codec);
+        write(&sidecar, &path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with("format_version = 1\n"), "{}", text);
+
+        assert_eq!(verify(&path, &stego, b"stego bytes").unwrap(), sidecar);
+        assert!(verify_payload(&sidecar, b"payload").is_ok());
+        assert!(matches!(
+            verify(&path, &stego, b"stego bytez"),
+            Err(PngSecretError::ChecksumMismatch(_))
+        ));
+        assert!(matches!(
+            verify_payload(&sidecar, b"payloaf"),
+            Err(PngSecretError::ChecksumMismatch(_))
+        ));
+    }
+}
@@ -0,0 +1,257 @@
+//! PNG files as the stream of chunks they are, for `--method chunk`
+//!
+//! The chunk method keeps the payload in a private ancillary [`PAYLOAD_CHUNK`] instead of the
+//! pixels. Decoding and re-encoding the image would rewrite IDAT, so the cover is parsed into its
+//! chunks, the payload chunk goes in before IEND and every other chunk is copied byte for byte.
+//! The chunk holds the payload framed as in the pixels, with its length and CRC-32, compressed as
+//! asked.
+//!
+//! The pixel method writes a new PNG, which [`with_cover_metadata`] gives the ancillary chunks of
+//! the cover back: text, physical size, color space and the like. Chunks describing how the cover
+//! stored its pixels, e.g. its background color or bit depths, would lie about the new file and
+//! are left out.
+
+use pngsecret::format::{self, FrameHeader};
+
+use crate::pngio::NOTICE_KEYWORD;
+
+/// First bytes of every PNG file
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+/// Ancillary, private and safe to copy, so editors that keep unknown chunks keep it
+pub const PAYLOAD_CHUNK: [u8; 4] = *b"stEg";
+/// Longest chunk data PNG allows
+pub const MAX_CHUNK_BYTES: usize = i32::MAX as usize;
+
+/// One chunk of a PNG stream, borrowed from the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunk<'a> {
+    pub kind: [u8; 4],
+    pub data: &'a [u8],
+}
+
+/// The chunks of `png` up to IEND, `None` unless it is a PNG stream whose chunks have valid CRCs
+pub fn parse(png: &[u8]) -> Option<Vec<Chunk<'_>>> {
+    let mut rest = png.strip_prefix(&SIGNATURE)?;
+    let mut chunks = Vec::new();
+    while !rest.is_empty() {
+        let length = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+        let kind: [u8; 4] = rest.get(4..8)?.try_into().ok()?;
+        let data = rest.get(8..8usize.checked_add(length)?)?;
+        let crc = u32::from_be_bytes(rest.get(8 + length..12 + length)?.try_into().ok()?);
+        if crc != chunk_crc(&kind, data) {
+            return None;
+        }
+        chunks.push(Chunk { kind, data });
+        rest = &rest[12 + length..];
+        if &kind == b"IEND" {
+            break;
+        }
+    }
+    (chunks.last()?.kind == *b"IEND").then_some(chunks)
+}
+
+/// The PNG stream of `chunks`
+pub fn write(chunks: &[Chunk]) -> Vec<u8> {
+    let mut png = SIGNATURE.to_vec();
+    for chunk in chunks {
+        png.extend_from_slice(&(chunk.data.len() as u32).to_be_bytes());
+        png.extend_from_slice(&chunk.kind);
+        png.extend_from_slice(chunk.data);
+        png.extend_from_slice(&chunk_crc(&chunk.kind, chunk.data).to_be_bytes());
+    }
+    png
+}
+
+/// `png` with `framed` as its only payload chunk, right before IEND, `None` if `png` isn't a
+/// PNG stream
+///
+/// Panics if `framed` is longer than [`MAX_CHUNK_BYTES`].
+pub fn with_payload(png: &[u8], framed: &[u8]) -> Option<Vec<u8>> {
+    assert!(framed.len() <= MAX_CHUNK_BYTES, "{} bytes", framed.len());
+    let mut chunks: Vec<Chunk> = parse(png)?
+        .into_iter()
+        .filter(|chunk| chunk.kind != PAYLOAD_CHUNK)
+        .collect();
+    let end = chunks.len() - 1;
+    chunks.insert(
+        end,
+        Chunk {
+            kind: PAYLOAD_CHUNK,
+            data: framed,
+        },
+    );
+    Some(write(&chunks))
+}
+
+/// Ancillary chunks whose meaning doesn't depend on how the pixels are stored
+const KEPT: [[u8; 4]; 15] = [
+    *b"tEXt", *b"zTXt", *b"iTXt", *b"pHYs", *b"gAMA", *b"cHRM", *b"sRGB", *b"iCCP", *b"cICP",
+    *b"tIME", *b"eXIf", *b"oFFs", *b"pCAL", *b"sCAL", *b"sTER",
+];
+
+/// A PNG stream with the metadata of its cover, see [`with_cover_metadata`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WithMetadata {
+    pub png: Vec<u8>,
+    /// Kinds of the ancillary chunks of the cover that were left out, in file order
+    pub dropped: Vec<[u8; 4]>,
+}
+
+/// `png` with the ancillary chunks of `cover`, on the same side of the image data as in `cover`,
+/// `None` unless both are PNG streams
+///
+/// Known chunks in [`KEPT`] and unknown ones marked safe to copy are carried over. The payload
+/// chunk and the notice of an earlier payload are not, they describe the cover's payload.
+pub fn with_cover_metadata(png: &[u8], cover: &[u8]) -> Option<WithMetadata> {
+    let cover = parse(cover)?;
+    let stego = parse(png)?;
+    let mut dropped = Vec::new();
+    let (mut before, mut after) = (Vec::new(), Vec::new());
+    let mut seen_data = false;
+    for chunk in cover {
+        seen_data |= &chunk.kind == b"IDAT";
+        let ancillary = chunk.kind[0].is_ascii_lowercase();
+        let safe_to_copy = chunk.kind[3].is_ascii_lowercase();
+        let notice = &chunk.kind == b"tEXt"
+            && chunk.data.split(|&b| b == 0).next() == Some(NOTICE_KEYWORD.as_bytes());
+        if !ancillary || chunk.kind == PAYLOAD_CHUNK || notice {
+            continue;
+        }
+        if !KEPT.contains(&chunk.kind) && !safe_to_copy {
+            dropped.push(chunk.kind);
+            continue;
+        }
+        match seen_data {
+            false => before.push(chunk),
+            true => after.push(chunk),
+        }
+    }
+    let mut chunks = Vec::with_capacity(stego.len() + before.len() + after.len());
+    let (header, rest) = stego.split_first()?;
+    let (end, rest) = rest.split_last()?;
+    chunks.push(*header);
+    chunks.extend(before);
+    chunks.extend_from_slice(rest);
+    chunks.extend(after);
+    chunks.push(*end);
+    Some(WithMetadata {
+        png: write(&chunks),
+        dropped,
+    })
+}
+
+/// The data of the payload chunk of `png`, if it is a PNG stream with one
+pub fn payload(png: &[u8]) -> Option<&[u8]> {
+    parse(png)?
+        .into_iter()
+        .find(|chunk| chunk.kind == PAYLOAD_CHUNK)
+        .map(|chunk| chunk.data)
+}
+
+/// The message of a payload chunk, checked against the CRC-32 of its frame when verifying
+pub fn unframe(data: &[u8], verify: bool) -> Result<Vec<u8>, pngsecret::Error> {
+    if let Some(version) = format::newer_frame_version(data) {
+        return Err(pngsecret::Error::NewerFormat { version });
+    }
+    let header =
+        FrameHeader::parse(data, data.len() as u64).ok_or(pngsecret::Error::PayloadCorrupted)?;
+    let message = &data[header.bytes()..][..header.length as usize];
+    if verify && !header.verifies(message) {
+        return Err(pngsecret::Error::PayloadCorrupted);
+    }
+    Ok(message.to_vec())
+}
+
+fn chunk_crc(kind: &[u8; 4], data: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(data);
+    hasher.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+    use pngsecret::format::Framing;
+
+    fn image_data(png: &[u8]) -> Vec<u8> {
+        parse(png)
+            .unwrap()
+            .into_iter()
+            .filter(|chunk| &chunk.kind == b"IDAT")
+            .flat_map(|chunk| chunk.data.iter().copied())
+            .collect()
+    }
+
+    #[test]
+    fn payload_chunks_replace_each_other_and_leave_the_rest() {
+        let img = RgbaImage::from_fn(5, 4, |x, y| image::Rgba([x as u8, y as u8, 9, 255]));
+        let cover =
+            crate::pngio::encode_with_text(&img, &[("Comment".into(), "hi".into())]).unwrap();
+        assert_eq!(write(&parse(&cover).unwrap()), cover);
+        assert_eq!(payload(&cover), None);
+
+        let framed = Framing::LengthPrefixed.frame(b"a\0b");
+        let stego = with_payload(&cover, &framed).unwrap();
+        assert_eq!(payload(&stego), Some(&framed[..]));
+        assert_eq!(
+            unframe(payload(&stego).unwrap(), true),
+            Ok(b"a\0b".to_vec())
+        );
+        assert_eq!(image_data(&stego), image_data(&cover));
+        let kinds: Vec<[u8; 4]> = parse(&stego).unwrap().iter().map(|c| c.kind).collect();
+        assert_eq!(kinds[kinds.len() - 2..], [PAYLOAD_CHUNK, *b"IEND"]);
+
+        let replaced = with_payload(&stego, &Framing::LengthPrefixed.frame(b"c")).unwrap();
+        assert_eq!(
+            unframe(payload(&replaced).unwrap(), true),
+            Ok(b"c".to_vec())
+        );
+        assert_eq!(replaced.len(), stego.len() - 2);
+
+        let mut damaged = stego.clone();
+        let last = damaged.len() - 20;
+        damaged[last] ^= 1;
+        assert_eq!(parse(&damaged), None);
+        assert_eq!(parse(b"GIF89a"), None);
+        assert_eq!(parse(&cover[..cover.len() - 12]), None);
+    }
+
+    #[test]
+    fn cover_metadata_is_carried_over_where_it_was() {
+        let img = RgbaImage::from_fn(3, 2, |x, y| image::Rgba([x as u8, y as u8, 1, 255]));
+        let plain = crate::pngio::encode_with_text(&img, &[]).unwrap();
+        let parsed = parse(&plain).unwrap();
+        let chunk = |kind: &[u8; 4], data: &'static [u8]| Chunk { kind: *kind, data };
+        let notice = [NOTICE_KEYWORD.as_bytes(), b"\0old"].concat();
+        let mut cover = vec![
+            parsed[0],
+            chunk(b"iCCP", b"icc\0\0x"),
+            chunk(b"bKGD", b"\0\0\0\0\0\0"),
+            chunk(b"prVt", b"safe"),
+            chunk(b"prVT", b"unsafe"),
+            Chunk {
+                kind: *b"tEXt",
+                data: &notice,
+            },
+        ];
+        cover.extend_from_slice(&parsed[1..parsed.len() - 1]);
+        cover.push(chunk(b"tEXt", b"Comment\0after the pixels"));
+        cover.push(chunk(&PAYLOAD_CHUNK, b"old payload"));
+        cover.push(*parsed.last().unwrap());
+        let cover = write(&cover);
+
+        let stego =
+            crate::pngio::encode_with_text(&img, &[("Title".into(), "new".into())]).unwrap();
+        let kept = with_cover_metadata(&stego, &cover).unwrap();
+        assert_eq!(kept.dropped, [*b"bKGD", *b"prVT"]);
+        let kinds: Vec<[u8; 4]> = parse(&kept.png).unwrap().iter().map(|c| c.kind).collect();
+        assert_eq!(
+            kinds,
+            [*b"IHDR", *b"iCCP", *b"prVt", *b"tEXt", *b"IDAT", *b"tEXt", *b"IEND"]
+        );
+        assert_eq!(image_data(&kept.png), image_data(&stego));
+        assert_eq!(with_cover_metadata(&stego, b"BM"), None);
+    }
+}
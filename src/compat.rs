@@ -0,0 +1,163 @@
+//! Reference extractor of the legacy pixel format, written against the format alone
+//!
+//! Given the raw straight RGBA bytes of an image, its dimensions and the embedding parameters,
+//! [`extract`] returns the payload. It uses nothing else of the crate, no image decoding and no
+//! file I/O, so it doubles as a description of the format that any reimplementation can follow:
+//!
+//! 1. The slot picks which subpixels carry bits: all four channels of every pixel, the first
+//!    three, or only alpha. They are numbered in buffer order.
+//! 2. With block permutation, those numbers are cut into blocks of `block_size` (the last may
+//!    be shorter) and the blocks are visited in a Fisher-Yates order: for `i` from `n - 1` down
+//!    to 1, swap block `i` with block `next_u64() % (i + 1)` of a ChaCha8 generator seeded with
+//!    `seed_from_u64(seed)`. Subpixels within a block stay in order.
+//! 3. Each visited subpixel contributes its least significant bit, eight bits form a byte most
+//!    significant bit first, and the first zero byte ends the payload. Without one, or with
+//!    one as the very first byte as in clean images with zero LSBs, there is no payload.
+//!
+//! The tests decode the same buffers with this module and with the real reader, so any drift of
+//! the format in a refactor shows up as a disagreement.
+
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// Channels of each RGBA pixel that carry the payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channels {
+    All,
+    Rgb,
+    Alpha,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    pub channels: Channels,
+    /// Block size and seed of the block permutation, `None` for buffer order
+    pub blocks: Option<(usize, u64)>,
+}
+
+/// The payload of a `width` x `height` image given as RGBA bytes, `None` without a terminator
+/// after at least one byte or if the buffer doesn't match the dimensions
+pub fn extract(rgba: &[u8], width: u32, height: u32, params: &Params) -> Option<Vec<u8>> {
+    let pixels = width as usize * height as usize;
+    if rgba.len() != pixels * 4 {
+        return None;
+    }
+    let channels: &[usize] = match params.channels {
+        Channels::All => &[0, 1, 2, 3],
+        Channels::Rgb => &[0, 1, 2],
+        Channels::Alpha => &[3],
+    };
+    let carriers: Vec<usize> = (0..pixels)
+        .flat_map(|pixel| channels.iter().map(move |channel| pixel * 4 + channel))
+        .collect();
+    let visited: Vec<usize> = match params.blocks {
+        None => carriers,
+        Some((block_size, seed)) => {
+            let mut blocks: Vec<&[usize]> = carriers.chunks(block_size).collect();
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            for i in (1..blocks.len()).rev() {
+                let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+                blocks.swap(i, j);
+            }
+            blocks.concat()
+        }
+    };
+
+    let mut payload = Vec::new();
+    for byte in visited.chunks_exact(8) {
+        let value = byte
+            .iter()
+            .fold(0u8, |value, &index| (value << 1) | (rgba[index] & 1));
+        if value == 0 {
+            return (!payload.is_empty()).then_some(payload);
+        }
+        payload.push(value);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Framing;
+    use crate::order::{Slot, SubpixelOrder};
+    use crate::{NaiveDecoder, NaiveEncoder, PngSecretReader, PngSecretWriter};
+    use image::RgbaImage;
+    use rand::Rng;
+
+    fn reader(img: RgbaImage, slot: Slot, order: SubpixelOrder) -> Option<Vec<u8>> {
+        PngSecretReader::new(img, Box::new(NaiveDecoder::new()))
+            .with_order(order)
+            .with_slot(slot)
+            .with_framing(Framing::Terminated)
+            .read_image()
+            .ok()
+    }
+
+    #[test]
+    fn agrees_with_the_reader_across_parameters() {
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+        let slots = [
+            (Slot::All, Channels::All),
+            (Slot::Rgb, Channels::Rgb),
+            (Slot::Alpha, Channels::Alpha),
+        ];
+        let mut orders = vec![(SubpixelOrder::Sequential, None)];
+        for block_size in [1, 7, 64, 4096] {
+            for seed in [0, 99] {
+                orders.push((
+                    SubpixelOrder::Blocks { block_size, seed },
+                    Some((block_size, seed)),
+                ));
+            }
+        }
+        for (width, height) in [(2, 4), (5, 3), (16, 16), (31, 9)] {
+            for (slot, channels) in slots {
+                for (order, blocks) in &orders {
+                    let params = Params {
+                        channels,
+                        blocks: *blocks,
+                    };
+                    let mut cover = RgbaImage::new(width, height);
+                    rng.fill(&mut *cover);
+                    // A cover without a payload must read the same with both, too
+                    assert_eq!(
+                        extract(cover.as_raw(), width, height, &params),
+                        reader(cover.clone(), slot, *order),
+                        "{:?}",
+                        params
+                    );
+
+                    let mut writer = PngSecretWriter::new(cover, Box::new(NaiveEncoder::new()))
+                        .with_order(*order)
+                        .with_slot(slot);
+                    let payload: Vec<u8> = (0..writer.capacity())
+                        .map(|_| rng.gen_range(1..=255))
+                        .collect();
+                    writer.encoder.encode(&payload);
+                    writer.embed().unwrap();
+                    let stego = writer.buffer;
+                    let extracted = extract(stego.as_raw(), width, height, &params);
+                    // Slots of one byte hold only the terminator, which is no payload
+                    let expected = (!payload.is_empty()).then_some(&payload[..]);
+                    assert_eq!(extracted.as_deref(), expected, "{:?}", params);
+                    assert_eq!(extracted, reader(stego, slot, *order), "{:?}", params);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mismatched_dimensions_are_rejected() {
+        let params = Params {
+            channels: Channels::All,
+            blocks: None,
+        };
+        // "A" and the terminator
+        let mut rgba = [0; 16];
+        rgba[1] = 1;
+        rgba[7] = 1;
+        assert_eq!(extract(&rgba, 2, 2, &params), Some(b"A".to_vec()));
+        assert_eq!(extract(&rgba, 3, 2, &params), None);
+    }
+}
@@ -0,0 +1,213 @@
+//! Deflate compression of payloads, applied before they are framed and embedded
+//!
+//! A compressed payload starts with [`MAGIC`] and a [`Method`] byte, so readers recognize it
+//! without being told. Payloads that deflate doesn't shrink are stored as they are behind the same
+//! header, they only grow by [`OVERHEAD_BYTES`].
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::io::{Read, Write};
+
+use crate::format::{Features, Framing};
+use crate::order::Slot;
+use crate::{Error, PngSecretDecoder, PngSecretEncoder};
+
+/// First bytes of a payload written by [`CompressingEncoder`], before the method byte
+pub const MAGIC: [u8; 3] = *b"PSZ";
+/// Bytes compression adds to a payload it can't shrink
+pub const OVERHEAD_BYTES: usize = MAGIC.len() + 1;
+/// Longest payload a compressed one may expand to, so a small image can't fill the memory
+pub const MAX_INFLATED_BYTES: u64 = 256 << 20;
+
+/// How the body after the header is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Method {
+    Stored = 0,
+    Deflate = 1,
+}
+
+/// The method of a payload written by [`CompressingEncoder`], `None` for any other payload
+pub fn method(payload: &[u8]) -> Option<Method> {
+    match payload.strip_prefix(&MAGIC)?.first()? {
+        0 => Some(Method::Stored),
+        1 => Some(Method::Deflate),
+        _ => None,
+    }
+}
+
+/// `payload` deflated behind the header, or stored if deflate doesn't make it smaller
+pub fn compress(payload: &[u8]) -> Vec<u8> {
+    let header = [&MAGIC[..], &[Method::Deflate as u8]].concat();
+    let mut deflate = DeflateEncoder::new(header, Compression::best());
+    deflate
+        .write_all(payload)
+        .expect("writing to a Vec can't fail");
+    let compressed = deflate.finish().expect("writing to a Vec can't fail");
+    if compressed.len() < payload.len() + OVERHEAD_BYTES {
+        return compressed;
+    }
+    let mut stored = Vec::with_capacity(payload.len() + OVERHEAD_BYTES);
+    stored.extend_from_slice(&MAGIC);
+    stored.push(Method::Stored as u8);
+    stored.extend_from_slice(payload);
+    stored
+}
+
+/// The payload of a compressed one, any other payload unchanged
+pub fn decompress(payload: Vec<u8>) -> Result<Vec<u8>, Error> {
+    decompress_within(payload, MAX_INFLATED_BYTES)
+}
+
+fn decompress_within(payload: Vec<u8>, limit: u64) -> Result<Vec<u8>, Error> {
+    let body = &payload[OVERHEAD_BYTES.min(payload.len())..];
+    match method(&payload) {
+        None => Ok(payload),
+        Some(Method::Stored) => Ok(body.to_vec()),
+        Some(Method::Deflate) => {
+            let mut inflated = Vec::new();
+            DeflateDecoder::new(body)
+                .take(limit + 1)
+                .read_to_end(&mut inflated)
+                .map_err(|_| Error::CorruptPayload)?;
+            match inflated.len() as u64 > limit {
+                true => Err(Error::CorruptPayload),
+                false => Ok(inflated),
+            }
+        }
+    }
+}
+
+/// Encoder compressing the payload before `inner` frames it
+pub struct CompressingEncoder {
+    inner: Box<dyn PngSecretEncoder>,
+}
+
+impl CompressingEncoder {
+    /// Codec name in log records
+    pub const ID: &'static str = "deflate";
+
+    pub fn new(inner: Box<dyn PngSecretEncoder>) -> Self {
+        CompressingEncoder { inner }
+    }
+}
+
+impl PngSecretEncoder for CompressingEncoder {
+    fn encode_with(&mut self, seq: &[u8], features: Features) {
+        let features = Features {
+            compressed: true,
+            ..features
+        };
+        self.inner.encode_with(&compress(seq), features);
+    }
+    fn place(&mut self, slot: Slot) {
+        self.inner.place(slot);
+    }
+    fn text(&self) -> &[u8] {
+        self.inner.text()
+    }
+    fn framing(&self) -> Framing {
+        self.inner.framing()
+    }
+    fn bits(&self) -> u8 {
+        self.inner.bits()
+    }
+    /// The worst case, for payloads stored uncompressed
+    fn overhead_bytes(&self) -> u64 {
+        self.inner.overhead_bytes() + OVERHEAD_BYTES as u64
+    }
+    fn framed_capacity(&self, slot_bytes: u64) -> u64 {
+        self.inner.framed_capacity(slot_bytes)
+    }
+}
+
+/// Decoder inflating payloads of [`CompressingEncoder`] and passing any other through
+#[derive(Default)]
+pub struct CompressingDecoder {}
+
+impl CompressingDecoder {
+    pub fn new() -> Self {
+        CompressingDecoder {}
+    }
+}
+
+impl PngSecretDecoder for CompressingDecoder {
+    fn decode(&mut self, seq: Vec<u8>) -> Result<Vec<u8>, Error> {
+        decompress(seq)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NaiveEncoder, PngSecretReader, PngSecretWriter};
+    use image::RgbaImage;
+    use quickcheck::quickcheck;
+
+    fn writer(width: u32, height: u32) -> PngSecretWriter {
+        let inner = NaiveEncoder::with_framing(Framing::LengthPrefixed);
+        let encoder = CompressingEncoder::new(Box::new(inner));
+        PngSecretWriter::new(RgbaImage::new(width, height), Box::new(encoder))
+    }
+
+    fn read(writer: PngSecretWriter) -> Result<Vec<u8>, Error> {
+        PngSecretReader::new(writer.buffer, Box::new(CompressingDecoder::new())).read_image()
+    }
+
+    #[test]
+    fn repetitive_payloads_fit_far_past_the_raw_capacity() {
+        let payload = b"All work and no play makes Jack a dull boy.\n".repeat(2400);
+        assert!(payload.len() > 100_000);
+        // 200x200 RGBA holds 20000 framed bytes
+        let mut writer = writer(200, 200);
+        assert!(writer.capacity() < 20_000);
+        writer.encoder.encode(&payload);
+        writer.embed().unwrap();
+        assert_eq!(read(writer), Ok(payload));
+    }
+
+    #[test]
+    fn corrupt_and_bomb_payloads_are_refused() {
+        let mut corrupt = compress(&[b'x'; 1000]);
+        corrupt.truncate(OVERHEAD_BYTES + 1);
+        corrupt.push(0xFF);
+        assert_eq!(decompress(corrupt), Err(Error::CorruptPayload));
+
+        let bomb = compress(&[0; 100_001]);
+        assert!(bomb.len() < 1000);
+        assert_eq!(
+            decompress_within(bomb.clone(), 100_000),
+            Err(Error::CorruptPayload)
+        );
+        assert_eq!(
+            decompress_within(bomb, 100_001).map(|p| p.len()),
+            Ok(100_001)
+        );
+
+        assert_eq!(
+            decompress(b"PSZ\x07body".to_vec()),
+            Ok(b"PSZ\x07body".to_vec())
+        );
+        assert_eq!(decompress(b"plain".to_vec()), Ok(b"plain".to_vec()));
+    }
+
+    quickcheck! {
+        fn incompressible_payloads_are_stored(seed: u64) -> bool {
+            use rand::{RngCore, SeedableRng};
+            let mut payload = vec![0; 2000];
+            rand_chacha::ChaCha8Rng::seed_from_u64(seed).fill_bytes(&mut payload);
+            let compressed = compress(&payload);
+            let mut writer = writer(64, 64);
+            writer.encoder.encode(&payload);
+            method(&compressed) == Some(Method::Stored)
+                && compressed.len() == payload.len() + OVERHEAD_BYTES
+                && writer.embed().is_ok()
+                && read(writer) == Ok(payload)
+        }
+
+        fn any_payload_roundtrips(payload: Vec<u8>) -> bool {
+            decompress(compress(&payload)) == Ok(payload)
+        }
+    }
+}
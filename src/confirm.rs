@@ -0,0 +1,193 @@
+//! Confirmation before a command replaces existing files
+//!
+//! Every command that writes a file the user named describes the write as a [`Plan`] and passes
+//! it to [`confirm`] first. Nothing is asked when no existing file is replaced or `--yes` is
+//! given. Otherwise the plan is shown on stderr and the answer is read from the terminal itself,
+//! not stdin, so redirected input or output doesn't answer for the user. Without a terminal the
+//! command refuses to run.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use crate::bytesize;
+use crate::error::PngSecretError;
+use crate::output::{self, Channel};
+use crate::wizard::Prompt;
+
+/// The answer that confirms a plan, anything else declines
+const CONFIRMATION: &str = "yes";
+
+/// What a command is about to do and which existing files it replaces
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    action: String,
+    /// Existing files about to be replaced, with their current size
+    replaces: Vec<(PathBuf, u64)>,
+}
+
+impl Plan {
+    /// `action` writing `outputs`, of which only the existing ones need confirmation
+    pub fn new(action: impl Into<String>, outputs: &[&Path]) -> Self {
+        let replaces = outputs
+            .iter()
+            .filter_map(|path| {
+                let metadata = std::fs::metadata(path).ok()?;
+                Some((path.to_path_buf(), metadata.len()))
+            })
+            .collect();
+        Plan {
+            action: action.into(),
+            replaces,
+        }
+    }
+
+    fn is_destructive(&self) -> bool {
+        !self.replaces.is_empty()
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.action)?;
+        write!(f, "This replaces, without keeping a backup:")?;
+        for (path, size) in &self.replaces {
+            write!(f, "\n  {} ({})", path.display(), bytesize::format(*size))?;
+        }
+        Ok(())
+    }
+}
+
+/// Asks on stderr and reads the answer from the controlling terminal
+struct Tty {
+    input: BufReader<File>,
+}
+
+impl Tty {
+    /// The terminal, `None` when stderr isn't one or there is no controlling terminal
+    fn open() -> Option<Self> {
+        if !io::stderr().is_terminal() {
+            return None;
+        }
+        #[cfg(windows)]
+        let path = "CONIN$";
+        #[cfg(not(windows))]
+        let path = "/dev/tty";
+        let input = File::open(path).ok()?;
+        Some(Tty {
+            input: BufReader::new(input),
+        })
+    }
+}
+
+impl Prompt for Tty {
+    fn ask(&mut self, question: &str, _default: Option<&str>) -> io::Result<Option<String>> {
+        let mut stderr = io::stderr().lock();
+        write!(stderr, "{}: ", question)?;
+        stderr.flush()?;
+        let mut answer = String::new();
+        if self.input.read_line(&mut answer)? == 0 {
+            writeln!(stderr)?;
+            return Ok(None);
+        }
+        Ok(Some(answer.trim_end_matches(['\r', '\n']).to_string()))
+    }
+
+    fn say(&mut self, message: &str) {
+        eprintln!("{}", message);
+    }
+}
+
+/// Let the user confirm `plan` unless it replaces nothing or `yes` is set
+pub fn confirm(plan: &Plan, yes: bool) -> Result<(), PngSecretError> {
+    if yes || !plan.is_destructive() {
+        return Ok(());
+    }
+    match Tty::open() {
+        Some(mut tty) => confirm_with(&mut tty, plan),
+        None => {
+            output::line(Channel::Diagnostics, plan);
+            Err(PngSecretError::NotConfirmed { asked: false })
+        }
+    }
+}
+
+/// Show `plan` and require the confirmation to be typed out
+fn confirm_with(prompt: &mut dyn Prompt, plan: &Plan) -> Result<(), PngSecretError> {
+    prompt.say(&plan.to_string());
+    let question = format!("Type {:?} to continue", CONFIRMATION);
+    let answer = prompt
+        .ask(&question, None)
+        .map_err(|e| PngSecretError::Io("Couldn't read the confirmation".to_string(), e))?;
+    match answer {
+        Some(answer) if answer.trim() == CONFIRMATION => Ok(()),
+        _ => Err(PngSecretError::NotConfirmed { asked: true }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gives one scripted answer and records what was shown
+    struct Script {
+        answer: Option<&'static str>,
+        said: Vec<String>,
+    }
+
+    impl Prompt for Script {
+        fn ask(&mut self, question: &str, _default: Option<&str>) -> io::Result<Option<String>> {
+            self.said.push(question.to_string());
+            Ok(self.answer.take().map(str::to_string))
+        }
+
+        fn say(&mut self, message: &str) {
+            self.said.push(message.to_string());
+        }
+    }
+
+    #[test]
+    fn only_existing_files_need_confirmation() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("existing.png");
+        std::fs::write(&existing, [0; 2048]).unwrap();
+        let missing = dir.path().join("missing.png");
+
+        assert!(!Plan::new("Embed", &[&missing]).is_destructive());
+        assert!(confirm(&Plan::new("Embed", &[&missing]), false).is_ok());
+        let plan = Plan::new("Embed a payload", &[&existing, &missing]);
+        assert_eq!(plan.replaces, [(existing.clone(), 2048)]);
+        assert!(confirm(&plan, true).is_ok());
+        let summary = plan.to_string();
+        assert!(summary.starts_with("Embed a payload\n"), "{}", summary);
+        assert!(summary.contains("without keeping a backup"), "{}", summary);
+        assert!(summary.contains("existing.png (2.0 KiB)"), "{}", summary);
+    }
+
+    #[test]
+    fn scripted_answers() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("existing.png");
+        std::fs::write(&existing, b"x").unwrap();
+        let plan = Plan::new("Wipe", &[&existing]);
+
+        let mut script = Script {
+            answer: Some("yes"),
+            said: Vec::new(),
+        };
+        assert!(confirm_with(&mut script, &plan).is_ok());
+        assert_eq!(script.said[0], plan.to_string());
+        assert!(script.said[1].contains("\"yes\""));
+        for answer in [Some("y"), Some(""), None] {
+            let mut script = Script {
+                answer,
+                said: Vec::new(),
+            };
+            assert!(matches!(
+                confirm_with(&mut script, &plan),
+                Err(PngSecretError::NotConfirmed { asked: true })
+            ));
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//! The lossless formats a stego image is saved in, picked by the extension of the output
+//!
+//! Inputs in any format `image` reads work as covers. The output keeps the format of the input
+//! unless `--output` names another one, and unknown extensions get PNG as always. Lossy formats
+//! are refused up front, re-encoding them rewrites exactly the low bits the payload is in. So
+//! are covers larger than the container holds, before anything is embedded.
+
+use image::{ColorType, DynamicImage, ImageFormat};
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::error::PngSecretError;
+
+/// Extensions of formats that don't keep every sample bit for bit
+const LOSSY_EXTENSIONS: [&str; 6] = ["jpg", "jpeg", "jfif", "gif", "avif", "heic"];
+/// Widest and tallest image the PNG specification allows
+const PNG_MAX_DIMENSION: u32 = i32::MAX as u32;
+/// Widest and tallest lossless WebP image
+const WEBP_MAX_DIMENSION: u32 = 16384;
+/// Largest file the 32-bit offsets of BMP and classic TIFF address
+const MAX_32BIT_FILE_BYTES: u64 = u32::MAX as u64;
+/// File and info header of an RGBA BMP, the larger ones
+const BMP_HEADER_BYTES: u64 = 14 + 108;
+
+/// How large an image a container holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Widest and tallest image
+    pub max_dimension: u32,
+    /// Largest file, `None` if only the memory limits it
+    pub max_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    Png,
+    Bmp,
+    Tiff,
+    WebP,
+}
+
+impl Container {
+    /// The container `path` asks for by its extension, `None` for other extensions
+    pub fn of_path(path: &Path) -> Result<Option<Container>, PngSecretError> {
+        let Some(extension) = path.extension().and_then(|extension| extension.to_str()) else {
+            return Ok(None);
+        };
+        let extension = extension.to_ascii_lowercase();
+        if LOSSY_EXTENSIONS.contains(&extension.as_str()) {
+            return Err(PngSecretError::LossyOutput(path.to_path_buf()));
+        }
+        Ok(match extension.as_str() {
+            "png" => Some(Container::Png),
+            "bmp" => Some(Container::Bmp),
+            "tif" | "tiff" => Some(Container::Tiff),
+            "webp" => Some(Container::WebP),
+            _ => None,
+        })
+    }
+
+    /// The extension of the default output for `input`, `enc.` and its own extension if that
+    /// names a container
+    pub fn default_extension(input: &Path) -> String {
+        match (Container::of_path(input), input.extension()) {
+            (Ok(Some(_)), Some(extension)) => format!("enc.{}", extension.to_string_lossy()),
+            _ => "enc.png".to_string(),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Container::Png => "PNG",
+            Container::Bmp => "BMP",
+            Container::Tiff => "TIFF",
+            Container::WebP => "WebP",
+        }
+    }
+
+    fn format(self) -> ImageFormat {
+        match self {
+            Container::Png => ImageFormat::Png,
+            Container::Bmp => ImageFormat::Bmp,
+            Container::Tiff => ImageFormat::Tiff,
+            Container::WebP => ImageFormat::WebP,
+        }
+    }
+
+    /// Whether images of `color` read back with the same channels and samples
+    ///
+    /// BMP and WebP store gray images as RGB, which moves every payload bit.
+    pub fn stores(self, color: ColorType) -> bool {
+        matches!(
+            (self, color),
+            (Container::Png, _)
+                | (_, ColorType::Rgb8 | ColorType::Rgba8)
+                | (Container::Tiff, ColorType::L8)
+        )
+    }
+
+    /// Fail unless images of `color` can be saved in this container
+    pub fn check(self, color: ColorType) -> Result<(), PngSecretError> {
+        match self.stores(color) {
+            true => Ok(()),
+            false => Err(PngSecretError::UnsupportedContainer {
+                container: self.name(),
+                color,
+            }),
+        }
+    }
+
+    pub fn limits(self) -> Limits {
+        match self {
+            Container::Png => Limits {
+                max_dimension: PNG_MAX_DIMENSION,
+                max_bytes: None,
+            },
+            Container::Bmp => Limits {
+                max_dimension: i32::MAX as u32,
+                max_bytes: Some(MAX_32BIT_FILE_BYTES),
+            },
+            Container::Tiff => Limits {
+                max_dimension: u32::MAX,
+                max_bytes: Some(MAX_32BIT_FILE_BYTES),
+            },
+            Container::WebP => Limits {
+                max_dimension: WEBP_MAX_DIMENSION,
+                max_bytes: None,
+            },
+        }
+    }
+
+    /// Bytes of an uncompressed `width` x `height` file of `color`, BMP rows padded to 4 bytes
+    fn file_bytes(self, width: u32, height: u32, color: ColorType) -> u64 {
+        let row = width as u64 * color.bytes_per_pixel() as u64;
+        match self {
+            Container::Bmp => BMP_HEADER_BYTES + height as u64 * row.next_multiple_of(4),
+            _ => height as u64 * row,
+        }
+    }
+
+    /// Fail unless a `width` x `height` image of `color` fits the limits of this container
+    pub fn check_size(
+        self,
+        width: u32,
+        height: u32,
+        color: ColorType,
+    ) -> Result<(), PngSecretError> {
+        self.check_within(self.limits(), width, height, color)
+    }
+
+    fn check_within(
+        self,
+        limits: Limits,
+        width: u32,
+        height: u32,
+        color: ColorType,
+    ) -> Result<(), PngSecretError> {
+        let too_wide = width.max(height) > limits.max_dimension;
+        let too_large = limits
+            .max_bytes
+            .is_some_and(|max_bytes| self.file_bytes(width, height, color) > max_bytes);
+        match too_wide || too_large {
+            true => Err(PngSecretError::OutputTooLarge {
+                container: self.name(),
+                width,
+                height,
+                max_dimension: limits.max_dimension,
+                max_bytes: limits.max_bytes,
+            }),
+            false => Ok(()),
+        }
+    }
+
+    /// The bytes of the 8-bit `img` in this container, which must [`Container::stores`] it
+    pub fn encode(self, img: &DynamicImage) -> image::ImageResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        img.write_to(&mut Cursor::new(&mut bytes), self.format())?;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, RgbaImage};
+
+    #[test]
+    fn outputs_keep_the_container_and_every_stored_sample() {
+        let container = |path: &str| Container::of_path(Path::new(path));
+        assert_eq!(container("a.b.TIF").unwrap(), Some(Container::Tiff));
+        assert_eq!(container("stego").unwrap(), None);
+        assert_eq!(container("stego.out").unwrap(), None);
+        assert!(container("stego.JPG").is_err());
+        assert_eq!(
+            Container::default_extension(Path::new("a.b.bmp")),
+            "enc.bmp"
+        );
+        assert_eq!(Container::default_extension(Path::new("a.jpg")), "enc.png");
+        assert_eq!(Container::default_extension(Path::new("a")), "enc.png");
+
+        let rgba = DynamicImage::ImageRgba8(RgbaImage::from_fn(5, 3, |x, y| {
+            image::Rgba([x as u8, y as u8 * 3, 255 - x as u8, 128 + y as u8])
+        }));
+        let gray = DynamicImage::ImageLuma8(GrayImage::from_fn(5, 3, |x, y| {
+            image::Luma([x as u8 * 50 + y as u8])
+        }));
+        for container in [
+            Container::Png,
+            Container::Bmp,
+            Container::Tiff,
+            Container::WebP,
+        ] {
+            for img in [&rgba, &gray] {
+                if !container.stores(img.color()) {
+                    continue;
+                }
+                let bytes = container.encode(img).unwrap();
+                let read = image::load_from_memory(&bytes).unwrap();
+                assert_eq!(read.color(), img.color(), "{:?}", container);
+                assert_eq!(read.as_bytes(), img.as_bytes(), "{:?}", container);
+            }
+        }
+        assert!(!Container::Bmp.stores(ColorType::L8));
+    }
+
+    #[test]
+    fn oversized_covers_are_refused_with_their_limits() {
+        let rgba = ColorType::Rgba8;
+        assert!(Container::Png.check_size(40_000, 40_000, rgba).is_ok());
+        assert!(Container::WebP.check_size(16_384, 16_384, rgba).is_ok());
+        assert!(matches!(
+            Container::WebP.check_size(20_000, 1_000, rgba),
+            Err(PngSecretError::OutputTooLarge {
+                container: "WebP",
+                width: 20_000,
+                height: 1_000,
+                max_dimension: 16_384,
+                max_bytes: None,
+            })
+        ));
+        // 4 GiB of pixels, under the dimension limits but past the 32-bit offsets
+        assert!(Container::Bmp.check_size(32_768, 32_768, rgba).is_err());
+        assert!(Container::Tiff.check_size(32_768, 32_768, rgba).is_err());
+        assert!(Container::Tiff
+            .check_size(32_768, 32_767, ColorType::L8)
+            .is_ok());
+
+        let limits = Limits {
+            max_dimension: 10,
+            max_bytes: Some(100),
+        };
+        let check = |container: Container, width, height| {
+            container.check_within(limits, width, height, ColorType::Rgb8)
+        };
+        assert!(check(Container::Png, 10, 3).is_ok());
+        assert!(check(Container::Png, 11, 1).is_err());
+        assert!(check(Container::Png, 1, 11).is_err());
+        assert!(check(Container::Tiff, 5, 6).is_ok());
+        assert!(check(Container::Tiff, 5, 7).is_err());
+        // 15 bytes per row pad to 16, plus the headers
+        assert!(check(Container::Bmp, 5, 1).is_err());
+        let roomy = Limits {
+            max_bytes: Some(BMP_HEADER_BYTES + 96),
+            ..limits
+        };
+        assert!(Container::Bmp
+            .check_within(roomy, 5, 6, ColorType::Rgb8)
+            .is_ok());
+        assert!(Container::Bmp
+            .check_within(roomy, 5, 7, ColorType::Rgb8)
+            .is_err());
+    }
+}
@@ -0,0 +1,923 @@
+//! Reading a payload back: finding its frame, recovering reordered channels, opening records and
+//! compression and delivering the message, for decode, `cat` and `checksum verify --decode`
+
+use image::{DynamicImage, RgbaImage};
+use pngsecret::carrier::{self, Carrier};
+use pngsecret::compress::{self, CompressingDecoder};
+use pngsecret::format::Framing;
+use pngsecret::order::{Slot, SubpixelOrder};
+use pngsecret::records;
+use pngsecret::segments::{self, Segment};
+use pngsecret::slots::{self, SlotInfo};
+use pngsecret::{
+    bytesize, ImageInfo, Location, NaiveDecoder, PngSecretDecoder, PngSecretReader, ReadEvent,
+};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+
+use crate::encoding;
+use crate::error::PngSecretError;
+use crate::output::{self, Channel};
+use crate::sniff::{self, ContentType};
+use crate::summary::{Outcome, Summary};
+use crate::{
+    checksum, chunks, confirm, fsguard, inject, input, input_path, layout, paths, pngio, probe,
+    report, trace, Backend, DecodeFormat, Method, Opt, OrderOpt, SlotArg,
+};
+
+/// Write the message of every input to stdout as it is read, reporting each failure on stderr
+///
+/// Like `cat`, a failing input doesn't stop the others from being written; the run fails at the
+/// end unless the only failures were missing messages and `skip_missing` is set.
+pub fn cat(
+    opt: &Opt,
+    inputs: &[PathBuf],
+    skip_missing: bool,
+    order: &OrderOpt,
+    summary: &mut Summary,
+) -> Result<(), PngSecretError> {
+    let (slot, subpixel_order) = (order.given_slot()?, order.order()?);
+    let mut failed = 0;
+    for path in inputs {
+        if output::payload_closed() {
+            // Nobody reads the rest, e.g. `pngsecret cat *.png | head -c 100`
+            break;
+        }
+        let message = inject::check().and_then(|()| {
+            let bytes = input::read_stable(path, opt.modified_retries)?;
+            summary.bytes_in += bytes.len() as u64;
+            let img = probe::load(&bytes, path, None)?;
+            let message = read_message(
+                carrier::payload_samples(img),
+                subpixel_order,
+                slot,
+                order.offset,
+                opt.legacy,
+                !opt.no_verify,
+                opt.bits,
+            )?;
+            open_message(message)
+        });
+        match message {
+            Ok(message) => {
+                summary.item(Outcome::Succeeded);
+                summary.bytes_out += message.len() as u64;
+                output::write(Channel::Payload, &message)
+            }
+            Err(PngSecretError::NoMessage) if skip_missing => {
+                summary.item(Outcome::Skipped);
+                output::line(
+                    Channel::Diagnostics,
+                    format_args!("{}: no message, skipped", path.display()),
+                )
+            }
+            Err(e) => {
+                summary.item(Outcome::Failed);
+                failed += 1;
+                output::line(
+                    Channel::Diagnostics,
+                    format_args!("{}: {}", path.display(), e),
+                );
+            }
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        failed => Err(PngSecretError::IncompleteConcatenation {
+            failed,
+            total: inputs.len(),
+        }),
+    }
+}
+
+/// Check a stego file against its `.psum` sidecar, and its payload too with `decode`
+pub fn verify_checksum(
+    opt: &Opt,
+    sidecar: &Path,
+    file: Option<&Path>,
+    decode: bool,
+    summary: &mut Summary,
+) -> Result<(), PngSecretError> {
+    let file = match file {
+        Some(file) => file.to_path_buf(),
+        None => checksum::output_path(sidecar).ok_or_else(|| {
+            PngSecretError::Usage(format!(
+                "{:?} doesn't end in .{}, pass the stego file with --file",
+                sidecar,
+                checksum::EXTENSION
+            ))
+        })?,
+    };
+    let contents = input::read_stable(&file, opt.modified_retries)?;
+    summary.bytes_in += contents.len() as u64;
+    let verified = checksum::verify(sidecar, &file, &contents)?;
+    if decode {
+        let img = image::load_from_memory(&contents)
+            .map_err(|_| PngSecretError::InputUnreadable(file.clone()))?;
+        let (slot, order) = (opt.order.given_slot()?, opt.order.order()?);
+        let message = read_message(
+            carrier::payload_samples(img),
+            order,
+            slot,
+            opt.order.offset,
+            opt.legacy,
+            !opt.no_verify,
+            opt.bits,
+        )?;
+        checksum::verify_payload(&verified, &open_message(message)?)?;
+    }
+    output::line(
+        Channel::Payload,
+        format_args!(
+            "Checksum OK: {} matches {}{}",
+            file.display(),
+            sidecar.display(),
+            match decode {
+                true => ", payload included",
+                false => "",
+            }
+        ),
+    );
+    Ok(())
+}
+
+/// Inflate a message read from an image if it was compressed
+fn open_message(message: Vec<u8>) -> Result<Vec<u8>, PngSecretError> {
+    Ok(CompressingDecoder::new().decode(message)?)
+}
+
+/// Pick the `--name` record of a record set and open it like a single message, or open a single
+/// message as it is
+pub fn open_records(opt: &Opt, message: Vec<u8>) -> Result<Vec<u8>, PngSecretError> {
+    if !records::is_records(&message) {
+        return match opt.name.is_empty() {
+            true => open_message(message),
+            false => Err(PngSecretError::Usage(
+                "--name picks a record, but the image holds a single payload".to_string(),
+            )),
+        };
+    }
+    let records = records::unpack(&message)?;
+    let name = match opt.name.as_slice() {
+        [name] => name,
+        [] => {
+            let names: Vec<&str> = records.iter().map(|record| record.name.as_str()).collect();
+            return Err(PngSecretError::Usage(format!(
+                "The image holds the records {}, pick one with --name",
+                names.join(", ")
+            )));
+        }
+        _ => {
+            return Err(PngSecretError::Usage(
+                "Pass a single --name to decode".to_string(),
+            ))
+        }
+    };
+    let record =
+        records::find(&records, name).ok_or_else(|| PngSecretError::NoRecord(name.clone()))?;
+    Ok(CompressingDecoder::new().decode(record.data.clone())?)
+}
+
+/// Print a line with the name and stored size of every record for `--list`
+fn list_records(message: &[u8]) -> Result<(), PngSecretError> {
+    if !records::is_records(message) {
+        return Err(PngSecretError::Usage(
+            "--list shows records, but the image holds a single payload".to_string(),
+        ));
+    }
+    for record in records::unpack(message)? {
+        output::line(
+            Channel::Payload,
+            format_args!("{}\t{} bytes", record.name, record.data.len()),
+        );
+    }
+    Ok(())
+}
+
+/// The subpixels decode takes the first `--trace-bits` bits of the slot from, in reading order
+fn read_trace(opt: &Opt, img: &DynamicImage) -> Result<Vec<usize>, PngSecretError> {
+    let slot = match opt.order.slot_arg()? {
+        None => None,
+        Some(SlotArg::Named(slot)) => Some(slot),
+        Some(SlotArg::Index(_)) => {
+            return Err(PngSecretError::Usage(
+                "--trace-indices needs the --slot by name, not by its index".to_string(),
+            ))
+        }
+    };
+    let order = opt.order.order()?;
+    let reader = pinned_reader(img.clone(), order, slot, opt.order.offset, opt.bits);
+    Ok(reader
+        .bytes()
+        .flat_map(|(_, subpixels)| subpixels)
+        .take(opt.trace_bits)
+        .collect())
+}
+
+/// The dimensions of the image, and how much fits into it when encoding
+pub fn report_image(info: &ImageInfo) {
+    match info.capacity {
+        Some(capacity) => output::line(
+            Channel::Diagnostics,
+            format_args!(
+                "Image width {}, Image Height {}, message length limit {}",
+                info.width, info.height, capacity
+            ),
+        ),
+        None => output::line(
+            Channel::Diagnostics,
+            format_args!("Image width {}, Image Height {}", info.width, info.height),
+        ),
+    }
+}
+
+/// Decode the message of `img`, or of the payload chunk of its file `png` if it has one
+pub fn decode(
+    opt: &Opt,
+    img: DynamicImage,
+    png: &[u8],
+    summary: &mut Summary,
+) -> Result<(), PngSecretError> {
+    inject::check()?;
+    if opt.inspect {
+        return inspect_payload(opt, carrier::payload_samples(img), png);
+    }
+    // An armored input is text, its chunks are only in the dearmored PNG
+    let notice = match opt.dearmor {
+        true => None,
+        false => pngio::read_notice(input_path(opt)),
+    };
+    if let Some(notice) = notice {
+        output::line(
+            Channel::Diagnostics,
+            format_args!("Notice (tEXt chunk): {}", notice),
+        );
+    }
+    let (raw_message, original_length) = read_raw_message(opt, img, png)?;
+    if opt.list {
+        return list_records(&raw_message);
+    }
+    let raw_message = match segments::is_segment(&raw_message) {
+        true => segments::join(vec![Segment::parse(&raw_message)?])?,
+        false => raw_message,
+    };
+    let raw_message = open_records(opt, raw_message)?;
+    if let Some(original_length) = original_length {
+        summary.warn("truncated");
+        output::line(
+            Channel::Diagnostics,
+            format_args!(
+                "Warning: payload truncated from {} to {} bytes when it was embedded",
+                original_length,
+                raw_message.len()
+            ),
+        );
+    }
+    deliver_message(opt, raw_message, summary)
+}
+
+/// The message of `img`, or of the chunk of its file `png`, as embedded, still
+/// compressed, and the length `--truncate-to-fit` cut it from if it did
+pub fn read_raw_message(
+    opt: &Opt,
+    img: DynamicImage,
+    png: &[u8],
+) -> Result<(Vec<u8>, Option<u64>), PngSecretError> {
+    let img = carrier::payload_samples(img);
+    if let Some(path) = &opt.trace_indices {
+        trace::write(path, &read_trace(opt, &img)?)?;
+    }
+    if !opt.silent {
+        report_image(&ImageInfo {
+            width: img.width(),
+            height: img.height(),
+            capacity: None,
+        });
+    }
+    let chunk = match opt.method {
+        Some(Method::Lsb) => None,
+        Some(Method::Chunk) => Some(chunks::payload(png).ok_or(PngSecretError::NoMessage)?),
+        None => chunks::payload(png),
+    };
+    match (chunk, opt.order.slot_arg()?) {
+        (Some(chunk), _) => Ok((chunks::unframe(chunk, !opt.no_verify)?, None)),
+        (None, Some(SlotArg::Index(index))) => read_listed_slot(img, index, !opt.no_verify),
+        (None, _) => {
+            let (_, original_length, message) = read_located(
+                img,
+                opt.order.order()?,
+                opt.order.given_slot()?,
+                opt.order.offset,
+                opt.legacy,
+                !opt.no_verify,
+                opt.bits,
+            )?;
+            Ok((message, original_length))
+        }
+    }
+}
+
+/// Print or save the decoded `raw_message` and pass it to `--exec-on-success`
+pub fn deliver_message(
+    opt: &Opt,
+    raw_message: Vec<u8>,
+    summary: &mut Summary,
+) -> Result<(), PngSecretError> {
+    let content = sniff::sniff(&raw_message);
+    if content == ContentType::Png {
+        summary.warn("nested_png");
+        output::line(
+            Channel::Diagnostics,
+            "The message is itself a PNG image, save it with -o and decode that file for a \
+             nested payload",
+        );
+    }
+    match (opt.json, &opt.output) {
+        (true, Some(output)) => {
+            save_message(opt, output, &raw_message, content)?;
+            report::print(&report::Decoded::new(&raw_message, Some(output)));
+        }
+        (true, None) => report::print(&report::Decoded::new(&raw_message, None)),
+        (false, _) => print_message(opt, &raw_message, content, summary)?,
+    }
+    summary.bytes_out += raw_message.len() as u64;
+    if let Some(hook) = &opt.exec_on_success {
+        let kept = hook.run(&raw_message, input_path(opt), opt.keep_temp)?;
+        if let Some(kept) = kept {
+            output::line(
+                Channel::Diagnostics,
+                format_args!("Kept the payload passed to the hook at {}", kept.display()),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The `--format` to print in, raw for `--raw` and by default into pipes and files, where the
+/// bytes must come out unchanged
+fn decode_format(opt: &Opt) -> DecodeFormat {
+    match (opt.raw, opt.format) {
+        (true, _) => DecodeFormat::Raw,
+        (false, Some(format)) => format,
+        (false, None) if std::io::stdout().is_terminal() => DecodeFormat::Auto,
+        (false, None) => DecodeFormat::Raw,
+    }
+}
+
+/// `--inspect`: print what the payload of `img`, or of the chunk of its file `png`, is without
+/// printing the payload itself
+fn inspect_payload(opt: &Opt, img: DynamicImage, png: &[u8]) -> Result<(), PngSecretError> {
+    let (method, bits, message) =
+        find_payload(opt, img, png).map_err(|e| PngSecretError::NoPayloadDetected(Box::new(e)))?;
+    let compressed = compress::method(&message).is_some();
+    let utf8 = std::str::from_utf8(&CompressingDecoder::new().decode(message.clone())?).is_ok();
+    let flag = |value: bool| match value {
+        true => "yes",
+        false => "no",
+    };
+    output::line(
+        Channel::Payload,
+        format_args!(
+            "{}: payload bytes={} method={} bits={} compressed={} utf8={}",
+            input_path(opt).display(),
+            message.len(),
+            method.name(),
+            bits.map_or("-".to_string(), |bits| bits.to_string()),
+            flag(compressed),
+            flag(utf8)
+        ),
+    );
+    Ok(())
+}
+
+/// Where the payload of `img` or the chunk of its file `png` was found, the depth it was read at
+/// for pixel payloads, and the message read, checked against its frame unless `--no-verify`
+fn find_payload(
+    opt: &Opt,
+    img: DynamicImage,
+    png: &[u8],
+) -> Result<(Method, Option<u8>, Vec<u8>), PngSecretError> {
+    match (opt.method, chunks::payload(png)) {
+        (Some(Method::Chunk), None) => return Err(PngSecretError::NoMessage),
+        (None | Some(Method::Chunk), Some(chunk)) => {
+            return Ok((Method::Chunk, None, chunks::unframe(chunk, !opt.no_verify)?))
+        }
+        _ => {}
+    }
+    if let Some(SlotArg::Index(index)) = opt.order.slot_arg()? {
+        let bits = slots::enumerate_slots(&img)
+            .get(index)
+            .map(|info| info.bits);
+        let (message, _) = read_listed_slot(img, index, !opt.no_verify)?;
+        return Ok((Method::Lsb, bits, message));
+    }
+    let (location, _, message) = read_located(
+        img,
+        opt.order.order()?,
+        opt.order.given_slot()?,
+        opt.order.offset,
+        opt.legacy,
+        !opt.no_verify,
+        opt.bits,
+    )?;
+    Ok((Method::Lsb, Some(location.bits), message))
+}
+
+/// Save the message to `--output` or write it to stdout in `--format`
+fn print_message(
+    opt: &Opt,
+    raw_message: &[u8],
+    content: ContentType,
+    summary: &mut Summary,
+) -> Result<(), PngSecretError> {
+    if let Some(output) = &opt.output {
+        return save_message(opt, output, raw_message, content);
+    }
+    // Encoded output is text whatever the message is, so its content isn't looked at
+    if let Some(encoded) = opt.payload_encoding.and_then(|e| e.encode(raw_message)) {
+        output::line(Channel::Payload, encoded);
+        return Ok(());
+    }
+    if opt.escape {
+        output::line(Channel::Payload, encoding::escape(raw_message));
+        return Ok(());
+    }
+    let message = match decode_format(opt) {
+        DecodeFormat::Raw => {
+            output::write(Channel::Payload, raw_message);
+            return Ok(());
+        }
+        DecodeFormat::Text => {
+            std::str::from_utf8(raw_message).map_err(|_| PngSecretError::NotUtf8)?
+        }
+        DecodeFormat::Auto => &content
+            .text(raw_message)
+            .ok_or(PngSecretError::BinaryPayload(content))?,
+    };
+    if !opt.silent {
+        output::line(Channel::Diagnostics, "Here is the message (pixel payload):");
+    }
+    if encoding::has_control_characters(message) {
+        summary.warn("control_characters");
+        output::line(
+            Channel::Diagnostics,
+            "Warning: the message holds control characters, they are printed escaped, pass --raw \
+             for the bytes as they are",
+        );
+        output::line(Channel::Payload, encoding::escape(message.as_bytes()));
+        return Ok(());
+    }
+    output::line(Channel::Payload, message);
+    Ok(())
+}
+
+/// Read the message of the slot `info` lists at `index`, at the depth it was found at
+fn read_listed_slot(
+    img: DynamicImage,
+    index: usize,
+    verify: bool,
+) -> Result<(Vec<u8>, Option<u64>), PngSecretError> {
+    let slots = slots::enumerate_slots(&img);
+    let info = slots.get(index).ok_or_else(|| {
+        PngSecretError::Usage(format!(
+            "--slot {} is out of range, `info` lists {} slots in this image",
+            index,
+            slots.len()
+        ))
+    })?;
+    let mut reader = PngSecretReader::new(img, Box::new(NaiveDecoder::new()))
+        .with_slot(info.slot)
+        .with_bits(info.bits)
+        .with_framing(Framing::LengthPrefixed)
+        .with_verify(verify);
+    let message = reader.read_image()?;
+    Ok((message, original_length(&reader)))
+}
+
+/// The slots of an image as `info` lists them, the payload chunk of `chunk` bytes and the chunk
+/// notice last and without an index
+pub fn render_slots(slots: &[SlotInfo], notice: Option<&str>, chunk: Option<usize>) -> String {
+    let mut lines: Vec<String> = slots
+        .iter()
+        .enumerate()
+        .map(|(index, info)| {
+            format!(
+                "#{} {} {}, {} bit{}, {} at subpixel {}, codec {}",
+                index,
+                Backend::Pixel.name(),
+                format!("{:?}", info.slot).to_lowercase(),
+                info.bits,
+                if info.bits == 1 { "" } else { "s" },
+                bytesize::format(info.length),
+                info.offset,
+                info.codec
+            )
+        })
+        .collect();
+    if let Some(chunk) = chunk {
+        lines.push(format!(
+            "-  {} {}, {}",
+            Backend::Chunk.name(),
+            String::from_utf8_lossy(&chunks::PAYLOAD_CHUNK),
+            bytesize::format(chunk as u64)
+        ));
+    }
+    if let Some(notice) = notice {
+        lines.push(format!(
+            "-  {} tEXt {}, {}",
+            Backend::Chunk.name(),
+            pngio::NOTICE_KEYWORD,
+            bytesize::format(notice.len() as u64)
+        ));
+    }
+    lines.join("\n")
+}
+
+/// A reader of `img` in `order` pinned to whichever of the slot, offset and depth are given,
+/// looking up the others from the frame header
+pub fn pinned_reader<C: Carrier>(
+    img: C,
+    order: SubpixelOrder,
+    slot: Option<Slot>,
+    offset: Option<usize>,
+    bits: Option<u8>,
+) -> PngSecretReader<C> {
+    let mut reader = PngSecretReader::new(img, Box::new(NaiveDecoder::new())).with_order(order);
+    if let Some(slot) = slot {
+        reader = reader.with_slot(slot);
+    }
+    if let Some(offset) = offset {
+        reader = reader.with_offset(offset);
+    }
+    if let Some(bits) = bits {
+        reader = reader.with_bits(bits);
+    }
+    reader
+}
+
+/// Read the message of `img`, retrying under permuted channel layouts if the RGBA reading finds
+/// no message or unrecognizable bytes
+fn read_message(
+    img: DynamicImage,
+    order: SubpixelOrder,
+    slot: Option<Slot>,
+    offset: Option<usize>,
+    legacy: bool,
+    verify: bool,
+    bits: Option<u8>,
+) -> Result<Vec<u8>, PngSecretError> {
+    read_located(img, order, slot, offset, legacy, verify, bits).map(|(_, _, message)| message)
+}
+
+/// Like `read_message`, with where the message was read from in the RGBA reading and the length
+/// `--truncate-to-fit` cut it from if it did
+fn read_located(
+    img: DynamicImage,
+    order: SubpixelOrder,
+    slot: Option<Slot>,
+    offset: Option<usize>,
+    legacy: bool,
+    verify: bool,
+    bits: Option<u8>,
+) -> Result<(Location, Option<u64>, Vec<u8>), PngSecretError> {
+    let _span = tracing::info_span!(
+        "read_payload",
+        width = img.width(),
+        height = img.height(),
+        codec = NaiveDecoder::ID,
+        slot = ?slot,
+    )
+    .entered();
+    let reader = |img: DynamicImage, bits: Option<u8>| {
+        let reader = pinned_reader(img, order, slot, offset, bits).with_verify(verify);
+        match legacy {
+            true => reader.with_framing(Framing::Terminated),
+            false => reader,
+        }
+    };
+    let mut primary_reader = reader(img.clone(), bits);
+    let framed = primary_reader.framing() == Framing::LengthPrefixed;
+    let location = primary_reader.location();
+    let mut corrected = 0;
+    let primary_read = primary_reader.read_image_traced(&mut |event| {
+        if let ReadEvent::Corrected { bytes, .. } = event {
+            corrected = bytes;
+        }
+    });
+    if corrected > 0 && primary_read.is_ok() {
+        output::line(
+            Channel::Diagnostics,
+            format_args!(
+                "Error correction repaired {} damaged byte{} of the message",
+                corrected,
+                if corrected == 1 { "" } else { "s" }
+            ),
+        );
+    }
+    if framed && primary_read != Err(pngsecret::Error::PayloadCorrupted) {
+        // A frame header doesn't happen by accident, whatever the payload looks like
+        return Ok((location, original_length(&primary_reader), primary_read?));
+    }
+    if let (Some(given), false) = (bits, legacy) {
+        // A frame header at another depth than the one given tells what went wrong
+        let unpinned = reader(img.clone(), None);
+        let found = unpinned.location().bits;
+        if found != given && unpinned.framing() == Framing::LengthPrefixed {
+            return Err(PngSecretError::WrongBits { given, found });
+        }
+    }
+    let read = |img: RgbaImage| {
+        reader(DynamicImage::ImageRgba8(img), bits)
+            .read_image()
+            .ok()
+    };
+    let primary = primary_read.clone().ok();
+    if let Some(message) = &primary {
+        if sniff::sniff(message) != ContentType::Binary {
+            return Ok((location, original_length(&primary_reader), primary.unwrap()));
+        }
+    }
+    // Only RGBA buffers have been seen in another channel order, a checksum failing in this one
+    // may be one of them
+    let DynamicImage::ImageRgba8(img) = &img else {
+        return Ok((location, original_length(&primary_reader), primary_read?));
+    };
+    tracing::debug!(
+        found = primary.is_some(),
+        "no text in the RGBA channel order"
+    );
+    match layout::recover(img, read) {
+        Some((found, message)) => {
+            tracing::debug!(layout = ?found, message_bytes = message.len(), "recovered");
+            output::line(
+                Channel::Diagnostics,
+                format_args!(
+                    "No message in the RGBA channel order, but one was found assuming {:?}; the \
+                     image decoder probably reordered the channels",
+                    found
+                ),
+            );
+            Ok((location, None, message))
+        }
+        None => Ok((location, original_length(&primary_reader), primary_read?)),
+    }
+}
+
+/// The length `--truncate-to-fit` cut the payload `reader` last read from, if it did
+pub fn original_length<C: Carrier>(reader: &PngSecretReader<C>) -> Option<u64> {
+    reader.header()?.features?.original_length
+}
+
+/// Write a decoded message to `output` byte for byte, whatever its content
+fn save_message(
+    opt: &Opt,
+    output: &Path,
+    message: &[u8],
+    content: ContentType,
+) -> Result<(), PngSecretError> {
+    if paths::collides(input_path(opt), output) {
+        return Err(PngSecretError::OutputIsInput(output.to_path_buf()));
+    }
+    let plan = confirm::Plan::new(
+        format!(
+            "Save the decoded message of {} to {}",
+            bytesize::format(message.len() as u64),
+            output.display()
+        ),
+        &[output],
+    );
+    confirm::confirm(&plan, opt.yes)?;
+    if let Some(parent) = output
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fsguard::create_dir_all(parent)
+            .map_err(|e| PngSecretError::Io(format!("Couldn't create {:?}", parent), e))?;
+    }
+    fsguard::write_atomic(output, message).map_err(|e| {
+        PngSecretError::Io(format!("Couldn't write the message to {:?}", output), e)
+    })?;
+    if !opt.silent {
+        output::line(
+            Channel::Diagnostics,
+            format_args!(
+                "Saved {} of {} (pixel payload) to:",
+                bytesize::format(message.len() as u64),
+                content.description()
+            ),
+        );
+        match opt.json {
+            true => output::path_line(Channel::Diagnostics, output),
+            false => output::path_line(Channel::Payload, output),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pngsecret::{NaiveEncoder, PngSecretWriter};
+    use quickcheck::quickcheck;
+    use structopt::StructOpt;
+
+    fn embed_with(img: RgbaImage, payload: &[u8], order: SubpixelOrder) -> RgbaImage {
+        let encoder = NaiveEncoder::with_framing(Framing::LengthPrefixed);
+        let mut writer = PngSecretWriter::new(img, Box::new(encoder)).with_order(order);
+        writer.encoder.encode(payload);
+        writer.embed().unwrap();
+        writer.buffer
+    }
+
+    #[test]
+    fn decode_sniffs_untagged_payloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("stego.png");
+        let payloads: [(&[u8], ContentType); 4] = [
+            (b"%PDF-1.7\n%\xe2\xe3", ContentType::Pdf),
+            (b"\x1f\x8b\x08", ContentType::Gzip),
+            (b"PK\x03\x04\x14", ContentType::Zip),
+            (b"\x89PNG\r\n\x1a\n", ContentType::Png),
+        ];
+        for (payload, content) in payloads {
+            let stego = embed_with(RgbaImage::new(16, 16), payload, SubpixelOrder::Sequential);
+            stego.save(&input).unwrap();
+            let opt = Opt::from_iter([
+                "pngsecret",
+                "-s",
+                "--format",
+                "auto",
+                "-i",
+                input.to_str().unwrap(),
+            ]);
+            let printed = decode(
+                &opt,
+                DynamicImage::ImageRgba8(stego.clone()),
+                &[],
+                &mut Summary::new("decode"),
+            );
+            assert!(
+                matches!(printed, Err(PngSecretError::BinaryPayload(found)) if found == content),
+                "{:?}",
+                printed
+            );
+
+            let saved = dir.path().join(content.suggested_name());
+            let opt = Opt::from_iter([
+                "pngsecret",
+                "-s",
+                "-i",
+                input.to_str().unwrap(),
+                "-o",
+                saved.to_str().unwrap(),
+            ]);
+            decode(
+                &opt,
+                DynamicImage::ImageRgba8(stego),
+                &[],
+                &mut Summary::new("decode"),
+            )
+            .unwrap();
+            assert_eq!(std::fs::read(&saved).unwrap(), payload);
+        }
+    }
+
+    #[test]
+    fn decode_prints_utf16_and_honors_format_text() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("stego.png");
+        let stego = embed_with(
+            RgbaImage::new(16, 16),
+            b"\xff\xfeh\x01",
+            SubpixelOrder::Sequential,
+        );
+        stego.save(&input).unwrap();
+        let opt = |format| {
+            Opt::from_iter([
+                "pngsecret",
+                "-s",
+                "--format",
+                format,
+                "-i",
+                input.to_str().unwrap(),
+            ])
+        };
+        let stego = DynamicImage::ImageRgba8(stego);
+        assert!(decode(
+            &opt("auto"),
+            stego.clone(),
+            &[],
+            &mut Summary::new("decode")
+        )
+        .is_ok());
+        assert!(matches!(
+            decode(
+                &opt("text"),
+                stego.clone(),
+                &[],
+                &mut Summary::new("decode")
+            ),
+            Err(PngSecretError::NotUtf8)
+        ));
+        assert!(decode(&opt("raw"), stego, &[], &mut Summary::new("decode")).is_ok());
+    }
+
+    #[test]
+    fn channel_permuted_buffers_are_recovered() {
+        let stego = embed_with(
+            RgbaImage::new(16, 16),
+            b"found under another layout",
+            SubpixelOrder::Sequential,
+        );
+        // What a decoder returning BGRA would hand out for the stego image
+        let mut bgra = stego.clone();
+        for pixel in bgra.pixels_mut() {
+            pixel.0.swap(0, 2);
+        }
+        assert_ne!(
+            PngSecretReader::new(bgra.clone(), Box::new(NaiveDecoder::new()))
+                .read_image()
+                .ok(),
+            Some(b"found under another layout".to_vec())
+        );
+        assert_eq!(
+            read_message(
+                bgra.into(),
+                SubpixelOrder::Sequential,
+                None,
+                None,
+                false,
+                true,
+                None
+            )
+            .unwrap(),
+            b"found under another layout"
+        );
+        assert_eq!(
+            read_message(
+                stego.into(),
+                SubpixelOrder::Sequential,
+                None,
+                None,
+                false,
+                true,
+                None
+            )
+            .unwrap(),
+            b"found under another layout"
+        );
+        assert!(matches!(
+            read_message(
+                RgbaImage::from_pixel(4, 4, image::Rgba([1, 1, 1, 1])).into(),
+                SubpixelOrder::Sequential,
+                None,
+                None,
+                false,
+                true,
+                None
+            ),
+            Err(PngSecretError::NoMessage)
+        ));
+    }
+
+    quickcheck! {
+        fn framed_payloads_keep_nul_bytes(payload: Vec<u8>, nuls: Vec<usize>, seed: u64) -> bool {
+            let mut payload: Vec<u8> = payload.into_iter().take(32).collect();
+            for at in nuls {
+                if let Some(byte) = payload.get_mut(at % 32) {
+                    *byte = 0;
+                }
+            }
+            let order = SubpixelOrder::Blocks { block_size: 8, seed };
+            let mut writer = PngSecretWriter::new(
+                RgbaImage::new(13, 7),
+                Box::new(NaiveEncoder::with_framing(Framing::LengthPrefixed)),
+            )
+            .with_order(order);
+            writer.encoder.encode(&payload);
+            writer.embed().unwrap();
+            read_message(writer.buffer.into(), order, None, None, false, true, None).ok() == Some(payload)
+        }
+
+        fn framed_lengths_up_to_the_capacity(fill: u8, noise: Vec<u8>) -> bool {
+            // 13x7 RGBA holds 45 bytes, 32 of them after the frame header
+            let cover = RgbaImage::from_fn(13, 7, |x, y| {
+                let at = (y * 13 + x) as usize;
+                image::Rgba([noise.get(at).copied().unwrap_or(fill); 4])
+            });
+            [0, 1, 2, 31, 32].iter().all(|&length| {
+                let payload: Vec<u8> = (0..length).map(|i| fill.wrapping_mul(i as u8)).collect();
+                let mut writer = PngSecretWriter::new(
+                    cover.clone(),
+                    Box::new(NaiveEncoder::with_framing(Framing::LengthPrefixed)),
+                );
+                writer.encoder.encode(&payload);
+                writer.embed().is_ok()
+                    && read_message(writer.buffer.into(), SubpixelOrder::Sequential, None, None, false, true, None)
+                        .ok()
+                        == Some(payload)
+            })
+        }
+
+    }
+}
@@ -0,0 +1,132 @@
+//! `pngsecret demo`, a full round trip in a temp dir that proves the build works on this machine
+//!
+//! The demo generates a noise cover, writes a sample payload, encodes it in a subpixel order
+//! keyed by a throwaway key, decodes it back and compares the bytes. Every step runs the regular
+//! command path and prints the command line that does the same by hand, so the demo doubles as a
+//! tour of the options. The temp dir is removed afterwards unless `--keep` is given.
+
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use std::ffi::OsString;
+use std::path::Path;
+use structopt::StructOpt;
+
+use crate::error::PngSecretError;
+use crate::fixtures::Cover;
+use crate::output::{self, Channel};
+use crate::summary::Summary;
+use crate::{fsguard, pngio, wizard, Opt};
+
+const COVER_SIDE: u32 = 64;
+const PAYLOAD: &str = "Hello from pngsecret, this text went through a PNG and back.\n";
+const STEPS: usize = 5;
+
+/// Run the demo, leaving its files in place with `keep` and printing only the outcome with
+/// `silent`
+pub fn run(keep: bool, silent: bool, summary: &mut Summary) -> Result<(), PngSecretError> {
+    let dir = fsguard::temp_dir("pngsecret-demo-")
+        .map_err(|e| PngSecretError::Io("Couldn't create the demo directory".to_string(), e))?;
+    let result = round_trip(dir.path(), silent, summary);
+    if keep {
+        let kept = dir.keep();
+        output::line(
+            Channel::Diagnostics,
+            format_args!("Kept the demo files in {}", kept.display()),
+        );
+    }
+    result?;
+    output::line(
+        Channel::Payload,
+        "Demo passed: the payload came back byte for byte",
+    );
+    Ok(())
+}
+
+fn round_trip(dir: &Path, silent: bool, summary: &mut Summary) -> Result<(), PngSecretError> {
+    let [cover, payload, stego, decoded] =
+        ["cover.png", "payload.txt", "stego.png", "decoded.txt"].map(|name| dir.join(name));
+    let step = |number, title: &dyn std::fmt::Display| {
+        if !silent {
+            output::line(
+                Channel::Diagnostics,
+                format_args!("[{}/{}] {}", number, STEPS, title),
+            );
+        }
+    };
+    let command = |args: &[OsString]| {
+        if !silent {
+            output::line(
+                Channel::Diagnostics,
+                format_args!("  $ {}", wizard::command_line(args)),
+            );
+        }
+    };
+    let key: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(16)
+        .map(char::from)
+        .collect();
+    let secured = ["--permute", "subpixels", "--key", &key].map(OsString::from);
+
+    step(
+        1,
+        &format_args!("Generate a {0}x{0} noise cover", COVER_SIDE),
+    );
+    let noise = Cover::Noise {
+        width: COVER_SIDE,
+        height: COVER_SIDE,
+    };
+    pngio::save_with_text(&noise.render(), &cover, &[])
+        .map_err(|_| PngSecretError::SaveFailed(cover.clone()))?;
+    step(2, &"Write the sample payload");
+    fsguard::write(&payload, PAYLOAD.as_bytes())
+        .map_err(|e| PngSecretError::Io(format!("Couldn't write {:?}", payload), e))?;
+
+    step(3, &"Encode it under a throwaway key");
+    let mut encode = vec!["-e".into(), "-i".into(), cover.into(), "-o".into()];
+    encode.extend([
+        stego.clone().into(),
+        "--file".into(),
+        payload.clone().into(),
+    ]);
+    run_step(encode, &secured, &command, summary)?;
+
+    step(4, &"Decode it back");
+    let decode = vec![
+        "-i".into(),
+        stego.into(),
+        "-o".into(),
+        decoded.clone().into(),
+    ];
+    run_step(decode, &secured, &command, summary)?;
+
+    step(5, &"Compare the decoded payload with the original");
+    command(&["cmp".into(), payload.into(), decoded.clone().into()]);
+    let read = std::fs::read(&decoded)
+        .map_err(|e| PngSecretError::Io(format!("Couldn't read {:?}", decoded), e))?;
+    match read == PAYLOAD.as_bytes() {
+        true => Ok(()),
+        false => Err(PngSecretError::DemoMismatch {
+            sent: PAYLOAD.len(),
+            received: read.len(),
+        }),
+    }
+}
+
+/// Print `args` and `secured` as a pngsecret command and run it silently through the regular
+/// path
+fn run_step(
+    args: Vec<OsString>,
+    secured: &[OsString],
+    command: &dyn Fn(&[OsString]),
+    summary: &mut Summary,
+) -> Result<(), PngSecretError> {
+    let args: Vec<OsString> = std::iter::once("pngsecret".into())
+        .chain(args)
+        .chain(secured.iter().cloned())
+        .collect();
+    command(&args);
+    let mut opt = Opt::from_iter_safe(&args).map_err(|e| PngSecretError::Usage(e.message))?;
+    opt.silent = true;
+    crate::run(&opt, summary)
+}
@@ -0,0 +1,454 @@
+//! `doctor` runs a battery of checks on an image and explains why a decode would fail
+
+use image::ImageFormat;
+use pngsecret::carrier::{self, Carrier};
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+
+use crate::analysis::{binary_entropy, CoverStats};
+use crate::error::PngSecretError;
+use crate::format::{self, Framing};
+use crate::sniff::{self, ContentType};
+use crate::{pngio, probe, NaiveDecoder, PngSecretReader, ReadEvent};
+
+/// Subpixels at the start of the image inspected separately, where a payload would live
+const START_REGION: usize = 8 * 256;
+/// Binary entropy above which an LSB plane is considered noise-like
+const NOISE_ENTROPY: f64 = 0.95;
+/// Share of printable bytes above which a probed message looks like text
+const TEXT_LIKE: f64 = 0.9;
+/// Message bytes traced individually by `--explain`, later ones are only counted
+const EXPLAINED_BYTES: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LegacyProbe {
+    pub found: bool,
+    /// Whether the message has a frame header or ends with a terminator
+    pub framing: Option<Framing>,
+    pub length: Option<usize>,
+    /// Whole bytes of capacity after the message, which the reader never looks at
+    pub unused_bytes: Option<usize>,
+    pub utf8: bool,
+    pub printable_ratio: Option<f64>,
+    pub content_type: Option<ContentType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DoctorReport {
+    pub format: Option<String>,
+    pub color_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub legacy_probe: LegacyProbe,
+    pub notice: Option<String>,
+    pub lsb_ones_ratio: f64,
+    pub lsb_entropy_start: f64,
+    pub lsb_entropy_all: f64,
+    /// Input for the detectability estimate of the encode summary and `--max-detectability`
+    pub cover_stats: CoverStats,
+    pub findings: Vec<String>,
+}
+
+fn lsb_ones(samples: &[u8]) -> usize {
+    samples.iter().filter(|s| *s % 2 == 1).count()
+}
+
+fn probe_legacy<C: Carrier + Clone>(img: &C) -> LegacyProbe {
+    let mut reader =
+        PngSecretReader::new(img.clone(), Box::new(NaiveDecoder::new())).with_verify(false);
+    match reader.read_image() {
+        Ok(message) => {
+            let framing = reader.framing();
+            let printable = message
+                .iter()
+                .filter(|b| b.is_ascii_graphic() || b.is_ascii_whitespace() || **b >= 0x80)
+                .count();
+            LegacyProbe {
+                found: true,
+                framing: Some(framing),
+                length: Some(message.len()),
+                unused_bytes: Some(
+                    format::slot_capacity(img.samples().len() as u64, framing, format::DEFAULT_BITS)
+                        as usize
+                        - message.len(),
+                ),
+                utf8: std::str::from_utf8(&message).is_ok(),
+                printable_ratio: (!message.is_empty())
+                    .then(|| printable as f64 / message.len() as f64),
+                content_type: Some(sniff::sniff(&message)),
+            }
+        }
+        Err(_) => LegacyProbe {
+            found: false,
+            framing: None,
+            length: None,
+            unused_bytes: None,
+            utf8: false,
+            printable_ratio: None,
+            content_type: None,
+        },
+    }
+}
+
+/// Run every check against the image at `path`
+pub fn diagnose(path: &Path) -> Result<DoctorReport, PngSecretError> {
+    let format = ImageFormat::from_path(path).ok();
+    let img = probe::open(path)?;
+    let color_type = format!("{:?}", img.color());
+    let img = carrier::payload_samples(img);
+    let samples = img.samples();
+    let start = &samples[..samples.len().min(START_REGION)];
+
+    let mut report = DoctorReport {
+        format: format.map(|f| format!("{:?}", f)),
+        color_type,
+        width: img.width(),
+        height: img.height(),
+        legacy_probe: probe_legacy(&img),
+        notice: pngio::read_notice(path),
+        lsb_ones_ratio: lsb_ones(samples) as f64 / samples.len().max(1) as f64,
+        lsb_entropy_start: binary_entropy(lsb_ones(start), start.len()),
+        lsb_entropy_all: binary_entropy(lsb_ones(samples), samples.len()),
+        cover_stats: CoverStats::of(&img),
+        findings: Vec::new(),
+    };
+    report.findings = findings(&report, format);
+    Ok(report)
+}
+
+/// The steps the legacy payload reader took on an image, for `doctor --explain`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    /// The first [`EXPLAINED_BYTES`] bytes and the event that ended the read
+    events: Vec<ReadEvent>,
+    /// Bytes read but not traced individually
+    omitted: usize,
+}
+
+/// Trace the legacy payload reader over the image at `path`
+pub fn explain(path: &Path) -> Result<Explanation, PngSecretError> {
+    Ok(trace(carrier::payload_samples(probe::open(path)?)))
+}
+
+fn trace<C: Carrier>(img: C) -> Explanation {
+    let mut explanation = Explanation {
+        events: Vec::new(),
+        omitted: 0,
+    };
+    let mut reader = PngSecretReader::new(img, Box::new(NaiveDecoder::new())).with_verify(false);
+    let _ = reader.read_image_traced(&mut |event| match event {
+        ReadEvent::Byte { index, .. } if index >= EXPLAINED_BYTES => explanation.omitted += 1,
+        event => explanation.events.push(event),
+    });
+    explanation
+}
+
+/// `0..=7` for consecutive subpixels, a list otherwise
+fn subpixel_range(subpixels: &[usize; 8]) -> String {
+    if subpixels.windows(2).all(|pair| pair[1] == pair[0] + 1) {
+        return format!("{}..={}", subpixels[0], subpixels[7]);
+    }
+    let listed: Vec<String> = subpixels.iter().map(usize::to_string).collect();
+    listed.join(",")
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Reader trace, one bit from the LSB of each subpixel, most significant bit first, \
+             until a NUL byte or as many bytes as a frame header announces:"
+        )?;
+        for event in &self.events {
+            match event {
+                ReadEvent::Header(header) => {
+                    write!(
+                        f,
+                        "  frame header from the first {} bytes: the message is {} bytes",
+                        header.bytes(),
+                        header.length
+                    )?;
+                    match header.checksum {
+                        Some(checksum) => writeln!(f, " with CRC-32 {:08x}", checksum)?,
+                        None => writeln!(f, " without a checksum")?,
+                    }
+                }
+                ReadEvent::Corrected { blocks, bytes } => writeln!(
+                    f,
+                    "  error correction header: {} Reed-Solomon blocks follow, {} bytes in them \
+                     were corrected",
+                    blocks, bytes
+                )?,
+                ReadEvent::End { bytes } => {
+                    if self.omitted > 0 {
+                        writeln!(f, "  ... {} more bytes", self.omitted)?;
+                    }
+                    writeln!(f, "  end of the {} bytes the header announced", bytes)?;
+                }
+                ReadEvent::Byte {
+                    index,
+                    subpixels,
+                    value,
+                } => {
+                    let shown = if value.is_ascii_graphic() || *value == b' ' {
+                        format!(" {:?}", *value as char)
+                    } else {
+                        String::new()
+                    };
+                    writeln!(
+                        f,
+                        "  byte {} from subpixels {}: bits {:08b} = 0x{:02x}{}",
+                        index,
+                        subpixel_range(subpixels),
+                        value,
+                        value,
+                        shown
+                    )?;
+                }
+                ReadEvent::Terminator { index, subpixels } => {
+                    if self.omitted > 0 {
+                        writeln!(f, "  ... {} more bytes", self.omitted)?;
+                    }
+                    writeln!(
+                        f,
+                        "  terminator from subpixels {}: bits 00000000, the message is {} bytes",
+                        subpixel_range(subpixels),
+                        index
+                    )?;
+                }
+                ReadEvent::Exhausted {
+                    bytes,
+                    leftover_bits,
+                } => {
+                    if self.omitted > 0 {
+                        writeln!(f, "  ... {} more bytes", self.omitted)?;
+                    }
+                    writeln!(
+                        f,
+                        "  stopped: the image ended after {} bytes and {} bits without a \
+                         terminator, so there is no legacy message",
+                        bytes, leftover_bits
+                    )?;
+                }
+                ReadEvent::Limit { bytes } => {
+                    if self.omitted > 0 {
+                        writeln!(f, "  ... {} more bytes", self.omitted)?;
+                    }
+                    writeln!(
+                        f,
+                        "  stopped: no terminator in the first {} bytes, longer legacy messages \
+                         aren't read",
+                        bytes
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn findings(report: &DoctorReport, format: Option<ImageFormat>) -> Vec<String> {
+    let mut findings = Vec::new();
+    if matches!(format, Some(ImageFormat::Jpeg)) {
+        findings.push(
+            "The file is a JPEG, lossy compression destroys LSB payloads so nothing can be \
+             recovered from it."
+                .to_string(),
+        );
+    }
+    if report.color_type != "Rgba8" {
+        findings.push(format!(
+            "The image is stored as {} and gets converted to RGBA before reading, payloads \
+             embedded by pngsecret are always in RGBA images.",
+            report.color_type
+        ));
+    }
+    let probe = &report.legacy_probe;
+    let text_like = probe.printable_ratio.is_some_and(|r| r >= TEXT_LIKE);
+    match (probe.found, probe.length) {
+        (true, Some(length))
+            if !matches!(
+                probe.content_type,
+                Some(ContentType::Utf8Text | ContentType::Binary)
+            ) =>
+        {
+            let content = probe.content_type.unwrap_or(ContentType::Binary);
+            findings.push(format!(
+                "A message of {} bytes was found that looks like {}, decode it with -o {} to \
+                 save it.",
+                length,
+                content.description(),
+                content.suggested_name()
+            ))
+        }
+        (true, Some(length)) if probe.utf8 && text_like => findings.push(format!(
+            "A message of {} bytes was found and it is valid text, decode should work.",
+            length
+        )),
+        (true, Some(length)) if probe.framing == Some(Framing::LengthPrefixed) => {
+            findings.push(format!(
+                "A framed message of {} bytes was found, its header makes decoding exact; save \
+                 it with -o to inspect it.",
+                length
+            ))
+        }
+        (true, Some(length)) => findings.push(format!(
+            "The LSBs at the start of the image parse into {} bytes that don't look like an \
+             intact message; the image may have been resized, recompressed or edited after \
+             embedding, or it never carried a payload.",
+            length
+        )),
+        _ if report.lsb_entropy_all >= NOISE_ENTROPY => findings.push(
+            "No frame header or message terminator was found and the LSBs are noise-like everywhere; if this \
+             image carried a payload it was likely resized, recompressed or re-rendered."
+                .to_string(),
+        ),
+        _ => findings.push(
+            "No frame header or message terminator was found and the LSB plane looks like an untouched cover."
+                .to_string(),
+        ),
+    }
+    if report.lsb_entropy_start >= NOISE_ENTROPY && report.lsb_entropy_all < NOISE_ENTROPY {
+        findings.push(
+            "The LSBs at the start of the image are much noisier than the rest, as is typical \
+             for embedded data."
+                .to_string(),
+        );
+    }
+    if let Some(notice) = &report.notice {
+        findings.push(format!("A pngsecret notice chunk is present: {:?}", notice));
+    }
+    findings
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "The image is {}x{}, format {}, color type {}.",
+            self.width,
+            self.height,
+            self.format.as_deref().unwrap_or("unknown"),
+            self.color_type
+        )?;
+        writeln!(
+            f,
+            "{:.1}% of all LSBs are set, LSB entropy is {:.3} at the start and {:.3} overall.",
+            self.lsb_ones_ratio * 100.0,
+            self.lsb_entropy_start,
+            self.lsb_entropy_all
+        )?;
+        if let (Some(framing), Some(length), Some(unused)) = (
+            self.legacy_probe.framing,
+            self.legacy_probe.length,
+            self.legacy_probe.unused_bytes,
+        ) {
+            writeln!(
+                f,
+                "The message and its {} take {} bytes, the {} bytes of capacity after them are \
+                 not part of the message and are never read.",
+                match framing {
+                    Framing::Terminated => "terminator",
+                    Framing::LengthPrefixed => "frame header",
+                },
+                length as u64 + framing.overhead_bytes(),
+                unused
+            )?;
+        }
+        for finding in &self.findings {
+            writeln!(f, "{}", finding)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    #[test]
+    fn explained_bytes_match_the_decoded_message() {
+        let mut writer = crate::PngSecretWriter::new(
+            RgbaImage::new(16, 16),
+            Box::new(crate::NaiveEncoder::new()),
+        );
+        writer.encoder.encode(b"Hi there");
+        writer.embed().unwrap();
+        let stego = writer.buffer;
+
+        let message = PngSecretReader::new(stego.clone(), Box::new(NaiveDecoder::new()))
+            .with_verify(false)
+            .read_image()
+            .unwrap();
+        let explanation = trace(stego);
+        let explained: Vec<u8> = explanation
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                ReadEvent::Byte { value, .. } => Some(*value),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(explained, message);
+        assert_eq!(
+            explanation.events.last(),
+            Some(&ReadEvent::Terminator {
+                index: 8,
+                subpixels: [64, 65, 66, 67, 68, 69, 70, 71]
+            })
+        );
+        let text = explanation.to_string();
+        assert!(text.contains("byte 0 from subpixels 0..=7: bits 01001000 = 0x48 'H'"));
+        assert!(text.contains("the message is 8 bytes"));
+    }
+
+    #[test]
+    fn explain_truncates_long_reads() {
+        // All LSBs set never yields a terminator
+        let explanation = trace(RgbaImage::from_pixel(20, 20, image::Rgba([1, 1, 1, 1])));
+        assert_eq!(explanation.events.len(), EXPLAINED_BYTES + 1);
+        assert_eq!(explanation.omitted, 200 - EXPLAINED_BYTES);
+        assert_eq!(
+            explanation.events.last(),
+            Some(&ReadEvent::Exhausted {
+                bytes: 200,
+                leftover_bits: 0
+            })
+        );
+    }
+
+    #[test]
+    fn probe_reports_the_region_after_the_terminator() {
+        let mut writer =
+            crate::PngSecretWriter::new(RgbaImage::new(8, 8), Box::new(crate::NaiveEncoder::new()));
+        writer.encoder.encode(b"short");
+        writer.embed().unwrap();
+        let probe = probe_legacy(&writer.buffer);
+        // 256 subpixels hold 32 bytes, 6 of them carry the message and the terminator
+        assert_eq!((probe.length, probe.unused_bytes), (Some(5), Some(26)));
+        let mut writer = crate::PngSecretWriter::new(
+            RgbaImage::new(8, 8),
+            Box::new(crate::NaiveEncoder::with_framing(Framing::LengthPrefixed)),
+        );
+        writer.encoder.encode(b"sh\0rt");
+        writer.embed().unwrap();
+        let probe = probe_legacy(&writer.buffer);
+        // The frame header takes 13 bytes instead of the terminator's one
+        assert_eq!(probe.framing, Some(Framing::LengthPrefixed));
+        assert_eq!((probe.length, probe.unused_bytes), (Some(5), Some(14)));
+        assert_eq!(
+            probe_legacy(&RgbaImage::from_pixel(2, 2, image::Rgba([1, 1, 1, 1]))).unused_bytes,
+            None
+        );
+    }
+
+    #[test]
+    fn entropy_bounds() {
+        assert_eq!(binary_entropy(0, 100), 0.0);
+        assert_eq!(binary_entropy(100, 100), 0.0);
+        assert!((binary_entropy(50, 100) - 1.0).abs() < 1e-12);
+        assert!(binary_entropy(10, 100) < NOISE_ENTROPY);
+    }
+}
@@ -0,0 +1,488 @@
+//! Reed–Solomon error correction of framed payloads, so a few flipped bits don't lose them
+//!
+//! [`EccEncoder`] cuts the framed payload into blocks of at most 255 bytes, each ending in
+//! `parity` bytes over GF(256), and puts a [`HEADER_BYTES`] long header before them. The header
+//! records the parity and the framed length and is itself a Reed–Solomon codeword, so readers
+//! recognize and correct it without being told. Each block corrects up to half its parity bytes
+//! in errors; the CRC-32 of the frame inside then checks that the corrections were right.
+//! [`PngSecretReader`](crate::PngSecretReader) detects the header and corrects on its own.
+
+use crate::format::{Features, Framing};
+use crate::order::Slot;
+use crate::{Error, PngSecretEncoder};
+
+/// First bytes of a corrected header, the frame magics are `00 a1`, `00 a0` and `00 9f`
+pub const MAGIC: [u8; 2] = [0x00, 0xEC];
+/// Longest codeword over GF(256), parity included
+pub const BLOCK_BYTES: usize = 255;
+/// Fewest parity bytes per block, which correct one byte error
+pub const MIN_PARITY: u8 = 2;
+/// Most parity bytes per block, half the block
+pub const MAX_PARITY: u8 = 128;
+/// Parity bytes of the header, which correct 4 byte errors in it
+const HEADER_PARITY: usize = 8;
+/// Magic, parity and framed length
+const HEADER_DATA: usize = MAGIC.len() + 1 + 4;
+/// Bytes of the header, parity included
+pub const HEADER_BYTES: usize = HEADER_DATA + HEADER_PARITY;
+
+/// What a corrected header announces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    /// Parity bytes per block
+    pub parity: u8,
+    /// Bytes of the framed payload, without parity
+    pub framed_bytes: u32,
+}
+
+impl Header {
+    /// Bytes of the blocks after the header, parity included
+    pub fn encoded_bytes(&self) -> u64 {
+        encoded_bytes(self.framed_bytes as u64, self.parity)
+    }
+}
+
+/// Bytes of the blocks holding `framed` framed bytes under `parity` parity bytes each
+fn encoded_bytes(framed: u64, parity: u8) -> u64 {
+    let data = (BLOCK_BYTES - parity as usize) as u64;
+    framed + framed.div_ceil(data) * parity as u64
+}
+
+/// Most framed bytes that `slot_bytes` bytes hold with `parity` bytes per block
+pub fn framed_capacity(slot_bytes: u64, parity: u8) -> u64 {
+    let Some(blocks) = slot_bytes.checked_sub(HEADER_BYTES as u64) else {
+        return 0;
+    };
+    let data = (BLOCK_BYTES - parity as usize) as u64;
+    let partial = (blocks % BLOCK_BYTES as u64).saturating_sub(parity as u64);
+    (blocks / BLOCK_BYTES as u64 * data + partial).min(u32::MAX as u64)
+}
+
+/// `framed` behind a header, in blocks of `parity` parity bytes each
+///
+/// Panics unless `MIN_PARITY <= parity <= MAX_PARITY` and `framed` fits in u32, which no
+/// capacity exceeds.
+pub fn protect(framed: &[u8], parity: u8) -> Vec<u8> {
+    assert!(
+        (MIN_PARITY..=MAX_PARITY).contains(&parity),
+        "{} parity",
+        parity
+    );
+    let length = u32::try_from(framed.len()).expect("corrected payloads fit in u32");
+    let mut protected =
+        Vec::with_capacity(HEADER_BYTES + encoded_bytes(framed.len() as u64, parity) as usize);
+    let mut header = MAGIC.to_vec();
+    header.push(parity);
+    header.extend_from_slice(&length.to_be_bytes());
+    protected.extend_from_slice(&Codec::new(HEADER_PARITY).encode(&header));
+    let codec = Codec::new(parity as usize);
+    for block in framed.chunks(BLOCK_BYTES - parity as usize) {
+        protected.extend_from_slice(&codec.encode(block));
+    }
+    protected
+}
+
+/// The header at the start of a slot holding `slot_bytes` encoded bytes, if the first
+/// [`HEADER_BYTES`] bytes correct to one whose blocks fit
+pub fn parse_header(header: &[u8], slot_bytes: u64) -> Option<Header> {
+    let mut codeword = header.get(..HEADER_BYTES)?.to_vec();
+    Codec::new(HEADER_PARITY).correct(&mut codeword)?;
+    let parity = codeword[MAGIC.len()];
+    if codeword[..MAGIC.len()] != MAGIC || !(MIN_PARITY..=MAX_PARITY).contains(&parity) {
+        return None;
+    }
+    let length = u32::from_be_bytes(codeword[MAGIC.len() + 1..HEADER_DATA].try_into().ok()?);
+    let header = Header {
+        parity,
+        framed_bytes: length,
+    };
+    let room = slot_bytes.checked_sub(HEADER_BYTES as u64)?;
+    (header.encoded_bytes() <= room).then_some(header)
+}
+
+/// Whether the first [`crate::format::PREFIX_BYTES`] bytes of `prefix` could start an undamaged
+/// header whose blocks fit a slot holding `slot_bytes` encoded bytes, cheap enough to ask of
+/// every subpixel
+pub fn could_start(prefix: &[u8], slot_bytes: u64) -> bool {
+    let Some(data) = prefix.get(..HEADER_DATA) else {
+        return false;
+    };
+    let parity = data[MAGIC.len()];
+    let header = Header {
+        parity,
+        framed_bytes: u32::from_be_bytes(data[MAGIC.len() + 1..].try_into().unwrap()),
+    };
+    data[..MAGIC.len()] == MAGIC
+        && (MIN_PARITY..=MAX_PARITY).contains(&parity)
+        && header.encoded_bytes() + HEADER_BYTES as u64 <= slot_bytes
+}
+
+/// The framed payload of the blocks after `header`, and the number of bytes corrected in them
+///
+/// Fails with [`Error::Uncorrectable`] at the first block with more errors than its parity
+/// corrects.
+pub fn correct(header: &Header, blocks: &[u8]) -> Result<(Vec<u8>, usize), Error> {
+    let codec = Codec::new(header.parity as usize);
+    let mut framed = Vec::with_capacity(header.framed_bytes as usize);
+    let mut corrected = 0;
+    for (index, block) in blocks.chunks(BLOCK_BYTES).enumerate() {
+        let mut block = block.to_vec();
+        corrected += codec
+            .correct(&mut block)
+            .ok_or(Error::Uncorrectable { block: index })?;
+        framed.extend_from_slice(&block[..block.len() - header.parity as usize]);
+    }
+    Ok((framed, corrected))
+}
+
+/// Encoder adding Reed–Solomon parity to the framed payload of `inner`
+///
+/// Wraps the framing encoder itself, since the frame header needs protecting as much as the
+/// payload; compression wraps this one.
+pub struct EccEncoder {
+    inner: Box<dyn PngSecretEncoder>,
+    parity: u8,
+    text: Vec<u8>,
+}
+
+impl EccEncoder {
+    /// Codec name in log records
+    pub const ID: &'static str = "reed-solomon";
+
+    /// Panics unless `MIN_PARITY <= parity <= MAX_PARITY`, or if `inner` writes the legacy
+    /// NUL-terminated format, whose end can't be corrected
+    pub fn new(parity: u8, inner: Box<dyn PngSecretEncoder>) -> Self {
+        assert!(
+            (MIN_PARITY..=MAX_PARITY).contains(&parity),
+            "{} parity",
+            parity
+        );
+        assert_eq!(inner.framing(), Framing::LengthPrefixed);
+        EccEncoder {
+            inner,
+            parity,
+            text: Vec::new(),
+        }
+    }
+}
+
+impl PngSecretEncoder for EccEncoder {
+    fn encode_with(&mut self, seq: &[u8], features: Features) {
+        let features = Features {
+            ecc: true,
+            ..features
+        };
+        self.inner.encode_with(seq, features);
+        self.text = protect(self.inner.text(), self.parity);
+    }
+    fn place(&mut self, slot: Slot) {
+        self.inner.place(slot);
+        if !self.text.is_empty() {
+            self.text = protect(self.inner.text(), self.parity);
+        }
+    }
+    fn text(&self) -> &[u8] {
+        &self.text
+    }
+    fn framing(&self) -> Framing {
+        self.inner.framing()
+    }
+    fn bits(&self) -> u8 {
+        self.inner.bits()
+    }
+    fn overhead_bytes(&self) -> u64 {
+        self.inner.overhead_bytes()
+    }
+    fn framed_capacity(&self, slot_bytes: u64) -> u64 {
+        self.inner
+            .framed_capacity(framed_capacity(slot_bytes, self.parity))
+    }
+}
+
+/// GF(256) over the polynomial x^8 + x^4 + x^3 + x^2 + 1, with 2 generating it
+const GF_POLYNOMIAL: u16 = 0x11D;
+
+/// Powers of the generator, doubled so products of two logarithms index it directly
+const EXP: [u8; 512] = {
+    let mut exp = [0; 512];
+    let mut value: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = value as u8;
+        exp[i + 255] = value as u8;
+        value <<= 1;
+        if value & 0x100 != 0 {
+            value ^= GF_POLYNOMIAL;
+        }
+        i += 1;
+    }
+    exp
+};
+
+/// Logarithms to the generator, of every element but 0
+const LOG: [u8; 256] = {
+    let mut log = [0; 256];
+    let mut i = 0;
+    while i < 255 {
+        log[EXP[i] as usize] = i as u8;
+        i += 1;
+    }
+    log
+};
+
+fn mul(a: u8, b: u8) -> u8 {
+    match (a, b) {
+        (0, _) | (_, 0) => 0,
+        (a, b) => EXP[LOG[a as usize] as usize + LOG[b as usize] as usize],
+    }
+}
+
+/// `a / b` for any `b` but 0
+fn div(a: u8, b: u8) -> u8 {
+    match a {
+        0 => 0,
+        a => EXP[LOG[a as usize] as usize + 255 - LOG[b as usize] as usize],
+    }
+}
+
+/// The generator raised to `power`
+fn pow(power: usize) -> u8 {
+    EXP[power % 255]
+}
+
+/// `poly`, lowest degree first, at `x`
+fn eval(poly: &[u8], x: u8) -> u8 {
+    poly.iter().rev().fold(0, |acc, c| mul(acc, x) ^ c)
+}
+
+/// Reed–Solomon code with `parity` parity bytes, whose generator has the roots 2^0..2^parity
+///
+/// Codewords are highest degree first: the data, then the remainder of its division by the
+/// generator. Codewords shorter than [`BLOCK_BYTES`] are shortened ones, as if led by zeros.
+struct Codec {
+    parity: usize,
+    /// Highest degree first, without the leading 1
+    generator: Vec<u8>,
+}
+
+impl Codec {
+    fn new(parity: usize) -> Self {
+        // Lowest degree first while multiplying in (x - 2^i)
+        let mut generator = vec![1];
+        for i in 0..parity {
+            let root = pow(i);
+            let mut next = vec![0; generator.len() + 1];
+            for (degree, c) in generator.iter().enumerate() {
+                next[degree + 1] ^= c;
+                next[degree] ^= mul(*c, root);
+            }
+            generator = next;
+        }
+        generator.pop();
+        generator.reverse();
+        Codec { parity, generator }
+    }
+
+    /// `data` followed by its parity
+    fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let mut remainder = vec![0; self.parity];
+        for byte in data {
+            let factor = byte ^ remainder[0];
+            remainder.rotate_left(1);
+            remainder[self.parity - 1] = 0;
+            for (r, g) in remainder.iter_mut().zip(&self.generator) {
+                *r ^= mul(*g, factor);
+            }
+        }
+        [data, &remainder].concat()
+    }
+
+    /// The codeword at the roots of the generator, all 0 for an intact one
+    fn syndromes(&self, codeword: &[u8]) -> Vec<u8> {
+        (0..self.parity)
+            .map(|i| {
+                let x = pow(i);
+                codeword.iter().fold(0, |acc, c| mul(acc, x) ^ c)
+            })
+            .collect()
+    }
+
+    /// Correct `codeword` in place, returning the number of bytes corrected, `None` if it has
+    /// more errors than the parity corrects
+    fn correct(&self, codeword: &mut [u8]) -> Option<usize> {
+        let syndromes = self.syndromes(codeword);
+        if syndromes.iter().all(|s| *s == 0) {
+            return Some(0);
+        }
+        let locator = berlekamp_massey(&syndromes);
+        let errors = locator.len() - 1;
+        if 2 * errors > self.parity {
+            return None;
+        }
+        // The error evaluator, syndromes times locator modulo x^parity
+        let mut evaluator = vec![0; self.parity];
+        for (i, s) in syndromes.iter().enumerate() {
+            for (j, l) in locator.iter().enumerate().take(self.parity - i) {
+                evaluator[i + j] ^= mul(*s, *l);
+            }
+        }
+        // Formal derivative, only odd degrees survive in characteristic 2
+        let derivative: Vec<u8> = locator
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(degree, c)| if degree % 2 == 1 { *c } else { 0 })
+            .collect();
+        let mut found = 0;
+        let length = codeword.len();
+        for (index, byte) in codeword.iter_mut().enumerate() {
+            let x = pow(length - 1 - index);
+            let x_inverse = div(1, x);
+            if eval(&locator, x_inverse) != 0 {
+                continue;
+            }
+            let denominator = eval(&derivative, x_inverse);
+            if denominator == 0 {
+                return None;
+            }
+            *byte ^= mul(x, div(eval(&evaluator, x_inverse), denominator));
+            found += 1;
+        }
+        // Fewer roots than errors, or a correction that isn't a codeword, means too many errors
+        if found != errors || self.syndromes(codeword).iter().any(|s| *s != 0) {
+            return None;
+        }
+        Some(found)
+    }
+}
+
+/// The error locator of `syndromes`, lowest degree first and without trailing zeros
+fn berlekamp_massey(syndromes: &[u8]) -> Vec<u8> {
+    let mut locator = vec![1];
+    let mut previous = vec![1];
+    let mut errors = 0;
+    let mut shift = 1;
+    let mut previous_discrepancy = 1;
+    for r in 0..syndromes.len() {
+        let discrepancy = (1..=errors).fold(syndromes[r], |acc, i| {
+            acc ^ mul(*locator.get(i).unwrap_or(&0), syndromes[r - i])
+        });
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+        let factor = div(discrepancy, previous_discrepancy);
+        let mut next = locator.clone();
+        next.resize(next.len().max(previous.len() + shift), 0);
+        for (i, c) in previous.iter().enumerate() {
+            next[i + shift] ^= mul(factor, *c);
+        }
+        if 2 * errors <= r {
+            errors = r + 1 - errors;
+            previous = std::mem::replace(&mut locator, next);
+            previous_discrepancy = discrepancy;
+            shift = 1;
+        } else {
+            locator = next;
+            shift += 1;
+        }
+    }
+    locator.truncate(errors + 1);
+    locator
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NaiveEncoder, PngSecretReader, PngSecretWriter};
+    use image::RgbaImage;
+    use rand::seq::index::sample;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn blocks_correct_up_to_half_their_parity() {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(1);
+        for (parity, data) in [(2, 1), (8, 247), (16, 40), (32, 223)] {
+            let codec = Codec::new(parity);
+            let message: Vec<u8> = (0..data).map(|_| rng.gen()).collect();
+            let codeword = codec.encode(&message);
+            assert_eq!(codeword.len(), data + parity);
+            assert!(codec.syndromes(&codeword).iter().all(|s| *s == 0));
+            for errors in 0..=parity / 2 {
+                let mut damaged = codeword.clone();
+                for index in sample(&mut rng, damaged.len(), errors) {
+                    damaged[index] ^= rng.gen_range(1..=255);
+                }
+                assert_eq!(codec.correct(&mut damaged), Some(errors), "{}", parity);
+                assert_eq!(damaged, codeword);
+            }
+            let mut damaged = codeword.clone();
+            for index in sample(&mut rng, damaged.len(), parity / 2 + 1) {
+                damaged[index] ^= rng.gen_range(1..=255);
+            }
+            // Past the budget a block either fails or, rarely, lands on another codeword
+            assert_ne!(codec.correct(&mut damaged), Some(parity / 2 + 1));
+        }
+    }
+
+    #[test]
+    fn capacity_matches_the_protected_length() {
+        for parity in [MIN_PARITY, 16, MAX_PARITY] {
+            for framed in [0, 1, 100, 239, 240, 1000] {
+                let bytes = protect(&vec![7; framed], parity).len() as u64;
+                assert_eq!(framed_capacity(bytes, parity), framed as u64);
+                assert!(framed_capacity(bytes - 1, parity) < framed as u64 || framed == 0);
+            }
+        }
+        assert_eq!(framed_capacity(HEADER_BYTES as u64 - 1, 16), 0);
+    }
+
+    #[test]
+    fn headers_correct_and_must_fit() {
+        let protected = protect(b"framed", 16);
+        let header = Header {
+            parity: 16,
+            framed_bytes: 6,
+        };
+        let slot_bytes = protected.len() as u64;
+        assert_eq!(parse_header(&protected, slot_bytes), Some(header));
+        assert_eq!(parse_header(&protected, slot_bytes - 1), None);
+        let mut damaged = protected.clone();
+        for index in [0, 3, 9, 14] {
+            damaged[index] ^= 0x55;
+        }
+        assert_eq!(parse_header(&damaged, slot_bytes), Some(header));
+        assert_eq!(
+            parse_header(&Framing::LengthPrefixed.frame(&[0; 20]), 100),
+            None
+        );
+    }
+
+    #[test]
+    fn corrected_payloads_roundtrip_through_flipped_bits() {
+        let framed = NaiveEncoder::with_framing(Framing::LengthPrefixed);
+        let encoder = EccEncoder::new(32, Box::new(framed));
+        let mut writer = PngSecretWriter::new(RgbaImage::new(64, 64), Box::new(encoder));
+        // 64x64 RGBA holds 2048 bytes: the header, 7 blocks of 223 data bytes and one of 216
+        assert_eq!(writer.capacity(), 7 * 223 + 216 - 13);
+        let payload: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+        writer.encoder.encode(&payload);
+        writer.embed().unwrap();
+
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(2);
+        let mut stego = writer.buffer;
+        for subpixel in sample(&mut rng, 4 * 64 * 64, 40) {
+            stego.as_mut()[subpixel] ^= 1;
+        }
+        let mut reader = PngSecretReader::new(stego, Box::new(crate::NaiveDecoder::new()));
+        assert_eq!(reader.framing(), Framing::LengthPrefixed);
+        let mut corrected = None;
+        let read = reader.read_image_traced(&mut |event| {
+            if let crate::ReadEvent::Corrected { bytes, .. } = event {
+                corrected = Some(bytes);
+            }
+        });
+        assert_eq!(read, Ok(payload));
+        assert!(corrected.is_some_and(|bytes| (1..=40).contains(&bytes)));
+    }
+}
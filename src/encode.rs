@@ -0,0 +1,1167 @@
+//! Embedding a payload into a cover: gathering it from the flags, checking that it fits, `--dry-run`
+//! and saving the stego image
+
+use image::{ColorType, DynamicImage, RgbaImage};
+use pngsecret::carrier::{self, Carrier};
+use pngsecret::compress::{self, CompressingEncoder};
+use pngsecret::ecc::{self, EccEncoder};
+use pngsecret::format::{self, Features, Framing};
+use pngsecret::order::{self, Slot};
+use pngsecret::records::{self, Record};
+use pngsecret::{bytesize, Embedding, NaiveEncoder, PngSecretEncoder, PngSecretWriter};
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
+
+use crate::artifacts::{self, Artifact};
+use crate::container::Container;
+use crate::decode::report_image;
+use crate::encoding;
+use crate::error::PngSecretError;
+use crate::output::{self, Channel};
+use crate::render::Crop;
+use crate::summary::Summary;
+use crate::{
+    analysis, armor, checksum, chunks, confirm, fsguard, inject, input_path, parse_size, paths,
+    pngio, preflight, progress, receipt, render, report, trace, EmbeddingMode, Method, Opt, STDIN,
+};
+
+/// Embed the payload into the cover `img` read from the file `bytes`, or report how it would fit
+/// with `--dry-run`
+pub fn run(
+    opt: &Opt,
+    img: DynamicImage,
+    bytes: &[u8],
+    summary: &mut Summary,
+) -> Result<(), PngSecretError> {
+    // 4-bit gray covers are saved at 4 bits again, fewer leave no bit that can change unseen
+    let packed_depth = pngio::packed_gray_depth(bytes);
+    if let Some(depth @ (1 | 2)) = packed_depth {
+        return Err(PngSecretError::UnsupportedCoverDepth { depth });
+    }
+    // The chunk method copies the cover as it is
+    if !opt.force_8bit && opt.method != Some(Method::Chunk) {
+        check_cover_kept(&img, bytes)?;
+    }
+    if opt.dry_run {
+        return dry_run(opt, img);
+    }
+    let report = match opt.method {
+        Some(Method::Chunk) => encode_chunk(opt, bytes)?,
+        _ => encode(opt, img, bytes, packed_depth)?,
+    };
+    summary.bytes_out += report.output_bytes as u64;
+    if opt.json {
+        report::print(&report::Encoded {
+            output: report
+                .output
+                .as_ref()
+                .map(|output| output.to_string_lossy().into_owned()),
+            payload_bytes: report.payload_bytes,
+            embedded_bytes: report.embedded_bytes,
+            capacity_bytes: report.capacity_bytes,
+        });
+    }
+    for artifact in &report.artifacts {
+        summary.warn(artifact.id());
+        output::line(
+            Channel::Diagnostics,
+            format_args!("Warning: {}, consider a different cover", artifact),
+        );
+    }
+    for kind in &report.dropped_chunks {
+        summary.warn("dropped_chunk");
+        output::line(
+            Channel::Diagnostics,
+            format_args!(
+                "Warning: left out the {} chunk of the cover, it describes the pixels as the \
+                 cover stored them",
+                String::from_utf8_lossy(kind)
+            ),
+        );
+    }
+    if report.truncated() {
+        summary.warn("truncated");
+        output::line(
+            Channel::Diagnostics,
+            format_args!(
+                "Warning: payload truncated from {} to {} bytes to fit {}",
+                report.payload_bytes,
+                report.embedded_bytes,
+                report
+                    .output
+                    .as_ref()
+                    .map_or("the armored output".to_string(), |output| format!(
+                        "{:?}",
+                        output
+                    ))
+            ),
+        );
+    }
+    Ok(())
+}
+
+/// What an encode actually did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodeReport {
+    /// `None` for `--armor` without `-o`
+    pub output: Option<PathBuf>,
+    /// Size of the payload as given
+    pub payload_bytes: usize,
+    /// Size of the part of the payload that was embedded
+    pub embedded_bytes: usize,
+    pub capacity_bytes: usize,
+    /// Size of the stego PNG, before any armoring
+    pub output_bytes: usize,
+    /// Structure of the cover that makes the payload easy to spot
+    pub artifacts: Vec<Artifact>,
+    /// Ancillary chunks of the cover the stego image doesn't carry over
+    pub dropped_chunks: Vec<[u8; 4]>,
+}
+
+impl EncodeReport {
+    pub fn truncated(&self) -> bool {
+        self.embedded_bytes < self.payload_bytes
+    }
+}
+
+/// The payloads of an encode and where they go, checked before the cover is looked at
+pub struct EncodeInputs {
+    pub framing: Framing,
+    pub payload: Vec<u8>,
+    pub alpha_payload: Option<Vec<u8>>,
+    /// Slot of the main payload
+    pub slot: Slot,
+}
+
+pub fn encode_inputs(opt: &Opt) -> Result<EncodeInputs, PngSecretError> {
+    if let Some(parity) = opt.ecc {
+        if !(ecc::MIN_PARITY..=ecc::MAX_PARITY).contains(&parity) {
+            return Err(PngSecretError::Usage(format!(
+                "--ecc takes {} to {} parity bytes per block, not {}",
+                ecc::MIN_PARITY,
+                ecc::MAX_PARITY,
+                parity
+            )));
+        }
+    }
+    if let Some(notice) = &opt.also_chunk_text {
+        if !pngio::is_latin1(notice) {
+            return Err(PngSecretError::Usage(
+                "--also-chunk-text only supports Latin-1 text".to_string(),
+            ));
+        }
+    }
+    let framing = match opt.legacy {
+        true => Framing::Terminated,
+        false => Framing::LengthPrefixed,
+    };
+    let full_payload = match (&opt.file, opt.text.as_slice()) {
+        _ if opt.segment.is_some() => opt.segment.clone().unwrap_or_default(),
+        _ if !opt.name.is_empty() => record_set(opt)?,
+        (Some(path), _) => read_payload_file(path, "payload", framing)?,
+        (None, [text]) => {
+            let payload = decode_text(opt, text)?;
+            check_terminable(&payload, "--text", framing)?;
+            payload
+        }
+        (None, []) => read_payload_stdin(framing, opt.text_stdin, opt.silent)?,
+        (None, _) => {
+            return Err(PngSecretError::Usage(
+                "Pass a --name for every --text to embed several records".to_string(),
+            ))
+        }
+    };
+    if framing == Framing::Terminated && full_payload.is_empty() {
+        return Err(PngSecretError::Usage(
+            "An empty payload reads as no message in the legacy format, leave out --legacy"
+                .to_string(),
+        ));
+    }
+    if let Some(max_payload) = &opt.max_payload {
+        let limit = parse_size("max-payload", max_payload, opt.si)?;
+        if full_payload.len() as u64 > limit {
+            return Err(PngSecretError::PayloadLimitExceeded {
+                size: full_payload.len() as u64,
+                limit,
+            });
+        }
+    }
+    let alpha_payload = match &opt.alpha_payload {
+        Some(path) => Some(read_payload_file(path, "alpha payload", framing)?),
+        None => None,
+    };
+    let slot = match (&alpha_payload, opt.order.slot()?) {
+        (None, slot) => slot,
+        (Some(_), _) if opt.order.skip_alpha => {
+            return Err(PngSecretError::Usage(
+                "--alpha-payload fills the alpha channel that --skip-alpha leaves alone"
+                    .to_string(),
+            ))
+        }
+        (Some(_), Slot::All | Slot::Rgb) => Slot::Rgb,
+        (Some(_), Slot::Alpha) => {
+            return Err(PngSecretError::Usage(
+                "--alpha-payload fills the alpha slot, the main payload can't use it too"
+                    .to_string(),
+            ))
+        }
+    };
+    Ok(EncodeInputs {
+        framing,
+        payload: full_payload,
+        alpha_payload,
+        slot,
+    })
+}
+
+/// Payload bytes `img` holds with the slot, subpixel order and codec of `opt`
+pub fn capacity(opt: &Opt, img: DynamicImage) -> Result<usize, PngSecretError> {
+    let framing = match opt.legacy {
+        true => Framing::Terminated,
+        false => Framing::LengthPrefixed,
+    };
+    let (img, _) = payload_carrier(opt, img);
+    let writer = PngSecretWriter::new(img, payload_encoder(opt, framing))
+        .with_order(opt.order.order()?)
+        .with_slot(opt.order.slot()?)
+        .with_offset(opt.order.offset());
+    Ok(writer.capacity())
+}
+
+/// The `--embedding` of encode, matching seeded with `--seed` or `--key` if given so the same
+/// arguments give the same image
+fn embedding(opt: &Opt) -> Embedding {
+    match opt.embedding {
+        EmbeddingMode::Replace => Embedding::Replace,
+        EmbeddingMode::Match => {
+            let key = opt.order.key.as_deref().map(order::seed_from_key);
+            Embedding::Match {
+                seed: opt.order.seed.or(key).unwrap_or_else(rand::random),
+            }
+        }
+    }
+}
+
+/// Fail unless the stego image of `img`, decoded from `bytes`, can be saved as the cover was
+/// stored instead of being converted to 8-bit channels
+pub fn check_cover_kept(img: &DynamicImage, bytes: &[u8]) -> Result<(), PngSecretError> {
+    let cover = match img.color() {
+        _ if pngio::is_indexed(bytes) => "a palette",
+        ColorType::Rgb32F | ColorType::Rgba32F => "32-bit float samples",
+        _ => return Ok(()),
+    };
+    Err(PngSecretError::CoverConverted { cover })
+}
+
+/// The 8-bit samples encode writes the payload into and, for a 16-bit cover kept at 16 bits,
+/// the cover they are put back into before saving
+fn payload_carrier(opt: &Opt, img: DynamicImage) -> (DynamicImage, Option<DynamicImage>) {
+    match opt.force_8bit {
+        true => (carrier::to_8bit(img), None),
+        false => carrier::payload_carrier(img),
+    }
+}
+
+/// Report for `--dry-run` how the payloads fit into `img`, failing as encode would if one doesn't
+pub fn dry_run(opt: &Opt, img: DynamicImage) -> Result<(), PngSecretError> {
+    let inputs = encode_inputs(opt)?;
+    let (img, wide) = payload_carrier(opt, img);
+    checked_container(opt, wide.as_ref().unwrap_or(&img))?;
+    let mut writer = PngSecretWriter::new(img, payload_encoder(opt, inputs.framing))
+        .with_order(opt.order.order()?)
+        .with_slot(inputs.slot)
+        .with_offset(opt.order.offset());
+    let mut payloads = vec![(inputs.slot, &inputs.payload)];
+    payloads.extend(
+        inputs
+            .alpha_payload
+            .iter()
+            .map(|alpha| (Slot::Alpha, alpha)),
+    );
+    let mut too_large = None;
+    for (slot, payload) in payloads {
+        writer.encoder.place(slot);
+        writer.encoder.encode(payload);
+        let encoded = writer.encoder.text().len() as u64;
+        let subpixels = slot.subpixels_in(writer.buffer.samples().len(), writer.buffer.channels());
+        let carrier = format::slot_bytes(subpixels as u64, opt.bits());
+        let headroom = match carrier.checked_sub(encoded) {
+            Some(headroom) => format!("headroom {}", bytesize::format(headroom)),
+            None => format!("{} over capacity", bytesize::format(encoded - carrier)),
+        };
+        output::line(
+            Channel::Payload,
+            format_args!(
+                "{} slot: carrier {}, encoded payload {} ({} payload, {} overhead), {}",
+                format!("{:?}", slot).to_lowercase(),
+                bytesize::format(carrier),
+                bytesize::format(encoded),
+                bytesize::format(payload.len() as u64),
+                bytesize::format(encoded.saturating_sub(payload.len() as u64)),
+                headroom
+            ),
+        );
+        if encoded > carrier && too_large.is_none() {
+            too_large = Some(PngSecretError::PayloadTooLarge {
+                capacity: writer.capacity_in(slot),
+                requested: payload.len(),
+            });
+        }
+    }
+    if !opt.silent {
+        output::line(
+            Channel::Diagnostics,
+            "Dry run, nothing was embedded or written",
+        );
+    }
+    too_large.map_or(Ok(()), Err)
+}
+
+/// Embed the payload into `img` and save it, with `packed_depth` bits per sample for gray covers
+/// stored with fewer than 8
+pub fn encode(
+    opt: &Opt,
+    img: DynamicImage,
+    cover_png: &[u8],
+    packed_depth: Option<u8>,
+) -> Result<EncodeReport, PngSecretError> {
+    inject::check()?;
+    let EncodeInputs {
+        framing,
+        payload: full_payload,
+        alpha_payload,
+        slot,
+    } = encode_inputs(opt)?;
+    let output_filename = get_output_filename(opt);
+    if opt.write_checksum && output_filename.is_none() {
+        return Err(PngSecretError::Usage(
+            "--write-checksum needs an output file to put the sidecar next to".to_string(),
+        ));
+    }
+    if let Some(output_filename) = &output_filename {
+        if paths::collides(input_path(opt), output_filename) {
+            return Err(PngSecretError::OutputIsInput(output_filename.clone()));
+        }
+        let required_space = match &opt.min_free_space {
+            Some(size) => parse_size("min-free-space", size, opt.si)?,
+            None => preflight::estimate_required_space(input_path(opt)),
+        };
+        preflight::check_output(output_filename, opt.create_dirs, required_space)?;
+        if !opt.silent {
+            output::line(
+                Channel::Diagnostics,
+                format_args!("output filename {:?}", output_filename),
+            );
+        }
+    }
+    let (img, wide) = payload_carrier(opt, img);
+    let container = checked_container(opt, wide.as_ref().unwrap_or(&img))?;
+    let _span = tracing::info_span!(
+        "encode",
+        width = img.width(),
+        height = img.height(),
+        payload_bytes = full_payload.len(),
+        codec = NaiveEncoder::ID,
+        slot = ?slot,
+    )
+    .entered();
+    let sign_key = match &opt.sign_key {
+        Some(path) if opt.receipt.is_some() => Some(receipt::read_signing_key(path)?),
+        _ => None,
+    };
+    // The cover checks look at the pixels, whatever channels store them
+    let rgba = wide.as_ref().unwrap_or(&img).to_rgba8();
+    let artifacts = artifacts::detect(&rgba);
+    tracing::debug!(artifacts = artifacts.len(), "analyzed cover");
+    if opt.strict && !artifacts.is_empty() {
+        return Err(PngSecretError::UnsuitableCover(artifacts));
+    }
+    let order = opt.order.order()?;
+    let mut writer = PngSecretWriter::new(img, payload_encoder(opt, framing))
+        .with_order(order)
+        .with_slot(slot)
+        .with_offset(opt.order.offset())
+        .with_embedding(embedding(opt))
+        .with_padding(opt.pad_to_capacity);
+    if !opt.silent {
+        report_image(&writer.info());
+    }
+    let capacity = writer.capacity();
+    if alpha_payload.is_some() && !opt.silent {
+        output::line(
+            Channel::Diagnostics,
+            format_args!(
+                "Capacity per slot: rgb {}, alpha {}",
+                bytesize::format(capacity as u64),
+                bytesize::format(writer.capacity_in(Slot::Alpha) as u64)
+            ),
+        );
+    }
+    let payload = match opt.truncate_to_fit {
+        true => fitting_prefix(&mut writer, &full_payload, capacity),
+        false => &full_payload[..],
+    };
+    let detectability = analysis::estimate_detectability(
+        &analysis::CoverStats::of(&rgba),
+        &analysis::Params {
+            payload_bytes: payload.len() as u64 + writer.encoder.overhead_bytes(),
+            slot,
+            order,
+            framing,
+        },
+    );
+    tracing::debug!(%detectability, "estimated detectability");
+    if !opt.silent {
+        output::line(
+            Channel::Diagnostics,
+            format_args!("Estimated detectability: {}", detectability),
+        );
+    }
+    if let Some(limit) = opt.max_detectability {
+        if detectability > limit {
+            return Err(PngSecretError::TooDetectable {
+                score: detectability,
+                limit,
+            });
+        }
+    }
+    tracing::debug_span!(
+        "codec",
+        codec = NaiveEncoder::ID,
+        input_bytes = payload.len()
+    )
+    .in_scope(|| {
+        writer
+            .encoder
+            .encode_with(payload, truncation(&full_payload, payload.len()))
+    });
+    let cover = opt.preview_crop.map(|_| rgba);
+    let mut traced = Vec::new();
+    let mut bar = progress::Bar::new("Embedding", opt.silent);
+    let mut trace = |subpixel| {
+        if traced.len() < opt.trace_bits {
+            traced.push(subpixel);
+        }
+    };
+    writer.embed_observed(
+        match opt.trace_indices {
+            Some(_) => Some(&mut trace),
+            None => None,
+        },
+        &mut |done, total| bar.update(done, total),
+    )?;
+    if let Some(path) = &opt.trace_indices {
+        trace::write(path, &traced)?;
+    }
+    if let Some(alpha_payload) = &alpha_payload {
+        let mut alpha_encoder = payload_encoder(opt, framing);
+        alpha_encoder.place(Slot::Alpha);
+        alpha_encoder.encode(alpha_payload);
+        writer.embed_text(Slot::Alpha, alpha_encoder.text())?;
+    }
+    let stego_img = match wide {
+        Some(wide) => carrier::with_low_bytes(wide, &writer.buffer),
+        None => writer.buffer,
+    };
+    if let (Some(crop), Some(cover)) = (&opt.preview_crop, &cover) {
+        preview(opt.silent, cover, &stego_img.to_rgba8(), crop);
+    }
+    if let Some(output_filename) = &output_filename {
+        let plan = confirm::Plan::new(
+            format!(
+                "Embed a payload of {} into {}",
+                bytesize::format(payload.len() as u64),
+                output_filename.display()
+            ),
+            &[output_filename],
+        );
+        confirm::confirm(&plan, opt.yes)?;
+    }
+    // The chunk is only added here, after all pixel mutation is done
+    let (stego, dropped_chunks) = save_stego(
+        &stego_img,
+        container,
+        output_filename.as_deref(),
+        opt.also_chunk_text.as_deref(),
+        packed_depth,
+        cover_png,
+    )?;
+    match &output_filename {
+        _ if opt.armor => output::write(Channel::Payload, armor::armor(&stego).as_bytes()),
+        Some(output_filename) if !opt.silent && !opt.json => {
+            output::path_line(Channel::Payload, output_filename)
+        }
+        _ => {}
+    }
+    let parameters = receipt::Parameters {
+        slot: format!("{:?}", slot).to_lowercase(),
+        permute: format!("{:?}", opt.order.permute).to_lowercase(),
+        truncated: payload.len() < full_payload.len(),
+        chunk_notice: opt.also_chunk_text.is_some(),
+        alpha_payload: alpha_payload.is_some(),
+    };
+    if let (Some(path), Some(key)) = (&opt.receipt, &sign_key) {
+        let receipt = receipt::issue(key, &stego, payload, parameters.clone());
+        receipt::write(&receipt, path)?;
+        if !opt.silent && !opt.armor && !opt.json {
+            output::path_line(Channel::Payload, path);
+        }
+    }
+    if let (true, Some(output_filename)) = (opt.write_checksum, &output_filename) {
+        let codec = checksum::Codec {
+            bits: opt.bits(),
+            legacy: opt.legacy,
+            compressed: opt.compress,
+        };
+        let path = checksum::sidecar_path(output_filename);
+        checksum::write(&checksum::new(&stego, payload, parameters, codec), &path)?;
+        if !opt.silent && !opt.armor && !opt.json {
+            output::path_line(Channel::Payload, &path);
+        }
+    }
+    Ok(EncodeReport {
+        output: output_filename,
+        payload_bytes: full_payload.len(),
+        embedded_bytes: payload.len(),
+        capacity_bytes: capacity,
+        output_bytes: stego.len(),
+        artifacts,
+        dropped_chunks,
+    })
+}
+
+/// Put the payload into a private chunk of the PNG `cover` and save it, copying every other
+/// chunk as it is
+pub fn encode_chunk(opt: &Opt, cover: &[u8]) -> Result<EncodeReport, PngSecretError> {
+    inject::check()?;
+    for (given, flag) in [
+        (opt.armor, "--armor"),
+        (opt.also_chunk_text.is_some(), "--also-chunk-text"),
+        (opt.preview_crop.is_some(), "--preview-crop"),
+        (opt.receipt.is_some(), "--receipt"),
+        (opt.write_checksum, "--write-checksum"),
+        (opt.trace_indices.is_some(), "--trace-indices"),
+    ] {
+        if given {
+            return Err(PngSecretError::Usage(format!(
+                "{} works on pixel payloads, not with --method chunk",
+                flag
+            )));
+        }
+    }
+    if chunks::parse(cover).is_none() {
+        return Err(PngSecretError::Usage(
+            "--method chunk needs a PNG cover".to_string(),
+        ));
+    }
+    let EncodeInputs {
+        framing, payload, ..
+    } = encode_inputs(opt)?;
+    let output_filename = get_output_filename(opt).expect("only --armor writes no file");
+    if output_container(opt)? != Container::Png {
+        return Err(PngSecretError::Usage(
+            "--method chunk writes PNG files only".to_string(),
+        ));
+    }
+    if paths::collides(input_path(opt), &output_filename) {
+        return Err(PngSecretError::OutputIsInput(output_filename));
+    }
+    let required_space = match &opt.min_free_space {
+        Some(size) => parse_size("min-free-space", size, opt.si)?,
+        None => preflight::estimate_required_space(input_path(opt)),
+    };
+    preflight::check_output(&output_filename, opt.create_dirs, required_space)?;
+    let mut encoder = payload_encoder(opt, framing);
+    let capacity =
+        chunks::MAX_CHUNK_BYTES - (framing.overhead_bytes() + encoder.overhead_bytes()) as usize;
+    if payload.len() > capacity {
+        return Err(PngSecretError::PayloadTooLarge {
+            capacity,
+            requested: payload.len(),
+        });
+    }
+    encoder.encode(&payload);
+    let plan = confirm::Plan::new(
+        format!(
+            "Put a payload of {} into a {} chunk of {}",
+            bytesize::format(payload.len() as u64),
+            String::from_utf8_lossy(&chunks::PAYLOAD_CHUNK),
+            output_filename.display()
+        ),
+        &[&output_filename],
+    );
+    confirm::confirm(&plan, opt.yes)?;
+    let stego = chunks::with_payload(cover, encoder.text()).expect("the cover was parsed");
+    fsguard::write_atomic(&output_filename, &stego)
+        .map_err(|_| PngSecretError::SaveFailed(output_filename.clone()))?;
+    if !opt.silent && !opt.json {
+        output::path_line(Channel::Payload, &output_filename);
+    }
+    Ok(EncodeReport {
+        output: Some(output_filename),
+        payload_bytes: payload.len(),
+        embedded_bytes: payload.len(),
+        capacity_bytes: capacity,
+        output_bytes: stego.len(),
+        artifacts: Vec::new(),
+        dropped_chunks: Vec::new(),
+    })
+}
+
+/// The longest prefix of `payload` that fits `writer` once encoded with the length it was cut
+/// from, at least the `capacity` bytes that fit however `--compress` and `--ecc` change its size
+/// short of that length
+///
+/// Compressed prefixes barely ever shrink as they grow, so bisecting between the two finds the
+/// longest one or one a few bytes short of it.
+fn fitting_prefix<'a>(
+    writer: &mut PngSecretWriter<DynamicImage>,
+    payload: &'a [u8],
+    capacity: usize,
+) -> &'a [u8] {
+    let mut fits = |len: usize| {
+        writer
+            .encoder
+            .encode_with(&payload[..len], truncation(payload, len));
+        writer.fits()
+    };
+    if fits(payload.len()) {
+        return payload;
+    }
+    // Fits, unlike the whole payload
+    let fitting = capacity.saturating_sub(format::ORIGINAL_LENGTH_BYTES);
+    let (mut fitting, mut too_long) = (fitting.min(payload.len()), payload.len());
+    while too_long - fitting > 1 {
+        let middle = fitting + (too_long - fitting) / 2;
+        match fits(middle) {
+            true => fitting = middle,
+            false => too_long = middle,
+        }
+    }
+    &payload[..fitting]
+}
+
+/// The features of the first `len` bytes of `payload`, which record its length if they are short
+/// of it
+fn truncation(payload: &[u8], len: usize) -> Features {
+    Features {
+        original_length: (len < payload.len()).then_some(payload.len() as u64),
+        ..Features::default()
+    }
+}
+
+/// The encoder of the payloads, compressing them with `--compress` and adding `--ecc` parity to
+/// the frame
+fn payload_encoder(opt: &Opt, framing: Framing) -> Box<dyn PngSecretEncoder> {
+    let naive = NaiveEncoder::with_framing(framing).with_bits(opt.bits());
+    let naive: Box<dyn PngSecretEncoder> = match opt.ecc {
+        Some(parity) => Box::new(EccEncoder::new(parity, Box::new(naive))),
+        None => Box::new(naive),
+    };
+    // `record_set` compresses the records one by one
+    match opt.compress && opt.name.is_empty() {
+        true => Box::new(CompressingEncoder::new(naive)),
+        false => naive,
+    }
+}
+
+/// The record set of the `--text` and `--name` pairs, each compressed with `--compress`
+fn record_set(opt: &Opt) -> Result<Vec<u8>, PngSecretError> {
+    for (given, flag) in [
+        (opt.file.is_some(), "--file"),
+        (opt.legacy, "--legacy"),
+        (opt.alpha_payload.is_some(), "--alpha-payload"),
+        (opt.truncate_to_fit, "--truncate-to-fit"),
+    ] {
+        if given {
+            return Err(PngSecretError::Usage(format!(
+                "{} can't be combined with --name records",
+                flag
+            )));
+        }
+    }
+    if opt.text.len() != opt.name.len() {
+        return Err(PngSecretError::Usage(format!(
+            "Pass one --name for every --text, got {} texts and {} names",
+            opt.text.len(),
+            opt.name.len()
+        )));
+    }
+    for (i, name) in opt.name.iter().enumerate() {
+        if opt.name[..i].contains(name) {
+            return Err(PngSecretError::Usage(format!(
+                "The record name {:?} is given twice",
+                name
+            )));
+        }
+    }
+    let mut records = Vec::with_capacity(opt.name.len());
+    for (name, text) in opt.name.iter().zip(&opt.text) {
+        let text = decode_text(opt, text)?;
+        let data = match opt.compress {
+            true => compress::compress(&text),
+            false => text,
+        };
+        records.push(Record::new(name, data));
+    }
+    Ok(records::pack(&records)?)
+}
+
+/// Read the file for `--file` or `--alpha-payload`, which the terminated legacy framing can only
+/// carry without NUL bytes
+fn read_payload_file(path: &Path, what: &str, framing: Framing) -> Result<Vec<u8>, PngSecretError> {
+    let payload = std::fs::read(path)
+        .map_err(|e| PngSecretError::Io(format!("Couldn't read the {} {:?}", what, path), e))?;
+    check_terminable(&payload, &format!("{} {:?}", what, path), framing)?;
+    Ok(payload)
+}
+
+/// The bytes a `--text` stands for, in `--escape` or the `--payload-encoding`
+fn decode_text(opt: &Opt, text: &str) -> Result<Vec<u8>, PngSecretError> {
+    match opt.escape {
+        true => encoding::unescape(text),
+        false => opt.payload_encoding.unwrap_or_default().decode(text),
+    }
+}
+
+/// Read the payload piped in on stdin, for `cat secret.tar.gz | pngsecret -e ...`
+///
+/// A terminal is only read from with `--text-stdin`, which makes typing the message the intent.
+fn read_payload_stdin(
+    framing: Framing,
+    text_stdin: bool,
+    silent: bool,
+) -> Result<Vec<u8>, PngSecretError> {
+    let mut stdin = std::io::stdin().lock();
+    if stdin.is_terminal() {
+        if !text_stdin {
+            return Err(PngSecretError::Usage(
+                "No payload given, pass --text or --file or pipe it in on stdin".to_string(),
+            ));
+        }
+        if !silent {
+            output::line(
+                Channel::Diagnostics,
+                "Type the message, then Ctrl-D on an empty line to end it:",
+            );
+        }
+    }
+    let mut payload = Vec::new();
+    stdin
+        .read_to_end(&mut payload)
+        .map_err(|e| PngSecretError::Io("Couldn't read the payload from stdin".to_string(), e))?;
+    check_terminable(&payload, "payload on stdin", framing)?;
+    Ok(payload)
+}
+
+/// Refuse a payload with NUL bytes, which the terminated legacy framing can't store
+fn check_terminable(payload: &[u8], what: &str, framing: Framing) -> Result<(), PngSecretError> {
+    let terminated = framing == Framing::Terminated;
+    match payload.iter().position(|byte| *byte == 0 && terminated) {
+        Some(offset) => Err(PngSecretError::Usage(format!(
+            "The {} contains a NUL byte at offset {}, which the legacy format can't store",
+            what, offset
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Show the cover and the modified buffer next to each other, only on interactive truecolor
+/// terminals since this is a visual aid
+fn preview(silent: bool, cover: &RgbaImage, modified: &RgbaImage, crop: &Crop) {
+    if silent || !std::io::stderr().is_terminal() {
+        return;
+    }
+    if !render::supports_truecolor() {
+        output::line(
+            Channel::Diagnostics,
+            "Preview skipped: the terminal doesn't report truecolor support",
+        );
+        return;
+    }
+    match render::side_by_side(cover, modified, crop) {
+        Some(rendered) => output::write(Channel::Diagnostics, rendered.as_bytes()),
+        None => output::line(
+            Channel::Diagnostics,
+            "Preview skipped: the crop lies outside the image",
+        ),
+    }
+}
+
+/// Where encode saves the result, `None` if it only goes to stdout with `--armor`
+fn get_output_filename(opt: &Opt) -> Option<PathBuf> {
+    let path = match &opt.output {
+        Some(path) => path.clone(),
+        None if opt.armor => return None,
+        None => paths::derive_output(
+            input_path(opt),
+            &Container::default_extension(input_path(opt)),
+        ),
+    };
+    if cfg!(windows) && opt.long_paths {
+        Some(paths::with_long_path_prefix(&path))
+    } else {
+        Some(path)
+    }
+}
+
+/// The format encode saves in, by the extension of the output file
+pub fn output_container(opt: &Opt) -> Result<Container, PngSecretError> {
+    let container = match get_output_filename(opt) {
+        Some(path) => Container::of_path(&path)?,
+        None => None,
+    };
+    Ok(container.unwrap_or(Container::Png))
+}
+
+/// The format encode saves `img` in, if it can store it and the other outputs
+fn checked_container(opt: &Opt, img: &DynamicImage) -> Result<Container, PngSecretError> {
+    let container = output_container(opt)?;
+    container.check(img.color())?;
+    container.check_size(img.width(), img.height(), img.color())?;
+    if container != Container::Png && opt.also_chunk_text.is_some() {
+        return Err(PngSecretError::Usage(format!(
+            "--also-chunk-text needs a PNG output, {} has no tEXt chunks",
+            container.name()
+        )));
+    }
+    Ok(container)
+}
+
+/// Encode the image in `container`, saved to `output_filename` if given, and return its bytes
+///
+/// Gray images are packed to `packed_depth` bits per sample if given and 16-bit ones stay 16-bit,
+/// the notice and packing are PNG only.
+fn save_stego(
+    img: &DynamicImage,
+    container: Container,
+    output_filename: Option<&Path>,
+    notice: Option<&str>,
+    packed_depth: Option<u8>,
+    cover: &[u8],
+) -> Result<(Vec<u8>, Vec<[u8; 4]>), PngSecretError> {
+    let _span = tracing::info_span!(
+        "save",
+        path = ?output_filename,
+        notice = notice.is_some()
+    )
+    .entered();
+    let texts: Vec<(String, String)> = notice
+        .map(|notice| (pngio::NOTICE_KEYWORD.to_string(), notice.to_string()))
+        .into_iter()
+        .collect();
+    let failed =
+        || PngSecretError::SaveFailed(output_filename.unwrap_or(Path::new(STDIN)).to_path_buf());
+    let wide = matches!(
+        img.color(),
+        ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16
+    );
+    let stego = match (container, packed_depth) {
+        (Container::Png, _) if wide => pngio::encode_16bit(img, &texts),
+        (Container::Png, Some(depth)) => pngio::encode_packed_gray(img, depth, &texts),
+        (Container::Png, None) => pngio::encode_with_text(img, &texts),
+        (container, _) => container.encode(img).map_err(std::io::Error::other),
+    }
+    .map_err(|_| failed())?;
+    // Other containers have no place for the PNG chunks of the cover
+    let (stego, dropped) = match chunks::with_cover_metadata(&stego, cover) {
+        Some(kept) => (kept.png, kept.dropped),
+        None => (stego, Vec::new()),
+    };
+    if let Some(output_filename) = output_filename {
+        fsguard::write_atomic(output_filename, &stego).map_err(|_| failed())?;
+    }
+    Ok((stego, dropped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::original_length;
+    use pngsecret::compress::CompressingDecoder;
+    use pngsecret::{NaiveDecoder, PngSecretDecoder, PngSecretReader};
+    use structopt::StructOpt;
+
+    type Spans = Vec<(&'static str, Vec<(String, String)>)>;
+
+    /// Run `f` and return the spans it opened with their fields, in order
+    fn capture_spans<T>(f: impl FnOnce() -> T) -> (T, Spans) {
+        use std::sync::{Arc, Mutex};
+        use tracing::field::{Field, Visit};
+        use tracing_subscriber::layer::{Context, SubscriberExt};
+
+        struct Fields(Vec<(String, String)>);
+
+        impl Visit for Fields {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                self.0
+                    .push((field.name().to_string(), format!("{:?}", value)));
+            }
+
+            fn record_str(&mut self, field: &Field, value: &str) {
+                self.0.push((field.name().to_string(), value.to_string()));
+            }
+        }
+
+        struct Capture(Arc<Mutex<Spans>>);
+
+        impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for Capture {
+            fn on_new_span(
+                &self,
+                attrs: &tracing::span::Attributes<'_>,
+                _: &tracing::span::Id,
+                _: Context<'_, S>,
+            ) {
+                let mut fields = Fields(Vec::new());
+                attrs.record(&mut fields);
+                let name = attrs.metadata().name();
+                self.0.lock().unwrap().push((name, fields.0));
+            }
+        }
+
+        let spans = Arc::<Mutex<Spans>>::default();
+        let subscriber = tracing_subscriber::registry().with(Capture(spans.clone()));
+        let result = tracing::subscriber::with_default(subscriber, f);
+        let spans = std::mem::take(&mut *spans.lock().unwrap());
+        (result, spans)
+    }
+
+    fn embedded(spans: &Spans) -> bool {
+        spans.iter().any(|(name, _)| *name == "embed")
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn preflight_fails_before_embedding() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("readonly");
+        std::fs::create_dir(&target).unwrap();
+        std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o555)).unwrap();
+        let output = target.join("out.png");
+        let opt = Opt::from_iter([
+            "pngsecret",
+            "-s",
+            "-e",
+            "--text",
+            "Hello World",
+            "-i",
+            "cover.png",
+            "-o",
+            output.to_str().unwrap(),
+        ]);
+
+        let (encoded, spans) = capture_spans(|| {
+            encode(
+                &opt,
+                DynamicImage::ImageRgba8(RgbaImage::new(16, 16)),
+                &[],
+                None,
+            )
+        });
+        assert!(encoded.is_err());
+        assert!(!embedded(&spans));
+        assert!(!output.exists());
+        std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let output = dir.path().join("out.png");
+        let opt = Opt::from_iter([
+            "pngsecret",
+            "-s",
+            "-e",
+            "--text",
+            "Hello World",
+            "-i",
+            "cover.png",
+            "-o",
+            output.to_str().unwrap(),
+        ]);
+        let (encoded, spans) = capture_spans(|| {
+            encode(
+                &opt,
+                DynamicImage::ImageRgba8(RgbaImage::new(16, 16)),
+                &[],
+                None,
+            )
+        });
+        encoded.unwrap();
+        assert!(embedded(&spans));
+        assert!(output.exists());
+    }
+
+    fn encode_opts(text: &str, output: &Path, extra: &[&str]) -> Opt {
+        let mut args = vec![
+            "pngsecret",
+            "-s",
+            "-e",
+            "-i",
+            "cover.png",
+            "-o",
+            output.to_str().unwrap(),
+            "--text",
+            text,
+        ];
+        args.extend_from_slice(extra);
+        Opt::from_iter(args)
+    }
+
+    #[test]
+    fn oversized_payload_is_rejected_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.png");
+        // 4x8 RGBA holds 128 bits, i.e. 3 bytes plus the 13 byte frame header
+        let cover = DynamicImage::ImageRgba8(RgbaImage::new(4, 8));
+        let opt = encode_opts("1234567890123456", &output, &[]);
+        assert!(matches!(
+            encode(&opt, cover.clone(), &[], None),
+            Err(PngSecretError::PayloadTooLarge {
+                capacity: 3,
+                requested: 16
+            })
+        ));
+        assert!(!output.exists());
+        // or 15 bytes plus the legacy terminator
+        let opt = encode_opts("1234567890123456", &output, &["--legacy"]);
+        assert!(matches!(
+            encode(&opt, cover, &[], None),
+            Err(PngSecretError::PayloadTooLarge {
+                capacity: 15,
+                requested: 16
+            })
+        ));
+    }
+
+    #[test]
+    fn truncate_to_fit_embeds_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.png");
+        // 4x12 RGBA holds 24 bytes, a header recording the original length takes 21
+        let cover = DynamicImage::ImageRgba8(RgbaImage::new(4, 12));
+        let opt = encode_opts("123456789abc", &output, &["--truncate-to-fit"]);
+        let report = encode(&opt, cover, &[], None).unwrap();
+        assert!(report.truncated());
+        assert_eq!((report.payload_bytes, report.embedded_bytes), (12, 3));
+
+        let stego = image::open(&output).unwrap().into_rgba8();
+        let mut reader = PngSecretReader::new(stego, Box::new(NaiveDecoder::new()));
+        assert_eq!(reader.read_image().unwrap(), b"123");
+        assert_eq!(original_length(&reader), Some(12));
+    }
+
+    #[test]
+    fn truncate_to_fit_keeps_the_legacy_terminator() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.png");
+        // 4x4 RGBA holds 7 bytes plus the terminator, which must not be cut instead
+        let cover = DynamicImage::ImageRgba8(RgbaImage::new(4, 4));
+        for (text, truncated) in [("123456", false), ("1234567", false), ("12345678", true)] {
+            let opt = encode_opts(text, &output, &["--truncate-to-fit", "--legacy", "-y"]);
+            let report = encode(&opt, cover.clone(), &[], None).unwrap();
+            assert_eq!(report.truncated(), truncated, "{}", text);
+            let stego = image::open(&output).unwrap().into_rgba8();
+            let mut reader = PngSecretReader::new(stego, Box::new(NaiveDecoder::new()))
+                .with_framing(Framing::Terminated);
+            assert_eq!(
+                reader.read_image().unwrap(),
+                &text.as_bytes()[..text.len().min(7)]
+            );
+        }
+    }
+
+    #[test]
+    fn truncate_to_fit_goes_by_the_compressed_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.png");
+        // 16x16 RGBA holds 128 bytes, frame and compression header included
+        let cover = DynamicImage::ImageRgba8(RgbaImage::new(16, 16));
+        let mut state = 1u32;
+        let noise: String = (0..400)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (b'!' + (state >> 16) as u8 % 94) as char
+            })
+            .collect();
+        let text = "a".repeat(2000) + &noise;
+        for (text, truncated) in [(&text[..2000], false), (&text[..], true)] {
+            let opt = encode_opts(text, &output, &["--truncate-to-fit", "--compress", "-y"]);
+            let report = encode(&opt, cover.clone(), &[], None).unwrap();
+            assert_eq!(report.truncated(), truncated);
+            assert!(report.embedded_bytes > report.capacity_bytes);
+
+            let stego = image::open(&output).unwrap().into_rgba8();
+            let mut reader = PngSecretReader::new(stego, Box::new(NaiveDecoder::new()));
+            let message = CompressingDecoder::new()
+                .decode(reader.read_image().unwrap())
+                .unwrap();
+            assert_eq!(message, &text.as_bytes()[..report.embedded_bytes]);
+        }
+    }
+
+    #[test]
+    fn truncate_to_fit_keeps_payloads_that_fit() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.png");
+        let cover = DynamicImage::ImageRgba8(RgbaImage::new(4, 8));
+        let opt = encode_opts("123", &output, &["--truncate-to-fit"]);
+        assert!(!encode(&opt, cover, &[], None).unwrap().truncated());
+
+        let stego = image::open(&output).unwrap().into_rgba8();
+        let mut reader = PngSecretReader::new(stego, Box::new(NaiveDecoder::new()));
+        assert_eq!(reader.read_image().unwrap(), b"123");
+        assert_eq!(original_length(&reader), None);
+    }
+
+    #[test]
+    fn encode_records_spans_without_the_payload() {
+        let dir = tempfile::tempdir().unwrap();
+        let opt = encode_opts("top secret", &dir.path().join("out.png"), &[]);
+        let cover = DynamicImage::ImageRgba8(RgbaImage::new(24, 16));
+        let (encoded, spans) = capture_spans(|| encode(&opt, cover, &[], None));
+        encoded.unwrap();
+
+        let names: Vec<&str> = spans.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["encode", "codec", "embed", "save"]);
+        let field = |span: usize, name: &str| {
+            spans[span]
+                .1
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value.clone())
+        };
+        assert_eq!(field(0, "width").as_deref(), Some("24"));
+        assert_eq!(field(0, "height").as_deref(), Some("16"));
+        assert_eq!(field(0, "payload_bytes").as_deref(), Some("10"));
+        assert_eq!(field(1, "codec").as_deref(), Some("naive"));
+        assert_eq!(field(2, "encoded_bytes").as_deref(), Some("23"));
+        assert!(spans
+            .iter()
+            .flat_map(|(_, fields)| fields)
+            .all(|(_, value)| !value.contains("secret")));
+    }
+
+    #[test]
+    fn strict_refuses_posterized_covers() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.png");
+        let cover = DynamicImage::ImageRgba8(RgbaImage::new(64, 64));
+        let opt = encode_opts("flat", &output, &[]);
+        assert_eq!(
+            encode(&opt, cover.clone(), &[], None).unwrap().artifacts,
+            [
+                Artifact::Posterized { colors: 1 },
+                Artifact::FlatLsb {
+                    bit: 0,
+                    percent: 100
+                }
+            ]
+        );
+
+        let opt = encode_opts("flat", &dir.path().join("strict.png"), &["--strict"]);
+        assert!(matches!(
+            encode(&opt, cover, &[], None),
+            Err(PngSecretError::UnsuitableCover(_))
+        ));
+        assert!(!dir.path().join("strict.png").exists());
+    }
+}
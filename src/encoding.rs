@@ -0,0 +1,244 @@
+//! `--payload-encoding`, how `--text` is read on encode and the message is printed on decode
+//!
+//! Binary payloads often travel as base64 between tools, and hex is quicker to eyeball than raw
+//! bytes. Base64 is the standard alphabet, padded or not. Both are checked character by
+//! character first, so an invalid input is reported with the position of its first bad character.
+//!
+//! `--escape` reads `--text` with backslash escapes instead, for payloads with newlines or control
+//! bytes a shell argument can't easily hold, and prints the message with them on decode.
+
+use base64ct::{Base64, Base64Unpadded, Encoding as _};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::PngSecretError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PayloadEncoding {
+    /// The text as its UTF-8 bytes, the message as it is
+    #[default]
+    Raw,
+    Base64,
+    Hex,
+}
+
+impl PayloadEncoding {
+    /// The bytes `text` stands for
+    pub fn decode(self, text: &str) -> Result<Vec<u8>, PngSecretError> {
+        let invalid = |position, reason: &str| PngSecretError::InvalidEncoding {
+            encoding: self,
+            position,
+            reason: reason.to_string(),
+        };
+        match self {
+            PayloadEncoding::Raw => Ok(text.as_bytes().to_vec()),
+            PayloadEncoding::Hex => {
+                if let Some((position, c)) =
+                    text.char_indices().find(|(_, c)| !c.is_ascii_hexdigit())
+                {
+                    return Err(invalid(position, &format!("{:?} is not a hex digit", c)));
+                }
+                if !text.len().is_multiple_of(2) {
+                    return Err(invalid(
+                        text.len(),
+                        "an odd number of digits ends in half a byte",
+                    ));
+                }
+                Ok((0..text.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+                    .collect())
+            }
+            PayloadEncoding::Base64 => {
+                let body = text.trim_end_matches('=');
+                let is_base64 = |c: char| c.is_ascii_alphanumeric() || c == '+' || c == '/';
+                if let Some((position, c)) = body.char_indices().find(|(_, c)| !is_base64(*c)) {
+                    return Err(invalid(
+                        position,
+                        &format!("{:?} is not a base64 character", c),
+                    ));
+                }
+                let decoded = match body.len() == text.len() {
+                    true => Base64Unpadded::decode_vec(text),
+                    false => Base64::decode_vec(text),
+                };
+                decoded.map_err(|_| invalid(text.len(), "the length or the padding is wrong"))
+            }
+        }
+    }
+
+    /// `bytes` written in this encoding, `None` for raw
+    pub fn encode(self, bytes: &[u8]) -> Option<String> {
+        match self {
+            PayloadEncoding::Raw => None,
+            PayloadEncoding::Base64 => Some(Base64::encode_string(bytes)),
+            PayloadEncoding::Hex => {
+                Some(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+            }
+        }
+    }
+}
+
+impl FromStr for PayloadEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(PayloadEncoding::Raw),
+            "base64" => Ok(PayloadEncoding::Base64),
+            "hex" => Ok(PayloadEncoding::Hex),
+            _ => Err(format!("unknown payload encoding {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for PayloadEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PayloadEncoding::Raw => write!(f, "raw"),
+            PayloadEncoding::Base64 => write!(f, "base64"),
+            PayloadEncoding::Hex => write!(f, "hex"),
+        }
+    }
+}
+
+/// The bytes `text` stands for with the escapes `\\`, `\n`, `\t`, `\0` and `\xNN` replaced
+///
+/// Any other backslash is refused with its position, so a typo doesn't end up in the payload.
+pub fn unescape(text: &str) -> Result<Vec<u8>, PngSecretError> {
+    let invalid = |position, reason: String| PngSecretError::InvalidEscape { position, reason };
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut chars = text.char_indices();
+    while let Some((position, c)) = chars.next() {
+        if c != '\\' {
+            let mut utf8 = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some((_, '\\')) => bytes.push(b'\\'),
+            Some((_, 'n')) => bytes.push(b'\n'),
+            Some((_, 't')) => bytes.push(b'\t'),
+            Some((_, '0')) => bytes.push(0),
+            Some((at, 'x')) => {
+                let digits = text.get(at + 1..at + 3).filter(|digits| {
+                    digits.len() == 2 && digits.bytes().all(|b| b.is_ascii_hexdigit())
+                });
+                let Some(digits) = digits else {
+                    return Err(invalid(position, "\\x takes two hex digits".to_string()));
+                };
+                bytes.push(u8::from_str_radix(digits, 16).unwrap());
+                chars.nth(1);
+            }
+            Some((_, other)) => {
+                return Err(invalid(position, format!("\\{} is not an escape", other)))
+            }
+            None => {
+                return Err(invalid(
+                    position,
+                    "a lone backslash ends the text".to_string(),
+                ))
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+/// `bytes` written with the escapes [`unescape`] reads, every byte that isn't printable UTF-8
+/// text escaped
+pub fn escape(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => text.push_str("\\\\"),
+                '\n' => text.push_str("\\n"),
+                '\t' => text.push_str("\\t"),
+                '\0' => text.push_str("\\0"),
+                c if c.is_control() => {
+                    let mut utf8 = [0; 4];
+                    for byte in c.encode_utf8(&mut utf8).bytes() {
+                        text.push_str(&format!("\\x{:02x}", byte));
+                    }
+                }
+                c => text.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            text.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+    text
+}
+
+/// Whether `text` holds control characters other than newlines and tabs, which could move the
+/// cursor or recolor the terminal it is printed to
+pub fn has_control_characters(text: &str) -> bool {
+    text.chars()
+        .any(|c| c.is_control() && !matches!(c, '\n' | '\t'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodings_roundtrip_and_point_at_bad_input() {
+        let bytes = [0x00, 0xff, 0x10, b'a'];
+        for encoding in [PayloadEncoding::Base64, PayloadEncoding::Hex] {
+            let text = encoding.encode(&bytes).unwrap();
+            assert_eq!(encoding.decode(&text).unwrap(), bytes, "{}", encoding);
+        }
+        assert_eq!(PayloadEncoding::Hex.encode(&bytes).unwrap(), "00ff1061");
+        assert_eq!(PayloadEncoding::Base64.decode("AP8QYQ").unwrap(), bytes);
+        assert_eq!(PayloadEncoding::Raw.encode(&bytes), None);
+        assert_eq!(PayloadEncoding::Raw.decode("é").unwrap(), "é".as_bytes());
+
+        let position = |encoding: PayloadEncoding, text| match encoding.decode(text) {
+            Err(PngSecretError::InvalidEncoding { position, .. }) => position,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(position(PayloadEncoding::Hex, "00fg"), 3);
+        assert_eq!(position(PayloadEncoding::Hex, "00f"), 3);
+        assert_eq!(position(PayloadEncoding::Base64, "AP8-QYQ="), 3);
+        assert_eq!(position(PayloadEncoding::Base64, "AP8QY"), 5);
+        assert_eq!(position(PayloadEncoding::Base64, "A=P8"), 1);
+    }
+
+    #[test]
+    fn escapes_roundtrip_through_an_image() {
+        let text = r"tab\there\nline\\slash\0nul\x7f\xff\x1B[2J é";
+        let bytes = unescape(text).unwrap();
+        assert_eq!(
+            bytes,
+            b"tab\there\nline\\slash\0nul\x7f\xff\x1b[2J \xc3\xa9".to_vec()
+        );
+        assert_eq!(
+            escape(&bytes),
+            r"tab\there\nline\\slash\0nul\x7f\xff\x1b[2J é"
+        );
+        assert_eq!(unescape(&escape(&bytes)).unwrap(), bytes);
+        assert_eq!(escape("\u{9b}".as_bytes()), r"\xc2\x9b");
+
+        let cover = image::RgbaImage::from_fn(16, 16, |x, y| {
+            image::Rgba([(x * 16) as u8, (y * 16) as u8, (x ^ y) as u8, 255])
+        });
+        let stego = pngsecret::embed(cover, &bytes).unwrap();
+        assert_eq!(pngsecret::extract(stego).unwrap(), bytes);
+
+        let position = |text| match unescape(text) {
+            Err(PngSecretError::InvalidEscape { position, .. }) => position,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(position(r"ab\q"), 2);
+        assert_eq!(position(r"é\r"), 2);
+        assert_eq!(position(r"\x4"), 0);
+        assert_eq!(position(r"a\xg0"), 1);
+        assert_eq!(position(r"\x"), 0);
+        assert_eq!(position("trailing\\"), 8);
+
+        assert!(has_control_characters("bell\x07"));
+        assert!(has_control_characters("\x1b[31mred"));
+        assert!(!has_control_characters("lines\nand\ttabs"));
+    }
+}
@@ -0,0 +1,696 @@
+use image::ColorType;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::analysis::Score;
+use crate::armor::ArmorError;
+use crate::artifacts::Artifact;
+use crate::bytesize::{self, ByteSizeError};
+use crate::encoding::PayloadEncoding;
+use crate::preflight::PreflightError;
+use crate::sniff::ContentType;
+
+/// Stable identifier of each kind of failure, for programs that wrap pngsecret
+///
+/// The string codes and exit codes are part of the interface and must not change once
+/// released. Both are defined in [`ErrorKind::table`] only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    Usage,
+    InvalidArgument,
+    InputUnreadable,
+    CapacityExceeded,
+    LimitExceeded,
+    NoMessage,
+    NotUtf8,
+    PreflightFailed,
+    OutputIsInput,
+    SaveFailed,
+    Io,
+    VerificationFailed,
+    ConcurrentModification,
+    NotConfirmed,
+    HookFailed,
+    OutputClosed,
+    PayloadCorrupted,
+    BatchIncomplete,
+}
+
+impl ErrorKind {
+    pub const ALL: [ErrorKind; 18] = [
+        ErrorKind::Usage,
+        ErrorKind::InvalidArgument,
+        ErrorKind::InputUnreadable,
+        ErrorKind::CapacityExceeded,
+        ErrorKind::LimitExceeded,
+        ErrorKind::NoMessage,
+        ErrorKind::NotUtf8,
+        ErrorKind::PreflightFailed,
+        ErrorKind::OutputIsInput,
+        ErrorKind::SaveFailed,
+        ErrorKind::Io,
+        ErrorKind::VerificationFailed,
+        ErrorKind::ConcurrentModification,
+        ErrorKind::NotConfirmed,
+        ErrorKind::HookFailed,
+        ErrorKind::OutputClosed,
+        ErrorKind::PayloadCorrupted,
+        ErrorKind::BatchIncomplete,
+    ];
+
+    /// The string code and the process exit code of the kind
+    const fn table(self) -> (&'static str, i32) {
+        match self {
+            ErrorKind::Usage => ("usage", 1),
+            ErrorKind::InvalidArgument => ("invalid_argument", 1),
+            ErrorKind::InputUnreadable => ("input_unreadable", 2),
+            ErrorKind::CapacityExceeded => ("capacity_exceeded", 3),
+            ErrorKind::LimitExceeded => ("limit_exceeded", 3),
+            ErrorKind::NoMessage => ("no_message", 4),
+            ErrorKind::VerificationFailed => ("verification_failed", 5),
+            ErrorKind::NotUtf8 => ("not_utf8", 6),
+            ErrorKind::PreflightFailed => ("preflight_failed", 7),
+            ErrorKind::OutputIsInput => ("output_is_input", 1),
+            ErrorKind::SaveFailed => ("save_failed", 8),
+            ErrorKind::Io => ("io", 8),
+            ErrorKind::ConcurrentModification => ("concurrent_modification", 9),
+            ErrorKind::NotConfirmed => ("not_confirmed", 10),
+            ErrorKind::HookFailed => ("hook_failed", 11),
+            ErrorKind::OutputClosed => ("output_closed", 12),
+            ErrorKind::PayloadCorrupted => ("payload_corrupted", 14),
+            ErrorKind::BatchIncomplete => ("batch_incomplete", 15),
+        }
+    }
+
+    pub const fn code(self) -> &'static str {
+        self.table().0
+    }
+
+    pub const fn exit_code(self) -> i32 {
+        self.table().1
+    }
+
+    /// Human readable listing of all exit codes, for `--help`
+    pub fn exit_code_help() -> String {
+        let mut help = String::from("EXIT CODES:\n    0    success\n");
+        let mut exit_codes: Vec<i32> = ErrorKind::ALL.iter().map(|k| k.exit_code()).collect();
+        exit_codes.sort_unstable();
+        exit_codes.dedup();
+        for exit_code in exit_codes {
+            let codes: Vec<&str> = ErrorKind::ALL
+                .iter()
+                .filter(|k| k.exit_code() == exit_code)
+                .map(|k| k.code())
+                .collect();
+            help.push_str(&format!("    {:<4} {}\n", exit_code, codes.join(", ")));
+        }
+        help
+    }
+}
+
+/// Everything that can make a pngsecret run fail
+#[derive(Debug)]
+pub enum PngSecretError {
+    InputUnreadable(PathBuf),
+    /// The input changed while it was read, even after retrying
+    ConcurrentModification(PathBuf),
+    /// The `--dearmor` input isn't a valid armored image
+    Armor(ArmorError),
+    InvalidSize {
+        flag: &'static str,
+        value: String,
+        source: ByteSizeError,
+    },
+    /// The output extension names a lossy format, which would destroy the payload
+    LossyOutput(PathBuf),
+    /// The output format can't store the cover's channels bit for bit
+    UnsupportedContainer {
+        container: &'static str,
+        color: ColorType,
+    },
+    /// The `--text` isn't valid in the `--payload-encoding`, from `position` on
+    InvalidEncoding {
+        encoding: PayloadEncoding,
+        position: usize,
+        reason: String,
+    },
+    /// The `--text` has a backslash `--escape` doesn't know at `position`
+    InvalidEscape {
+        position: usize,
+        reason: String,
+    },
+    /// The cover is wider, taller or larger than the output format holds
+    OutputTooLarge {
+        container: &'static str,
+        width: u32,
+        height: u32,
+        max_dimension: u32,
+        max_bytes: Option<u64>,
+    },
+    PayloadLimitExceeded {
+        size: u64,
+        limit: u64,
+    },
+    PayloadTooLarge {
+        capacity: usize,
+        requested: usize,
+    },
+    Preflight(PreflightError),
+    /// The cover's structure would expose the payload and --strict was given
+    UnsuitableCover(Vec<Artifact>),
+    /// The input is in a container refused as a cover, see [`crate::probe`], an animated WebP
+    /// with `frames` frames to pick from
+    UnsupportedCoverFormat {
+        path: PathBuf,
+        format: &'static str,
+        frames: Option<usize>,
+    },
+    /// The cover is grayscale with so few bits per sample that its lowest bit is no noise
+    UnsupportedCoverDepth {
+        depth: u8,
+    },
+    /// The stego image of a cover with `cover` would be saved with 8-bit channels instead
+    CoverConverted {
+        cover: &'static str,
+    },
+    /// The estimated detectability exceeds --max-detectability
+    TooDetectable {
+        score: Score,
+        limit: Score,
+    },
+    NoMessage,
+    /// The image holds records, but none of the `--name` given
+    NoRecord(String),
+    /// No message at the `--bits` depth, but a frame header at the `found` depth
+    WrongBits {
+        given: u8,
+        found: u8,
+    },
+    NotUtf8,
+    /// The message isn't text and would garble the terminal
+    BinaryPayload(ContentType),
+    SaveFailed(PathBuf),
+    /// The output path points at the input file
+    OutputIsInput(PathBuf),
+    /// Invalid combination of arguments
+    Usage(String),
+    /// An I/O failure outside of reading the input and saving the output, with context
+    Io(String, io::Error),
+    /// Some images of an archive sweep failed their checks
+    VerificationFailed {
+        failed: usize,
+        total: usize,
+    },
+    /// `sanitize` found pngsecret traces in its own output
+    TracesRemain(Vec<String>),
+    /// A receipt is malformed, altered, or doesn't match the file it is checked against
+    ReceiptInvalid(String),
+    /// A checksum sidecar is malformed or doesn't match the file or payload it is checked against
+    ChecksumMismatch(String),
+    /// A provenance stamp is unsigned or not signed with the expected key
+    ProvenanceUntrusted(String),
+    /// Two `--trace-indices` files disagree on where a payload bit went
+    TracesDiverged(String),
+    /// `demo` decoded other bytes than it embedded
+    DemoMismatch {
+        sent: usize,
+        received: usize,
+    },
+    /// Some inputs of `cat` had no readable message
+    IncompleteConcatenation {
+        failed: usize,
+        total: usize,
+    },
+    /// Some images of an `--input` directory failed
+    BatchIncomplete {
+        failed: usize,
+        total: usize,
+    },
+    /// Replacing existing files was declined, or couldn't be asked for without a terminal
+    NotConfirmed {
+        asked: bool,
+    },
+    /// The `--exec-on-success` command exited unsuccessfully, `status` is `None` on a signal
+    HookFailed {
+        command: String,
+        status: Option<i32>,
+    },
+    /// The reader of stdout went away before the payload was written and --strict was given
+    OutputClosed,
+    /// The message is compressed but doesn't inflate, or inflates past the limit
+    CorruptMessage,
+    /// The message doesn't match the checksum of its frame, or has none and --no-verify wasn't
+    /// given
+    PayloadCorrupted,
+    /// `--inspect` found no valid payload, for the reason inside
+    NoPayloadDetected(Box<PngSecretError>),
+    /// Block `block` of an error corrected message has more errors than --ecc corrects
+    Uncorrectable {
+        block: usize,
+    },
+}
+
+impl PngSecretError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            PngSecretError::NoPayloadDetected(reason) => reason.kind(),
+            PngSecretError::InputUnreadable(_)
+            | PngSecretError::Armor(_)
+            | PngSecretError::UnsupportedCoverFormat { .. } => ErrorKind::InputUnreadable,
+            PngSecretError::ConcurrentModification(_) => ErrorKind::ConcurrentModification,
+            PngSecretError::InvalidSize { .. }
+            | PngSecretError::LossyOutput(_)
+            | PngSecretError::UnsupportedContainer { .. }
+            | PngSecretError::OutputTooLarge { .. }
+            | PngSecretError::InvalidEncoding { .. }
+            | PngSecretError::InvalidEscape { .. } => ErrorKind::InvalidArgument,
+            PngSecretError::PayloadLimitExceeded { .. } => ErrorKind::LimitExceeded,
+            PngSecretError::PayloadTooLarge { .. } => ErrorKind::CapacityExceeded,
+            PngSecretError::Preflight(_)
+            | PngSecretError::UnsuitableCover(_)
+            | PngSecretError::UnsupportedCoverDepth { .. }
+            | PngSecretError::CoverConverted { .. }
+            | PngSecretError::TooDetectable { .. } => ErrorKind::PreflightFailed,
+            PngSecretError::NoMessage
+            | PngSecretError::NoRecord(_)
+            | PngSecretError::WrongBits { .. }
+            | PngSecretError::IncompleteConcatenation { .. } => ErrorKind::NoMessage,
+            PngSecretError::NotUtf8 | PngSecretError::BinaryPayload(_) => ErrorKind::NotUtf8,
+            PngSecretError::SaveFailed(_) => ErrorKind::SaveFailed,
+            PngSecretError::OutputIsInput(_) => ErrorKind::OutputIsInput,
+            PngSecretError::Usage(_) => ErrorKind::Usage,
+            PngSecretError::Io(..) => ErrorKind::Io,
+            PngSecretError::NotConfirmed { .. } => ErrorKind::NotConfirmed,
+            PngSecretError::HookFailed { .. } => ErrorKind::HookFailed,
+            PngSecretError::OutputClosed => ErrorKind::OutputClosed,
+            PngSecretError::PayloadCorrupted | PngSecretError::Uncorrectable { .. } => {
+                ErrorKind::PayloadCorrupted
+            }
+            PngSecretError::BatchIncomplete { .. } => ErrorKind::BatchIncomplete,
+            PngSecretError::VerificationFailed { .. }
+            | PngSecretError::CorruptMessage
+            | PngSecretError::ReceiptInvalid(_)
+            | PngSecretError::ChecksumMismatch(_)
+            | PngSecretError::ProvenanceUntrusted(_)
+            | PngSecretError::TracesDiverged(_)
+            | PngSecretError::DemoMismatch { .. }
+            | PngSecretError::TracesRemain(_) => ErrorKind::VerificationFailed,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        self.kind().exit_code()
+    }
+}
+
+impl fmt::Display for PngSecretError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PngSecretError::InputUnreadable(path) => {
+                write!(f, "The file {:?} couldn't be correctly read", path)
+            }
+            PngSecretError::ConcurrentModification(path) => write!(
+                f,
+                "The file {:?} kept changing while it was read, is another process writing it?",
+                path
+            ),
+            PngSecretError::Armor(e) => write!(f, "Invalid armored input: {}", e),
+            PngSecretError::InvalidSize {
+                flag,
+                value,
+                source,
+            } => write!(f, "Invalid --{} {:?}: {}", flag, value, source),
+            PngSecretError::LossyOutput(path) => write!(
+                f,
+                "{:?} would be saved in a lossy format, whose re-encoding rewrites the low bits \
+                 and destroys the payload. Use a .png, .bmp, .tif or .webp output",
+                path
+            ),
+            PngSecretError::UnsupportedContainer { container, color } => write!(
+                f,
+                "{} can't store this {:?} cover without changing its samples, save it as .png",
+                container, color
+            ),
+            PngSecretError::InvalidEncoding {
+                encoding,
+                position,
+                reason,
+            } => write!(
+                f,
+                "The --text isn't valid {} at position {}: {}",
+                encoding, position, reason
+            ),
+            PngSecretError::InvalidEscape { position, reason } => write!(
+                f,
+                "The --text has an invalid escape at position {}: {}",
+                position, reason
+            ),
+            PngSecretError::OutputTooLarge {
+                container,
+                width,
+                height,
+                max_dimension,
+                max_bytes,
+            } => {
+                write!(
+                    f,
+                    "The {}x{} cover doesn't fit into {}, which holds at most {} pixels per side",
+                    width, height, container, max_dimension
+                )?;
+                if let Some(max_bytes) = max_bytes {
+                    write!(f, " and {} bytes per file", max_bytes)?;
+                }
+                write!(f, "; crop the cover into tiles")?;
+                match *container {
+                    "PNG" => Ok(()),
+                    _ => write!(f, " or save it as .png"),
+                }
+            }
+            PngSecretError::PayloadLimitExceeded { size, limit } => write!(
+                f,
+                "The payload ({}) exceeds --max-payload ({})",
+                bytesize::format(*size),
+                bytesize::format(*limit)
+            ),
+            PngSecretError::PayloadTooLarge {
+                capacity,
+                requested,
+            } => write!(
+                f,
+                "You are writing more message ({}) than the image could support ({})!",
+                bytesize::format(*requested as u64),
+                bytesize::format(*capacity as u64)
+            ),
+            PngSecretError::Preflight(e) => write!(f, "{}", e),
+            PngSecretError::UnsuitableCover(artifacts) => {
+                let reasons: Vec<String> = artifacts.iter().map(|a| a.to_string()).collect();
+                write!(
+                    f,
+                    "Refusing to embed with --strict: {}; use a different cover",
+                    reasons.join("; ")
+                )
+            }
+            PngSecretError::UnsupportedCoverFormat {
+                path,
+                format,
+                frames,
+            } => {
+                write!(
+                    f,
+                    "{:?} is in the {} format, which pngsecret doesn't take as a cover; convert \
+                     it losslessly to PNG first",
+                    path, format
+                )?;
+                match frames {
+                    Some(0) | None => Ok(()),
+                    Some(frames) => write!(
+                        f,
+                        ", or pick one of its {} frames with --frame 0 to {}",
+                        frames,
+                        frames - 1
+                    ),
+                }
+            }
+            PngSecretError::UnsupportedCoverDepth { depth } => write!(
+                f,
+                "The cover has {} bit(s) per gray sample, so its lowest bit is a third of the \
+                 range or more and every change shows; convert it to 8 bits or use a cover with \
+                 4 or more",
+                depth
+            ),
+            PngSecretError::CoverConverted { cover } => write!(
+                f,
+                "The cover has {}, which the stego image can't keep, it would be saved with 8-bit \
+                 channels; pass --force-8bit to embed anyway",
+                cover
+            ),
+            PngSecretError::TooDetectable { score, limit } => write!(
+                f,
+                "The estimated detectability {} exceeds --max-detectability {}; embed less, \
+                 permute the order or use a noisier cover",
+                score, limit
+            ),
+            PngSecretError::NoMessage => write!(f, "This image doesn't have embedded message!"),
+            PngSecretError::NoPayloadDetected(reason) => {
+                write!(f, "No payload detected: {}", reason)
+            }
+            PngSecretError::NoRecord(name) => write!(
+                f,
+                "The image holds no record named {:?}, --list shows the ones it has",
+                name
+            ),
+            PngSecretError::WrongBits { given, found } => write!(
+                f,
+                "No message in the lowest {} bit(s) of each channel, but one was embedded in the \
+                 lowest {}; decode with --bits {}",
+                given, found, found
+            ),
+            PngSecretError::NotUtf8 => write!(f, "The message cannot printed as string!"),
+            PngSecretError::BinaryPayload(content) => write!(
+                f,
+                "The message looks like {}, save it with -o {} or write it to stdout with \
+                 --format raw",
+                content.description(),
+                content.suggested_name()
+            ),
+            PngSecretError::SaveFailed(path) => write!(f, "saving file failure {:?}", path),
+            PngSecretError::OutputIsInput(path) => {
+                write!(f, "Refusing to overwrite the input file {:?}", path)
+            }
+            PngSecretError::Usage(message) => write!(f, "{}", message),
+            PngSecretError::Io(context, e) => write!(f, "{}: {}", context, e),
+            PngSecretError::VerificationFailed { failed, total } => {
+                write!(f, "{} of {} images failed verification", failed, total)
+            }
+            PngSecretError::TracesRemain(traces) => write!(
+                f,
+                "Traces remain after sanitizing, the output was removed: {}",
+                traces.join(", ")
+            ),
+            PngSecretError::ReceiptInvalid(reason) => {
+                write!(f, "The receipt doesn't check out: {}", reason)
+            }
+            PngSecretError::ChecksumMismatch(reason) => {
+                write!(f, "The checksum sidecar doesn't check out: {}", reason)
+            }
+            PngSecretError::ProvenanceUntrusted(reason) => {
+                write!(f, "The provenance stamp can't be trusted: {}", reason)
+            }
+            PngSecretError::TracesDiverged(divergence) => write!(f, "{}", divergence),
+            PngSecretError::DemoMismatch { sent, received } => write!(
+                f,
+                "The demo embedded {} bytes but decoded {} other bytes, this build doesn't \
+                 round-trip payloads",
+                sent, received
+            ),
+            PngSecretError::IncompleteConcatenation { failed, total } => write!(
+                f,
+                "{} of {} images had no readable message, pass --skip-missing to leave out \
+                 images without one",
+                failed, total
+            ),
+            PngSecretError::BatchIncomplete { failed, total } => write!(
+                f,
+                "{} of {} images failed, the others were processed",
+                failed, total
+            ),
+            PngSecretError::NotConfirmed { asked: true } => {
+                write!(f, "Not confirmed, nothing was changed")
+            }
+            PngSecretError::NotConfirmed { asked: false } => write!(
+                f,
+                "Refusing to replace existing files without confirmation, there is no terminal \
+                 to ask on; pass --yes to proceed"
+            ),
+            PngSecretError::HookFailed { command, status } => match status {
+                Some(status) => write!(f, "The hook {} exited with status {}", command, status),
+                None => write!(f, "The hook {} was killed by a signal", command),
+            },
+            PngSecretError::OutputClosed => write!(
+                f,
+                "The payload was cut short, stdout was closed before all of it was written"
+            ),
+            PngSecretError::CorruptMessage => write!(
+                f,
+                "The compressed message is corrupt or expands past {} bytes",
+                pngsecret::compress::MAX_INFLATED_BYTES
+            ),
+            PngSecretError::PayloadCorrupted => write!(
+                f,
+                "The payload is corrupted or this is not a pngsecret image; images written \
+                 before payload checksums decode with --no-verify"
+            ),
+            PngSecretError::Uncorrectable { block } => write!(
+                f,
+                "The message is uncorrectable: block {} has more damaged bytes than its \
+                 error correction repairs",
+                block
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PngSecretError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PngSecretError::InvalidSize { source, .. } => Some(source),
+            PngSecretError::Preflight(e) => Some(e),
+            PngSecretError::Armor(e) => Some(e),
+            PngSecretError::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<PreflightError> for PngSecretError {
+    fn from(e: PreflightError) -> Self {
+        PngSecretError::Preflight(e)
+    }
+}
+
+impl From<pngsecret::Error> for PngSecretError {
+    fn from(e: pngsecret::Error) -> Self {
+        match e {
+            pngsecret::Error::PayloadTooLarge {
+                capacity,
+                requested,
+            } => PngSecretError::PayloadTooLarge {
+                capacity,
+                requested,
+            },
+            pngsecret::Error::NoMessage { .. } => PngSecretError::NoMessage,
+            pngsecret::Error::CorruptPayload => PngSecretError::CorruptMessage,
+            pngsecret::Error::PayloadCorrupted => PngSecretError::PayloadCorrupted,
+            pngsecret::Error::Uncorrectable { block } => PngSecretError::Uncorrectable { block },
+            e @ (pngsecret::Error::UnreadableImage
+            | pngsecret::Error::FieldTooLong { .. }
+            | pngsecret::Error::TooManyRecords { .. }
+            | pngsecret::Error::OffsetOutOfRange { .. }
+            | pngsecret::Error::TooManySegments { .. }
+            | pngsecret::Error::SegmentMissing { .. }
+            | pngsecret::Error::SegmentDuplicated { .. }
+            | pngsecret::Error::SegmentsMixed
+            | pngsecret::Error::NewerFormat { .. }) => PngSecretError::Usage(e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn codes_are_unique_and_mapped() {
+        let codes: HashSet<&str> = ErrorKind::ALL.iter().map(|k| k.code()).collect();
+        assert_eq!(codes.len(), ErrorKind::ALL.len());
+        for kind in ErrorKind::ALL {
+            assert!(
+                kind.exit_code() > 0,
+                "{:?} must not exit with success",
+                kind
+            );
+            assert!(kind
+                .code()
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'));
+            assert!(ErrorKind::exit_code_help().contains(kind.code()));
+        }
+    }
+
+    #[test]
+    fn every_error_has_a_listed_kind() {
+        let errors = [
+            PngSecretError::InputUnreadable(PathBuf::new()),
+            PngSecretError::ConcurrentModification(PathBuf::new()),
+            PngSecretError::Armor(ArmorError::MissingHeader),
+            PngSecretError::InvalidSize {
+                flag: "max-payload",
+                value: String::new(),
+                source: ByteSizeError::Empty,
+            },
+            PngSecretError::LossyOutput(PathBuf::new()),
+            PngSecretError::UnsupportedContainer {
+                container: "BMP",
+                color: ColorType::L8,
+            },
+            PngSecretError::InvalidEncoding {
+                encoding: PayloadEncoding::Hex,
+                position: 1,
+                reason: String::new(),
+            },
+            PngSecretError::InvalidEscape {
+                position: 1,
+                reason: String::new(),
+            },
+            PngSecretError::OutputTooLarge {
+                container: "WebP",
+                width: 20_000,
+                height: 1,
+                max_dimension: 16_384,
+                max_bytes: None,
+            },
+            PngSecretError::PayloadLimitExceeded { size: 2, limit: 1 },
+            PngSecretError::PayloadTooLarge {
+                capacity: 1,
+                requested: 2,
+            },
+            PngSecretError::Preflight(PreflightError::OutputDirMissing(PathBuf::new())),
+            PngSecretError::UnsuitableCover(Vec::new()),
+            PngSecretError::UnsupportedCoverFormat {
+                path: PathBuf::new(),
+                format: "HEIF/HEIC",
+                frames: None,
+            },
+            PngSecretError::UnsupportedCoverDepth { depth: 1 },
+            PngSecretError::CoverConverted { cover: "a palette" },
+            PngSecretError::TooDetectable {
+                score: Score(1.0),
+                limit: Score(0.5),
+            },
+            PngSecretError::NoMessage,
+            PngSecretError::NoRecord(String::new()),
+            PngSecretError::WrongBits { given: 1, found: 2 },
+            PngSecretError::NotUtf8,
+            PngSecretError::BinaryPayload(ContentType::Pdf),
+            PngSecretError::SaveFailed(PathBuf::new()),
+            PngSecretError::OutputIsInput(PathBuf::new()),
+            PngSecretError::Usage(String::new()),
+            PngSecretError::Io(String::new(), io::Error::other("test")),
+            PngSecretError::VerificationFailed {
+                failed: 1,
+                total: 2,
+            },
+            PngSecretError::ReceiptInvalid(String::new()),
+            PngSecretError::ChecksumMismatch(String::new()),
+            PngSecretError::ProvenanceUntrusted(String::new()),
+            PngSecretError::TracesDiverged(String::new()),
+            PngSecretError::DemoMismatch {
+                sent: 2,
+                received: 1,
+            },
+            PngSecretError::TracesRemain(Vec::new()),
+            PngSecretError::IncompleteConcatenation {
+                failed: 1,
+                total: 2,
+            },
+            PngSecretError::BatchIncomplete {
+                failed: 1,
+                total: 2,
+            },
+            PngSecretError::NotConfirmed { asked: false },
+            PngSecretError::HookFailed {
+                command: String::new(),
+                status: Some(1),
+            },
+            PngSecretError::OutputClosed,
+            PngSecretError::CorruptMessage,
+            PngSecretError::PayloadCorrupted,
+            PngSecretError::Uncorrectable { block: 0 },
+            PngSecretError::NoPayloadDetected(Box::new(PngSecretError::NoMessage)),
+        ];
+        let kinds: HashSet<ErrorKind> = errors.iter().map(PngSecretError::kind).collect();
+        assert_eq!(kinds, ErrorKind::ALL.into_iter().collect());
+    }
+}
@@ -0,0 +1,264 @@
+//! Deterministic catalog of covers and stego images for docs, bug reports and tests
+//!
+//! Every fixture is produced by the regular encode path, so regenerating the catalog after a
+//! format change keeps it honest. Each stego image gets a JSON sidecar with the arguments that
+//! produced it and the payload decode must return. Fixtures replace the low bits, [`REPLACE`],
+//! since matching draws its directions from a random seed and the catalog must come out the same
+//! every time.
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+use crate::error::PngSecretError;
+use crate::{encode, fsguard, pngio, Opt};
+
+/// Kinds of covers, kept tiny so the whole catalog stays a few kilobytes
+#[derive(Debug, Clone, Copy)]
+pub enum Cover {
+    Gradient { width: u32, height: u32 },
+    Noise { width: u32, height: u32 },
+}
+
+impl Cover {
+    pub fn render(&self) -> RgbaImage {
+        match *self {
+            Cover::Gradient { width, height } => RgbaImage::from_fn(width, height, |x, y| {
+                Rgba([(x * 8) as u8, (y * 8) as u8, 128, 255])
+            }),
+            Cover::Noise { width, height } => {
+                let mut state: u32 = 0x2545_f491;
+                let mut next = move || {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    state as u8
+                };
+                RgbaImage::from_fn(width, height, |_, _| Rgba([next(), next(), next(), 255]))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub cover: Cover,
+    pub payload: &'static str,
+    /// Arguments besides input, output and text that encode needs
+    pub encode_args: &'static [&'static str],
+    /// Arguments besides input that decode needs
+    pub decode_args: &'static [&'static str],
+}
+
+/// The sidecar written next to every stego image
+#[derive(Debug, Serialize)]
+pub struct Sidecar<'a> {
+    pub name: &'a str,
+    pub description: &'a str,
+    pub cover: String,
+    pub stego: String,
+    pub encode_args: Vec<&'a str>,
+    pub decode_args: &'a [&'a str],
+    pub payload: &'a str,
+}
+
+/// Arguments every fixture is encoded with, ahead of its own
+pub const REPLACE: &[&str] = &["--embedding", "replace"];
+
+const PERMUTE: &[&str] = &["--permute", "blocks", "--block-size", "64", "--seed", "42"];
+
+pub fn catalog() -> Vec<Fixture> {
+    vec![
+        Fixture {
+            name: "legacy-gradient",
+            description: "Legacy NUL-terminated payload in a smooth gradient",
+            cover: Cover::Gradient {
+                width: 32,
+                height: 32,
+            },
+            payload: "Hello World",
+            encode_args: &["--legacy"],
+            decode_args: &["--legacy"],
+        },
+        Fixture {
+            name: "legacy-unicode-noise",
+            description: "Multi-byte UTF-8 payload in a noise cover",
+            cover: Cover::Noise {
+                width: 32,
+                height: 32,
+            },
+            payload: "héllo, 秘密 😀",
+            encode_args: &["--legacy"],
+            decode_args: &["--legacy"],
+        },
+        Fixture {
+            name: "legacy-full-capacity",
+            description: "Payload filling an 8x8 cover exactly",
+            cover: Cover::Noise {
+                width: 8,
+                height: 8,
+            },
+            payload: "0123456789abcdefghijklmnopqrstu",
+            encode_args: &["--legacy"],
+            decode_args: &["--legacy"],
+        },
+        Fixture {
+            name: "framed-gradient",
+            description: "Length-prefixed payload in a smooth gradient",
+            cover: Cover::Gradient {
+                width: 32,
+                height: 32,
+            },
+            payload: "Hello World",
+            encode_args: &[],
+            decode_args: &[],
+        },
+        Fixture {
+            name: "framed-full-capacity",
+            description: "Length-prefixed payload filling an 8x8 cover exactly",
+            cover: Cover::Noise {
+                width: 8,
+                height: 8,
+            },
+            payload: "0123456789abcdefghi",
+            encode_args: &[],
+            decode_args: &[],
+        },
+        Fixture {
+            name: "chunk-notice",
+            description: "Pixel payload plus a visible tEXt notice chunk",
+            cover: Cover::Gradient {
+                width: 32,
+                height: 32,
+            },
+            payload: "behind the notice",
+            encode_args: &[
+                "--also-chunk-text",
+                "This image contains a pngsecret payload",
+            ],
+            decode_args: &[],
+        },
+        Fixture {
+            name: "permuted-blocks",
+            description: "Payload scattered by a keyed block permutation",
+            cover: Cover::Noise {
+                width: 32,
+                height: 32,
+            },
+            payload: "scattered",
+            encode_args: PERMUTE,
+            decode_args: PERMUTE,
+        },
+    ]
+}
+
+impl Fixture {
+    /// Write the cover, the stego image and the sidecar into `dir`, returning the stego path
+    pub fn write(&self, dir: &Path) -> Result<PathBuf, PngSecretError> {
+        let cover_path = dir.join(format!("{}.cover.png", self.name));
+        let stego_path = dir.join(format!("{}.png", self.name));
+        let cover = self.cover.render();
+        pngio::save_with_text(&cover, &cover_path, &[])
+            .map_err(|_| PngSecretError::SaveFailed(cover_path.clone()))?;
+
+        let mut args = vec![
+            "pngsecret".into(),
+            "-e".into(),
+            "-i".into(),
+            cover_path.clone().into_os_string(),
+            "-o".into(),
+            stego_path.clone().into_os_string(),
+            "--text".into(),
+            self.payload.into(),
+        ];
+        let encode_args: Vec<&str> = REPLACE.iter().chain(self.encode_args).copied().collect();
+        args.extend(encode_args.iter().map(Into::into));
+        // The cover has no ancillary chunks to carry over
+        encode::encode(
+            &Opt::from_iter(args),
+            DynamicImage::ImageRgba8(cover),
+            &[],
+            None,
+        )?;
+
+        let sidecar = Sidecar {
+            name: self.name,
+            description: self.description,
+            cover: cover_path
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned(),
+            stego: stego_path
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned(),
+            encode_args,
+            decode_args: self.decode_args,
+            payload: self.payload,
+        };
+        let sidecar_path = dir.join(format!("{}.json", self.name));
+        let json = serde_json::to_string_pretty(&sidecar).expect("sidecar serializes");
+        fsguard::write(&sidecar_path, (json + "\n").as_bytes())
+            .map_err(|e| PngSecretError::Io(format!("Couldn't write {:?}", sidecar_path), e))?;
+        Ok(stego_path)
+    }
+}
+
+/// Generate the whole catalog into `dir`, creating it if needed
+pub fn generate(dir: &Path) -> Result<Vec<PathBuf>, PngSecretError> {
+    fsguard::create_dir_all(dir)
+        .map_err(|e| PngSecretError::Io(format!("Couldn't create {:?}", dir), e))?;
+    catalog().iter().map(|fixture| fixture.write(dir)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NaiveDecoder, PngSecretReader};
+    use pngsecret::format::Framing;
+    use std::fs;
+
+    /// Options decode needs for the stego image at `stego`
+    fn decode_opt(fixture: &Fixture, stego: &Path) -> Opt {
+        let mut args = vec!["pngsecret".into(), "-s".into(), "-i".into(), stego.into()];
+        args.extend(fixture.decode_args.iter().map(Into::into));
+        Opt::from_iter::<Vec<std::ffi::OsString>>(args)
+    }
+
+    #[test]
+    fn every_fixture_decodes_to_its_payload() {
+        let dir = tempfile::tempdir().unwrap();
+        let stegos = generate(dir.path()).unwrap();
+        for (fixture, stego) in catalog().iter().zip(stegos) {
+            let opt = decode_opt(fixture, &stego);
+            let img = image::open(&stego).unwrap().into_rgba8();
+            let reader = PngSecretReader::new(img, Box::new(NaiveDecoder::new()))
+                .with_order(opt.order.order().unwrap());
+            let message = match opt.legacy {
+                true => reader.with_framing(Framing::Terminated),
+                false => reader,
+            }
+            .read_image()
+            .unwrap();
+            assert_eq!(message, fixture.payload.as_bytes(), "{}", fixture.name);
+        }
+    }
+
+    #[test]
+    fn catalog_is_deterministic() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        for (a, b) in generate(first.path())
+            .unwrap()
+            .into_iter()
+            .zip(generate(second.path()).unwrap())
+        {
+            assert_eq!(fs::read(a).unwrap(), fs::read(b).unwrap());
+        }
+    }
+}
@@ -0,0 +1,696 @@
+//! Constants and capacity math of the pixel formats, in one place
+//!
+//! A payload is written into the low 1 to [`MAX_BITS`] bits of each subpixel of its [`Slot`], most
+//! significant bit first, in one of two [`Framing`]s. The legacy format ends it with a single
+//! [`TERMINATOR`] byte, so it can't carry NUL bytes. The framed format starts with a
+//! [`FrameHeader`] instead and carries anything:
+//!
+//! ```text
+//! | magic | length | checksum | header | flags | depth | feature fields | payload |
+//! |   2   |   4    |    4     |   1    |   1   |   1   |                |    n    |
+//! ```
+//!
+//! The magic begins with the terminator, so a legacy reader sees no message rather than garbage,
+//! and its second byte is the version of the layout. The length is big-endian and counts the
+//! payload, the checksum is the CRC-32 of the length and everything after the checksum, `header`
+//! counts the bytes of the whole header. The flags tell whether the payload is compressed, error
+//! corrected or truncated and which channels hold the frame, the low nibble of `depth` how many
+//! bits of each subpixel do. Feature fields follow for flags that need them, in the order of the
+//! flags: a truncated payload records the big-endian length it was cut from in
+//! [`ORIGINAL_LENGTH_BYTES`].
+//!
+//! Every layout from this one on starts with the same [`PREFIX_BYTES`], so frames of the
+//! [`RESERVED_FRAME_MAGICS`] are told apart from noise by their checksum and refused as written by
+//! a newer pngsecret rather than read as a legacy message. Frames written before headers
+//! described the payload start with [`UNDESCRIBED_FRAME_MAGIC`], a length and a checksum of the
+//! payload alone, and before checksums with [`UNCHECKED_FRAME_MAGIC`] and a length; both are
+//! still read. These functions only need the image dimensions, never the pixels, and the encoder
+//! goes through them too, so they can't drift from what it actually does.
+
+use serde::{Serialize, Serializer};
+use std::ops::RangeInclusive;
+
+use crate::order::Slot;
+
+/// Byte ending every legacy payload, which is why those can't contain NUL bytes
+pub const TERMINATOR: u8 = 0;
+/// First bytes of a framed payload, followed by the rest of its [`FrameHeader`]
+pub const FRAME_MAGIC: [u8; 2] = [TERMINATOR, 0xA1];
+/// First bytes of a framed payload with a checksum of the payload alone, as written before
+/// headers described the payload
+pub const UNDESCRIBED_FRAME_MAGIC: [u8; 2] = [TERMINATOR, 0xA0];
+/// First bytes of a framed payload without a checksum, as written before it existed
+pub const UNCHECKED_FRAME_MAGIC: [u8; 2] = [TERMINATOR, 0x9F];
+/// Second magic bytes of frame layouts after [`FRAME_MAGIC`], which this version can't read
+pub const RESERVED_FRAME_MAGICS: RangeInclusive<u8> = 0xA2..=0xAF;
+/// Bytes of the big-endian payload length after the magic
+pub const LENGTH_BYTES: usize = 4;
+/// Bytes of the big-endian CRC-32 after the length
+pub const CHECKSUM_BYTES: usize = 4;
+/// Bytes every frame layout from version 3 on starts with: magic, length, checksum and the size
+/// of the header, which the checksum covers along with the length and everything after it
+pub const PREFIX_BYTES: usize = FRAME_MAGIC.len() + LENGTH_BYTES + CHECKSUM_BYTES + 1;
+/// Bytes of a header of this version without feature fields
+pub const HEADER_BYTES: usize = PREFIX_BYTES + 2;
+/// Bytes of the length a truncated payload was cut from, the feature field of its flag
+pub const ORIGINAL_LENGTH_BYTES: usize = 8;
+/// Bytes of a header of this version with every feature field
+pub const MAX_HEADER_BYTES: usize = HEADER_BYTES + ORIGINAL_LENGTH_BYTES;
+/// Payload bits carried by each subpixel of the slot unless `--bits` says otherwise
+pub const DEFAULT_BITS: u8 = 1;
+/// Most payload bits a subpixel carries, past that the changes stop looking like noise
+pub const MAX_BITS: u8 = 4;
+
+/// The payload was deflated by [`crate::compress`]
+const COMPRESSED: u8 = 1;
+/// The frame is held in the blocks of [`crate::ecc`]
+const ECC: u8 = 1 << 1;
+/// The frame lies in [`Slot::Rgb`]
+const SKIP_ALPHA: u8 = 1 << 2;
+/// The frame lies in [`Slot::Alpha`]
+const ALPHA_ONLY: u8 = 1 << 3;
+/// The payload is the start of a longer one, whose length follows the depth
+const TRUNCATED: u8 = 1 << 4;
+/// Flags this version knows, a header with any other is corrupt
+const KNOWN_FLAGS: u8 = COMPRESSED | ECC | SKIP_ALPHA | ALPHA_ONLY | TRUNCATED;
+
+/// How the end of a payload is marked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Framing {
+    /// Payload followed by a [`TERMINATOR`], the format before framing existed
+    Terminated,
+    /// A [`FrameHeader`] and the payload
+    #[default]
+    LengthPrefixed,
+}
+
+impl Framing {
+    pub const ALL: [Framing; 2] = [Framing::LengthPrefixed, Framing::Terminated];
+
+    /// Name of the format in `--version -v` and `doctor`
+    pub fn name(self) -> &'static str {
+        match self {
+            Framing::Terminated => "legacy",
+            Framing::LengthPrefixed => "framed",
+        }
+    }
+
+    /// Bytes the framing adds to a payload of any length
+    pub fn overhead_bytes(self) -> u64 {
+        match self {
+            Framing::Terminated => 1,
+            Framing::LengthPrefixed => HEADER_BYTES as u64,
+        }
+    }
+
+    /// Longest payload the framing can describe at all
+    fn max_payload_bytes(self) -> u64 {
+        match self {
+            Framing::Terminated => u64::MAX,
+            Framing::LengthPrefixed => u32::MAX as u64,
+        }
+    }
+
+    /// `payload` with the framing applied, ready to embed, described as one-bit deep in every
+    /// subpixel
+    ///
+    /// Panics for framed payloads past 4 GiB, which no capacity admits.
+    pub fn frame(self, payload: &[u8]) -> Vec<u8> {
+        self.frame_with(payload, Features::default())
+    }
+
+    /// Like `frame`, describing the payload with `features` in the header
+    pub fn frame_with(self, payload: &[u8], features: Features) -> Vec<u8> {
+        let header = match self {
+            Framing::Terminated => Vec::new(),
+            Framing::LengthPrefixed => FrameHeader::new(payload, features).serialize(),
+        };
+        let mut framed = Vec::with_capacity(header.len() + payload.len() + 1);
+        framed.extend_from_slice(&header);
+        framed.extend_from_slice(payload);
+        if self == Framing::Terminated {
+            framed.push(TERMINATOR);
+        }
+        framed
+    }
+}
+
+/// The version of the frame layout `magic` starts, 1 for [`UNCHECKED_FRAME_MAGIC`] up to
+/// [`FRAME_VERSION`] for [`FRAME_MAGIC`] and past it for the [`RESERVED_FRAME_MAGICS`], `None`
+/// unless it is a frame magic
+pub fn frame_version(magic: &[u8]) -> Option<u8> {
+    match magic {
+        [TERMINATOR, second, ..]
+            if (UNCHECKED_FRAME_MAGIC[1]..=*RESERVED_FRAME_MAGICS.end()).contains(second) =>
+        {
+            Some(second - UNCHECKED_FRAME_MAGIC[1] + 1)
+        }
+        _ => None,
+    }
+}
+
+/// The frame layout this version writes and the newest it reads
+pub const FRAME_VERSION: u8 = FRAME_MAGIC[1] - UNCHECKED_FRAME_MAGIC[1] + 1;
+
+/// What a header of this version records about its payload beyond length and checksum
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Features {
+    /// Deflated by [`crate::compress`]
+    pub compressed: bool,
+    /// Held in the blocks of [`crate::ecc`], whose header comes first
+    pub ecc: bool,
+    /// Channels holding the frame
+    pub slot: Slot,
+    /// Low bits of each subpixel holding the frame
+    pub bits: u8,
+    /// Bytes of the payload `--truncate-to-fit` cut this one from, `None` unless it did
+    pub original_length: Option<u64>,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Features {
+            compressed: false,
+            ecc: false,
+            slot: Slot::All,
+            bits: DEFAULT_BITS,
+            original_length: None,
+        }
+    }
+}
+
+impl Features {
+    fn flags(&self) -> u8 {
+        let slot = match self.slot {
+            Slot::All => 0,
+            Slot::Rgb => SKIP_ALPHA,
+            Slot::Alpha => ALPHA_ONLY,
+        };
+        let flag = |set: bool, flag: u8| if set { flag } else { 0 };
+        flag(self.compressed, COMPRESSED)
+            | flag(self.ecc, ECC)
+            | slot
+            | flag(self.original_length.is_some(), TRUNCATED)
+    }
+
+    /// Bytes of the feature fields after the fixed part of the header
+    fn field_bytes(&self) -> usize {
+        match self.original_length {
+            Some(_) => ORIGINAL_LENGTH_BYTES,
+            None => 0,
+        }
+    }
+}
+
+/// What the first bytes of a framed payload announce
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    /// Version of the layout, see [`frame_version`]
+    pub version: u8,
+    /// Payload bytes after the header
+    pub length: u64,
+    /// CRC-32 of the header after it and the payload, of the payload alone in version 2 and
+    /// `None` in version 1
+    pub checksum: Option<u32>,
+    /// What the header describes, `None` before version 3
+    pub features: Option<Features>,
+}
+
+impl FrameHeader {
+    /// The header this version writes before `payload`
+    ///
+    /// Panics for payloads past 4 GiB, which no capacity admits.
+    pub fn new(payload: &[u8], features: Features) -> Self {
+        assert!(
+            u32::try_from(payload.len()).is_ok(),
+            "framed payloads fit in u32"
+        );
+        let mut header = FrameHeader {
+            version: FRAME_VERSION,
+            length: payload.len() as u64,
+            checksum: Some(0),
+            features: Some(features),
+        };
+        header.checksum = Some(covered_checksum(&header.serialize(), payload));
+        header
+    }
+
+    /// Bytes of the header itself
+    pub fn bytes(&self) -> usize {
+        match (self.checksum, self.features) {
+            (_, Some(features)) => HEADER_BYTES + features.field_bytes(),
+            (Some(_), None) => UNDESCRIBED_FRAME_MAGIC.len() + LENGTH_BYTES + CHECKSUM_BYTES,
+            (None, None) => UNCHECKED_FRAME_MAGIC.len() + LENGTH_BYTES,
+        }
+    }
+
+    /// The header as it is embedded, [`FrameHeader::bytes`] long
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(self.bytes());
+        header.extend_from_slice(&[TERMINATOR, UNCHECKED_FRAME_MAGIC[1] + self.version - 1]);
+        header.extend_from_slice(&(self.length as u32).to_be_bytes());
+        if let Some(checksum) = self.checksum {
+            header.extend_from_slice(&checksum.to_be_bytes());
+        }
+        if let Some(features) = self.features {
+            header.extend_from_slice(&[self.bytes() as u8, features.flags(), features.bits]);
+            if let Some(original_length) = features.original_length {
+                header.extend_from_slice(&original_length.to_be_bytes());
+            }
+        }
+        header
+    }
+
+    /// The header at the start of a slot holding `slot_bytes` encoded bytes, if the first bytes
+    /// are one of a version this one reads whose payload fits
+    ///
+    /// `header` needs [`FrameHeader::bytes`] bytes, [`MAX_HEADER_BYTES`] do for any header of a
+    /// version this one reads. Unknown flags, depths past [`MAX_BITS`] and a header size that doesn't
+    /// match the flags make a header corrupt, which parses to `None` like noise does.
+    pub fn parse(header: &[u8], slot_bytes: u64) -> Option<FrameHeader> {
+        let version = frame_version(header).filter(|&version| version <= FRAME_VERSION)?;
+        let mut fields = &header[FRAME_MAGIC.len()..];
+        let length = u32::from_be_bytes(take(&mut fields)?) as u64;
+        let checksum = match version {
+            1 => None,
+            _ => Some(u32::from_be_bytes(take(&mut fields)?)),
+        };
+        let features = match version {
+            1 | 2 => None,
+            _ => {
+                let [size, flags, bits] = take(&mut fields)?;
+                if flags & !KNOWN_FLAGS != 0 || !(1..=MAX_BITS).contains(&bits) {
+                    return None;
+                }
+                let slot = match (flags & SKIP_ALPHA != 0, flags & ALPHA_ONLY != 0) {
+                    (false, false) => Slot::All,
+                    (true, false) => Slot::Rgb,
+                    (false, true) => Slot::Alpha,
+                    (true, true) => return None,
+                };
+                let features = Features {
+                    compressed: flags & COMPRESSED != 0,
+                    ecc: flags & ECC != 0,
+                    slot,
+                    bits,
+                    original_length: match flags & TRUNCATED {
+                        0 => None,
+                        _ => Some(u64::from_be_bytes(take(&mut fields)?)),
+                    },
+                };
+                if size as usize != HEADER_BYTES + features.field_bytes() {
+                    return None;
+                }
+                Some(features)
+            }
+        };
+        let header = FrameHeader {
+            version,
+            length,
+            checksum,
+            features,
+        };
+        let room = slot_bytes.checked_sub(header.bytes() as u64)?;
+        (length <= room).then_some(header)
+    }
+
+    /// Whether `payload` matches the checksum, `false` without one
+    pub fn verifies(&self, payload: &[u8]) -> bool {
+        match (self.features, self.checksum) {
+            (_, None) => false,
+            (None, Some(checksum)) => checksum == crc32fast::hash(payload),
+            (Some(_), Some(checksum)) => checksum == covered_checksum(&self.serialize(), payload),
+        }
+    }
+}
+
+/// The first `N` bytes of `rest`, which moves past them
+fn take<const N: usize>(rest: &mut &[u8]) -> Option<[u8; N]> {
+    let taken = rest.get(..N)?.try_into().ok()?;
+    *rest = &rest[N..];
+    Some(taken)
+}
+
+/// The CRC-32 of what the checksum of a frame from version 3 on covers, the length, the `header`
+/// past the checksum and the `payload`
+fn covered_checksum(header: &[u8], payload: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&header[FRAME_MAGIC.len()..FRAME_MAGIC.len() + LENGTH_BYTES]);
+    hasher.update(&header[PREFIX_BYTES - 1..]);
+    hasher.update(payload);
+    hasher.finalize()
+}
+
+/// What the [`PREFIX_BYTES`] every frame from version 3 on starts with announce, all there is to
+/// know about layouts newer than this one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramePrefix {
+    pub version: u8,
+    /// Payload bytes after the header
+    pub length: u64,
+    pub checksum: u32,
+    /// Bytes of the header, feature fields included
+    pub header_bytes: usize,
+}
+
+impl FramePrefix {
+    /// The prefix at the start of a slot holding `slot_bytes` encoded bytes, if the first
+    /// [`PREFIX_BYTES`] bytes are one of version 3 or later whose frame fits
+    pub fn parse(prefix: &[u8], slot_bytes: u64) -> Option<FramePrefix> {
+        let version = frame_version(prefix).filter(|&version| version >= 3)?;
+        let mut fields = &prefix[FRAME_MAGIC.len()..];
+        let length = u32::from_be_bytes(take(&mut fields)?) as u64;
+        let checksum = u32::from_be_bytes(take(&mut fields)?);
+        let [header_bytes] = take(&mut fields)?;
+        let prefix = FramePrefix {
+            version,
+            length,
+            checksum,
+            header_bytes: header_bytes as usize,
+        };
+        (prefix.header_bytes >= PREFIX_BYTES && prefix.frame_bytes() <= slot_bytes)
+            .then_some(prefix)
+    }
+
+    /// Bytes of the whole frame, header and payload
+    pub fn frame_bytes(&self) -> u64 {
+        self.header_bytes as u64 + self.length
+    }
+
+    /// Whether `frame`, the [`FramePrefix::frame_bytes`] bytes this prefix starts, matches the
+    /// checksum
+    pub fn verifies(&self, frame: &[u8]) -> bool {
+        let (header, payload) = frame.split_at(self.header_bytes.min(frame.len()));
+        frame.len() as u64 == self.frame_bytes()
+            && covered_checksum(header, payload) == self.checksum
+    }
+}
+
+/// The version of the frame of a layout newer than this one at the start of `data`, if its
+/// checksum holds up
+pub fn newer_frame_version(data: &[u8]) -> Option<u8> {
+    let prefix = FramePrefix::parse(data, data.len() as u64)?;
+    let frame = &data[..prefix.frame_bytes() as usize];
+    (prefix.version > FRAME_VERSION && prefix.verifies(frame)).then_some(prefix.version)
+}
+
+impl Serialize for Framing {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+/// Number of RGBA subpixels in a `width` x `height` image
+///
+/// Counted in u64 since strips like 200000x6000 pass 2^32 subpixels while both dimensions are
+/// far from any limit.
+pub fn subpixel_count(width: u32, height: u32) -> u64 {
+    (width as u64 * height as u64).saturating_mul(4)
+}
+
+/// Number of encoded bytes, payload and overhead, that `subpixels` subpixels of a slot hold when
+/// each carries `bits` bits
+pub fn slot_bytes(subpixels: u64, bits: u8) -> u64 {
+    subpixels.saturating_mul(bits as u64) / 8
+}
+
+/// Number of payload bytes that fit into `slot` of a `width` x `height` RGBA image with `framing`,
+/// `bits` bits per subpixel
+pub fn capacity_bytes(width: u32, height: u32, slot: Slot, framing: Framing, bits: u8) -> u64 {
+    let subpixels = match slot {
+        Slot::All => subpixel_count(width, height),
+        Slot::Rgb => width as u64 * height as u64 * 3,
+        Slot::Alpha => width as u64 * height as u64,
+    };
+    slot_capacity(subpixels, framing, bits)
+}
+
+/// Number of payload bytes that fit into `subpixels` subpixels of a slot with `framing`, `bits`
+/// bits per subpixel
+pub fn slot_capacity(subpixels: u64, framing: Framing, bits: u8) -> u64 {
+    frame_capacity(slot_bytes(subpixels, bits), framing)
+}
+
+/// Number of payload bytes that `framed` bytes hold with `framing`
+pub fn frame_capacity(framed: u64, framing: Framing) -> u64 {
+    framed
+        .saturating_sub(framing.overhead_bytes())
+        .min(framing.max_payload_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::SubpixelOrder;
+    use crate::{NaiveEncoder, PngSecretWriter};
+    use image::RgbaImage;
+
+    #[test]
+    fn frame_magics_carry_their_version() {
+        assert_eq!(frame_version(&UNCHECKED_FRAME_MAGIC), Some(1));
+        assert_eq!(frame_version(&UNDESCRIBED_FRAME_MAGIC), Some(2));
+        assert_eq!(frame_version(&FRAME_MAGIC), Some(FRAME_VERSION));
+        assert_eq!(FRAME_VERSION, 3);
+        assert_eq!(frame_version(&[TERMINATOR, 0xA2, 7]), Some(4));
+        assert_eq!(frame_version(&[TERMINATOR, 0xAF]), Some(17));
+        for magic in [
+            &[TERMINATOR, 0xB0][..],
+            &[TERMINATOR, 0x9E],
+            &[1, 0xA1],
+            &[TERMINATOR],
+            &[],
+        ] {
+            assert_eq!(frame_version(magic), None, "{:?}", magic);
+        }
+        assert_eq!(frame_version(&crate::ecc::MAGIC), None);
+    }
+
+    #[test]
+    fn newer_frames_are_only_told_by_a_checksum_that_holds() {
+        // A frame of this version with the magic of the next, the prefix is the same
+        let mut newer = Framing::LengthPrefixed.frame(b"from the future");
+        newer[1] = *RESERVED_FRAME_MAGICS.start();
+        assert_eq!(FrameHeader::parse(&newer, 100), None);
+        assert_eq!(newer_frame_version(&newer), Some(4));
+        assert_eq!(
+            newer_frame_version(&[&newer[..], b"rest"].concat()),
+            Some(4)
+        );
+        let prefix = FramePrefix::parse(&newer, 100).unwrap();
+        assert_eq!((prefix.header_bytes, prefix.length), (HEADER_BYTES, 15));
+
+        // The magic alone, e.g. noise after an empty legacy message, is no frame
+        let mut corrupt = newer.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert_eq!(newer_frame_version(&corrupt), None);
+        assert_eq!(newer_frame_version(&newer[..newer.len() - 1]), None);
+        assert_eq!(newer_frame_version(&[TERMINATOR, 0xA2, 0xFF, 0xFF]), None);
+        let mut short = newer.clone();
+        short[PREFIX_BYTES - 1] = PREFIX_BYTES as u8 - 1;
+        assert_eq!(FramePrefix::parse(&short, 100), None);
+        assert_eq!(
+            newer_frame_version(&Framing::LengthPrefixed.frame(b"now")),
+            None
+        );
+    }
+
+    #[test]
+    fn frame_headers_roundtrip_and_must_fit() {
+        let framed = Framing::LengthPrefixed.frame(b"a\0b");
+        let header = FrameHeader::parse(&framed, 16).unwrap();
+        assert_eq!(
+            framed[..HEADER_BYTES],
+            [0x00, 0xA1, 0, 0, 0, 3, 0x3C, 0xE9, 0xA7, 0x33, 13, 0, 1]
+        );
+        assert_eq!(header.serialize(), framed[..HEADER_BYTES]);
+        assert_eq!(header, FrameHeader::new(b"a\0b", Features::default()));
+        assert!(header.verifies(b"a\0b"));
+        assert!(!header.verifies(b"a\0c"));
+        assert_eq!(FrameHeader::parse(&framed, 15), None);
+        assert_eq!(
+            FrameHeader::parse(&Framing::Terminated.frame(b""), 16),
+            None
+        );
+        assert_eq!(Framing::Terminated.frame(b"ab"), b"ab\0");
+
+        let features = Features {
+            compressed: true,
+            ecc: true,
+            slot: Slot::Alpha,
+            bits: 3,
+            original_length: None,
+        };
+        let header = FrameHeader::new(b"payload", features);
+        let serialized = header.serialize();
+        assert_eq!(serialized[PREFIX_BYTES..], [0b1011, 3]);
+        assert_eq!(FrameHeader::parse(&serialized, 20), Some(header));
+        assert_eq!(
+            Framing::LengthPrefixed.frame_with(b"payload", features)[..13],
+            serialized
+        );
+
+        // A truncated payload records the length it was cut from after the depth
+        let features = Features {
+            original_length: Some(0x1_0000_0001),
+            ..Features::default()
+        };
+        let header = FrameHeader::new(b"pay", features);
+        let serialized = header.serialize();
+        assert_eq!(serialized.len(), MAX_HEADER_BYTES);
+        assert_eq!(
+            serialized[PREFIX_BYTES - 1..],
+            [21, 1 << 4, 1, 0, 0, 0, 1, 0, 0, 0, 1]
+        );
+        assert_eq!(FrameHeader::parse(&serialized, 24), Some(header));
+        assert_eq!(FrameHeader::parse(&serialized, 23), None);
+
+        // Frames from before headers described the payload, and before the checksum
+        let undescribed = [
+            0x00, 0xA0, 0, 0, 0, 3, 0x15, 0xE8, 0x78, 0x71, b'a', 0, b'b',
+        ];
+        let header = FrameHeader::parse(&undescribed, 13).unwrap();
+        assert_eq!(
+            (header.version, header.bytes(), header.features),
+            (2, 10, None)
+        );
+        assert!(header.verifies(b"a\0b"));
+        assert_eq!(header.serialize(), undescribed[..10]);
+        let unchecked = [0x00, 0x9F, 0, 0, 0, 3, b'a', 0, b'b'];
+        let header = FrameHeader::parse(&unchecked[..6], 9).unwrap();
+        assert_eq!(
+            (header.length, header.checksum, header.bytes()),
+            (3, None, 6)
+        );
+        assert!(!header.verifies(b"a\0b"));
+        assert_eq!(FrameHeader::parse(&unchecked, 8), None);
+    }
+
+    #[test]
+    fn truncated_and_corrupt_headers_never_parse() {
+        let features = [
+            Features::default(),
+            Features {
+                compressed: true,
+                ecc: false,
+                slot: Slot::Rgb,
+                bits: MAX_BITS,
+                original_length: None,
+            },
+            Features {
+                original_length: Some(1000),
+                ..Features::default()
+            },
+        ];
+        for features in features {
+            let framed = Framing::LengthPrefixed.frame_with(b"payload", features);
+            let header = FrameHeader::parse(&framed, 100).unwrap();
+            for cut in 0..header.bytes() {
+                assert_eq!(FrameHeader::parse(&framed[..cut], 100), None, "{}", cut);
+            }
+            // Every flipped bit fails the parse or the checksum
+            for bit in 0..header.bytes() * 8 {
+                let mut corrupt = framed.clone();
+                corrupt[bit / 8] ^= 0x80 >> (bit % 8);
+                let (head, payload) = corrupt.split_at(header.bytes());
+                if let Some(parsed) = FrameHeader::parse(head, 100) {
+                    let payload = payload.get(..parsed.length as usize).unwrap_or(payload);
+                    assert!(!parsed.verifies(payload), "bit {} of {:?}", bit, features);
+                }
+            }
+        }
+
+        let valid = Framing::LengthPrefixed.frame(b"x");
+        let with = |at: usize, value: u8| {
+            let mut corrupt = valid.clone();
+            corrupt[at] = value;
+            FrameHeader::parse(&corrupt, 100)
+        };
+        let (size, flags, depth) = (PREFIX_BYTES - 1, PREFIX_BYTES, PREFIX_BYTES + 1);
+        assert_eq!(with(flags, 1 << 7), None);
+        assert_eq!(with(flags, SKIP_ALPHA | ALPHA_ONLY), None);
+        assert_eq!(with(depth, 0), None);
+        assert_eq!(with(depth, MAX_BITS + 1), None);
+        assert_eq!(with(depth, 0x11), None);
+        assert_eq!(with(size, HEADER_BYTES as u8 + 1), None);
+        assert_eq!(with(size, PREFIX_BYTES as u8), None);
+        assert!(with(depth, MAX_BITS).is_some());
+        assert!(with(flags, COMPRESSED).is_some());
+        assert_eq!(with(flags, TRUNCATED), None);
+    }
+
+    #[test]
+    fn subpixel_math_past_32_bits() {
+        assert_eq!(subpixel_count(200_000, 6_000), 4_800_000_000);
+        assert!(subpixel_count(200_000, 6_000) > u32::MAX as u64);
+        let legacy = Framing::Terminated;
+        assert_eq!(
+            capacity_bytes(200_000, 6_000, Slot::All, legacy, 1),
+            600_000_000 - 1
+        );
+        assert_eq!(subpixel_count(u32::MAX, u32::MAX), u64::MAX);
+        assert_eq!(capacity_bytes(0, 200_000, Slot::All, legacy, 1), 0);
+        assert_eq!(
+            capacity_bytes(200_000, 6_000, Slot::Rgb, legacy, 1),
+            450_000_000 - 1
+        );
+        let framed = Framing::LengthPrefixed;
+        assert_eq!(
+            capacity_bytes(200_000, 6_000, Slot::Rgb, framed, 1),
+            450_000_000 - 13
+        );
+        assert_eq!(
+            capacity_bytes(200_000, 60_000, Slot::All, framed, 1),
+            u32::MAX as u64
+        );
+        assert_eq!(capacity_bytes(1, 1, Slot::All, framed, 1), 0);
+        assert_eq!(capacity_bytes(4, 2, Slot::All, framed, 4), 3);
+        assert_eq!(
+            capacity_bytes(200_000, 6_000, Slot::Rgb, framed, 3),
+            1_350_000_000 - 13
+        );
+        assert_eq!(capacity_bytes(1, 3, Slot::Alpha, legacy, 3), 0);
+    }
+
+    #[test]
+    fn encoder_fills_exactly_the_capacity() {
+        let orders = [
+            SubpixelOrder::Sequential,
+            SubpixelOrder::Blocks {
+                block_size: 7,
+                seed: 1,
+            },
+        ];
+        for (width, height) in [(8, 6), (1, 49), (7, 7), (13, 7), (16, 16)] {
+            for slot in [Slot::All, Slot::Rgb, Slot::Alpha] {
+                for (order, framing, bits) in orders.into_iter().flat_map(|order| {
+                    Framing::ALL.into_iter().flat_map(move |framing| {
+                        (1..=MAX_BITS).map(move |bits| (order, framing, bits))
+                    })
+                }) {
+                    let capacity = capacity_bytes(width, height, slot, framing, bits) as usize;
+                    let embed = |len: usize| {
+                        let mut writer = PngSecretWriter::new(
+                            RgbaImage::new(width, height),
+                            Box::new(NaiveEncoder::with_framing(framing).with_bits(bits)),
+                        )
+                        .with_order(order)
+                        .with_slot(slot);
+                        assert_eq!(writer.capacity(), capacity);
+                        writer.encoder.encode(&vec![b'x'; len]);
+                        writer.embed().is_ok()
+                    };
+                    let case = (width, height, slot, order, framing, bits);
+                    let subpixels = slot.subpixels_in(width as usize * height as usize * 4, 4);
+                    if slot_bytes(subpixels as u64, bits) < framing.overhead_bytes() {
+                        // Not even the framing fits
+                        assert_eq!(capacity, 0, "{:?}", case);
+                        assert!(!embed(0), "{:?}", case);
+                        continue;
+                    }
+                    assert!(embed(capacity), "{:?}", case);
+                    assert!(!embed(capacity + 1), "{:?}", case);
+                }
+            }
+        }
+    }
+}
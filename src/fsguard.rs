@@ -0,0 +1,331 @@
+//! Every file the crate writes, creates or deletes goes through here so that read-only runs can
+//! be enforced in one place
+//!
+//! Once [`set_read_only`] is called, write operations fail with `PermissionDenied`. In debug
+//! builds they panic instead, so a feature that writes during a read-only sweep is caught by the
+//! tests rather than by the write-protected medium. Reads may also go through libraries like
+//! `image`, since they can't violate the guard.
+//!
+//! Temp files all come from [`temp_file_in`]: owner-only, with unpredictable names and removed
+//! when dropped. The live ones are also registered so that [`remove_temp_files`] can clean up
+//! after a panic, which aborts without dropping anything in release builds.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+static TEMP_FILES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Refuse every write for the rest of the process
+pub fn set_read_only() {
+    READ_ONLY.store(true, Ordering::SeqCst);
+}
+
+fn ensure_writable(path: &Path) -> io::Result<()> {
+    if !READ_ONLY.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    debug_assert!(false, "write to {:?} attempted in read-only mode", path);
+    Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("refusing to write {:?} in read-only mode", path),
+    ))
+}
+
+pub fn open(path: &Path) -> io::Result<File> {
+    File::open(path)
+}
+
+/// Create `path` for writing, failing if it exists
+pub fn create_new(path: &Path) -> io::Result<File> {
+    ensure_writable(path)?;
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+/// Open `path` for appending, creating it if needed
+pub fn append(path: &Path) -> io::Result<File> {
+    ensure_writable(path)?;
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+pub fn write(path: &Path, contents: &[u8]) -> io::Result<()> {
+    ensure_writable(path)?;
+    fs::write(path, contents)
+}
+
+/// Replace `path` with `contents` through a temp file next to it, so readers see either the old
+/// or the new file and never a partial one
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut staged = stage(path)?;
+    staged.write_all(contents)?;
+    staged.commit()
+}
+
+/// Start writing `path` through a temp file in its directory, which [`Staged::commit`] renames
+/// over it
+///
+/// Targets that exist and aren't regular files, like `/dev/stdout` or a FIFO, are written in
+/// place since a rename would replace them rather than write to them.
+pub fn stage(path: &Path) -> io::Result<Staged> {
+    ensure_writable(path)?;
+    let existing = fs::metadata(path).ok();
+    let file = match existing {
+        Some(metadata) if !metadata.is_file() => StagedFile::Direct(File::create(path)?),
+        _ => {
+            let dir = match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            StagedFile::Temp(temp_file_in(dir, ".pngsecret-staged-")?)
+        }
+    };
+    Ok(Staged {
+        file,
+        path: path.to_path_buf(),
+    })
+}
+
+/// A file being written by [`stage`], removed on drop unless committed
+pub struct Staged {
+    file: StagedFile,
+    path: PathBuf,
+}
+
+enum StagedFile {
+    Temp(TempFile),
+    Direct(File),
+}
+
+impl Staged {
+    pub fn as_file_mut(&mut self) -> &mut File {
+        match &mut self.file {
+            StagedFile::Temp(temp) => temp.inner.as_file_mut(),
+            StagedFile::Direct(file) => file,
+        }
+    }
+
+    /// Sync the contents and move them to the target
+    ///
+    /// A replaced target keeps its permissions. A new one gets those of a file created in its
+    /// place, `0o666` less the umask, the temp file only being owner-only until the rename.
+    pub fn commit(mut self) -> io::Result<()> {
+        self.as_file_mut().sync_all()?;
+        let StagedFile::Temp(temp) = self.file else {
+            return Ok(());
+        };
+        match fs::metadata(&self.path) {
+            Ok(metadata) => temp
+                .inner
+                .as_file()
+                .set_permissions(metadata.permissions())?,
+            #[cfg(unix)]
+            Err(_) => {
+                use std::os::unix::fs::PermissionsExt;
+                temp.inner
+                    .as_file()
+                    .set_permissions(fs::Permissions::from_mode(0o666 & !umask()))?
+            }
+            #[cfg(not(unix))]
+            Err(_) => {}
+        }
+        temp.persist(&self.path)
+    }
+}
+
+impl Write for Staged {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.as_file_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.as_file_mut().flush()
+    }
+}
+
+/// A temp file from [`temp_file_in`], removed when dropped unless kept or persisted
+pub struct TempFile {
+    inner: tempfile::NamedTempFile,
+    _registration: Registration,
+}
+
+impl TempFile {
+    pub fn path(&self) -> &Path {
+        self.inner.path()
+    }
+
+    /// Leave the file in place and return its path
+    pub fn keep(self) -> io::Result<PathBuf> {
+        let (_, path) = self.inner.keep().map_err(|e| e.error)?;
+        Ok(path)
+    }
+
+    fn persist(self, target: &Path) -> io::Result<()> {
+        self.inner.persist(target).map_err(|e| e.error)?;
+        Ok(())
+    }
+}
+
+impl Write for TempFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Entry of a live temp file in the registry, dropped after the file itself
+struct Registration(PathBuf);
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        lock_temp_files().retain(|registered| *registered != self.0);
+    }
+}
+
+/// The umask of the process, read once
+///
+/// It can only be read by setting it, so it is put back right away and cached rather than read
+/// again while other threads may be creating files.
+#[cfg(unix)]
+fn umask() -> u32 {
+    static UMASK: std::sync::OnceLock<u32> = std::sync::OnceLock::new();
+    *UMASK.get_or_init(|| {
+        // SAFETY: umask can't fail, the previous mask is restored on the next line
+        let mask = unsafe { libc::umask(0o077) };
+        unsafe { libc::umask(mask) };
+        mask as u32
+    })
+}
+
+/// Create a temp file in `dir` only the current user can access, removed when dropped
+pub fn temp_file_in(dir: &Path, prefix: &str) -> io::Result<TempFile> {
+    ensure_writable(dir)?;
+    let inner = tempfile::Builder::new().prefix(prefix).tempfile_in(dir)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        inner
+            .as_file()
+            .set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    lock_temp_files().push(inner.path().to_path_buf());
+    Ok(TempFile {
+        _registration: Registration(inner.path().to_path_buf()),
+        inner,
+    })
+}
+
+/// Create a temp file in the system temp directory, see [`temp_file_in`]
+pub fn temp_file(prefix: &str) -> io::Result<TempFile> {
+    temp_file_in(&std::env::temp_dir(), prefix)
+}
+
+/// Create a directory in the system temp directory only the current user can access, removed
+/// with everything in it when dropped
+pub fn temp_dir(prefix: &str) -> io::Result<tempfile::TempDir> {
+    let parent = std::env::temp_dir();
+    ensure_writable(&parent)?;
+    tempfile::Builder::new().prefix(prefix).tempdir_in(parent)
+}
+
+/// Remove every temp file still alive, for the panic hook
+pub fn remove_temp_files() {
+    for path in lock_temp_files().drain(..) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+fn lock_temp_files() -> std::sync::MutexGuard<'static, Vec<PathBuf>> {
+    // A panic while holding the lock must not stop the cleanup
+    TEMP_FILES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+pub fn create_dir_all(path: &Path) -> io::Result<()> {
+    ensure_writable(path)?;
+    fs::create_dir_all(path)
+}
+
+pub fn remove_file(path: &Path) -> io::Result<()> {
+    ensure_writable(path)?;
+    fs::remove_file(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(dir: &Path) -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect()
+    }
+
+    #[test]
+    fn staged_files_are_only_left_once_committed() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target.bin");
+
+        let mut staged = stage(&target).unwrap();
+        staged.write_all(b"abandoned").unwrap();
+        let [temp] = entries(dir.path()).try_into().unwrap();
+        assert!(lock_temp_files().contains(&temp));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&temp).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        drop(staged);
+        assert!(entries(dir.path()).is_empty());
+        assert!(!lock_temp_files().contains(&temp));
+
+        write_atomic(&target, b"committed").unwrap();
+        assert_eq!(entries(dir.path()), std::slice::from_ref(&target));
+        assert_eq!(fs::read(&target).unwrap(), b"committed");
+    }
+
+    #[test]
+    fn failed_commits_remove_the_temp_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("occupied");
+        fs::create_dir(&target).unwrap();
+        fs::write(target.join("inside"), b"").unwrap();
+        assert!(write_atomic(&target, b"never lands").is_err());
+        assert_eq!(entries(dir.path()), std::slice::from_ref(&target));
+
+        let missing = dir.path().join("missing").join("target.bin");
+        assert!(stage(&missing).is_err());
+        assert_eq!(entries(dir.path()), [target]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replaced_files_keep_their_permissions_new_ones_follow_the_umask() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("shared.png");
+        fs::write(&target, b"old").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o644)).unwrap();
+        write_atomic(&target, b"new").unwrap();
+        let mode = fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o644);
+
+        // Like a file created in its place, whatever the umask of the test run
+        let created = dir.path().join("created.png");
+        fs::write(&created, b"new").unwrap();
+        let fresh = dir.path().join("fresh.png");
+        write_atomic(&fresh, b"new").unwrap();
+        let mode = fs::metadata(&fresh).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o666 & !umask());
+        assert_eq!(mode, fs::metadata(&created).unwrap().permissions().mode());
+    }
+}
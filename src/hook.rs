@@ -0,0 +1,177 @@
+//! `--exec-on-success` hands every extracted payload to another program
+//!
+//! The payload is written to a temp file only the current user can read, the command runs with
+//! `{payload_path}` and `{source}` filled in, and the file is removed afterwards unless
+//! `--keep-temp` is given. A command starting with `[` is a JSON array and runs as that argv
+//! without a shell. Anything else runs through `sh -c` (`cmd /C` on Windows); there the
+//! placeholders become positional parameters instead of being pasted into the script, so paths
+//! never need quoting. The hook sees the environment of pngsecret and nothing more.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::PngSecretError;
+use crate::fsguard;
+
+const PAYLOAD_PATH: &str = "{payload_path}";
+const SOURCE: &str = "{source}";
+
+/// A parsed `--exec-on-success` command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Hook {
+    /// Run through the shell
+    Shell(String),
+    /// Run directly, placeholders substituted in every argument
+    Argv(Vec<String>),
+}
+
+impl std::str::FromStr for Hook {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.trim_start().starts_with('[') {
+            return match s.trim() {
+                "" => Err("the command is empty".to_string()),
+                _ => Ok(Hook::Shell(s.to_string())),
+            };
+        }
+        let argv: Vec<String> =
+            serde_json::from_str(s).map_err(|e| format!("not a JSON array of strings: {}", e))?;
+        match argv.first() {
+            Some(program) if !program.is_empty() => Ok(Hook::Argv(argv)),
+            _ => Err("the argv array needs a program".to_string()),
+        }
+    }
+}
+
+impl Hook {
+    fn command(&self, payload_path: &Path, source: &Path) -> Command {
+        match self {
+            Hook::Argv(argv) => {
+                let substitute = |arg: &String| {
+                    arg.replace(PAYLOAD_PATH, &payload_path.to_string_lossy())
+                        .replace(SOURCE, &source.to_string_lossy())
+                };
+                let mut command = Command::new(substitute(&argv[0]));
+                command.args(argv[1..].iter().map(substitute));
+                command
+            }
+            #[cfg(not(windows))]
+            Hook::Shell(script) => {
+                let script = script
+                    .replace(PAYLOAD_PATH, "\"$1\"")
+                    .replace(SOURCE, "\"$2\"");
+                let mut command = Command::new("sh");
+                command
+                    .arg("-c")
+                    .arg(script)
+                    .arg("pngsecret-hook")
+                    .arg(payload_path)
+                    .arg(source);
+                command
+            }
+            #[cfg(windows)]
+            Hook::Shell(script) => {
+                let quote = |path: &Path| format!("\"{}\"", path.display());
+                let script = script
+                    .replace(PAYLOAD_PATH, &quote(payload_path))
+                    .replace(SOURCE, &quote(source));
+                let mut command = Command::new("cmd");
+                command.arg("/C").arg(script);
+                command
+            }
+        }
+    }
+
+    /// Write `payload` to a private temp file and run the hook on it
+    ///
+    /// Returns the kept temp file with `keep_temp`, which is removed otherwise, also on failure.
+    pub fn run(
+        &self,
+        payload: &[u8],
+        source: &Path,
+        keep_temp: bool,
+    ) -> Result<Option<PathBuf>, PngSecretError> {
+        let io = |e| PngSecretError::Io("Couldn't write the payload for the hook".to_string(), e);
+        let mut temp = fsguard::temp_file("pngsecret-payload-").map_err(io)?;
+        temp.write_all(payload)
+            .and_then(|_| temp.flush())
+            .map_err(io)?;
+        let status = self
+            .command(temp.path(), source)
+            .status()
+            .map_err(|e| PngSecretError::Io(format!("Couldn't run the hook {}", self), e))?;
+        let kept = match keep_temp {
+            true => Some(temp.keep().map_err(io)?),
+            false => None,
+        };
+        if !status.success() {
+            return Err(PngSecretError::HookFailed {
+                command: self.to_string(),
+                status: status.code(),
+            });
+        }
+        Ok(kept)
+    }
+}
+
+impl std::fmt::Display for Hook {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Hook::Shell(script) => write!(f, "{:?}", script),
+            Hook::Argv(argv) => write!(f, "{:?}", argv),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_shell_and_argv_forms() {
+        assert_eq!(
+            "cat {payload_path}".parse(),
+            Ok(Hook::Shell("cat {payload_path}".to_string()))
+        );
+        assert_eq!(
+            r#"["cp", "{payload_path}", "out/{source}.bin"]"#.parse(),
+            Ok(Hook::Argv(vec![
+                "cp".to_string(),
+                "{payload_path}".to_string(),
+                "out/{source}.bin".to_string()
+            ]))
+        );
+        assert!(" ".parse::<Hook>().is_err());
+        assert!("[]".parse::<Hook>().is_err());
+        assert!(r#"["cp", 1]"#.parse::<Hook>().is_err());
+    }
+
+    #[test]
+    fn argv_placeholders_are_substituted_per_argument() {
+        let hook: Hook = r#"["tool", "--in={payload_path}", "{source}"]"#.parse().unwrap();
+        let command = hook.command(Path::new("/tmp/p x"), Path::new("a b.png"));
+        assert_eq!(command.get_program(), "tool");
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, ["--in=/tmp/p x", "a b.png"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_placeholders_become_positional_parameters() {
+        let hook: Hook = "cp {payload_path} {source}.copy".parse().unwrap();
+        let command = hook.command(Path::new("/tmp/p"), Path::new("$(reboot).png"));
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(
+            args,
+            [
+                "-c",
+                "cp \"$1\" \"$2\".copy",
+                "pngsecret-hook",
+                "/tmp/p",
+                "$(reboot).png"
+            ]
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Failure injection for testing integrations against the errors pngsecret reports, compiled in
+//! only with the `failure-injection` feature and never in release builds
+//!
+//! `PNGSECRET_INJECT_FAILURE=<n>:<code>` makes the nth encode, decode or `cat` input of the
+//! process fail with `code`, one of `capacity_exceeded`, `limit_exceeded` or `io`.
+//! The injected error is the one the organic failure returns, so its exit code, `kind` and
+//! message shape can't be told apart; only the numbers it carries are zero. Without the feature
+//! [`check`] does nothing and the variable is ignored.
+
+use crate::error::PngSecretError;
+
+#[cfg(all(feature = "failure-injection", not(debug_assertions)))]
+compile_error!("the failure-injection feature is for test builds only");
+
+/// Environment variable holding `<n>:<code>`
+#[cfg(feature = "failure-injection")]
+pub const VARIABLE: &str = "PNGSECRET_INJECT_FAILURE";
+
+#[cfg(feature = "failure-injection")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Failure {
+    CapacityExceeded,
+    LimitExceeded,
+    Io,
+}
+
+#[cfg(feature = "failure-injection")]
+impl Failure {
+    fn parse(code: &str) -> Option<Self> {
+        match code {
+            "capacity_exceeded" => Some(Failure::CapacityExceeded),
+            "limit_exceeded" => Some(Failure::LimitExceeded),
+            "io" => Some(Failure::Io),
+            _ => None,
+        }
+    }
+
+    fn error(self) -> PngSecretError {
+        match self {
+            Failure::CapacityExceeded => PngSecretError::PayloadTooLarge {
+                capacity: 0,
+                requested: 0,
+            },
+            Failure::LimitExceeded => PngSecretError::PayloadLimitExceeded { size: 0, limit: 0 },
+            Failure::Io => PngSecretError::Io(
+                "Couldn't complete the operation".to_string(),
+                std::io::Error::other("injected failure"),
+            ),
+        }
+    }
+}
+
+/// `<n>:<code>`, with `n` counting from 1
+#[cfg(feature = "failure-injection")]
+fn parse(spec: &str) -> Result<(u64, Failure), PngSecretError> {
+    let usage = || {
+        PngSecretError::Usage(format!(
+            "{} must be <n>:<code>, with a code among capacity_exceeded, limit_exceeded \
+             and io, got {:?}",
+            VARIABLE, spec
+        ))
+    };
+    let (n, code) = spec.split_once(':').ok_or_else(usage)?;
+    let n = n.parse().ok().filter(|&n| n > 0).ok_or_else(usage)?;
+    Ok((n, Failure::parse(code).ok_or_else(usage)?))
+}
+
+/// Count one operation and fail it if it is the one `PNGSECRET_INJECT_FAILURE` names
+#[cfg(feature = "failure-injection")]
+pub fn check() -> Result<(), PngSecretError> {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static OPERATIONS: AtomicU64 = AtomicU64::new(0);
+    let count = OPERATIONS.fetch_add(1, Ordering::SeqCst) + 1;
+    let Ok(spec) = std::env::var(VARIABLE) else {
+        return Ok(());
+    };
+    match parse(&spec)? {
+        (n, failure) if n == count => Err(failure.error()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(feature = "failure-injection"))]
+pub fn check() -> Result<(), PngSecretError> {
+    Ok(())
+}
+
+#[cfg(all(test, feature = "failure-injection"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn specs_name_an_operation_and_a_code() {
+        assert_eq!(parse("3:io").unwrap(), (3, Failure::Io));
+        assert_eq!(
+            parse("1:limit_exceeded").unwrap(),
+            (1, Failure::LimitExceeded)
+        );
+        for spec in ["0:io", "io", "2:teapot", ":io", "x:io"] {
+            assert!(
+                matches!(parse(spec), Err(PngSecretError::Usage(_))),
+                "{}",
+                spec
+            );
+        }
+    }
+}
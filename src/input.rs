@@ -0,0 +1,155 @@
+//! Reading input images that other processes may be rewriting at the same time
+//!
+//! A scanner fanning out decodes can hit a file while a watcher rewrites it, which used to show
+//! up as confusing decode errors. The file is read in one go and its size, modification time and
+//! first block are compared before and after, so a concurrent write is reported as such and can
+//! be retried.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::error::PngSecretError;
+use crate::fsguard;
+
+/// Bytes at the start of the file hashed to detect rewrites that keep size and mtime
+const HEAD_BLOCK: usize = 4096;
+/// Delay before the first retry, doubled for each further one
+pub const RETRY_BACKOFF: Duration = Duration::from_millis(50);
+
+#[derive(Debug)]
+enum ReadError {
+    Unreadable,
+    /// The file changed between the start and the end of the read
+    Modified,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Snapshot {
+    len: u64,
+    modified: Option<SystemTime>,
+    head: u64,
+}
+
+fn head_hash(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&bytes[..bytes.len().min(HEAD_BLOCK)]);
+    hasher.finish()
+}
+
+fn snapshot(path: &Path) -> io::Result<Snapshot> {
+    let file = open_shared(path)?;
+    let mut head = Vec::with_capacity(HEAD_BLOCK);
+    (&file).take(HEAD_BLOCK as u64).read_to_end(&mut head)?;
+    let metadata = file.metadata()?;
+    Ok(Snapshot {
+        len: metadata.len(),
+        modified: metadata.modified().ok(),
+        head: head_hash(&head),
+    })
+}
+
+/// Open for reading while letting other processes read, write and rename the file
+fn open_shared(path: &Path) -> io::Result<File> {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        const FILE_SHARE_ALL: u32 = 0x1 | 0x2 | 0x4;
+        std::fs::OpenOptions::new()
+            .read(true)
+            .share_mode(FILE_SHARE_ALL)
+            .open(path)
+    }
+    #[cfg(not(windows))]
+    fsguard::open(path)
+}
+
+/// Read the whole file, calling `between` after the read and before the second check
+fn read_checked(path: &Path, between: impl FnOnce()) -> Result<Vec<u8>, ReadError> {
+    let before = snapshot(path).map_err(|_| ReadError::Unreadable)?;
+    let mut bytes = Vec::new();
+    open_shared(path)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|_| ReadError::Unreadable)?;
+    between();
+    // A file replaced or removed in the meantime counts as modified, not unreadable
+    let after = snapshot(path).map_err(|_| ReadError::Modified)?;
+    if before != after || after.len != bytes.len() as u64 || after.head != head_hash(&bytes) {
+        return Err(ReadError::Modified);
+    }
+    Ok(bytes)
+}
+
+/// Read `path`, retrying with exponential backoff up to `retries` times while it is being
+/// modified
+pub fn read_stable(path: &Path, retries: u32) -> Result<Vec<u8>, PngSecretError> {
+    let mut backoff = RETRY_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        match read_checked(path, || {}) {
+            Ok(bytes) => return Ok(bytes),
+            Err(ReadError::Unreadable) => {
+                return Err(PngSecretError::InputUnreadable(path.to_path_buf()))
+            }
+            Err(ReadError::Modified) if attempt < retries => {
+                std::thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+            Err(ReadError::Modified) => {
+                return Err(PngSecretError::ConcurrentModification(path.to_path_buf()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn stable_file_reads_fully() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("input.png");
+        fs::write(&path, vec![7u8; 10_000]).unwrap();
+        assert_eq!(read_checked(&path, || {}).unwrap(), vec![7u8; 10_000]);
+        assert_eq!(read_stable(&path, 0).unwrap().len(), 10_000);
+    }
+
+    #[test]
+    fn rewrite_from_another_thread_is_detected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("input.png");
+        fs::write(&path, b"first version").unwrap();
+        let writer_path = path.clone();
+        let result = read_checked(&path, || {
+            std::thread::spawn(move || fs::write(writer_path, b"second version!").unwrap())
+                .join()
+                .unwrap();
+        });
+        assert!(matches!(result, Err(ReadError::Modified)));
+
+        // Same size, so only the head hash can tell
+        let writer_path = path.clone();
+        fs::write(&path, b"aaaa").unwrap();
+        let result = read_checked(&path, || {
+            std::thread::spawn(move || fs::write(writer_path, b"bbbb").unwrap())
+                .join()
+                .unwrap();
+        });
+        assert!(matches!(result, Err(ReadError::Modified)));
+    }
+
+    #[test]
+    fn missing_file_is_unreadable_not_modified() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            read_stable(&dir.path().join("missing.png"), 3),
+            Err(PngSecretError::InputUnreadable(_))
+        ));
+    }
+}
@@ -0,0 +1,95 @@
+//! Channel layouts of decoded covers
+//!
+//! Payloads are defined on straight (non-premultiplied) 8-bit samples, so every cover goes through
+//! [`pngsecret::carrier::to_8bit`] before a bit is read or written. `image` never hands out
+//! premultiplied alpha. Some decoders have been seen handing out RGBA buffers in another channel
+//! order; [`recover`] retries the read under the common permutations when the RGBA reading yields
+//! nothing sensible.
+
+use image::RgbaImage;
+
+/// A channel order other than RGBA that a buffer labelled RGBA may actually be in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    Bgra,
+    Argb,
+    Abgr,
+}
+
+/// Layouts tried by [`recover`], in order
+pub const ALTERNATIVES: [Layout; 3] = [Layout::Bgra, Layout::Argb, Layout::Abgr];
+/// Shortest message accepted from a permuted layout, shorter text shows up by chance
+const MIN_RECOVERED_LEN: usize = 4;
+
+impl Layout {
+    /// Position in the buffer of the R, G, B and A channel of a pixel
+    fn positions(&self) -> [usize; 4] {
+        match self {
+            Layout::Bgra => [2, 1, 0, 3],
+            Layout::Argb => [1, 2, 3, 0],
+            Layout::Abgr => [3, 2, 1, 0],
+        }
+    }
+}
+
+/// Reorder `img`, stored in `layout` but labelled RGBA, to real RGBA
+pub fn reinterpret(img: &RgbaImage, layout: Layout) -> RgbaImage {
+    let positions = layout.positions();
+    let mut reordered = img.clone();
+    for (pixel, source) in reordered.pixels_mut().zip(img.pixels()) {
+        for (channel, position) in positions.iter().enumerate() {
+            pixel.0[channel] = source.0[*position];
+        }
+    }
+    reordered
+}
+
+/// Text that is unlikely to be a chance reading of noise
+fn plausible(message: &[u8]) -> bool {
+    message.len() >= MIN_RECOVERED_LEN
+        && std::str::from_utf8(message)
+            .is_ok_and(|text| text.chars().all(|c| !c.is_control() || c.is_whitespace()))
+}
+
+/// Retry `read` under every alternative layout, returning the first that yields plausible text
+pub fn recover(
+    img: &RgbaImage,
+    read: impl Fn(RgbaImage) -> Option<Vec<u8>>,
+) -> Option<(Layout, Vec<u8>)> {
+    ALTERNATIVES.iter().find_map(|layout| {
+        read(reinterpret(img, *layout))
+            .filter(|message| plausible(message))
+            .map(|message| (*layout, message))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn reinterpret_reorders_channels() {
+        let img = RgbaImage::from_pixel(1, 1, Rgba([1, 2, 3, 4]));
+        assert_eq!(
+            reinterpret(&img, Layout::Bgra).get_pixel(0, 0).0,
+            [3, 2, 1, 4]
+        );
+        assert_eq!(
+            reinterpret(&img, Layout::Argb).get_pixel(0, 0).0,
+            [2, 3, 4, 1]
+        );
+        assert_eq!(
+            reinterpret(&img, Layout::Abgr).get_pixel(0, 0).0,
+            [4, 3, 2, 1]
+        );
+    }
+
+    #[test]
+    fn short_or_binary_readings_are_not_plausible() {
+        assert!(plausible("recovered text\n".as_bytes()));
+        assert!(!plausible(b"abc"));
+        assert!(!plausible(b"\x01\x02\x03\x04"));
+        assert!(!plausible(b"\xff\xfe\xfd\xfc"));
+    }
+}
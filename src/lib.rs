@@ -0,0 +1,1898 @@
+//! Hide bytes in the least significant bits of 8-bit images
+//!
+//! [`embed`] and [`extract`] cover the common case of one payload in every subpixel.
+//! [`PngSecretWriter`] and [`PngSecretReader`] add the subpixel [`order`], the [`Slot`] and the
+//! [`Framing`], [`compress`] deflates payloads and [`ecc`] adds error correction to them.
+//! [`slots::enumerate_slots`] lists the payloads of an image without reading them,
+//! [`provenance`] stamps build information into release screenshots, [`records`] packs
+//! several named payloads into one and [`segments`] spreads one over several images. Nothing
+//! here touches the file system or prints, images go in and out in their own channels as a
+//! [`Carrier`], [`RgbaImage`] unless said otherwise, and problems come back as [`Error`]. What a
+//! caller may want to tell its user comes back as data, e.g. [`PngSecretWriter::info`], and the
+//! steps are logged as `tracing` spans and events.
+//!
+//! ```
+//! let cover = image::RgbaImage::new(16, 16);
+//! let stego = pngsecret::embed(cover, b"a\0b").unwrap();
+//! assert_eq!(pngsecret::extract(stego).unwrap(), b"a\0b");
+//! ```
+
+use image::RgbaImage;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::cmp::Ordering;
+use std::fmt;
+
+pub mod bytesize;
+pub mod carrier;
+pub mod compress;
+pub mod ecc;
+pub mod format;
+pub mod order;
+pub mod provenance;
+pub mod records;
+pub mod segments;
+pub mod slots;
+#[cfg(feature = "test-util")]
+pub mod testing;
+
+use carrier::Carrier;
+use format::{Features, FrameHeader, FramePrefix, Framing};
+use order::{Slot, SubpixelOrder};
+
+/// The size of an image and how much a writer can embed into it, for callers to report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub width: u32,
+    pub height: u32,
+    /// Payload bytes that fit into the writer's slot, `None` for a reader
+    pub capacity: Option<usize>,
+}
+
+/// Why a payload couldn't be embedded or extracted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The payload doesn't fit into the slot, both sizes without the framing
+    PayloadTooLarge { capacity: usize, requested: usize },
+    /// Neither a frame header nor a terminated message was found in the first `scanned` bytes
+    NoMessage { scanned: usize },
+    /// A compressed payload doesn't inflate, or inflates past the limit
+    CorruptPayload,
+    /// The payload doesn't match the checksum of its frame, or its frame has none and the reader
+    /// verifies
+    PayloadCorrupted,
+    /// The bytes given as an image don't decode as one
+    UnreadableImage,
+    /// A field of a [`provenance::Provenance`] is longer than its stamp holds
+    FieldTooLong { field: &'static str, length: usize },
+    /// More records were given than a [`records`] set holds
+    TooManyRecords { count: usize },
+    /// Block `block` of an [`ecc`] payload has more errors than its parity corrects
+    Uncorrectable { block: usize },
+    /// The start `offset` isn't one of the `subpixels` of the slot
+    OffsetOutOfRange { offset: usize, subpixels: usize },
+    /// A payload was to be spread over more images than [`segments`] number
+    TooManySegments { count: usize },
+    /// Segment `index` of a payload spread over `total` images wasn't given
+    SegmentMissing { index: usize, total: usize },
+    /// Segment `index` was given more than once
+    SegmentDuplicated { index: usize },
+    /// The segments belong to different payloads
+    SegmentsMixed,
+    /// The payload starts with a frame of layout `version`, past [`format::FRAME_VERSION`]
+    NewerFormat { version: u8 },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::PayloadTooLarge {
+                capacity,
+                requested,
+            } => write!(
+                f,
+                "The payload of {} bytes doesn't fit, the image holds {} bytes",
+                requested, capacity
+            ),
+            Error::NoMessage { scanned } => {
+                write!(f, "No hidden message was found in {} bytes", scanned)
+            }
+            Error::CorruptPayload => write!(
+                f,
+                "The compressed message is corrupt or expands past {} bytes",
+                compress::MAX_INFLATED_BYTES
+            ),
+            Error::PayloadCorrupted => {
+                write!(
+                    f,
+                    "The payload is corrupted or this is not a pngsecret image"
+                )
+            }
+            Error::UnreadableImage => write!(f, "The image couldn't be decoded"),
+            Error::FieldTooLong { field, length } => write!(
+                f,
+                "The {} of {} bytes is too long, at most {} fit",
+                field,
+                length,
+                provenance::MAX_FIELD_BYTES
+            ),
+            Error::TooManyRecords { count } => write!(
+                f,
+                "{} records don't fit into one image, at most {} do",
+                count,
+                records::MAX_RECORDS
+            ),
+            Error::Uncorrectable { block } => write!(
+                f,
+                "The payload is uncorrectable, block {} has more errors than its parity corrects",
+                block
+            ),
+            Error::OffsetOutOfRange { offset, subpixels } => write!(
+                f,
+                "The offset {} is past the {} subpixels of the slot",
+                offset, subpixels
+            ),
+            Error::TooManySegments { count } => write!(
+                f,
+                "A payload can't be spread over {} images, at most {} hold one",
+                count,
+                segments::MAX_SEGMENTS
+            ),
+            Error::SegmentMissing { index, total } => write!(
+                f,
+                "Segment {} of {} is missing, pass every image the payload was spread over",
+                index + 1,
+                total
+            ),
+            Error::SegmentDuplicated { index } => {
+                write!(f, "Segment {} was given more than once", index + 1)
+            }
+            Error::SegmentsMixed => write!(
+                f,
+                "The images hold segments of different payloads, pass the images of one"
+            ),
+            Error::NewerFormat { version } => write!(
+                f,
+                "The payload was produced by a newer pngsecret, in frame version {} where this \
+                 one reads up to {}, update pngsecret to read it",
+                version,
+                format::FRAME_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Hide `payload` in every subpixel of `img`, in sequence and length-prefixed
+pub fn embed<C: Carrier>(img: C, payload: &[u8]) -> Result<C, Error> {
+    let mut writer = PngSecretWriter::new(
+        img,
+        Box::new(NaiveEncoder::with_framing(Framing::LengthPrefixed)),
+    );
+    writer.encoder.encode(payload);
+    writer.embed()?;
+    Ok(writer.buffer)
+}
+
+/// Most payload bytes that fit into every subpixel of `img`, length-prefixed, `bits` bits deep
+///
+/// [`embed`] fits this many at the default depth; [`PngSecretWriter::capacity`] adds the slot
+/// and the overhead of its encoder to the same calculation.
+pub fn capacity_bytes(img: &impl Carrier, bits: u8) -> usize {
+    let subpixels = img.samples().len() as u64;
+    format::slot_capacity(subpixels, Framing::LengthPrefixed, bits) as usize
+}
+
+/// The payload [`embed`] hid in `img`, checked against the CRC-32 of its frame
+///
+/// Legacy NUL-terminated payloads and frames without a checksum need a [`PngSecretReader`] with
+/// verification turned off.
+pub fn extract<C: Carrier>(img: C) -> Result<Vec<u8>, Error> {
+    PngSecretReader::new(img, Box::new(NaiveDecoder::new())).read_image()
+}
+
+/// This function split one byte into 8 bit, the element is still u8 to simplify the addition to
+/// pixel
+pub fn byte_to_8bits(byte: &u8) -> [u8; 8] {
+    let mut x = *byte;
+    let mut bits: [u8; 8] = [0; 8];
+    for i in 0..8 {
+        bits[7 - i] = x % 2;
+        x /= 2;
+    }
+    bits
+}
+
+/// Split `text` into `bits` wide chunks, most significant bit first, the last one padded with
+/// zero bits
+///
+/// A chunk spans two bytes when `bits` doesn't divide 8.
+pub fn bytes_to_chunks(text: &[u8], bits: u8) -> impl Iterator<Item = u8> + '_ {
+    let mask = (1u16 << bits) - 1;
+    (0..text.len() * 8)
+        .step_by(bits as usize)
+        .map(move |start| {
+            // A chunk lies within the byte it starts in and the next one, zero past the end
+            let byte = start / 8;
+            let next = text.get(byte + 1).copied().unwrap_or(0);
+            let window = u16::from_be_bytes([text[byte], next]);
+            ((window >> (16 - bits as usize - start % 8)) & mask) as u8
+        })
+}
+
+/// How a sample whose low bits differ from the payload's is changed, reading is the same for both
+///
+/// Replacing the low bits maps every value onto its pair, 2k and 2k + 1, and the evened-out pairs
+/// are what chi-square attacks detect. Matching instead moves the sample to the nearest value with
+/// the payload's low bits, up or down at random when both are as near, which changes a sample by
+/// at most 1 at one bit per sample and never past 0 or 255.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Embedding {
+    #[default]
+    Replace,
+    /// The directions are drawn from a PRNG seeded with `seed`, the same seed gives the same image
+    Match { seed: u64 },
+}
+
+impl Embedding {
+    /// `sample` with `chunk` as its low `bits` bits, `rng` breaking ties when matching
+    fn apply(self, sample: u8, chunk: u8, bits: u8, rng: &mut impl Rng) -> u8 {
+        let mask = (1 << bits) - 1;
+        let replaced = (sample & !mask) | chunk;
+        if self == Embedding::Replace || replaced == sample {
+            return replaced;
+        }
+        let step = 1i16 << bits;
+        let (replaced, sample) = (replaced as i16, sample as i16);
+        // `replaced` is within a step of the sample, the nearest value is it or a step past it
+        let other = match replaced > sample {
+            true => replaced - step,
+            false => replaced + step,
+        };
+        if !(0..=255).contains(&other) {
+            return replaced as u8;
+        }
+        let nearest = match (replaced - sample).abs().cmp(&(other - sample).abs()) {
+            Ordering::Less => replaced,
+            Ordering::Greater => other,
+            Ordering::Equal if rng.gen() => replaced,
+            Ordering::Equal => other,
+        };
+        nearest as u8
+    }
+}
+
+/// A Writer using the last bits of the pixel channels, one unless the encoder says otherwise, to
+/// encode the message
+pub struct PngSecretWriter<C = RgbaImage> {
+    pub buffer: C,
+    pub encoder: Box<dyn PngSecretEncoder>,
+    order: SubpixelOrder,
+    slot: Slot,
+    offset: usize,
+    embedding: Embedding,
+    padding: bool,
+}
+
+impl<C: Carrier> PngSecretWriter<C> {
+    pub fn new(img: C, encoder: Box<dyn PngSecretEncoder>) -> Self {
+        PngSecretWriter {
+            buffer: img,
+            encoder,
+            order: SubpixelOrder::Sequential,
+            slot: Slot::All,
+            offset: 0,
+            embedding: Embedding::Replace,
+            padding: false,
+        }
+    }
+    /// The dimensions of the image and the capacity of the writer's slot
+    pub fn info(&self) -> ImageInfo {
+        let (width, height) = self.buffer.dimensions();
+        ImageInfo {
+            width,
+            height,
+            capacity: Some(self.capacity()),
+        }
+    }
+    pub fn with_order(mut self, order: SubpixelOrder) -> Self {
+        self.order = order;
+        self
+    }
+    /// Write into `slot`, which the encoder's frame header then describes
+    pub fn with_slot(mut self, slot: Slot) -> Self {
+        self.slot = slot;
+        self.encoder.place(slot);
+        self
+    }
+    /// Start `offset` subpixels into the order and wrap around, see [`Slot::indices_from`]
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+    /// Change samples by `embedding` instead of replacing their low bits
+    pub fn with_embedding(mut self, embedding: Embedding) -> Self {
+        self.embedding = embedding;
+        self
+    }
+    /// Fill the rest of the slot after the payload with random bits, so its low bits look alike
+    /// everywhere rather than only where the payload went
+    pub fn with_padding(mut self, padding: bool) -> Self {
+        self.padding = padding;
+        self
+    }
+    pub fn capacity(&self) -> usize {
+        self.capacity_in(self.slot)
+    }
+    pub fn capacity_in(&self, slot: Slot) -> usize {
+        let slot_bytes = format::slot_bytes(self.subpixels_in(slot) as u64, self.encoder.bits());
+        format::frame_capacity(
+            self.encoder.framed_capacity(slot_bytes),
+            self.encoder.framing(),
+        )
+        .saturating_sub(self.encoder.overhead_bytes()) as usize
+    }
+    fn subpixels_in(&self, slot: Slot) -> usize {
+        slot.subpixels_in(self.buffer.samples().len(), self.buffer.channels())
+    }
+    pub fn embed(&mut self) -> Result<(), Error> {
+        self.embed_observed(None, &mut |_, _| {})
+    }
+    /// Like `embed`, reporting the subpixel each bit of the framed payload went to, in order
+    ///
+    /// Traced embeds walk the payload subpixel by subpixel, which is slower on large payloads.
+    pub fn embed_traced(&mut self, trace: &mut dyn FnMut(usize)) -> Result<(), Error> {
+        self.embed_observed(Some(trace), &mut |_, _| {})
+    }
+    /// Like `embed`, reporting the subpixels to `trace` if given as `embed_traced` does and the
+    /// bytes of the framed payload embedded so far and in total, every [`PROGRESS_BYTES`] and
+    /// once at the end
+    pub fn embed_observed(
+        &mut self,
+        trace: Option<&mut dyn FnMut(usize)>,
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<(), Error> {
+        self.check_fits(self.slot, self.encoder.text().len())?;
+        let bits = self.encoder.bits();
+        let mut text = Cow::Borrowed(self.encoder.text());
+        if self.padding {
+            let slot_bytes = format::slot_bytes(self.subpixels_in(self.slot) as u64, bits);
+            text.to_mut().resize_with(slot_bytes as usize, rand::random);
+        }
+        write_chunks(
+            &mut self.buffer,
+            &self.order,
+            self.slot,
+            self.offset,
+            self.embedding,
+            &text,
+            bits,
+            trace,
+            progress,
+        );
+        Ok(())
+    }
+    /// Embed encoded `text`, framing included, into the subpixels of `slot`
+    ///
+    /// The encoder of `text` should have been placed in `slot` so its frame header says so.
+    pub fn embed_text(&mut self, slot: Slot, text: &[u8]) -> Result<(), Error> {
+        self.check_fits(slot, text.len())?;
+        let bits = self.encoder.bits();
+        write_chunks(
+            &mut self.buffer,
+            &self.order,
+            slot,
+            self.offset,
+            self.embedding,
+            text,
+            bits,
+            None,
+            &mut |_, _| {},
+        );
+        Ok(())
+    }
+    /// Whether the payload last encoded, framing included, fits into the subpixels of the slot
+    pub fn fits(&self) -> bool {
+        self.check_fits(self.slot, self.encoder.text().len())
+            .is_ok()
+    }
+    /// Fail unless `encoded` bytes, framing included, fit into the subpixels of `slot`
+    fn check_fits(&self, slot: Slot, encoded: usize) -> Result<(), Error> {
+        check_offset(self.offset, self.subpixels_in(slot))?;
+        let bits = self.encoder.bits();
+        if encoded as u64 <= format::slot_bytes(self.subpixels_in(slot) as u64, bits) {
+            return Ok(());
+        }
+        let overhead =
+            (self.encoder.framing().overhead_bytes() + self.encoder.overhead_bytes()) as usize;
+        let framed = self.encoder.framed_capacity(encoded as u64) as usize;
+        Err(Error::PayloadTooLarge {
+            capacity: self.capacity_in(slot),
+            requested: framed.saturating_sub(overhead),
+        })
+    }
+}
+
+/// Bytes of the framed payload between two progress reports of
+/// [`PngSecretWriter::embed_observed`]
+pub const PROGRESS_BYTES: usize = 1 << 20;
+
+/// Fail unless `offset` is one of `subpixels`, an empty slot has no offset but 0
+fn check_offset(offset: usize, subpixels: usize) -> Result<(), Error> {
+    if offset == 0 || offset < subpixels {
+        return Ok(());
+    }
+    Err(Error::OffsetOutOfRange { offset, subpixels })
+}
+
+/// Write `text` in `bits` wide chunks into the subpixels of `slot` in `order` from `offset`,
+/// which must hold it, changing them by `embedding`
+///
+/// Untraced payloads one bit deep in every subpixel from the first are written a byte into 8
+/// samples at a time, anything else subpixel by subpixel, to the same samples either way.
+#[allow(clippy::too_many_arguments)]
+fn write_chunks<C: Carrier>(
+    buffer: &mut C,
+    order: &SubpixelOrder,
+    slot: Slot,
+    offset: usize,
+    embedding: Embedding,
+    text: &[u8],
+    bits: u8,
+    trace: Option<&mut dyn FnMut(usize)>,
+    progress: &mut dyn FnMut(usize, usize),
+) {
+    let _span = tracing::info_span!("embed", slot = ?slot, encoded_bytes = text.len()).entered();
+    let mut rng = ChaCha8Rng::seed_from_u64(match embedding {
+        Embedding::Match { seed } => seed,
+        Embedding::Replace => 0,
+    });
+    let channels = buffer.channels();
+    let samples = buffer.samples_mut();
+    match trace {
+        None if is_packed(order, slot, offset, bits) => {
+            write_packed(samples, embedding, text, &mut rng, progress)
+        }
+        trace => {
+            let mut untraced = |_| {};
+            let trace: &mut dyn FnMut(usize) = match trace {
+                Some(trace) => trace,
+                None => &mut untraced,
+            };
+            // Chunks first, the indices stop being computed with the payload
+            let chunks = bytes_to_chunks(text, bits).zip(slot.indices_from(
+                order,
+                samples.len(),
+                channels,
+                offset,
+            ));
+            let chunks_per_report = PROGRESS_BYTES * 8 / bits as usize;
+            for (subpixels, (chunk, index)) in chunks.enumerate() {
+                let sample = &mut samples[index];
+                *sample = embedding.apply(*sample, chunk, bits, &mut rng);
+                for _ in 0..bits {
+                    trace(index);
+                }
+                if (subpixels + 1) % chunks_per_report == 0 {
+                    progress((subpixels + 1) * bits as usize / 8, text.len());
+                }
+            }
+        }
+    }
+    progress(text.len(), text.len());
+    let subpixels = (text.len() * 8).div_ceil(bits as usize);
+    tracing::debug!(bits_written = text.len() * 8, subpixels, bits, "embedded");
+}
+
+/// The low bit of each of 8 samples read as one big-endian word
+const LOW_BITS: u64 = 0x0101_0101_0101_0101;
+
+/// Whether bytes lie in runs of 8 samples, `bits` deep in `slot` in `order` from `offset`
+fn is_packed(order: &SubpixelOrder, slot: Slot, offset: usize, bits: u8) -> bool {
+    *order == SubpixelOrder::Sequential && slot == Slot::All && offset == 0 && bits == 1
+}
+
+/// `byte` spread over the low bits of a big-endian word of 8 samples, its MSB in the first
+fn spread_bits(byte: u8) -> u64 {
+    let mut word = byte as u64;
+    word = (word | word << 28) & 0x0000_000F_0000_000F;
+    word = (word | word << 14) & 0x0003_0003_0003_0003;
+    (word | word << 7) & LOW_BITS
+}
+
+/// The byte [`spread_bits`] spread over `word`, whose other bits are ignored
+fn gather_bits(word: u64) -> u8 {
+    let mut word = word & LOW_BITS;
+    word = (word | word >> 7) & 0x0003_0003_0003_0003;
+    word = (word | word >> 14) & 0x0000_000F_0000_000F;
+    (word | word >> 28) as u8
+}
+
+/// [`write_chunks`] of a payload [`is_packed`], 8 samples per byte of `text`
+fn write_packed(
+    samples: &mut [u8],
+    embedding: Embedding,
+    text: &[u8],
+    rng: &mut ChaCha8Rng,
+    progress: &mut dyn FnMut(usize, usize),
+) {
+    let mut done = 0;
+    for (bytes, samples) in text
+        .chunks(PROGRESS_BYTES)
+        .zip(samples.chunks_mut(PROGRESS_BYTES * 8))
+    {
+        for (&byte, samples) in bytes.iter().zip(samples.chunks_exact_mut(8)) {
+            let samples: &mut [u8; 8] = samples.try_into().unwrap();
+            match embedding {
+                Embedding::Replace => {
+                    let word = u64::from_be_bytes(*samples) & !LOW_BITS;
+                    *samples = (word | spread_bits(byte)).to_be_bytes();
+                }
+                // Ties take a random draw each, so the samples are matched in order
+                Embedding::Match { .. } => {
+                    for (i, sample) in samples.iter_mut().enumerate() {
+                        *sample = embedding.apply(*sample, byte >> (7 - i) & 1, 1, rng);
+                    }
+                }
+            }
+        }
+        done += bytes.len();
+        if bytes.len() == PROGRESS_BYTES {
+            progress(done, text.len());
+        }
+    }
+}
+
+/// Longest legacy message [`PngSecretReader`] looks for a terminator in by default
+///
+/// Without a bound, the noise of a 100 MP image without a message would be read into a 50 MB
+/// buffer before failing.
+pub const DEFAULT_SCAN_LIMIT: usize = 4 << 20;
+
+pub struct PngSecretReader<C = RgbaImage> {
+    buffer: C,
+    decoder: Box<dyn PngSecretDecoder>,
+    order: SubpixelOrder,
+    /// `None` reads whichever slot a frame header describes, like `offset` and `bits` do
+    slot: Option<Slot>,
+    offset: Option<usize>,
+    /// `None` detects the framing from the first bytes
+    framing: Option<Framing>,
+    scan_limit: usize,
+    bits: Option<u8>,
+    verify: bool,
+    /// What [`PngSecretReader::location`] found, looked for once
+    found: OnceCell<Found>,
+    /// The frame header of the payload last read
+    header: Option<FrameHeader>,
+}
+
+/// Where in an image a payload lies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub slot: Slot,
+    /// Low bits of each subpixel holding the payload
+    pub bits: u8,
+    /// Subpixels into the order of the slot the payload starts at
+    pub offset: usize,
+}
+
+/// What a [`PngSecretReader`] found looking for a payload
+#[derive(Debug, Clone, Copy)]
+enum Found {
+    /// A frame header of this version describing the location, or an [`ecc`] header
+    Header(Location),
+    /// A frame of a newer version, whose checksum holds up
+    Newer(Location, u8),
+    /// Neither, the location set or the start of every subpixel one bit deep
+    Nothing(Location),
+}
+
+impl<C: Carrier> PngSecretReader<C> {
+    pub fn new(img: C, decoder: Box<dyn PngSecretDecoder>) -> Self {
+        PngSecretReader {
+            buffer: img,
+            decoder,
+            order: SubpixelOrder::Sequential,
+            slot: None,
+            offset: None,
+            framing: None,
+            scan_limit: DEFAULT_SCAN_LIMIT,
+            bits: None,
+            verify: true,
+            found: OnceCell::new(),
+            header: None,
+        }
+    }
+    pub fn with_order(mut self, order: SubpixelOrder) -> Self {
+        self.order = order;
+        self.found = OnceCell::new();
+        self
+    }
+    /// Only read `slot` instead of the slot a frame header describes
+    pub fn with_slot(mut self, slot: Slot) -> Self {
+        self.slot = Some(slot);
+        self.found = OnceCell::new();
+        self
+    }
+    /// Start `offset` subpixels into the order and wrap around, as the payload was embedded with,
+    /// instead of looking for a frame header at every offset
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = Some(offset);
+        self.found = OnceCell::new();
+        self
+    }
+    /// Only read payloads in `framing` instead of detecting it
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = Some(framing);
+        self.found = OnceCell::new();
+        self
+    }
+    /// Give up on legacy messages after `bytes` bytes without a terminator
+    pub fn with_scan_limit(mut self, bytes: usize) -> Self {
+        self.scan_limit = bytes;
+        self
+    }
+    /// Whether payloads must match the checksum of their frame, on by default
+    ///
+    /// When verifying, a detected framing must be a frame with a checksum: frames written before
+    /// checksums fail with [`Error::PayloadCorrupted`] and images without a frame header with
+    /// [`Error::NoMessage`] instead of being read as legacy messages of whatever bytes they hold.
+    /// A legacy framing set with `with_framing` is read without verification, it has nothing to
+    /// verify against.
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+    /// Only read the low `bits` bits of every subpixel instead of the depth a frame header
+    /// describes
+    ///
+    /// Legacy messages and frames written before headers described the depth record none, they
+    /// are read one bit deep unless this says otherwise. Panics unless `1 <= bits <= MAX_BITS`.
+    pub fn with_bits(mut self, bits: u8) -> Self {
+        assert!((1..=format::MAX_BITS).contains(&bits), "{} bits", bits);
+        self.bits = Some(bits);
+        self.found = OnceCell::new();
+        self
+    }
+    /// Where the payload is read from
+    ///
+    /// Whatever isn't set with `with_slot`, `with_bits` and `with_offset` is looked for: a frame
+    /// header of this version describing the slot and depth it is read in, at the start of each
+    /// slot at each depth, then, unless the offset is set, one whose checksum holds up at every
+    /// offset, which walks the whole image. [`ecc`] headers are found the same way, frames of a
+    /// newer version only by their checksum. Without any, the payload is read from the start of
+    /// every subpixel one bit deep.
+    pub fn location(&self) -> Location {
+        match self.found() {
+            Found::Header(location) | Found::Newer(location, _) | Found::Nothing(location) => {
+                location
+            }
+        }
+    }
+    fn found(&self) -> Found {
+        *self.found.get_or_init(|| self.find())
+    }
+    fn find(&self) -> Found {
+        let start = Location {
+            slot: self.slot.unwrap_or_default(),
+            bits: self.bits.unwrap_or(format::DEFAULT_BITS),
+            offset: self.offset.unwrap_or(0),
+        };
+        if self.framing == Some(Framing::Terminated) {
+            return Found::Nothing(start);
+        }
+        let slots = match self.slot {
+            Some(slot) => vec![slot],
+            None => slots::PROBED.to_vec(),
+        };
+        let depths = match self.bits {
+            Some(bits) => bits..=bits,
+            None => 1..=format::MAX_BITS,
+        };
+        let places: Vec<(Slot, u8)> = slots
+            .into_iter()
+            .flat_map(|slot| depths.clone().map(move |bits| (slot, bits)))
+            .collect();
+        let at_start = places.iter().map(|&(slot, bits)| Location {
+            slot,
+            bits,
+            offset: start.offset,
+        });
+        if let Some(found) = at_start.filter_map(|at| self.probe(at, false)).next() {
+            return found;
+        }
+        if self.offset.is_none() {
+            for &(slot, bits) in &places {
+                let offsets = self.frame_starts(slot, bits).into_iter();
+                let mut anywhere = offsets.map(|offset| Location { slot, bits, offset });
+                if let Some(found) = anywhere.find_map(|at| self.probe(at, true)) {
+                    return found;
+                }
+            }
+        }
+        Found::Nothing(start)
+    }
+    /// What starts at `at`: a frame header of this version describing it, an [`ecc`] header or a
+    /// frame of a newer version whose checksum holds up; `checked` also needs the checksum of a
+    /// frame of this version to hold up
+    fn probe(&self, at: Location, checked: bool) -> Option<Found> {
+        check_offset(at.offset, self.subpixels_in(at.slot)).ok()?;
+        let slot_bytes = self.slot_bytes(at);
+        let head = self.head(at, format::MAX_HEADER_BYTES.max(ecc::HEADER_BYTES));
+        if let Some(header) = FrameHeader::parse(&head, slot_bytes) {
+            let features = header.features?;
+            if features.slot != at.slot || features.bits != at.bits {
+                return None;
+            }
+            let frame = match checked {
+                true => self.head(at, header.bytes() + header.length as usize),
+                false => Vec::new(),
+            };
+            return (!checked || header.verifies(&frame[header.bytes()..]))
+                .then_some(Found::Header(at));
+        }
+        let newer = FramePrefix::parse(&head, slot_bytes)
+            .filter(|prefix| prefix.version > format::FRAME_VERSION);
+        if let Some(prefix) = newer {
+            let frame = self.head(at, prefix.frame_bytes() as usize);
+            return prefix
+                .verifies(&frame)
+                .then_some(Found::Newer(at, prefix.version));
+        }
+        ecc::parse_header(&head, slot_bytes).map(|_| Found::Header(at))
+    }
+    /// Offsets into the order of `slot` at which the bytes `bits` deep could start a frame of
+    /// version 3 on or an [`ecc`] header, by their first [`format::PREFIX_BYTES`] bytes
+    ///
+    /// The slot is walked once, its bits shifted through a window as long as the prefix.
+    fn frame_starts(&self, slot: Slot, bits: u8) -> Vec<usize> {
+        let (samples, channels) = (self.buffer.samples(), self.buffer.channels());
+        let slot_bytes = self.slot_bytes(Location {
+            slot,
+            bits,
+            offset: 0,
+        });
+        let prefix_bits = format::PREFIX_BYTES * 8;
+        let span = prefix_bits.div_ceil(bits as usize);
+        if self.subpixels_in(slot) < span {
+            return Vec::new();
+        }
+        let excess = span * bits as usize - prefix_bits;
+        let mask = (1u128 << (span * bits as usize)) - 1;
+        let mut window = 0u128;
+        let mut shift_in = |index: usize| {
+            window = (window << bits | (samples[index] & ((1 << bits) - 1)) as u128) & mask;
+            let prefix = (window >> excess).to_be_bytes();
+            let prefix = &prefix[prefix.len() - format::PREFIX_BYTES..];
+            prefix[0] == format::TERMINATOR
+                && (FramePrefix::parse(prefix, slot_bytes).is_some()
+                    || ecc::could_start(prefix, slot_bytes))
+        };
+        let indices = slot.indices_in(&self.order, samples.len(), channels);
+        let (mut starts, mut wrapped) = (Vec::new(), Vec::with_capacity(span - 1));
+        for (i, index) in indices.enumerate() {
+            if i < span - 1 {
+                wrapped.push(index);
+            }
+            if shift_in(index) && i + 1 >= span {
+                starts.push(i + 1 - span);
+            }
+        }
+        // Frames wrapping around from the end of the slot to its start
+        let subpixels = self.subpixels_in(slot);
+        for (i, index) in wrapped.into_iter().enumerate() {
+            if shift_in(index) {
+                starts.push(subpixels + i + 1 - span);
+            }
+        }
+        starts
+    }
+    /// The bytes of the slot in reading order from where the payload starts, with the subpixel
+    /// each of their bits came from
+    pub fn bytes(&self) -> impl Iterator<Item = (u8, [usize; 8])> + '_ {
+        self.bytes_at(self.location())
+    }
+    fn bytes_at(&self, at: Location) -> Box<dyn Iterator<Item = (u8, [usize; 8])> + '_> {
+        read_slot_bytes(
+            self.buffer.samples(),
+            self.buffer.channels(),
+            at.slot,
+            &self.order,
+            at.offset,
+            at.bits,
+        )
+    }
+    /// The first `bytes` bytes from `at`
+    fn head(&self, at: Location, bytes: usize) -> Vec<u8> {
+        self.bytes_at(at)
+            .take(bytes)
+            .map(|(value, _)| value)
+            .collect()
+    }
+    fn subpixels_in(&self, slot: Slot) -> usize {
+        slot.subpixels_in(self.buffer.samples().len(), self.buffer.channels())
+    }
+    /// Encoded bytes the slot of `at` holds at its depth
+    fn slot_bytes(&self, at: Location) -> u64 {
+        format::slot_bytes(self.subpixels_in(at.slot) as u64, at.bits)
+    }
+    /// The frame header where the payload starts, if there is one
+    fn frame_header(&self) -> Option<FrameHeader> {
+        let at = self.location();
+        FrameHeader::parse(
+            &self.head(at, format::MAX_HEADER_BYTES),
+            self.slot_bytes(at),
+        )
+    }
+    /// The [`ecc`] header where the payload starts, if there is one
+    fn ecc_header(&self) -> Option<ecc::Header> {
+        let at = self.location();
+        ecc::parse_header(&self.head(at, ecc::HEADER_BYTES), self.slot_bytes(at))
+    }
+    /// The framing the payload is read in, detected unless set with `with_framing`
+    ///
+    /// Error corrected payloads are framed under their header.
+    pub fn framing(&self) -> Framing {
+        match self.framing {
+            Some(framing) => framing,
+            None if self.frame_header().is_some() || self.ecc_header().is_some() => {
+                Framing::LengthPrefixed
+            }
+            None => Framing::Terminated,
+        }
+    }
+    /// The frame header of the payload last read, `None` before reading and for legacy messages
+    pub fn header(&self) -> Option<FrameHeader> {
+        self.header
+    }
+    pub fn read_image(&mut self) -> Result<Vec<u8>, Error> {
+        self.read_image_traced(&mut |_| {})
+    }
+    /// Like `read_image`, reporting every step to `trace`
+    pub fn read_image_traced(
+        &mut self,
+        trace: &mut dyn FnMut(ReadEvent),
+    ) -> Result<Vec<u8>, Error> {
+        self.header = None;
+        let at = match self.found() {
+            // Read as legacy, a newer frame would be an empty message
+            Found::Newer(_, version) => return Err(Error::NewerFormat { version }),
+            Found::Header(at) | Found::Nothing(at) => at,
+        };
+        check_offset(at.offset, self.subpixels_in(at.slot))?;
+        let overhead = Framing::LengthPrefixed.overhead_bytes() as usize;
+        let (header, message) = match (self.framing(), self.ecc_header()) {
+            (Framing::LengthPrefixed, Some(header)) => self.read_corrected(header, trace)?,
+            (Framing::LengthPrefixed, None) => self.read_framed(trace)?,
+            (Framing::Terminated, _) if self.verify && self.framing.is_none() => {
+                return Err(Error::NoMessage { scanned: overhead })
+            }
+            (Framing::Terminated, _) => (None, self.read_terminated(trace)?),
+        };
+        self.header = header;
+        tracing::debug!(encoded_bytes = message.len(), slot = ?at.slot, bits = at.bits, "extracted");
+        self.decoder.decode(message)
+    }
+    /// The dimensions of the image
+    pub fn info(&self) -> ImageInfo {
+        let (width, height) = self.buffer.dimensions();
+        ImageInfo {
+            width,
+            height,
+            capacity: None,
+        }
+    }
+    fn read_framed(
+        &self,
+        trace: &mut dyn FnMut(ReadEvent),
+    ) -> Result<(Option<FrameHeader>, Vec<u8>), Error> {
+        let overhead = Framing::LengthPrefixed.overhead_bytes() as usize;
+        let header = self
+            .frame_header()
+            .ok_or(Error::NoMessage { scanned: overhead })?;
+        let length = header.length as usize;
+        trace(ReadEvent::Header(header));
+        let mut message = Vec::with_capacity(length);
+        // The header only parses if the slot holds `length` more bytes
+        for (value, subpixels) in self.bytes().skip(header.bytes()).take(length) {
+            trace(ReadEvent::Byte {
+                index: message.len(),
+                subpixels,
+                value,
+            });
+            message.push(value);
+        }
+        trace(ReadEvent::End { bytes: length });
+        if self.verify && !header.verifies(&message) {
+            return Err(Error::PayloadCorrupted);
+        }
+        Ok((Some(header), message))
+    }
+    /// Correct the blocks after an [`ecc`] `header` and read the frame they hold
+    fn read_corrected(
+        &self,
+        header: ecc::Header,
+        trace: &mut dyn FnMut(ReadEvent),
+    ) -> Result<(Option<FrameHeader>, Vec<u8>), Error> {
+        let blocks: Vec<u8> = self
+            .bytes()
+            .skip(ecc::HEADER_BYTES)
+            .take(header.encoded_bytes() as usize)
+            .map(|(value, _)| value)
+            .collect();
+        let (framed, corrected) = ecc::correct(&header, &blocks)?;
+        trace(ReadEvent::Corrected {
+            blocks: blocks.len().div_ceil(ecc::BLOCK_BYTES),
+            bytes: corrected,
+        });
+        let frame =
+            FrameHeader::parse(&framed, framed.len() as u64).ok_or(Error::PayloadCorrupted)?;
+        let length = frame.length as usize;
+        trace(ReadEvent::Header(frame));
+        let message = framed[frame.bytes()..][..length].to_vec();
+        trace(ReadEvent::End { bytes: length });
+        if self.verify && !frame.verifies(&message) {
+            return Err(Error::PayloadCorrupted);
+        }
+        Ok((Some(frame), message))
+    }
+    fn read_terminated(&self, trace: &mut dyn FnMut(ReadEvent)) -> Result<Vec<u8>, Error> {
+        let mut message = Vec::new();
+        for (value, subpixels) in self.bytes() {
+            if value == format::TERMINATOR {
+                trace(ReadEvent::Terminator {
+                    index: message.len(),
+                    subpixels,
+                });
+                // Eight zero bits up front are what clean renders and screenshots start with
+                return match message.is_empty() {
+                    true => Err(Error::NoMessage { scanned: 0 }),
+                    false => Ok(message),
+                };
+            }
+            // A message that can't be held can't be a message either
+            if message.len() >= self.scan_limit || message.try_reserve(1).is_err() {
+                trace(ReadEvent::Limit {
+                    bytes: message.len(),
+                });
+                return Err(Error::NoMessage {
+                    scanned: message.len(),
+                });
+            }
+            trace(ReadEvent::Byte {
+                index: message.len(),
+                subpixels,
+                value,
+            });
+            message.push(value);
+        }
+        let at = self.location();
+        trace(ReadEvent::Exhausted {
+            bytes: message.len(),
+            leftover_bits: self.subpixels_in(at.slot) * at.bits as usize % 8,
+        });
+        Err(Error::NoMessage {
+            scanned: message.len(),
+        })
+    }
+}
+
+/// The bytes `bits` deep in `slot` of the subpixels `samples`, `channels` per pixel, walked in
+/// `order` from `offset`, with the subpixel each of their bits came from
+pub(crate) fn read_slot_bytes<'a>(
+    samples: &'a [u8],
+    channels: u8,
+    slot: Slot,
+    order: &SubpixelOrder,
+    offset: usize,
+    bits: u8,
+) -> Box<dyn Iterator<Item = (u8, [usize; 8])> + 'a> {
+    match is_packed(order, slot, offset, bits) {
+        true => Box::new(read_packed(samples)),
+        false => Box::new(read_per_subpixel(
+            samples, channels, slot, order, offset, bits,
+        )),
+    }
+}
+
+/// [`read_slot_bytes`] of a payload [`is_packed`], a byte from every 8 samples
+fn read_packed(samples: &[u8]) -> impl Iterator<Item = (u8, [usize; 8])> + '_ {
+    samples.chunks_exact(8).enumerate().map(|(i, samples)| {
+        let word = u64::from_be_bytes(samples.try_into().unwrap());
+        (gather_bits(word), std::array::from_fn(|bit| i * 8 + bit))
+    })
+}
+
+/// [`read_slot_bytes`] of any payload, a bit at a time
+fn read_per_subpixel<'a>(
+    samples: &'a [u8],
+    channels: u8,
+    slot: Slot,
+    order: &SubpixelOrder,
+    offset: usize,
+    bits: u8,
+) -> impl Iterator<Item = (u8, [usize; 8])> + 'a {
+    let mut stream = slot
+        .indices_from(order, samples.len(), channels, offset)
+        .flat_map(move |subpixel| {
+            (0..bits)
+                .rev()
+                .map(move |shift| (subpixel, (samples[subpixel] >> shift) & 1))
+        });
+    std::iter::from_fn(move || {
+        let mut subpixels = [0; 8];
+        let mut value = 0;
+        for subpixel in subpixels.iter_mut() {
+            let (index, bit) = stream.next()?;
+            *subpixel = index;
+            value = (value << 1) | bit;
+        }
+        Some((value, subpixels))
+    })
+}
+
+/// One step of reading a payload, reported for `doctor --explain`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadEvent {
+    /// A frame header announcing the length and the checksum of the payload
+    Header(FrameHeader),
+    /// A message byte assembled MSB first from the low bits of `subpixels`, one entry per bit
+    Byte {
+        index: usize,
+        subpixels: [usize; 8],
+        value: u8,
+    },
+    /// The NUL byte ending a legacy message after `index` bytes
+    Terminator { index: usize, subpixels: [usize; 8] },
+    /// The last of the `bytes` announced by the frame header was read
+    End { bytes: usize },
+    /// `bytes` bytes were corrected in the `blocks` [`ecc`] blocks before the frame header,
+    /// whose bytes aren't reported one by one
+    Corrected { blocks: usize, bytes: usize },
+    /// The image ran out of subpixels before a terminator
+    Exhausted { bytes: usize, leftover_bits: usize },
+    /// `bytes` bytes were read without a terminator, the scan limit
+    Limit { bytes: usize },
+}
+
+/// Encoder should support encode and write
+/// Could extend to support different encoding format and encryption scheme
+pub trait PngSecretEncoder {
+    /// The text should be carried within the encoder
+    fn encode(&mut self, seq: &[u8]) {
+        self.encode_with(seq, Features::default())
+    }
+    /// Like `encode`, adding to the `features` the frame header describes what the encoder does
+    fn encode_with(&mut self, seq: &[u8], features: Features);
+    /// Describe `slot` as the one `text` is written into, [`Slot::All`] until placed
+    fn place(&mut self, slot: Slot);
+    /// The encoded payload, framing included, borrowed so large payloads aren't copied
+    fn text(&self) -> &[u8];
+    /// How `text` marks the end of the payload
+    fn framing(&self) -> Framing;
+    /// How many low bits of each subpixel `text` is written into
+    fn bits(&self) -> u8;
+    /// Bytes `text` adds to every payload on top of the framing
+    fn overhead_bytes(&self) -> u64;
+    /// Framed bytes that `slot_bytes` bytes of `text` hold, all of them unless `text` adds
+    /// error correction to the frame
+    fn framed_capacity(&self, slot_bytes: u64) -> u64 {
+        slot_bytes
+    }
+}
+
+/// Decoder
+pub trait PngSecretDecoder {
+    /// The payload of the unframed message `seq`, or why it can't be recovered
+    fn decode(&mut self, seq: Vec<u8>) -> Result<Vec<u8>, Error>;
+}
+
+// WARN: Is the data member really needed?
+pub struct NaiveEncoder {
+    text: Vec<u8>,
+    framing: Framing,
+    bits: u8,
+    slot: Slot,
+}
+
+#[derive(Default)]
+pub struct NaiveDecoder {}
+
+impl PngSecretDecoder for NaiveDecoder {
+    fn decode(&mut self, seq: Vec<u8>) -> Result<Vec<u8>, Error> {
+        Ok(seq)
+    }
+}
+
+impl NaiveDecoder {
+    /// Codec name in log records
+    pub const ID: &'static str = "naive";
+
+    pub fn new() -> Self {
+        NaiveDecoder {}
+    }
+}
+impl PngSecretEncoder for NaiveEncoder {
+    fn encode_with(&mut self, seq: &[u8], features: Features) {
+        let features = Features {
+            slot: self.slot,
+            bits: self.bits,
+            ..features
+        };
+        self.text = self.framing.frame_with(seq, features);
+    }
+    fn place(&mut self, slot: Slot) {
+        let placed = std::mem::replace(&mut self.slot, slot);
+        if placed == slot || self.framing == Framing::Terminated {
+            return;
+        }
+        if let Some(header) = FrameHeader::parse(&self.text, u64::MAX) {
+            let features = Features {
+                slot,
+                ..header.features.unwrap_or_default()
+            };
+            self.text = self
+                .framing
+                .frame_with(&self.text[header.bytes()..], features);
+        }
+    }
+    fn text(&self) -> &[u8] {
+        &self.text
+    }
+    fn framing(&self) -> Framing {
+        self.framing
+    }
+    fn bits(&self) -> u8 {
+        self.bits
+    }
+    fn overhead_bytes(&self) -> u64 {
+        0
+    }
+}
+
+impl NaiveEncoder {
+    /// Codec name in log records
+    pub const ID: &'static str = "naive";
+
+    /// An encoder writing the legacy NUL-terminated format
+    pub fn new() -> Self {
+        Self::with_framing(Framing::Terminated)
+    }
+
+    pub fn with_framing(framing: Framing) -> Self {
+        NaiveEncoder {
+            text: Vec::new(),
+            framing,
+            bits: format::DEFAULT_BITS,
+            slot: Slot::All,
+        }
+    }
+
+    /// Write into the low `bits` bits of every subpixel, more capacity for more visible noise
+    ///
+    /// Panics unless `1 <= bits <= MAX_BITS`.
+    pub fn with_bits(mut self, bits: u8) -> Self {
+        assert!((1..=format::MAX_BITS).contains(&bits), "{} bits", bits);
+        self.bits = bits;
+        self
+    }
+}
+
+impl Default for NaiveEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::quickcheck;
+
+    fn embed_with(img: RgbaImage, payload: &[u8], order: SubpixelOrder) -> RgbaImage {
+        let mut writer = PngSecretWriter::new(img, Box::new(NaiveEncoder::new())).with_order(order);
+        writer.encoder.encode(payload);
+        writer.embed().unwrap();
+        writer.buffer
+    }
+
+    fn changed_span(before: &RgbaImage, after: &RgbaImage) -> usize {
+        let changed: Vec<usize> = before
+            .iter()
+            .zip(after.iter())
+            .enumerate()
+            .filter(|(_, (a, b))| a != b)
+            .map(|(index, _)| index)
+            .collect();
+        changed.last().unwrap() - changed.first().unwrap()
+    }
+
+    #[test]
+    fn embed_and_extract_report_errors() {
+        // 4x8 RGBA holds 16 bytes, 3 after the frame header
+        assert_eq!(
+            embed(RgbaImage::new(4, 8), b"abcd"),
+            Err(Error::PayloadTooLarge {
+                capacity: 3,
+                requested: 4
+            })
+        );
+        let stego = embed(RgbaImage::new(4, 8), b"abc").unwrap();
+        assert_eq!(extract(stego), Ok(b"abc".to_vec()));
+        let noise = RgbaImage::from_pixel(4, 8, image::Rgba([1, 1, 1, 1]));
+        assert_eq!(extract(noise), Err(Error::NoMessage { scanned: 13 }));
+    }
+
+    #[test]
+    fn traced_embeds_report_the_subpixels_the_reader_walks() {
+        let order = SubpixelOrder::Blocks {
+            block_size: 3,
+            seed: 11,
+        };
+        let mut writer = PngSecretWriter::new(
+            RgbaImage::new(16, 16),
+            Box::new(NaiveEncoder::with_framing(Framing::LengthPrefixed).with_bits(2)),
+        )
+        .with_order(order)
+        .with_slot(Slot::Rgb);
+        writer.encoder.encode(b"traced");
+        let mut embedded = Vec::new();
+        writer
+            .embed_traced(&mut |subpixel| embedded.push(subpixel))
+            .unwrap();
+        assert_eq!(embedded.len(), (13 + 6) * 8);
+        assert!(embedded.iter().all(|subpixel| subpixel % 4 != 3));
+
+        let reader = PngSecretReader::new(writer.buffer, Box::new(NaiveDecoder::new()))
+            .with_order(order)
+            .with_slot(Slot::Rgb)
+            .with_bits(2);
+        let read: Vec<usize> = reader
+            .bytes()
+            .flat_map(|(_, subpixels)| subpixels)
+            .take(embedded.len())
+            .collect();
+        assert_eq!(read, embedded);
+    }
+
+    #[test]
+    fn flipped_payload_bits_fail_the_checksum() {
+        let cover = RgbaImage::from_fn(16, 16, |x, y| image::Rgba([x as u8, y as u8, 9, 255]));
+        let stego = embed(cover, b"checked on the way out").unwrap();
+        let header_bits = Framing::LengthPrefixed.overhead_bytes() as usize * 8;
+        for flipped in [header_bits, header_bits + 100, header_bits + 22 * 8 - 1] {
+            let mut corrupt = stego.clone();
+            corrupt.as_mut()[flipped] ^= 1;
+            assert_eq!(extract(corrupt.clone()), Err(Error::PayloadCorrupted));
+            let unverified = PngSecretReader::new(corrupt, Box::new(NaiveDecoder::new()))
+                .with_verify(false)
+                .read_image()
+                .unwrap();
+            assert_ne!(unverified, b"checked on the way out");
+        }
+        // A flipped checksum bit is just as wrong
+        let mut corrupt = stego.clone();
+        corrupt.as_mut()[(format::PREFIX_BYTES - 1) * 8 - 1] ^= 1;
+        assert_eq!(extract(corrupt), Err(Error::PayloadCorrupted));
+
+        // Frames from before checksums only read without verification
+        let mut old = RgbaImage::from_fn(16, 16, |x, y| image::Rgba([x as u8, y as u8, 9, 255]));
+        let mut unchecked = format::UNCHECKED_FRAME_MAGIC.to_vec();
+        unchecked.extend_from_slice(&3u32.to_be_bytes());
+        unchecked.extend_from_slice(b"old");
+        for (sample, bit) in old.iter_mut().zip(unchecked.iter().flat_map(byte_to_8bits)) {
+            *sample = (*sample & !1) | bit;
+        }
+        assert_eq!(extract(old.clone()), Err(Error::PayloadCorrupted));
+        let read = PngSecretReader::new(old, Box::new(NaiveDecoder::new()))
+            .with_verify(false)
+            .read_image();
+        assert_eq!(read, Ok(b"old".to_vec()));
+    }
+
+    #[test]
+    fn readers_locate_payloads_by_their_frame_header() {
+        let cover = RgbaImage::from_fn(16, 16, |x, y| {
+            image::Rgba([(x * 16) as u8, (y * 16) as u8, 7, 255])
+        });
+        for (slot, bits, offset) in [(Slot::Rgb, 2, 0), (Slot::Alpha, 3, 5), (Slot::All, 1, 1000)] {
+            let encoder = NaiveEncoder::with_framing(Framing::LengthPrefixed).with_bits(bits);
+            let mut writer = PngSecretWriter::new(cover.clone(), Box::new(encoder))
+                .with_slot(slot)
+                .with_offset(offset);
+            writer.encoder.encode(b"found");
+            writer.embed().unwrap();
+            let mut reader = PngSecretReader::new(writer.buffer, Box::new(NaiveDecoder::new()));
+            assert_eq!(reader.location(), Location { slot, bits, offset });
+            assert_eq!(reader.read_image(), Ok(b"found".to_vec()));
+            let features = reader.header().and_then(|header| header.features);
+            assert_eq!(features.map(|f| (f.slot, f.bits)), Some((slot, bits)));
+        }
+
+        // Error corrected payloads by theirs, and a location given is taken at its word
+        let framed = NaiveEncoder::with_framing(Framing::LengthPrefixed);
+        let encoder = ecc::EccEncoder::new(4, Box::new(framed));
+        let mut writer = PngSecretWriter::new(cover, Box::new(encoder)).with_offset(300);
+        writer.encoder.encode(b"corrected");
+        writer.embed().unwrap();
+        let reader = || PngSecretReader::new(writer.buffer.clone(), Box::new(NaiveDecoder::new()));
+        assert_eq!(reader().location().offset, 300);
+        assert_eq!(reader().read_image(), Ok(b"corrected".to_vec()));
+        assert_eq!(
+            reader().with_offset(0).read_image(),
+            Err(Error::NoMessage { scanned: 13 })
+        );
+    }
+
+    #[test]
+    fn newer_frame_versions_are_refused_rather_than_read_as_legacy() {
+        let stego = embed(RgbaImage::new(8, 8), b"from the future").unwrap();
+        // The second magic byte, 0xA1, turned into the first reserved one, which the checksum
+        // doesn't cover
+        let mut newer = stego.clone();
+        newer.as_mut()[14] |= 1;
+        newer.as_mut()[15] &= !1;
+        assert_eq!(
+            extract(newer.clone()),
+            Err(Error::NewerFormat { version: 4 })
+        );
+        assert!(Error::NewerFormat { version: 4 }
+            .to_string()
+            .contains("produced by a newer pngsecret"));
+        // Asked for legacy explicitly, the leading zero byte is no message
+        let legacy = PngSecretReader::new(newer, Box::new(NaiveDecoder::new()))
+            .with_framing(Framing::Terminated)
+            .read_image();
+        assert_eq!(legacy, Err(Error::NoMessage { scanned: 0 }));
+        assert_eq!(extract(stego), Ok(b"from the future".to_vec()));
+    }
+
+    #[test]
+    fn payloads_around_the_capacity_roundtrip_or_are_refused() {
+        // 5x3 at 3 bits leaves 4 spare bits after the last whole byte
+        for (width, height) in [(4, 4), (5, 3), (24, 9)] {
+            let cover = RgbaImage::from_fn(width, height, |x, y| {
+                image::Rgba([(x * 37) as u8, (y * 53) as u8, 0xFF, 0x0F])
+            });
+            for (bits, framing) in (1..=format::MAX_BITS)
+                .flat_map(|bits| Framing::ALL.into_iter().map(move |framing| (bits, framing)))
+            {
+                let encoder = || Box::new(NaiveEncoder::with_framing(framing).with_bits(bits));
+                let capacity = PngSecretWriter::new(cover.clone(), encoder()).capacity();
+                if capacity == 0 {
+                    continue;
+                }
+                let case = (width, bits, framing, capacity);
+                for len in [capacity - 1, capacity, capacity + 1] {
+                    // No NUL, so the legacy terminator is the only one
+                    let payload = vec![0xA5; len];
+                    let mut writer = PngSecretWriter::new(cover.clone(), encoder());
+                    writer.encoder.encode(&payload);
+                    if len > capacity {
+                        // A legacy payload filling the slot leaves no room for its terminator
+                        assert_eq!(
+                            writer.embed(),
+                            Err(Error::PayloadTooLarge {
+                                capacity,
+                                requested: len
+                            }),
+                            "{:?}",
+                            case
+                        );
+                        assert_eq!(writer.buffer, cover, "{:?}", case);
+                        continue;
+                    }
+                    writer.embed().unwrap();
+                    let read = PngSecretReader::new(writer.buffer, Box::new(NaiveDecoder::new()))
+                        .with_bits(bits)
+                        .with_framing(framing)
+                        .read_image();
+                    assert_eq!(read, Ok(payload), "{:?} at {} bytes", case, len);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn oversized_payloads_leave_the_image_alone() {
+        let cover = RgbaImage::from_pixel(4, 4, image::Rgba([1, 1, 1, 1]));
+        let mut writer = PngSecretWriter::new(cover.clone(), Box::new(NaiveEncoder::new()));
+        writer.encoder.encode(b"12345678");
+        assert!(matches!(
+            writer.embed(),
+            Err(Error::PayloadTooLarge {
+                capacity: 7,
+                requested: 8
+            })
+        ));
+        assert_eq!(writer.buffer, cover);
+    }
+
+    #[test]
+    fn chunks_span_bytes_when_the_depth_does_not_divide_8() {
+        let text = [0b1011_0110, 0xFF];
+        let chunks = |bits| bytes_to_chunks(&text, bits).collect::<Vec<u8>>();
+        assert_eq!(
+            chunks(1),
+            text.iter().flat_map(byte_to_8bits).collect::<Vec<u8>>()
+        );
+        assert_eq!(chunks(2), [0b10, 0b11, 0b01, 0b10, 0b11, 0b11, 0b11, 0b11]);
+        assert_eq!(chunks(3), [0b101, 0b101, 0b101, 0b111, 0b111, 0b100]);
+        assert_eq!(chunks(4), [0xB, 0x6, 0xF, 0xF]);
+        assert_eq!(bytes_to_chunks(&[], 3).count(), 0);
+        for bits in 1..=8 {
+            let mut stream = text.iter().flat_map(byte_to_8bits);
+            let folded: Vec<u8> = std::iter::from_fn(|| {
+                let first = stream.next()?;
+                Some((1..bits).fold(first, |chunk, _| (chunk << 1) | stream.next().unwrap_or(0)))
+            })
+            .collect();
+            assert_eq!(chunks(bits), folded, "{} bits", bits);
+        }
+    }
+
+    #[test]
+    fn large_embeds_report_their_progress() {
+        // 5.4 M subpixels at 4 bits hold a bit more than 2.5 MiB
+        let cover = RgbaImage::new(1160, 1160);
+        let encoder = NaiveEncoder::with_framing(Framing::LengthPrefixed).with_bits(4);
+        let mut writer = PngSecretWriter::new(cover, Box::new(encoder));
+        writer.encoder.encode(&vec![7; PROGRESS_BYTES * 5 / 2]);
+        let total = writer.encoder.text().len();
+        let mut reports = Vec::new();
+        writer
+            .embed_observed(None, &mut |done, total| reports.push((done, total)))
+            .unwrap();
+        assert_eq!(
+            reports,
+            [
+                (PROGRESS_BYTES, total),
+                (2 * PROGRESS_BYTES, total),
+                (total, total)
+            ]
+        );
+
+        let mut reports = 0;
+        let mut writer = PngSecretWriter::new(RgbaImage::new(8, 8), Box::new(NaiveEncoder::new()));
+        writer.encoder.encode(b"small");
+        writer
+            .embed_observed(None, &mut |_, _| reports += 1)
+            .unwrap();
+        assert_eq!(reports, 1);
+    }
+
+    #[test]
+    fn bits_spread_over_and_gather_from_the_low_bits_of_8_samples() {
+        for byte in 0..=255u8 {
+            let word = spread_bits(byte);
+            let samples = word.to_be_bytes();
+            assert_eq!(word & !LOW_BITS, 0);
+            assert_eq!(samples, byte_to_8bits(&byte));
+            assert_eq!(gather_bits(word | !LOW_BITS), byte);
+        }
+    }
+
+    #[test]
+    fn writer_reports_the_capacity_of_its_depth() {
+        // 16x16 RGBA has 1024 subpixels, minus the 13 bytes of the frame header
+        for (bits, capacity) in [(1, 115), (2, 243), (3, 371), (4, 499)] {
+            let encoder = NaiveEncoder::with_framing(Framing::LengthPrefixed).with_bits(bits);
+            let writer = PngSecretWriter::new(RgbaImage::new(16, 16), Box::new(encoder));
+            let info = ImageInfo {
+                width: 16,
+                height: 16,
+                capacity: Some(capacity),
+            };
+            assert_eq!(writer.info(), info);
+        }
+        let reader = PngSecretReader::new(RgbaImage::new(3, 5), Box::new(NaiveDecoder::new()));
+        assert_eq!(reader.info().capacity, None);
+    }
+
+    #[test]
+    fn naive_encoder_correct_normal() {
+        let raw_message = "Hello World!";
+        let mut encoder = NaiveEncoder::new();
+        encoder.encode(raw_message.as_bytes());
+        let mut expected_message = Vec::from(raw_message.as_bytes());
+        let encode_message = encoder.text().to_vec();
+        expected_message.push(0);
+        println!("{:?} {:?}", expected_message, encode_message);
+        assert!(expected_message
+            .iter()
+            .zip(encode_message.iter())
+            .all(|(a, b)| a == b));
+    }
+
+    #[test]
+    fn naive_encoder_correct_empty() {
+        let raw_message = "";
+        let mut encoder = NaiveEncoder::new();
+        encoder.encode(raw_message.as_bytes());
+        let mut expected_message = Vec::from(raw_message.as_bytes());
+        let encode_message = encoder.text().to_vec();
+        expected_message.push(0);
+        println!("{:?} {:?}", expected_message, encode_message);
+        assert!(expected_message
+            .iter()
+            .zip(encode_message.iter())
+            .all(|(a, b)| a == b));
+    }
+
+    #[test]
+    fn naive_encoder_correct_long() {
+        let raw_message = "Under the surface, the assert_eq! and assert_ne! macros use the operators == and !=, respectively. When the assertions fail, these macros print their arguments using debug formatting, which means the values being compared must implement the PartialEq and Debug traits. All primitive types and most of the standard library types implement these traits. For structs and enums that you define yourself, you’ll need to implement PartialEq to assert equality of those types. You’ll also need to implement Debug to print the values when the assertion fails. Because both traits are derivable traits, as mentioned in Listing 5-12 in Chapter 5, this is usually as straightforward as adding the #[derive(PartialEq, Debug)] annotation to your struct or enum definition. See Appendix C, “Derivable Traits,” for more details about these and other derivable traits.";
+        let mut encoder = NaiveEncoder::new();
+        encoder.encode(raw_message.as_bytes());
+        let mut expected_message = Vec::from(raw_message.as_bytes());
+        let encode_message = encoder.text().to_vec();
+        expected_message.push(0);
+        println!("{:?} {:?}", expected_message, encode_message);
+        assert!(expected_message
+            .iter()
+            .zip(encode_message.iter())
+            .all(|(a, b)| a == b));
+    }
+
+    #[test]
+    fn block_permutation_disperses_small_payloads() {
+        // Every subpixel has its low bit set, so each embedded zero bit is a visible change
+        let cover = RgbaImage::from_pixel(64, 64, image::Rgba([1, 1, 1, 1]));
+        let order = SubpixelOrder::Blocks {
+            block_size: 8,
+            seed: 7,
+        };
+        let sequential = embed_with(cover.clone(), b"hi there", SubpixelOrder::Sequential);
+        let permuted = embed_with(cover.clone(), b"hi there", order);
+        assert!(changed_span(&cover, &sequential) < 72);
+        assert!(changed_span(&cover, &permuted) > cover.len() / 4);
+    }
+
+    #[test]
+    fn block_permutation_needs_the_same_seed() {
+        let cover = RgbaImage::new(32, 32);
+        let order = |seed| SubpixelOrder::Blocks {
+            block_size: 16,
+            seed,
+        };
+        let stego = embed_with(cover, b"secret", order(1));
+        let read = |order| {
+            PngSecretReader::new(stego.clone(), Box::new(NaiveDecoder::new()))
+                .with_order(order)
+                .with_verify(false)
+                .read_image()
+                .ok()
+        };
+        assert_eq!(read(order(1)), Some(b"secret".to_vec()));
+        assert_ne!(read(order(2)), Some(b"secret".to_vec()));
+        assert_ne!(read(SubpixelOrder::Sequential), Some(b"secret".to_vec()));
+    }
+
+    #[test]
+    fn padding_fills_the_slot_after_the_payload_with_noise() {
+        let encoder = NaiveEncoder::with_framing(Framing::LengthPrefixed);
+        let mut writer =
+            PngSecretWriter::new(RgbaImage::new(32, 32), Box::new(encoder)).with_padding(true);
+        writer.encoder.encode(b"padded");
+        writer.embed().unwrap();
+        let ones = writer
+            .buffer
+            .iter()
+            .filter(|sample| *sample & 1 == 1)
+            .count();
+        assert!((1638..2458).contains(&ones), "{}", ones);
+        assert_eq!(extract(writer.buffer), Ok(b"padded".to_vec()));
+    }
+
+    #[test]
+    fn offsets_wrap_around_and_must_match() {
+        // 256 subpixels, the framed payload takes 224 of them
+        let cover = RgbaImage::new(8, 8);
+        let embed_at = |offset| {
+            let encoder = NaiveEncoder::with_framing(Framing::LengthPrefixed);
+            let mut writer =
+                PngSecretWriter::new(cover.clone(), Box::new(encoder)).with_offset(offset);
+            writer.encoder.encode(b"wraps past the end");
+            writer.embed().map(|()| writer.buffer)
+        };
+        let read_at = |stego: &RgbaImage, offset| {
+            PngSecretReader::new(stego.clone(), Box::new(NaiveDecoder::new()))
+                .with_offset(offset)
+                .read_image()
+        };
+        for offset in [0, 1, 200] {
+            let stego = embed_at(offset).unwrap();
+            assert_eq!(read_at(&stego, offset).unwrap(), b"wraps past the end");
+            assert!(read_at(&stego, (offset + 8) % 256).is_err(), "{}", offset);
+        }
+        // The wrapped payload runs from subpixel 200 to the end and on from the start
+        let stego = embed_at(200).unwrap();
+        assert!(changed_span(&cover, &stego) > 200);
+
+        let out_of_range = Error::OffsetOutOfRange {
+            offset: 256,
+            subpixels: 256,
+        };
+        assert_eq!(embed_at(256), Err(out_of_range.clone()));
+        assert_eq!(read_at(&stego, 256), Err(out_of_range));
+    }
+
+    #[test]
+    fn matching_moves_samples_both_ways_and_repeats_with_its_seed() {
+        let cover = RgbaImage::from_fn(32, 32, |x, y| {
+            image::Rgba([(x * 8) as u8, (y * 8) as u8, 0, 255])
+        });
+        let embed_with_seed = |seed| {
+            let encoder = NaiveEncoder::with_framing(Framing::LengthPrefixed);
+            let mut writer = PngSecretWriter::new(cover.clone(), Box::new(encoder))
+                .with_embedding(Embedding::Match { seed });
+            writer.encoder.encode(&[0xA5; 100]);
+            writer.embed().unwrap();
+            writer.buffer
+        };
+        let stego = embed_with_seed(3);
+        let deltas: Vec<i16> = cover
+            .iter()
+            .zip(stego.iter())
+            .map(|(before, after)| *after as i16 - *before as i16)
+            .collect();
+        assert!(deltas.contains(&1) && deltas.contains(&-1));
+        assert!(deltas.iter().all(|delta| delta.abs() <= 1));
+        // Blue 0 can only go up, alpha 255 only down
+        assert!(stego.pixels().all(|pixel| pixel[2] <= 1 && pixel[3] >= 254));
+        assert_eq!(embed_with_seed(3), stego);
+        assert_ne!(embed_with_seed(4), stego);
+        assert_eq!(extract(stego), Ok(vec![0xA5; 100]));
+    }
+
+    #[test]
+    fn wide_strip_roundtrip() {
+        let strip = RgbaImage::new(200_000, 4);
+        let writer = PngSecretWriter::new(strip.clone(), Box::new(NaiveEncoder::new()));
+        assert_eq!(writer.capacity(), 399_999);
+        let order = SubpixelOrder::Blocks {
+            block_size: 4096,
+            seed: 3,
+        };
+        let stego = embed_with(strip, b"tile sheet", order);
+        let mut reader = PngSecretReader::new(stego, Box::new(NaiveDecoder::new()))
+            .with_order(order)
+            .with_verify(false);
+        assert_eq!(reader.read_image().unwrap(), b"tile sheet");
+    }
+
+    /// An image whose LSBs, in sequential order, are `bit(i)`
+    fn lsb_image(width: u32, height: u32, bit: impl Fn(usize) -> u8) -> RgbaImage {
+        let mut img = RgbaImage::new(width, height);
+        for (i, sample) in img.iter_mut().enumerate() {
+            *sample = 0x10 | bit(i);
+        }
+        img
+    }
+
+    /// Read `img` as legacy with `limit`, returning the result and the bytes traced
+    fn scan(img: RgbaImage, limit: usize) -> (Result<Vec<u8>, Error>, usize) {
+        let mut traced = 0;
+        let read = PngSecretReader::new(img, Box::new(NaiveDecoder::new()))
+            .with_framing(Framing::Terminated)
+            .with_scan_limit(limit)
+            .read_image_traced(&mut |event| {
+                if let ReadEvent::Byte { .. } = event {
+                    traced += 1;
+                }
+            });
+        (read, traced)
+    }
+
+    #[test]
+    fn legacy_scan_stops_at_the_limit() {
+        // 512x512 of ones would be read as 128 KiB without a terminator
+        let (read, traced) = scan(lsb_image(512, 512, |_| 1), 1000);
+        assert_eq!(read, Err(Error::NoMessage { scanned: 1000 }));
+        assert_eq!(traced, 1000);
+        let (read, _) = scan(lsb_image(512, 512, |_| 1), usize::MAX);
+        assert_eq!(read, Err(Error::NoMessage { scanned: 131_072 }));
+        // A message of exactly the limit still has its terminator read
+        let (read, _) = scan(lsb_image(8, 8, |i| (i < 80) as u8), 10);
+        assert_eq!(read, Ok(vec![0xFF; 10]));
+    }
+
+    quickcheck! {
+        fn block_permutation_roundtrip(payload: Vec<u8>, block_size: u8, seed: u64) -> bool {
+            // 13x7 RGBA leaves a partial last block for most block sizes
+            let payload: Vec<u8> = payload.into_iter().filter(|&b| b != 0).take(40).collect();
+            let order = SubpixelOrder::Blocks { block_size: block_size as usize + 1, seed };
+            let stego = embed_with(RgbaImage::new(13, 7), &payload, order);
+            let read = PngSecretReader::new(stego, Box::new(NaiveDecoder::new()))
+                .with_order(order)
+                .with_verify(false)
+                .read_image()
+                .ok();
+            read == (!payload.is_empty()).then_some(payload)
+        }
+
+        fn capacity_is_the_exact_embedding_limit(width: u8, height: u8, bits: u8) -> bool {
+            let bits = bits % format::MAX_BITS + 1;
+            // From 5x5 on, the slot holds at least the frame header
+            let img = RgbaImage::new(width as u32 % 40 + 5, height as u32 % 40 + 5);
+            let capacity = capacity_bytes(&img, bits);
+            let embed = |len: usize| {
+                let encoder = NaiveEncoder::with_framing(Framing::LengthPrefixed).with_bits(bits);
+                let mut writer = PngSecretWriter::new(img.clone(), Box::new(encoder));
+                writer.encoder.encode(&vec![0xA5; len]);
+                writer.capacity() == capacity && writer.embed().is_ok()
+            };
+            let default_depth = bits != format::DEFAULT_BITS
+                || (super::embed(img.clone(), &vec![1; capacity]).is_ok()
+                    && super::embed(img.clone(), &vec![1; capacity + 1]).is_err());
+            embed(capacity) && !embed(capacity + 1) && default_depth
+        }
+
+        fn arbitrary_images_roundtrip(payload: Vec<u8>, width: u8, height: u8) -> bool {
+            // Down to empty images and single rows, which hold no frame header at all
+            let img = RgbaImage::new(width as u32 % 48, height as u32 % 48);
+            let capacity = capacity_bytes(&img, format::DEFAULT_BITS);
+            // Too small for the frame header, not even an empty payload fits
+            let overhead = Framing::LengthPrefixed.overhead_bytes();
+            let fits = payload.len() as u64 + overhead
+                <= format::slot_bytes(img.len() as u64, format::DEFAULT_BITS);
+            match super::embed(img, &payload) {
+                Ok(stego) => fits && extract(stego) == Ok(payload),
+                Err(Error::PayloadTooLarge { capacity: reported, requested }) => {
+                    !fits && reported == capacity && requested == payload.len()
+                }
+                Err(_) => false,
+            }
+        }
+
+        fn embedding_moves_each_channel_by_at_most_one(payload: Vec<u8>, seed: u8) -> bool {
+            // Channel values at both ends, where flipping the LSB must not wrap around
+            let cover = RgbaImage::from_fn(17, 11, |x, y| {
+                let v = (x * 31 + y * 7 + seed as u32) as u8;
+                image::Rgba([v, 255 - v, v | 1, v & !1])
+            });
+            let payload: Vec<u8> = payload.into_iter().take(80).collect();
+            let stego = super::embed(cover.clone(), &payload).unwrap();
+            cover.iter().zip(stego.iter()).all(|(a, b)| a.abs_diff(*b) <= 1)
+        }
+
+        fn matching_roundtrips_at_every_depth(payload: Vec<u8>, bits: u8, seed: u64) -> bool {
+            let bits = bits % format::MAX_BITS + 1;
+            let payload: Vec<u8> = payload.into_iter().take(100).collect();
+            // Both ends of the range, where only one direction is left
+            let cover = RgbaImage::from_fn(13, 7, |x, y| {
+                image::Rgba([0, 255, (x * 19 + y * 37) as u8, 254 - x as u8])
+            });
+            let encoder = NaiveEncoder::with_framing(Framing::LengthPrefixed).with_bits(bits);
+            let mut writer = PngSecretWriter::new(cover.clone(), Box::new(encoder))
+                .with_embedding(Embedding::Match { seed });
+            writer.encoder.encode(&payload);
+            if writer.embed().is_err() {
+                return payload.len() > writer.capacity();
+            }
+            let least_moved = cover
+                .iter()
+                .zip(writer.buffer.iter())
+                .all(|(before, after)| before.abs_diff(*after) < 1 << bits);
+            let read = PngSecretReader::new(writer.buffer, Box::new(NaiveDecoder::new()))
+                .with_bits(bits)
+                .read_image();
+            least_moved && read == Ok(payload)
+        }
+
+        fn packed_and_per_subpixel_paths_agree(payload: Vec<u8>, seed: Option<u64>) -> bool {
+            let cover = RgbaImage::from_fn(23, 9, |x, y| {
+                image::Rgba([(x * 11 + y * 3) as u8, 255, 0, (x ^ y) as u8])
+            });
+            let embedding = seed.map_or(Embedding::Replace, |seed| Embedding::Match { seed });
+            let payload: Vec<u8> = payload.into_iter().take(80).collect();
+            let writer = || {
+                let encoder = NaiveEncoder::with_framing(Framing::LengthPrefixed);
+                let mut writer = PngSecretWriter::new(cover.clone(), Box::new(encoder))
+                    .with_embedding(embedding);
+                writer.encoder.encode(&payload);
+                writer
+            };
+            // Tracing forces the subpixel by subpixel path
+            let (mut packed, mut traced) = (writer(), writer());
+            packed.embed().unwrap();
+            traced.embed_traced(&mut |_| {}).unwrap();
+            let samples = packed.buffer.as_raw();
+            let read_per_subpixel =
+                read_per_subpixel(samples, 4, Slot::All, &SubpixelOrder::Sequential, 0, 1);
+            packed.buffer == traced.buffer
+                && read_packed(samples).eq(read_per_subpixel)
+                && extract(packed.buffer).ok() == Some(payload)
+        }
+
+        fn every_depth_roundtrips(payload: Vec<u8>, bits: u8, seed: u64) -> bool {
+            let bits = bits % format::MAX_BITS + 1;
+            let payload: Vec<u8> = payload.into_iter().take(150).collect();
+            let order = SubpixelOrder::Blocks { block_size: 7, seed };
+            let cover = RgbaImage::from_fn(13, 7, |x, y| {
+                image::Rgba([x as u8 * 19, y as u8 * 37, (x * y) as u8, 255 - x as u8])
+            });
+            let encoder = NaiveEncoder::with_framing(Framing::LengthPrefixed).with_bits(bits);
+            let mut writer = PngSecretWriter::new(cover.clone(), Box::new(encoder)).with_order(order);
+            writer.encoder.encode(&payload);
+            if writer.embed().is_err() {
+                return payload.len() > writer.capacity();
+            }
+            let high_bits_kept = cover
+                .iter()
+                .zip(writer.buffer.iter())
+                .all(|(before, after)| before >> bits == after >> bits);
+            let read = PngSecretReader::new(writer.buffer, Box::new(NaiveDecoder::new()))
+                .with_order(order)
+                .with_bits(bits)
+                .read_image();
+            high_bits_kept && read == Ok(payload)
+        }
+
+        fn adversarial_legacy_scans_are_bounded(pattern: u8, burst_at: u16, limit: u16) -> bool {
+            let (burst_at, limit) = (burst_at as usize, limit as usize);
+            // 64x64 RGBA holds 2048 legacy bytes
+            let bit = move |i: usize| match pattern % 3 {
+                0 => (i % 2) as u8,
+                1 => 1,
+                _ => !(burst_at..burst_at + 8).contains(&i) as u8,
+            };
+            let (read, traced) = scan(lsb_image(64, 64, bit), limit);
+            let bounded = traced <= limit.min(2048);
+            let aligned =
+                pattern % 3 == 2 && burst_at % 8 == 0 && burst_at / 8 <= limit.min(2047);
+            bounded && match read {
+                Ok(message) => aligned && burst_at > 0 && message.len() == burst_at / 8,
+                // A terminator up front is no message, like the zero LSBs of a clean image
+                Err(Error::NoMessage { scanned: 0 }) if aligned && burst_at == 0 => true,
+                Err(Error::NoMessage { scanned }) => !aligned && scanned == limit.min(2048),
+                Err(_) => false,
+            }
+        }
+
+        fn bits_after_the_terminator_are_never_read(payload: Vec<u8>, noise: Vec<u8>) -> bool {
+            // Whatever follows the terminator, e.g. leftovers of an older, longer payload
+            let payload: Vec<u8> = payload.into_iter().filter(|&b| b != 0).take(40).collect();
+            let mut stego = embed_with(RgbaImage::new(13, 7), &payload, SubpixelOrder::Sequential);
+            let end = (payload.len() + 1) * 8;
+            let noise = noise.iter().flat_map(byte_to_8bits).chain(std::iter::repeat(1));
+            for (sample, bit) in stego.iter_mut().skip(end).zip(noise) {
+                *sample = *sample - (*sample % 2) + bit;
+            }
+            let mut last_read = 0;
+            let read = PngSecretReader::new(stego, Box::new(NaiveDecoder::new()))
+                .with_verify(false)
+                .read_image_traced(&mut |event| {
+                    if let ReadEvent::Byte { subpixels, .. } | ReadEvent::Terminator { subpixels, .. } = event {
+                        last_read = subpixels[7];
+                    }
+                });
+            read.ok() == (!payload.is_empty()).then_some(payload) && last_read == end - 1
+        }
+
+        fn naive_encoder_length(message:String)->bool {
+            let raw_message = message;
+            let mut encoder = NaiveEncoder::new();
+            encoder.encode(raw_message.as_bytes());
+            let mut expected_message = Vec::from(raw_message.as_bytes());
+            expected_message.push(0);
+            let encode_message = encoder.text().to_vec();
+            return encode_message.len() == expected_message.len() ;
+
+        }
+
+        fn naive_encoder_content(message: String)->bool {
+            let raw_message = message;
+            let mut encoder = NaiveEncoder::new();
+            encoder.encode(raw_message.as_bytes());
+            let mut expected_message = Vec::from(raw_message.as_bytes());
+            expected_message.push(0);
+            let encode_message = encoder.text().to_vec();
+            return expected_message.iter().zip(encode_message.iter()).all(|(a, b)| a== b) ;
+        }
+    }
+}
@@ -1,20 +1,87 @@
-use image::RgbaImage;
-use std::path::PathBuf;
-use std::sync::OnceLock;
+use encoding::PayloadEncoding;
+use error::PngSecretError;
+use image::DynamicImage;
+use order::{Slot, SubpixelOrder};
+use output::Channel;
+use pngsecret::carrier;
+use pngsecret::slots;
+use pngsecret::{
+    bytesize, format, order, NaiveDecoder, NaiveEncoder, PngSecretReader, PngSecretWriter,
+    ReadEvent,
+};
+use render::Crop;
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
+use structopt::clap::{Error as ClapError, ErrorKind as ClapErrorKind};
 use structopt::StructOpt;
+use summary::{Outcome, Summary};
 
-// TODO: Should find better name
-#[derive(Debug, Clone)]
-struct ReaderError;
+mod analysis;
+mod armor;
+mod artifacts;
+mod batch;
+mod buildinfo;
+mod checksum;
+mod chunks;
+mod confirm;
+mod container;
+mod decode;
+mod demo;
+mod doctor;
+mod encode;
+mod encoding;
+mod error;
+mod fixtures;
+mod fsguard;
+mod hook;
+mod inject;
+mod input;
+mod layout;
+mod output;
+mod paths;
+mod pngio;
+mod preflight;
+mod probe;
+mod progress;
+mod receipt;
+mod render;
+mod report;
+mod sanitize;
+mod sniff;
+mod spread;
+mod stamp;
+mod stats;
+mod summary;
+mod sweep;
+mod timefmt;
+mod trace;
+mod verify;
+mod visualize;
+mod wipe;
+mod wizard;
 
-static SILENT: OnceLock<bool> = OnceLock::new();
+#[cfg(test)]
+mod compat;
+#[cfg(test)]
+#[path = "../tests/regressions/mod.rs"]
+mod regressions;
 
-#[derive(Debug, StructOpt)]
+#[derive(Debug, Clone, StructOpt)]
 #[structopt(
     name = "PngSecret",
-    about = "A simple tool to embed secret bytes to png images"
+    about = "A simple tool to embed secret bytes to png images",
+    global_settings = &[structopt::clap::AppSettings::DisableVersion]
 )]
 struct Opt {
+    #[structopt(
+        short = "V",
+        long,
+        help = "print the version, with -v also the supported formats, codecs and backends"
+    )]
+    version: bool,
+
     #[structopt(short, long, help = "reduce stdout print")]
     silent: bool,
 
@@ -22,288 +89,1412 @@ struct Opt {
     encode: bool,
 
     #[structopt(
+        short,
         long,
-        default_value = "Hello World",
-        help = "the secret you want to embed"
+        alias = "force",
+        help = "replace existing output files without asking for confirmation"
     )]
-    text: String,
+    yes: bool,
 
-    #[structopt(short, long, parse(from_os_str), help = "RGBA image file expected")]
-    input: PathBuf,
+    #[structopt(
+        short,
+        long,
+        parse(from_occurrences),
+        help = "log the internal steps to stderr, repeat for more detail"
+    )]
+    verbose: u8,
+
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "the secret you want to embed, read from stdin without it or --file; repeat it \
+                with a --name for each to embed several records"
+    )]
+    text: Vec<String>,
+
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "on encode, the name of the record for each --text, in order; on decode, print \
+                only the record of this name"
+    )]
+    name: Vec<String>,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &["encode", "name"],
+        help = "print the name and stored size of every record instead of decoding one"
+    )]
+    list: bool,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &[
+            "encode", "output", "list", "json", "format", "raw", "payload-encoding", "name",
+            "exec-on-success",
+        ],
+        help = "check that the image holds a valid payload and print only what it is, never its \
+                content: size, method, depth, and whether it is compressed and UTF-8; \
+                with a directory as --input, scan every PNG in it"
+    )]
+    inspect: bool,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        conflicts_with = "text",
+        help = "embed the bytes of this file instead of --text"
+    )]
+    file: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        help = "write the old NUL-terminated format, which can't carry NUL bytes; on decode, \
+                don't look for a length header"
+    )]
+    legacy: bool,
+
+    #[structopt(
+        long,
+        conflicts_with = "encode",
+        help = "read payloads without checking them against the checksum of their frame, for \
+                images written before payloads had one"
+    )]
+    no_verify: bool,
+
+    #[structopt(
+        long,
+        possible_values = &["1", "2", "3", "4"],
+        help = "embed in this many low bits of each channel, 1 unless given, more capacity for \
+                more visible noise; decode reads the depth from the frame header"
+    )]
+    bits: Option<u8>,
+
+    #[structopt(
+        long,
+        conflicts_with = "legacy",
+        help = "deflate the payload before embedding, decode detects and inflates it on its own"
+    )]
+    compress: bool,
+
+    #[structopt(
+        long,
+        value_name = "parity-bytes",
+        conflicts_with = "legacy",
+        help = "add this many Reed-Solomon parity bytes to every 255 byte block of the payload, \
+                2 to 128, to repair up to half as many damaged bytes per block; decode detects \
+                and corrects on its own"
+    )]
+    ecc: Option<u8>,
+
+    #[structopt(
+        long,
+        possible_values = &["lsb", "chunk"],
+        conflicts_with_all = &["legacy", "ecc", "alpha-payload", "dry-run"],
+        help = "where encode hides the payload: lsb for the pixels, the default, or chunk for a \
+                private stEg chunk of a PNG that leaves the pixels untouched; decode tries the \
+                chunk, then the pixels, unless given"
+    )]
+    method: Option<Method>,
+
+    #[structopt(
+        long,
+        default_value = "match",
+        possible_values = &["match", "replace"],
+        help = "how encode changes a sample whose low bits differ from the payload's: match moves \
+                it to the nearest value that has them, up or down at random, which chi-square \
+                attacks don't pick up, replace overwrites them; --seed or --key make match \
+                reproducible"
+    )]
+    embedding: EmbeddingMode,
+
+    #[structopt(
+        short,
+        long,
+        parse(from_os_str),
+        number_of_values = 1,
+        help = "RGBA image file expected, or a directory to process every PNG in it into the \
+                --output directory; given more than once, spread one payload over all the images, \
+                or put it back together from them in any order"
+    )]
+    input: Vec<PathBuf>,
+
+    /// The segment of a payload spread over several images that encode embeds instead of the
+    /// payload, see [`spread`]
+    #[structopt(skip)]
+    segment: Option<Vec<u8>>,
 
     #[structopt(
         short,
         long,
         parse(from_os_str),
-        help = "optional, output would be *.enc.png if skipped"
+        help = "optional, output would be *.enc.<input extension> if skipped, saved as BMP, TIFF \
+                or WebP by its extension and PNG otherwise; on decode, save the message here byte \
+                for byte"
     )]
     output: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        possible_values = &["auto", "text", "raw"],
+        help = "how decode prints the message, auto refuses to print binary content; by default \
+                raw when stdout isn't a terminal and auto otherwise"
+    )]
+    format: Option<DecodeFormat>,
+
+    #[structopt(
+        long,
+        possible_values = &["raw", "base64", "hex"],
+        conflicts_with_all = &["format", "raw", "json"],
+        help = "on encode, decode every --text from this encoding; on decode, print the message \
+                in it"
+    )]
+    payload_encoding: Option<PayloadEncoding>,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &["payload-encoding", "format", "raw", "json"],
+        help = "on encode, read \\\\, \\n, \\t, \\0 and \\xNN in every --text as the bytes they stand \
+                for; on decode, print the message with them"
+    )]
+    escape: bool,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &["text", "file"],
+        help = "read the message from stdin until end of file, even from a terminal"
+    )]
+    text_stdin: bool,
+
+    #[structopt(
+        long,
+        conflicts_with = "format",
+        help = "write the message bytes to stdout as they are, same as --format raw"
+    )]
+    raw: bool,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &["armor", "dry-run", "list", "format", "raw"],
+        help = "print the result, or the error, as one JSON object on stdout instead of the output \
+                path or the payload"
+    )]
+    json: bool,
+
+    #[structopt(
+        long,
+        requires = "encode",
+        help = "write the result to stdout as armored base64 text for text-only channels; with \
+                -o it is also saved there"
+    )]
+    armor: bool,
+
+    #[structopt(
+        long,
+        conflicts_with = "encode",
+        help = "read the image as armored text from --input, or from stdin without it or with -i -"
+    )]
+    dearmor: bool,
+
+    #[structopt(
+        long,
+        help = "refuse to embed payloads larger than this, e.g. 512KiB or 2MB"
+    )]
+    max_payload: Option<String>,
+
+    #[structopt(
+        long,
+        help = "read bare K/M/G suffixes and fractions in sizes as SI units"
+    )]
+    si: bool,
+
+    #[structopt(
+        long,
+        help = "render x,y,width,height of the cover and the result side by side before saving"
+    )]
+    preview_crop: Option<Crop>,
+
+    #[structopt(long, help = "create the output directory if it doesn't exist")]
+    create_dirs: bool,
+
+    #[structopt(
+        long,
+        help = "free space required in the output directory, defaults to 1.5x the input size"
+    )]
+    min_free_space: Option<String>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        env = "PNGSECRET_STATS_FILE",
+        help = "append anonymous usage statistics of this run to a local file"
+    )]
+    stats_file: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "also write the closing summary of the run to this file as a JSON object"
+    )]
+    summary_json: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        help = "also store this Latin-1 text in a visible tEXt chunk next to the pixel payload"
+    )]
+    also_chunk_text: Option<String>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "embed this file as a second payload in the alpha channel, independent of --text"
+    )]
+    alpha_payload: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        help = "after decoding, run this command with {payload_path} and {source} filled in; \
+                a JSON array runs as argv without a shell"
+    )]
+    exec_on_success: Option<hook::Hook>,
+
+    #[structopt(
+        long,
+        requires = "exec-on-success",
+        help = "keep the temp file holding the payload for --exec-on-success"
+    )]
+    keep_temp: bool,
+
+    #[structopt(
+        long,
+        help = "embed as much of the payload as fits instead of failing when it's too large"
+    )]
+    truncate_to_fit: bool,
+
+    #[structopt(
+        long,
+        help = "fill the rest of the slot after the payload with random bits, so covers whose \
+                lowest bits are nearly all the same don't show where the payload ends"
+    )]
+    pad_to_capacity: bool,
+
+    #[structopt(
+        long,
+        requires = "encode",
+        help = "report the capacity, the encoded payload size and the headroom without embedding \
+                or writing anything; fails like encode if the payload doesn't fit"
+    )]
+    dry_run: bool,
+
+    #[structopt(
+        long,
+        help = "fail instead of warning when the cover's structure would expose the payload; on \
+                decode, fail when stdout is closed before the whole payload was written"
+    )]
+    strict: bool,
+
+    #[structopt(
+        long = "force-8bit",
+        help = "embed into covers the output can't keep as they are, palettes and float samples, \
+                and save them with 8-bit channels; 16-bit covers are then converted too instead \
+                of staying 16-bit"
+    )]
+    force_8bit: bool,
+
+    #[structopt(
+        long,
+        help = "fail instead of saving when the estimated detectability exceeds this score \
+                between 0 and 1"
+    )]
+    max_detectability: Option<analysis::Score>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        requires = "sign-key",
+        help = "write a signed receipt with hashes of the output and the payload"
+    )]
+    receipt: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Ed25519 key to sign the --receipt with, see `receipt keygen`"
+    )]
+    sign_key: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        help = "write a .psum sidecar next to the output with hashes of it and the payload, see \
+                `verify --checksum`"
+    )]
+    write_checksum: bool,
+
+    #[structopt(
+        long,
+        help = "on Windows, prefix long output paths with \\\\?\\ to lift the 260 character limit"
+    )]
+    long_paths: bool,
+
+    #[structopt(
+        long,
+        default_value = "3",
+        help = "times to re-read an input that changes while being read, with backoff"
+    )]
+    modified_retries: u32,
+
+    #[structopt(
+        long,
+        help = "use this frame of an animated WebP input as the cover, the first is 0"
+    )]
+    frame: Option<usize>,
+
+    #[structopt(
+        long,
+        default_value = "rfc3339-utc",
+        help = "how times are printed: rfc3339-utc, local, unix or a strftime pattern with the \
+                date, time and a numeric offset; JSON always has the epoch value and UTC"
+    )]
+    time_format: timefmt::TimeFormat,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "write which subpixel each payload bit went to or came from into this file, for \
+                `trace diff`"
+    )]
+    trace_indices: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        default_value = "4096",
+        help = "how many bits from the start of the payload --trace-indices records"
+    )]
+    trace_bits: usize,
+
+    #[structopt(flatten)]
+    order: OrderOpt,
+
+    #[structopt(subcommand)]
+    cmd: Option<Command>,
 }
 
-fn main() {
-    let opt = Opt::from_args();
-    if opt.silent && SILENT.set(opt.silent).is_err() {
-        println!("cannot set global variable silent!");
-        return;
+impl Opt {
+    /// The depth encode writes at, [`format::DEFAULT_BITS`] unless `--bits` says otherwise
+    fn bits(&self) -> u8 {
+        self.bits.unwrap_or(format::DEFAULT_BITS)
     }
+}
 
-    if let Ok(img) = image::open(&opt.input) {
-        if opt.encode {
-            let output_filename = get_output_filename(&opt);
-            if SILENT.get().is_none() {
-                println!("output filename {:?}", output_filename);
-            }
-            let mut writer = PngSecretWriter::new(img.into_rgba8(), Box::new(NaiveEncoder::new()));
-            writer.encoder.encode(opt.text.as_bytes());
-            writer.write_image(output_filename);
-        } else {
-            let mut reader = PngSecretReader::new(img.into_rgba8(), Box::new(NaiveDecoder::new()));
-            if let Ok(raw_message) = reader.read_image() {
-                if let Ok(message) = String::from_utf8(raw_message.clone()) {
-                    if SILENT.get().is_none() {
-                        println!("Here is the message:");
-                    }
-                    println!("{:}", message);
-                } else {
-                    println!("The message cannot printed as string!");
-                    // TODO: Implement binary dump here for unparsable message
-                }
-            } else {
-                println!("This image doesn't have embedded message!")
-            }
+#[derive(Debug, Clone, StructOpt)]
+enum Command {
+    /// Work with the local usage statistics written by --stats-file
+    Stats(StatsCommand),
+    /// Create signing keys for --receipt and check receipts
+    Receipt(ReceiptCommand),
+    /// Stamp the commit and build id into a release screenshot, or read them back
+    Provenance(ProvenanceCommand),
+    /// Compare the files written by --trace-indices
+    Trace(TraceCommand),
+    /// Explain why decoding an image fails, or what it contains
+    Doctor {
+        #[structopt(short, long, parse(from_os_str))]
+        input: PathBuf,
+
+        #[structopt(
+            long,
+            help = "trace which subpixels the payload reader consumed and why it stopped"
+        )]
+        explain: bool,
+    },
+    /// List the payloads of an image without extracting them, the index is for decode --slot
+    #[structopt(alias = "detect")]
+    Info {
+        #[structopt(short, long, parse(from_os_str))]
+        input: PathBuf,
+    },
+    /// Remove the pixel payload or the chunk notice from an image, keeping the other
+    Wipe {
+        #[structopt(short, long, parse(from_os_str))]
+        input: PathBuf,
+
+        #[structopt(
+            short,
+            long,
+            parse(from_os_str),
+            help = "optional, output would be *.wiped.png if skipped"
+        )]
+        output: Option<PathBuf>,
+
+        #[structopt(long, possible_values = &["pixel", "chunk"], help = "which payload to remove")]
+        backend: Backend,
+
+        #[structopt(flatten)]
+        order: OrderOpt,
+    },
+    /// Remove every pngsecret trace from an image before publishing it
+    Sanitize {
+        #[structopt(short, long, parse(from_os_str))]
+        input: PathBuf,
+
+        #[structopt(
+            short,
+            long,
+            parse(from_os_str),
+            help = "optional, output would be *.sanitized.png if skipped"
+        )]
+        output: Option<PathBuf>,
+
+        #[structopt(
+            long,
+            default_value = "randomize",
+            possible_values = &["randomize", "zero", "keep"],
+            help = "what to do with the LSBs of every subpixel"
+        )]
+        lsb: sanitize::Lsb,
+    },
+    /// Ask step by step what to do, printing the equivalent command line before running it
+    Wizard,
+    /// Embed a payload with every configuration in memory and compare capacity and quality
+    Sweep {
+        #[structopt(short, long, parse(from_os_str))]
+        input: PathBuf,
+
+        #[structopt(long, parse(from_os_str), help = "file whose contents to embed")]
+        payload: PathBuf,
+
+        #[structopt(
+            long,
+            help = "sweep the cover itself instead of a downscaled working copy, slower"
+        )]
+        full_size: bool,
+    },
+    /// Write the payloads of several images to stdout back to back, in argument order
+    Cat {
+        #[structopt(parse(from_os_str), required = true)]
+        inputs: Vec<PathBuf>,
+
+        #[structopt(long, help = "leave out images without a message instead of failing")]
+        skip_missing: bool,
+
+        #[structopt(flatten)]
+        order: OrderOpt,
+    },
+    /// Check a stego file against the .psum sidecar written by --write-checksum
+    Verify {
+        #[structopt(long, parse(from_os_str))]
+        checksum: PathBuf,
+
+        #[structopt(
+            long,
+            parse(from_os_str),
+            help = "the stego file to check, by default the sidecar path without .psum"
+        )]
+        file: Option<PathBuf>,
+
+        #[structopt(long, help = "also decode the file and check the payload hash")]
+        decode: bool,
+    },
+    /// Check that every image of a manifest still decodes, for periodic archive sweeps
+    VerifyArchive {
+        #[structopt(long, parse(from_os_str))]
+        manifest: PathBuf,
+
+        #[structopt(long, help = "refuse every file write, for write-protected media")]
+        read_only: bool,
+    },
+    /// Count the channels a stego image changed in its cover and draw where they are
+    Diff {
+        #[structopt(parse(from_os_str))]
+        cover: PathBuf,
+
+        #[structopt(parse(from_os_str))]
+        stego: PathBuf,
+
+        #[structopt(
+            long,
+            parse(from_os_str),
+            help = "write a PNG with the modified pixels in red over the dimmed cover"
+        )]
+        out: Option<PathBuf>,
+    },
+    /// Encode and decode a sample payload in a temp dir, printing the command of every step
+    Demo {
+        #[structopt(
+            long,
+            help = "leave the cover, stego image and payloads in the temp dir"
+        )]
+        keep: bool,
+    },
+    /// Write a deterministic catalog of covers, stego images and JSON sidecars into a directory
+    #[structopt(setting = structopt::clap::AppSettings::Hidden)]
+    GenFixtures {
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+    },
+}
+
+impl Command {
+    /// Name of the subcommand in the summary
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Stats(_) => "stats",
+            Command::Receipt(_) => "receipt",
+            Command::Provenance(_) => "provenance",
+            Command::Trace(_) => "trace",
+            Command::Doctor { .. } => "doctor",
+            Command::Info { .. } => "info",
+            Command::Wipe { .. } => "wipe",
+            Command::Sanitize { .. } => "sanitize",
+            Command::Wizard => "wizard",
+            Command::Sweep { .. } => "sweep",
+            Command::Cat { .. } => "cat",
+            Command::Verify { .. } => "verify",
+            Command::VerifyArchive { .. } => "verify-archive",
+            Command::Diff { .. } => "diff",
+            Command::Demo { .. } => "demo",
+            Command::GenFixtures { .. } => "gen-fixtures",
         }
-    } else {
-        println!("The file {:?} couldn't be correctly read", opt.input);
     }
-    #[cfg(debug_assertions)]
-    println!("{:?}", opt);
 }
 
-fn get_output_filename(opt: &Opt) -> PathBuf {
-    match &opt.output {
-        Some(path) => path.clone(),
-        None => {
-            let mut temp = opt.input.to_owned();
-            temp.set_extension("enc.png");
-            temp
+/// Where a payload lives in the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    /// The LSBs of the pixel data
+    Pixel,
+    /// A tEXt chunk next to the pixel data
+    Chunk,
+}
+
+impl Backend {
+    const ALL: [Backend; 2] = [Backend::Pixel, Backend::Chunk];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Backend::Pixel => "pixel",
+            Backend::Chunk => "chunk",
         }
     }
 }
 
-/// This function split one byte into 8 bit, the element is still u8 to simplify the addition to
-/// pixel
-fn byte_to_8bits(byte: &u8) -> [u8; 8] {
-    let mut x = *byte;
-    let mut bits: [u8; 8] = [0; 8];
-    for i in 0..8 {
-        bits[7 - i] = x % 2;
-        x /= 2;
+impl std::str::FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Backend::ALL
+            .into_iter()
+            .find(|backend| backend.name() == s)
+            .ok_or_else(|| format!("unknown backend {:?}", s))
     }
-    bits
 }
 
-/// A Writer using the last ONE bit of the pixel RGBA channel to encode the message
-struct PngSecretWriter {
-    buffer: RgbaImage,
-    encoder: Box<dyn PngSecretEncoder>,
+/// Where encode hides the payload, `--method`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Method {
+    /// The low bits of the pixels
+    Lsb,
+    /// A private ancillary chunk, see [`chunks`]
+    Chunk,
 }
 
-impl PngSecretWriter {
-    fn new(img: RgbaImage, encoder: Box<dyn PngSecretEncoder>) -> Self {
-        if SILENT.get().is_none() {
-            println!(
-                "Image width {:}, Image Height {:}, message length limit {:} bytes",
-                img.width(),
-                img.height(),
-                img.width() * img.height() / 2 - 1,
-            );
-        }
-        PngSecretWriter {
-            buffer: img,
-            encoder,
+impl Method {
+    const ALL: [Method; 2] = [Method::Lsb, Method::Chunk];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Method::Lsb => "lsb",
+            Method::Chunk => "chunk",
         }
     }
-    fn write_image(&mut self, output_filename: PathBuf) {
-        let text = self.encoder.get_text();
-        if (self.buffer.width() * self.buffer.height()) < text.len() as u32 {
-            // TODO: Should find more elegant way to handle this error
-            println!("You are writing more message than the image could support!");
+}
+
+impl std::str::FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Method::ALL
+            .into_iter()
+            .find(|method| method.name() == s)
+            .ok_or_else(|| format!("unknown method {:?}", s))
+    }
+}
+
+/// How encode changes samples, `--embedding`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EmbeddingMode {
+    /// LSB matching, see [`Embedding::Match`]
+    Match,
+    Replace,
+}
+
+impl EmbeddingMode {
+    const ALL: [EmbeddingMode; 2] = [EmbeddingMode::Match, EmbeddingMode::Replace];
+
+    fn name(&self) -> &'static str {
+        match self {
+            EmbeddingMode::Match => "match",
+            EmbeddingMode::Replace => "replace",
         }
-        let mut text_iter = text.iter().flat_map(byte_to_8bits);
-        for i in self.buffer.iter_mut() {
-            if let Some(t) = text_iter.next() {
-                *i = *i - (*i % 2) + t;
-            } else {
-                break;
-            }
+    }
+}
+
+impl std::str::FromStr for EmbeddingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EmbeddingMode::ALL
+            .into_iter()
+            .find(|mode| mode.name() == s)
+            .ok_or_else(|| format!("unknown embedding {:?}", s))
+    }
+}
+
+/// How decode writes the message to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecodeFormat {
+    /// Print text, refuse binary content and suggest a file name instead
+    Auto,
+    /// Print the message as UTF-8 text, failing if it isn't
+    Text,
+    /// Write the message bytes unchanged
+    Raw,
+}
+
+impl std::str::FromStr for DecodeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(DecodeFormat::Auto),
+            "text" => Ok(DecodeFormat::Text),
+            "raw" => Ok(DecodeFormat::Raw),
+            _ => Err(format!("unknown format {:?}", s)),
         }
-        if self.buffer.save(output_filename.clone()).is_ok() {
-            if SILENT.get().is_none() {
-                println!("Writing modified image to file {:?}", output_filename);
-            }
-        } else {
-            println!("saving file failure");
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Permute {
+    None,
+    Blocks,
+    Subpixels,
+}
+
+impl FromStr for Permute {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Permute::None),
+            "blocks" => Ok(Permute::Blocks),
+            "subpixels" => Ok(Permute::Subpixels),
+            _ => Err(format!("unknown permutation {:?}", s)),
         }
     }
 }
 
-struct PngSecretReader {
-    buffer: RgbaImage,
-    decoder: Box<dyn PngSecretDecoder>,
+// Documented for rustdoc only, structopt would make a doc comment the about text of every
+// command flattening these options
+#[cfg_attr(
+    doc,
+    doc = "Command line options selecting the subpixel order, shared by every command touching \
+           payloads"
+)]
+#[derive(Debug, Clone, StructOpt)]
+struct OrderOpt {
+    #[structopt(
+        long,
+        default_value = "none",
+        possible_values = &["none", "blocks", "subpixels"],
+        help = "scatter the payload by shuffling blocks of subpixels or every subpixel, needs \
+                --seed or --key"
+    )]
+    permute: Permute,
+
+    #[structopt(
+        long,
+        default_value = "4096",
+        help = "subpixels per block for --permute blocks"
+    )]
+    block_size: usize,
+
+    #[structopt(
+        long,
+        help = "key of the permutation, must match between encode and decode"
+    )]
+    seed: Option<u64>,
+
+    #[structopt(
+        long,
+        conflicts_with = "seed",
+        help = "passphrase keying the permutation instead of a numeric --seed"
+    )]
+    key: Option<String>,
+
+    #[structopt(
+        long,
+        help = "channels holding the payload: all unless given, rgb or alpha, --alpha-payload \
+                moves the main payload to rgb; decode reads the slot from the frame header and \
+                also takes the index of a slot `info` lists"
+    )]
+    slot: Option<SlotArg>,
+
+    #[structopt(long, help = "leave every alpha byte as it is, the same as --slot rgb")]
+    skip_alpha: bool,
+
+    #[structopt(
+        long,
+        help = "start the payload this many subpixels into the slot's order, wrapping around to \
+                its start; decode looks for the frame header at every offset unless given"
+    )]
+    offset: Option<usize>,
 }
 
-impl PngSecretReader {
-    fn new(img: RgbaImage, decoder: Box<dyn PngSecretDecoder>) -> Self {
-        if SILENT.get().is_none() {
-            println!(
-                "Image width {:}, Image Height {:}",
-                img.width(),
-                img.height()
-            );
+/// `--slot`, a slot by name or one of the slots `info` lists by index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SlotArg {
+    Named(Slot),
+    Index(usize),
+}
+
+impl FromStr for SlotArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse() {
+            Ok(index) => Ok(SlotArg::Index(index)),
+            Err(_) => Slot::from_str(s).map(SlotArg::Named),
+        }
+    }
+}
+
+impl OrderOpt {
+    /// `--slot`, turned into the rgb slot by `--skip-alpha`, `None` without either
+    fn slot_arg(&self) -> Result<Option<SlotArg>, PngSecretError> {
+        match (self.skip_alpha, self.slot) {
+            (false, slot) => Ok(slot),
+            (true, None | Some(SlotArg::Named(Slot::All | Slot::Rgb))) => {
+                Ok(Some(SlotArg::Named(Slot::Rgb)))
+            }
+            (true, _) => Err(PngSecretError::Usage(
+                "--skip-alpha means --slot rgb and takes no other slot".to_string(),
+            )),
         }
-        PngSecretReader {
-            buffer: img,
-            decoder,
+    }
+
+    /// The named `--slot`, `None` unless given; an index only selects a slot on decode
+    fn given_slot(&self) -> Result<Option<Slot>, PngSecretError> {
+        match self.slot_arg()? {
+            None => Ok(None),
+            Some(SlotArg::Named(slot)) => Ok(Some(slot)),
+            Some(SlotArg::Index(index)) => Err(PngSecretError::Usage(format!(
+                "--slot {} picks a slot listed by `info`, only decode takes an index",
+                index
+            ))),
         }
     }
-    fn read_image(&mut self) -> Result<Vec<u8>, ReaderError> {
-        let mut message: Vec<u8> = Vec::new();
-        let pixel_iter = self.buffer.iter();
-        let mut count = 0;
-        let mut sum = 0;
-        for i in pixel_iter {
-            sum = sum * 2 + i % 2;
-            count += 1;
-            if count == 8 {
-                if sum == 0 {
-                    return Ok(self.decoder.decode(message));
+
+    /// The slot encode writes into, all subpixels unless `--slot` says otherwise
+    fn slot(&self) -> Result<Slot, PngSecretError> {
+        Ok(self.given_slot()?.unwrap_or_default())
+    }
+
+    /// The offset encode starts at, the start of the order unless `--offset` says otherwise
+    fn offset(&self) -> usize {
+        self.offset.unwrap_or(0)
+    }
+
+    fn order(&self) -> Result<SubpixelOrder, PngSecretError> {
+        let seed = |permute: &str| {
+            let key = self.key.as_deref().map(order::seed_from_key);
+            self.seed.or(key).ok_or_else(|| {
+                PngSecretError::Usage(format!("--permute {} requires --seed or --key", permute))
+            })
+        };
+        match self.permute {
+            Permute::None => Ok(SubpixelOrder::Sequential),
+            Permute::Subpixels => Ok(SubpixelOrder::Shuffled {
+                seed: seed("subpixels")?,
+            }),
+            Permute::Blocks => {
+                let seed = seed("blocks")?;
+                if self.block_size == 0 {
+                    return Err(PngSecretError::Usage(
+                        "--block-size must be positive".to_string(),
+                    ));
                 }
-                message.push(sum);
-                count = 0;
-                sum = 0;
+                Ok(SubpixelOrder::Blocks {
+                    block_size: self.block_size,
+                    seed,
+                })
             }
         }
-        Err(ReaderError)
     }
 }
 
-/// Encoder should support encode and write
-/// Could extend to support different encoding format and encryption scheme
-trait PngSecretEncoder {
-    /// The text should be carried within the encoder
-    fn encode(&mut self, seq: &[u8]);
-    fn get_text(&self) -> Vec<u8>;
+#[derive(Debug, Clone, StructOpt)]
+enum StatsCommand {
+    /// Print run counts per operation and flag usage
+    Summarize,
 }
 
-/// Decoder
-trait PngSecretDecoder {
-    fn decode(&mut self, seq: Vec<u8>) -> Vec<u8>;
+#[derive(Debug, Clone, StructOpt)]
+enum ReceiptCommand {
+    /// Create a signing key for --sign-key and print its public key
+    Keygen {
+        #[structopt(parse(from_os_str))]
+        key: PathBuf,
+    },
+    /// Check the signature of a receipt and that the file still matches it
+    Verify {
+        #[structopt(parse(from_os_str))]
+        receipt: PathBuf,
+
+        #[structopt(
+            long,
+            parse(from_os_str),
+            help = "the stego file the receipt was issued for"
+        )]
+        file: PathBuf,
+
+        #[structopt(long, help = "hex public key the receipt must be signed with")]
+        public_key: Option<String>,
+    },
 }
 
-// WARN: Is the data member really needed?
-struct NaiveEncoder {
-    text: Vec<u8>,
+#[derive(Debug, Clone, StructOpt)]
+enum ProvenanceCommand {
+    /// Hide the commit, build id and time of a build in an image
+    Stamp {
+        #[structopt(short, long, parse(from_os_str))]
+        input: PathBuf,
+
+        #[structopt(
+            short,
+            long,
+            parse(from_os_str),
+            help = "optional, output would be *.stamped.png if skipped"
+        )]
+        output: Option<PathBuf>,
+
+        #[structopt(long, help = "by default $GITHUB_SHA, $CI_COMMIT_SHA or $GIT_COMMIT")]
+        commit: Option<String>,
+
+        #[structopt(long, help = "by default $GITHUB_RUN_ID, $CI_PIPELINE_ID or $BUILD_ID")]
+        build_id: Option<String>,
+
+        #[structopt(
+            long,
+            help = "seconds since the Unix epoch, by default $SOURCE_DATE_EPOCH or the current time"
+        )]
+        timestamp: Option<u64>,
+
+        #[structopt(
+            long,
+            parse(from_os_str),
+            help = "key from `receipt keygen` to sign the stamp with"
+        )]
+        sign_key: Option<PathBuf>,
+    },
+    /// Print the provenance stamped into an image
+    Read {
+        #[structopt(short, long, parse(from_os_str))]
+        input: PathBuf,
+
+        #[structopt(long, help = "hex public key the stamp must be signed with")]
+        public_key: Option<String>,
+    },
 }
 
-struct NaiveDecoder {}
+#[derive(Debug, Clone, StructOpt)]
+enum TraceCommand {
+    /// Report the first bit two traces put into or took from different subpixels
+    Diff {
+        #[structopt(parse(from_os_str))]
+        a: PathBuf,
 
-impl PngSecretDecoder for NaiveDecoder {
-    fn decode(&mut self, seq: Vec<u8>) -> Vec<u8> {
-        seq
-    }
+        #[structopt(parse(from_os_str))]
+        b: PathBuf,
+    },
 }
 
-impl NaiveDecoder {
-    fn new() -> Self {
-        NaiveDecoder {}
+fn main() {
+    // Release builds abort on panic without running destructors, which would leave temp files
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        fsguard::remove_temp_files();
+        default_hook(info)
+    }));
+    let exit_codes = error::ErrorKind::exit_code_help();
+    let opt = Opt::from_clap(&Opt::clap().after_help(exit_codes.as_str()).get_matches());
+    init_logging(opt.verbose);
+
+    if opt.version {
+        print_version(opt.verbose > 0);
+        return;
+    }
+
+    if let Some(cmd) = &opt.cmd {
+        let started = Instant::now();
+        let mut summary = Summary::new(cmd.name());
+        let result =
+            run_command(&opt, cmd, &mut summary).and_then(|()| check_payload_delivered(&opt));
+        if let Err(e) = &result {
+            output::line(Channel::Diagnostics, e);
+        }
+        report_summary(&opt, summary, result.is_ok(), started);
+        if let Err(e) = result {
+            std::process::exit(e.exit_code());
+        }
+        return;
+    }
+
+    if opt.input.is_empty() && !opt.dearmor {
+        ClapError::with_description(
+            "The following required arguments were not provided: --input <input>",
+            ClapErrorKind::MissingRequiredArgument,
+        )
+        .exit();
+    }
+
+    let started = Instant::now();
+    let operation = if opt.encode { "encode" } else { "decode" };
+    let mut summary = Summary::new(operation);
+    let result = run(&opt, &mut summary).and_then(|()| check_payload_delivered(&opt));
+    if let Err(e) = &result {
+        output::line(Channel::Diagnostics, e);
+        if opt.json {
+            report::print(&report::Failed::from(e));
+        }
+    }
+    if let Some(stats_file) = &opt.stats_file {
+        let record = stats::Record::new(
+            operation,
+            &used_flags(&opt),
+            started.elapsed(),
+            result.is_ok(),
+        );
+        // Statistics are best effort and must never fail the actual operation
+        if let Err(e) = stats::append(stats_file, &record) {
+            output::line(
+                Channel::Diagnostics,
+                format_args!("Couldn't write statistics to {:?}: {}", stats_file, e),
+            );
+        }
+    }
+    report_summary(&opt, summary, result.is_ok(), started);
+    if let Err(e) = result {
+        std::process::exit(e.exit_code());
     }
 }
-impl PngSecretEncoder for NaiveEncoder {
-    fn encode(&mut self, seq: &[u8]) {
-        self.text = seq.to_vec();
-        self.text.push(0);
+
+/// Print the summary as the last line on stderr and write it to `--summary-json`
+///
+/// Like the statistics, a summary that can't be written never fails the run.
+fn report_summary(opt: &Opt, mut summary: Summary, success: bool, started: Instant) {
+    summary.finish(success, started.elapsed());
+    if !opt.silent {
+        output::line(Channel::Diagnostics, format_args!("Summary: {}", summary));
     }
-    fn get_text(&self) -> Vec<u8> {
-        self.text.clone()
+    if let Some(path) = &opt.summary_json {
+        if let Err(e) = summary::write_json(&summary, path) {
+            output::line(
+                Channel::Diagnostics,
+                format_args!("Couldn't write the summary to {:?}: {}", path, e),
+            );
+        }
     }
 }
 
-impl NaiveEncoder {
-    fn new() -> Self {
-        NaiveEncoder { text: Vec::new() }
+/// Under `--strict`, fail when the reader of stdout went away before the payload was written
+fn check_payload_delivered(opt: &Opt) -> Result<(), PngSecretError> {
+    match opt.strict && output::payload_closed() {
+        true => Err(PngSecretError::OutputClosed),
+        false => Ok(()),
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use quickcheck::quickcheck;
-
-    #[test]
-    fn naive_encoder_correct_normal() {
-        let raw_message = "Hello World!";
-        let mut encoder = NaiveEncoder::new();
-        encoder.encode(raw_message.as_bytes());
-        let mut expected_message = Vec::from(raw_message.as_bytes());
-        let encode_message = encoder.get_text();
-        expected_message.push(0);
-        println!("{:?} {:?}", expected_message, encode_message);
-        assert!(expected_message
-            .iter()
-            .zip(encode_message.iter())
-            .all(|(a, b)| a == b));
-    }
-
-    #[test]
-    fn naive_encoder_correct_empty() {
-        let raw_message = "";
-        let mut encoder = NaiveEncoder::new();
-        encoder.encode(raw_message.as_bytes());
-        let mut expected_message = Vec::from(raw_message.as_bytes());
-        let encode_message = encoder.get_text();
-        expected_message.push(0);
-        println!("{:?} {:?}", expected_message, encode_message);
-        assert!(expected_message
-            .iter()
-            .zip(encode_message.iter())
-            .all(|(a, b)| a == b));
-    }
-
-    #[test]
-    fn naive_encoder_correct_long() {
-        let raw_message = "Under the surface, the assert_eq! and assert_ne! macros use the operators == and !=, respectively. When the assertions fail, these macros print their arguments using debug formatting, which means the values being compared must implement the PartialEq and Debug traits. All primitive types and most of the standard library types implement these traits. For structs and enums that you define yourself, you’ll need to implement PartialEq to assert equality of those types. You’ll also need to implement Debug to print the values when the assertion fails. Because both traits are derivable traits, as mentioned in Listing 5-12 in Chapter 5, this is usually as straightforward as adding the #[derive(PartialEq, Debug)] annotation to your struct or enum definition. See Appendix C, “Derivable Traits,” for more details about these and other derivable traits.";
-        let mut encoder = NaiveEncoder::new();
-        encoder.encode(raw_message.as_bytes());
-        let mut expected_message = Vec::from(raw_message.as_bytes());
-        let encode_message = encoder.get_text();
-        expected_message.push(0);
-        println!("{:?} {:?}", expected_message, encode_message);
-        assert!(expected_message
-            .iter()
-            .zip(encode_message.iter())
-            .all(|(a, b)| a == b));
-    }
-    quickcheck! {
-        fn naive_encoder_length(message:String)->bool {
-            let raw_message = message;
-            let mut encoder = NaiveEncoder::new();
-            encoder.encode(raw_message.as_bytes());
-            let mut expected_message = Vec::from(raw_message.as_bytes());
-            expected_message.push(0);
-            let encode_message = encoder.get_text();
-            return encode_message.len() == expected_message.len() ;
+/// Print the version line, or with `verbose` everything [`buildinfo`] knows as text and JSON
+fn print_version(verbose: bool) {
+    if !verbose {
+        output::line(Channel::Payload, buildinfo::TOOL);
+        return;
+    }
+    let info = buildinfo::collect();
+    let json = serde_json::to_string_pretty(&info).expect("build info serializes");
+    output::write(
+        Channel::Payload,
+        format!("{}JSON:\n{}\n", info, json).as_bytes(),
+    );
+}
 
-        }
+/// Log the spans and events of the internal steps to stderr, more of them with each `-v`
+///
+/// Without `-v` no subscriber is installed and stderr only carries the regular diagnostics.
+/// Records of the `log` facade are forwarded to the same subscriber.
+fn init_logging(verbose: u8) {
+    let level = match verbose {
+        0 => return,
+        1 => tracing::Level::INFO,
+        2 => tracing::Level::DEBUG,
+        _ => tracing::Level::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+        .with_writer(std::io::stderr)
+        .init();
+}
 
-        fn naive_encoder_content(message: String)->bool {
-            let raw_message = message;
-            let mut encoder = NaiveEncoder::new();
-            encoder.encode(raw_message.as_bytes());
-            let mut expected_message = Vec::from(raw_message.as_bytes());
-            expected_message.push(0);
-            let encode_message = encoder.get_text();
-            return expected_message.iter().zip(encode_message.iter()).all(|(a, b)| a== b) ;
+fn run_command(opt: &Opt, cmd: &Command, summary: &mut Summary) -> Result<(), PngSecretError> {
+    match cmd {
+        Command::Stats(StatsCommand::Summarize) => {
+            let stats_file = opt.stats_file.as_ref().ok_or_else(|| {
+                PngSecretError::Usage(
+                    "No statistics file configured, pass --stats-file or set PNGSECRET_STATS_FILE"
+                        .to_string(),
+                )
+            })?;
+            let summary = stats::summarize(stats_file).map_err(|e| {
+                PngSecretError::Io(format!("Couldn't read statistics from {:?}", stats_file), e)
+            })?;
+            output::write(Channel::Payload, summary.to_string().as_bytes());
+            Ok(())
+        }
+        Command::Receipt(ReceiptCommand::Keygen { key }) => {
+            let public = receipt::generate_key(key)?;
+            output::line(Channel::Payload, receipt::public_key_hex(&public));
+            Ok(())
+        }
+        Command::Receipt(ReceiptCommand::Verify {
+            receipt,
+            file,
+            public_key,
+        }) => {
+            let verified = receipt::verify(receipt, file, public_key.as_deref())?;
+            output::line(
+                Channel::Payload,
+                format_args!(
+                    "Receipt OK: {} carries a {} byte payload, signed by {} at {}",
+                    file.display(),
+                    verified.receipt.payload_bytes,
+                    verified.receipt.public_key,
+                    opt.time_format.render(verified.receipt.created)
+                ),
+            );
+            Ok(())
+        }
+        Command::Provenance(cmd) => stamp::run(opt, cmd, summary),
+        Command::Trace(TraceCommand::Diff { a, b }) => {
+            let comparison = trace::compare(&trace::read(a)?, &trace::read(b)?);
+            match comparison {
+                trace::Comparison::Same { .. } => {
+                    output::line(Channel::Payload, comparison);
+                    Ok(())
+                }
+                trace::Comparison::Diverged { .. } => {
+                    Err(PngSecretError::TracesDiverged(comparison.to_string()))
+                }
+            }
+        }
+        Command::Doctor { input, explain } => {
+            summary.read_file(input);
+            let report = doctor::diagnose(input)?;
+            let json = serde_json::to_string_pretty(&report).expect("report serializes");
+            let mut text = report.to_string();
+            if *explain {
+                text.push_str(&format!("\n{}", doctor::explain(input)?));
+            }
+            output::write(
+                Channel::Payload,
+                format!("{}\nJSON:\n{}\n", text, json).as_bytes(),
+            );
+            Ok(())
+        }
+        Command::Info { input } => {
+            let bytes = input::read_stable(input, opt.modified_retries)?;
+            summary.bytes_in += bytes.len() as u64;
+            let img = probe::load(&bytes, input, None)?;
+            let slots = slots::enumerate_slots(&carrier::payload_samples(img));
+            let notice = pngio::read_notice(input);
+            let chunk = chunks::payload(&bytes).map(<[u8]>::len);
+            if slots.is_empty() && notice.is_none() && chunk.is_none() {
+                return Err(PngSecretError::NoMessage);
+            }
+            output::line(
+                Channel::Payload,
+                decode::render_slots(&slots, notice.as_deref(), chunk),
+            );
+            Ok(())
+        }
+        Command::Wipe {
+            input,
+            output,
+            backend,
+            order,
+        } => {
+            summary.read_file(input);
+            let output = wipe::wipe(
+                opt,
+                input,
+                output.as_deref(),
+                *backend,
+                order.order()?,
+                order.given_slot()?,
+                order.offset,
+            )?;
+            summary.wrote_file(&output);
+            Ok(())
+        }
+        Command::Sanitize { input, output, lsb } => {
+            let output = match output {
+                Some(path) => path.to_path_buf(),
+                None => paths::derive_output(input, "sanitized.png"),
+            };
+            if paths::collides(input, &output) {
+                return Err(PngSecretError::OutputIsInput(output));
+            }
+            let plan = confirm::Plan::new(
+                format!("Sanitize {} into {}", input.display(), output.display()),
+                &[&output],
+            );
+            confirm::confirm(&plan, opt.yes)?;
+            summary.read_file(input);
+            let report = sanitize::sanitize(input, &output, *lsb)?;
+            summary.wrote_file(&output);
+            if !opt.silent {
+                output::write(Channel::Diagnostics, report.to_string().as_bytes());
+            }
+            output::path_line(Channel::Payload, &output);
+            Ok(())
+        }
+        Command::Wizard => {
+            if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+                return Err(PngSecretError::Usage(
+                    "The wizard needs an interactive terminal, see --help for the regular \
+                     command line"
+                        .to_string(),
+                ));
+            }
+            let args = wizard::resolve(&mut wizard::Terminal)
+                .map_err(|e| PngSecretError::Io("Couldn't read the answer".to_string(), e))?;
+            let Some(args) = args else {
+                output::line(Channel::Diagnostics, "Cancelled, nothing was changed");
+                return Ok(());
+            };
+            output::line(
+                Channel::Diagnostics,
+                format_args!("Equivalent command: {}", wizard::command_line(&args)),
+            );
+            let opt = Opt::from_iter_safe(&args).map_err(|e| PngSecretError::Usage(e.message))?;
+            run(&opt, summary)
+        }
+        Command::Sweep {
+            input,
+            payload,
+            full_size,
+        } => {
+            let cover = probe::open(input)?.into_rgba8();
+            let payload = std::fs::read(payload).map_err(|e| {
+                PngSecretError::Io(format!("Couldn't read the payload {:?}", payload), e)
+            })?;
+            summary.read_file(input);
+            summary.bytes_in += payload.len() as u64;
+            let (cover, downscaled) = match full_size {
+                true => (cover, false),
+                false => sweep::working_copy(cover),
+            };
+            let report = sweep::sweep(&cover, &payload, downscaled);
+            let json = serde_json::to_string_pretty(&report).expect("report serializes");
+            output::write(
+                Channel::Payload,
+                format!("{}\nJSON:\n{}\n", report, json).as_bytes(),
+            );
+            Ok(())
+        }
+        Command::Cat {
+            inputs,
+            skip_missing,
+            order,
+        } => decode::cat(opt, inputs, *skip_missing, order, summary),
+        Command::Verify {
+            checksum,
+            file,
+            decode,
+        } => decode::verify_checksum(opt, checksum, file.as_deref(), *decode, summary),
+        Command::VerifyArchive {
+            manifest,
+            read_only,
+        } => {
+            if *read_only {
+                fsguard::set_read_only();
+            }
+            let report = verify::sweep(manifest)?;
+            summary.bytes_in += report.bytes_read;
+            for (_, status) in &report.files {
+                summary.item(match status {
+                    verify::Status::Ok { .. } => Outcome::Succeeded,
+                    verify::Status::Failed(_) => Outcome::Failed,
+                });
+            }
+            output::write(Channel::Payload, report.to_string().as_bytes());
+            match report.failed() {
+                0 => Ok(()),
+                failed => Err(PngSecretError::VerificationFailed {
+                    failed,
+                    total: report.files.len(),
+                }),
+            }
+        }
+        Command::Diff { cover, stego, out } => {
+            visualize::diff(opt, cover, stego, out.as_deref(), summary)
         }
+        Command::Demo { keep } => demo::run(*keep, opt.silent, summary),
+        Command::GenFixtures { dir } => fixtures::generate(dir).map(drop),
+    }
+}
+
+/// Names of the non-default flags of a run, the values are deliberately left out
+fn used_flags(opt: &Opt) -> Vec<&'static str> {
+    let flags = [
+        ("silent", opt.silent),
+        ("yes", opt.yes),
+        ("output", opt.output.is_some()),
+        ("format", opt.format.is_some()),
+        ("raw", opt.raw),
+        ("max-payload", opt.max_payload.is_some()),
+        ("si", opt.si),
+        ("preview-crop", opt.preview_crop.is_some()),
+        ("create-dirs", opt.create_dirs),
+        ("min-free-space", opt.min_free_space.is_some()),
+        ("also-chunk-text", opt.also_chunk_text.is_some()),
+        ("truncate-to-fit", opt.truncate_to_fit),
+        ("pad-to-capacity", opt.pad_to_capacity),
+        ("dry-run", opt.dry_run),
+        ("strict", opt.strict),
+        ("force-8bit", opt.force_8bit),
+        ("embedding", opt.embedding != EmbeddingMode::Match),
+        ("exec-on-success", opt.exec_on_success.is_some()),
+        ("keep-temp", opt.keep_temp),
+        ("receipt", opt.receipt.is_some()),
+        ("write-checksum", opt.write_checksum),
+        ("verbose", opt.verbose > 0),
+        ("long-paths", opt.long_paths),
+        ("frame", opt.frame.is_some()),
+        ("no-verify", opt.no_verify),
+        (
+            "time-format",
+            opt.time_format != timefmt::TimeFormat::Rfc3339Utc,
+        ),
+        ("permute", opt.order.permute != Permute::None),
+        (
+            "block-size",
+            opt.order.block_size != order::DEFAULT_BLOCK_SIZE,
+        ),
+        ("seed", opt.order.seed.is_some()),
+        ("key", opt.order.key.is_some()),
+        (
+            "slot",
+            opt.order
+                .slot
+                .is_some_and(|slot| slot != SlotArg::Named(Slot::All)),
+        ),
+        ("skip-alpha", opt.order.skip_alpha),
+        ("offset", opt.order.offset.is_some_and(|offset| offset != 0)),
+        ("alpha-payload", opt.alpha_payload.is_some()),
+        ("armor", opt.armor),
+        ("file", opt.file.is_some()),
+        ("legacy", opt.legacy),
+        ("bits", opt.bits() != format::DEFAULT_BITS),
+        ("summary-json", opt.summary_json.is_some()),
+        ("compress", opt.compress),
+        ("ecc", opt.ecc.is_some()),
+        ("method", opt.method.is_some()),
+        ("dearmor", opt.dearmor),
+        ("modified-retries", opt.modified_retries != 3),
+        ("max-detectability", opt.max_detectability.is_some()),
+        ("trace-indices", opt.trace_indices.is_some()),
+        ("name", !opt.name.is_empty()),
+        ("list", opt.list),
+        ("inspect", opt.inspect),
+        ("json", opt.json),
+        ("payload-encoding", opt.payload_encoding.is_some()),
+        ("escape", opt.escape),
+        ("text-stdin", opt.text_stdin),
+    ];
+    flags
+        .iter()
+        .filter(|(_, used)| *used)
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+fn input_path(opt: &Opt) -> &Path {
+    // The input is only optional for subcommands and --dearmor, main rejects it missing otherwise
+    opt.input.first().map_or(Path::new(STDIN), PathBuf::as_path)
+}
 
+/// The `--input` of `--dearmor` that stands for stdin
+const STDIN: &str = "-";
+
+/// The PNG of an armored input, from stdin or a file
+fn read_armored(opt: &Opt) -> Result<Vec<u8>, PngSecretError> {
+    let path = input_path(opt);
+    let mut text = String::new();
+    if path == Path::new(STDIN) {
+        std::io::stdin().read_to_string(&mut text).map_err(|e| {
+            PngSecretError::Io("Couldn't read the armored image from stdin".to_string(), e)
+        })?;
+    } else {
+        let bytes = input::read_stable(path, opt.modified_retries)?;
+        text = String::from_utf8(bytes)
+            .map_err(|_| PngSecretError::InputUnreadable(path.to_path_buf()))?;
+    }
+    armor::dearmor(&text).map_err(PngSecretError::Armor)
+}
+
+fn run(opt: &Opt, summary: &mut Summary) -> Result<(), PngSecretError> {
+    if opt.input.len() > 1 {
+        return spread::run(opt, summary);
+    }
+    if let Some(dir) = opt.input.first().filter(|input| input.is_dir()) {
+        return batch::run(opt, dir, summary);
     }
+    if opt.encode {
+        encode::output_container(opt)?;
+    }
+    let (bytes, img) = read_image(opt, summary)?;
+    match opt.encode {
+        true => encode::run(opt, img, &bytes, summary),
+        false => decode::decode(opt, img, &bytes, summary),
+    }
+}
+
+/// The file of `--input`, or of the armored text, and the image it holds
+fn read_image(opt: &Opt, summary: &mut Summary) -> Result<(Vec<u8>, DynamicImage), PngSecretError> {
+    let bytes = match opt.dearmor {
+        true => read_armored(opt)?,
+        false => input::read_stable(input_path(opt), opt.modified_retries)?,
+    };
+    summary.bytes_in += bytes.len() as u64;
+    let img = tracing::info_span!("decode_image", bytes = bytes.len()).in_scope(|| {
+        let img = probe::load(&bytes, input_path(opt), opt.frame)?;
+        tracing::debug!(
+            width = img.width(),
+            height = img.height(),
+            color = ?img.color(),
+            "decoded cover"
+        );
+        Ok::<_, PngSecretError>(img)
+    })?;
+    Ok((bytes, img))
+}
+
+fn parse_size(flag: &'static str, value: &str, si: bool) -> Result<u64, PngSecretError> {
+    bytesize::parse(value, si).map_err(|source| PngSecretError::InvalidSize {
+        flag,
+        value: value.to_string(),
+        source,
+    })
 }
@@ -0,0 +1,263 @@
+//! The order in which subpixels carry payload bits
+//!
+//! Sequential embedding packs the payload at the top of the image. The blocked permutation
+//! shuffles fixed-size blocks of subpixels with a PRNG keyed by `--seed` and walks each block
+//! sequentially, which scatters the payload over the image while keeping memory access mostly
+//! sequential. The subpixel permutation shuffles every subpixel on its own, which scatters even
+//! the first bits of the payload at the cost of random access and 4 bytes of memory per subpixel.
+//! Decoding needs the same mode, block size and seed.
+//!
+//! Orders run over the subpixels of one [`Slot`], so a payload in the alpha channel and one in
+//! the color channels never share a subpixel. An offset starts the walk that many subpixels into
+//! the order and wraps around to its start, so the payload doesn't begin at the first pixel.
+
+use rand::RngCore;
+use rand_chacha::rand_core::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+
+/// Default of `--block-size`
+pub const DEFAULT_BLOCK_SIZE: usize = 4096;
+
+/// Which channels of each pixel carry a payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Slot {
+    /// Every subpixel, as in the legacy format
+    #[default]
+    All,
+    Rgb,
+    Alpha,
+}
+
+impl FromStr for Slot {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Slot::All),
+            "rgb" => Ok(Slot::Rgb),
+            "alpha" => Ok(Slot::Alpha),
+            _ => Err(format!("unknown slot {:?}", s)),
+        }
+    }
+}
+
+impl Slot {
+    /// Number of subpixels of the slot in an RGBA buffer of `len` subpixels
+    pub fn subpixels(&self, len: usize) -> usize {
+        self.subpixels_in(len, 4)
+    }
+
+    /// Number of subpixels of the slot in a buffer of `len` subpixels, `channels` per pixel
+    ///
+    /// Two and four channels end in alpha, gray and RGB images have no alpha subpixels.
+    pub fn subpixels_in(&self, len: usize, channels: u8) -> usize {
+        let channels = channels as usize;
+        let colors = color_channels(channels);
+        match self {
+            Slot::All => len,
+            Slot::Rgb => len / channels * colors,
+            Slot::Alpha => len / channels * (channels - colors),
+        }
+    }
+
+    /// Buffer index of the `i`th subpixel of the slot
+    fn buffer_index(&self, i: usize, channels: usize) -> usize {
+        let colors = color_channels(channels);
+        match self {
+            Slot::All => i,
+            Slot::Rgb => i / colors * channels + i % colors,
+            Slot::Alpha => i * channels + colors,
+        }
+    }
+
+    /// Buffer indices of the slot's subpixels in an RGBA buffer of `len` subpixels, walked in
+    /// `order`
+    pub fn indices(self, order: &SubpixelOrder, len: usize) -> impl Iterator<Item = usize> {
+        self.indices_in(order, len, 4)
+    }
+
+    /// Buffer indices of the slot's subpixels in a buffer of `len` subpixels, `channels` per
+    /// pixel, walked in `order`
+    pub fn indices_in(
+        self,
+        order: &SubpixelOrder,
+        len: usize,
+        channels: u8,
+    ) -> impl Iterator<Item = usize> {
+        order
+            .indices(self.subpixels_in(len, channels))
+            .map(move |i| self.buffer_index(i, channels as usize))
+    }
+
+    /// Like [`Slot::indices_in`], starting `offset` subpixels into the order and wrapping around
+    /// to its start, every subpixel still exactly once when `offset` is less than their number
+    pub fn indices_from(
+        self,
+        order: &SubpixelOrder,
+        len: usize,
+        channels: u8,
+        offset: usize,
+    ) -> impl Iterator<Item = usize> {
+        self.indices_in(order, len, channels)
+            .skip(offset)
+            .chain(self.indices_in(order, len, channels).take(offset))
+    }
+}
+
+/// Channels of a pixel that aren't alpha
+fn color_channels(channels: usize) -> usize {
+    match channels {
+        2 | 4 => channels - 1,
+        _ => channels,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SubpixelOrder {
+    #[default]
+    Sequential,
+    Blocks {
+        block_size: usize,
+        seed: u64,
+    },
+    /// Every subpixel shuffled on its own, the order of blocks of one subpixel in less memory
+    Shuffled {
+        seed: u64,
+    },
+}
+
+impl SubpixelOrder {
+    /// Every subpixel index of a buffer with `len` subpixels, each exactly once
+    pub fn indices(&self, len: usize) -> Box<dyn Iterator<Item = usize>> {
+        match *self {
+            SubpixelOrder::Sequential => Box::new(0..len),
+            SubpixelOrder::Blocks { block_size, seed } => {
+                let blocks = shuffled(len.div_ceil(block_size), seed);
+                Box::new(blocks.into_iter().flat_map(move |block| {
+                    let start = block * block_size;
+                    start..start.saturating_add(block_size).min(len)
+                }))
+            }
+            SubpixelOrder::Shuffled { seed } => match u32::try_from(len) {
+                Ok(n) => Box::new(
+                    shuffle((0..n).collect(), seed)
+                        .into_iter()
+                        .map(|i| i as usize),
+                ),
+                Err(_) => Box::new(shuffled(len, seed).into_iter()),
+            },
+        }
+    }
+}
+
+/// The seed `--key` stands for, so a passphrase can key the permutation instead of a number
+pub fn seed_from_key(key: &str) -> u64 {
+    let digest = Sha256::new()
+        .chain_update(b"pngsecret subpixel order\0")
+        .chain_update(key.as_bytes())
+        .finalize();
+    u64::from_be_bytes(digest[..8].try_into().expect("SHA-256 has 32 bytes"))
+}
+
+/// `0..n` in the order of [`shuffle`]
+fn shuffled(n: usize, seed: u64) -> Vec<usize> {
+    shuffle((0..n).collect(), seed)
+}
+
+/// Fisher-Yates, implemented here rather than taken from `rand` so the order can't change with a
+/// dependency update
+fn shuffle<T>(mut items: Vec<T>, seed: u64) -> Vec<T> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    for i in (1..items.len()).rev() {
+        let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::quickcheck;
+
+    #[test]
+    fn shuffle_is_stable() {
+        // Pinned so an accidental change of the permutation is caught
+        assert_eq!(shuffled(8, 42), vec![5, 3, 2, 6, 7, 4, 0, 1]);
+        assert_ne!(shuffled(64, 1), shuffled(64, 2));
+        assert_eq!(shuffled(0, 1), Vec::<usize>::new());
+        assert_eq!(
+            seed_from_key("correct horse"),
+            seed_from_key("correct horse")
+        );
+        assert_ne!(
+            seed_from_key("correct horse"),
+            seed_from_key("correct horsf")
+        );
+    }
+
+    #[test]
+    fn slots_split_each_pixel() {
+        let order = SubpixelOrder::Sequential;
+        let rgb: Vec<usize> = Slot::Rgb.indices(&order, 8).collect();
+        let alpha: Vec<usize> = Slot::Alpha.indices(&order, 8).collect();
+        assert_eq!(rgb, [0, 1, 2, 4, 5, 6]);
+        assert_eq!(alpha, [3, 7]);
+
+        let gray_alpha: Vec<usize> = Slot::Rgb.indices_in(&order, 6, 2).collect();
+        assert_eq!(gray_alpha, [0, 2, 4]);
+        assert_eq!(
+            Slot::Alpha.indices_in(&order, 6, 2).collect::<Vec<_>>(),
+            [1, 3, 5]
+        );
+        assert_eq!(Slot::Rgb.indices_in(&order, 6, 3).count(), 6);
+        assert_eq!(Slot::Alpha.subpixels_in(6, 3), 0);
+        assert_eq!(Slot::Alpha.subpixels_in(6, 1), 0);
+
+        let from: Vec<usize> = Slot::Rgb.indices_from(&order, 8, 4, 4).collect();
+        assert_eq!(from, [5, 6, 0, 1, 2, 4]);
+        assert_eq!(
+            Slot::Alpha
+                .indices_from(&order, 8, 4, 0)
+                .collect::<Vec<_>>(),
+            alpha
+        );
+    }
+
+    quickcheck! {
+        fn slots_never_share_subpixels(pixels: u8, seed: u64) -> bool {
+            let len = pixels as usize * 4;
+            let order = SubpixelOrder::Blocks { block_size: 7, seed };
+            let mut visited: Vec<usize> = Slot::Rgb
+                .indices(&order, len)
+                .chain(Slot::Alpha.indices(&order, len))
+                .collect();
+            visited.sort_unstable();
+            visited == (0..len).collect::<Vec<_>>()
+        }
+
+        fn shuffled_subpixels_are_blocks_of_one(len: u16, seed: u64) -> bool {
+            let blocks = SubpixelOrder::Blocks { block_size: 1, seed };
+            SubpixelOrder::Shuffled { seed }.indices(len as usize).eq(blocks.indices(len as usize))
+        }
+
+        fn offsets_visit_every_index_once(len: u16, offset: u16, seed: u64) -> bool {
+            let len = len as usize;
+            let order = SubpixelOrder::Blocks { block_size: 5, seed };
+            let offset = offset as usize % len.max(1);
+            let mut visited: Vec<usize> = Slot::All.indices_from(&order, len, 4, offset).collect();
+            visited.sort_unstable();
+            visited == (0..len).collect::<Vec<_>>()
+        }
+
+        fn blocks_visit_every_index_once(len: u16, block_size: u8, seed: u64) -> bool {
+            let len = len as usize;
+            let order = SubpixelOrder::Blocks { block_size: block_size as usize + 1, seed };
+            let mut visited: Vec<usize> = order.indices(len).collect();
+            visited.sort_unstable();
+            visited == (0..len).collect::<Vec<_>>()
+        }
+    }
+}
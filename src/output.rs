@@ -0,0 +1,62 @@
+//! All user-facing text goes through this module
+//!
+//! The stdout contract: stdout only ever carries the extracted payload when decoding, or the
+//! single line output path when encoding. Everything else, including progress, image info,
+//! warnings and errors, goes to stderr so that stdout can be scraped reliably.
+
+use std::fmt::Display;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set once the reader of stdout went away
+static PAYLOAD_CLOSED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    /// The result of the operation, written to stdout
+    Payload,
+    /// Everything meant for humans, written to stderr
+    Diagnostics,
+}
+
+/// Write raw bytes to a channel without any framing
+pub fn write(channel: Channel, bytes: &[u8]) {
+    // A closed pipe or full disk on the standard streams is not worth panicking over
+    match channel {
+        Channel::Payload if payload_closed() => {}
+        Channel::Payload => {
+            let mut stdout = std::io::stdout().lock();
+            if let Err(e) = stdout.write_all(bytes).and_then(|_| stdout.flush()) {
+                if e.kind() == ErrorKind::BrokenPipe {
+                    PAYLOAD_CLOSED.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+        Channel::Diagnostics => {
+            let _ = std::io::stderr().lock().write_all(bytes);
+        }
+    }
+}
+
+/// Whether the reader of stdout went away, as `head -c 100` does once it has enough
+///
+/// Payload writes after that are dropped, so callers producing more can stop early.
+pub fn payload_closed() -> bool {
+    PAYLOAD_CLOSED.load(Ordering::Relaxed)
+}
+
+/// Write a line of text to a channel
+pub fn line(channel: Channel, text: impl Display) {
+    write(channel, format!("{}\n", text).as_bytes());
+}
+
+/// Write a path followed by a newline, byte exact on Unix even when it isn't valid UTF-8
+pub fn path_line(channel: Channel, path: &Path) {
+    #[cfg(unix)]
+    let mut bytes = std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()).to_vec();
+    #[cfg(not(unix))]
+    let mut bytes = path.to_string_lossy().into_owned().into_bytes();
+    bytes.push(b'\n');
+    write(channel, &bytes);
+}
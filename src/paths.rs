@@ -0,0 +1,211 @@
+//! Path handling that never goes through lossy string conversions
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::container::Container;
+use crate::fsguard;
+
+/// Windows refuses paths longer than this unless they carry the `\\?\` prefix
+const WINDOWS_MAX_PATH: usize = 260;
+
+/// Replace the image extension of `input` with `extension`, e.g. `cover.png` -> `cover.enc.png`
+///
+/// Other extensions are part of the name and kept, `archive.tar.gz` -> `archive.tar.gz.enc.png`.
+pub fn derive_output(input: &Path, extension: &str) -> PathBuf {
+    // Lossy formats are refused as outputs but are images all the same
+    if !matches!(Container::of_path(input), Ok(None)) {
+        return input.with_extension(extension);
+    }
+    let mut output = input.as_os_str().to_os_string();
+    output.push(".");
+    output.push(extension);
+    PathBuf::from(output)
+}
+
+/// Add the `\\?\` prefix to a long absolute Windows path so it can exceed 260 characters
+///
+/// This is plain string manipulation so that it can be tested on every platform; it is only
+/// applied on Windows behind `--long-paths`.
+pub fn with_long_path_prefix(path: &Path) -> PathBuf {
+    let raw = path.as_os_str();
+    let Some(text) = raw.to_str() else {
+        // Windows paths are always valid UTF-16, so anything else isn't a Windows path
+        return path.to_path_buf();
+    };
+    if text.len() <= WINDOWS_MAX_PATH || text.starts_with(r"\\?\") {
+        return path.to_path_buf();
+    }
+    let mut prefixed = OsString::new();
+    if let Some(share) = text.strip_prefix(r"\\") {
+        prefixed.push(r"\\?\UNC\");
+        prefixed.push(share);
+    } else if text.as_bytes().get(1) == Some(&b':') {
+        prefixed.push(r"\\?\");
+        prefixed.push(text);
+    } else {
+        // Relative paths can't be prefixed
+        return path.to_path_buf();
+    }
+    PathBuf::from(prefixed)
+}
+
+/// Whether files in `dir` are looked up case-insensitively, as on default Windows and macOS
+/// volumes
+pub fn is_case_insensitive(dir: &Path) -> bool {
+    let Ok(probe) = fsguard::temp_file_in(dir, ".pngsecret-case-probe-") else {
+        return cfg!(any(windows, target_os = "macos"));
+    };
+    let name = probe.path().file_name().unwrap_or_default();
+    dir.join(name.to_ascii_uppercase()).exists()
+}
+
+/// Whether writing to `output` would overwrite `input`, also through a link
+pub fn collides(input: &Path, output: &Path) -> bool {
+    if is_same_file(input, output) {
+        return true;
+    }
+    let resolve = |path: &Path| -> Option<(PathBuf, OsString)> {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        Some((
+            parent.canonicalize().ok()?,
+            path.file_name()?.to_os_string(),
+        ))
+    };
+    let (Some((input_dir, input_name)), Some((output_dir, output_name))) =
+        (resolve(input), resolve(output))
+    else {
+        return false;
+    };
+    if input_dir != output_dir {
+        return false;
+    }
+    if input_name == output_name {
+        return true;
+    }
+    match (input_name.to_str(), output_name.to_str()) {
+        (Some(a), Some(b)) => {
+            a.to_lowercase() == b.to_lowercase() && is_case_insensitive(&input_dir)
+        }
+        _ => false,
+    }
+}
+
+/// Whether `a` and `b` both exist and are one file, e.g. a symlink and its target or two hard
+/// links
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    let (Ok(a_metadata), Ok(b_metadata)) = (fs::metadata(a), fs::metadata(b)) else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if (a_metadata.dev(), a_metadata.ino()) == (b_metadata.dev(), b_metadata.ino()) {
+            return true;
+        }
+    }
+    let _ = (a_metadata, b_metadata);
+    matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn derive_output_keeps_non_utf8_names() {
+        use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+        let input = PathBuf::from(OsString::from_vec(b"/tmp/caf\xe9.png".to_vec()));
+        let output = derive_output(&input, "enc.png");
+        assert_eq!(output.as_os_str().as_bytes(), b"/tmp/caf\xe9.enc.png");
+    }
+
+    #[test]
+    fn derive_output_unicode_and_long_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let long_stem = "長".repeat(60);
+        for name in ["秘密😀.png".to_string(), format!("{}.png", long_stem)] {
+            let input = dir.path().join(&name);
+            fs::write(&input, b"cover").unwrap();
+            let output = derive_output(&input, "enc.png");
+            assert_eq!(output.parent(), input.parent());
+            assert!(output.to_str().unwrap().ends_with(".enc.png"));
+            fs::write(&output, b"stego").unwrap();
+            assert!(output.exists());
+        }
+    }
+
+    #[test]
+    fn derive_output_keeps_extensions_that_are_not_images() {
+        for (input, output) in [
+            ("cover.png", "cover.enc.png"),
+            ("Cover.TIFF", "Cover.enc.png"),
+            ("photo.jpg", "photo.enc.png"),
+            ("secret", "secret.enc.png"),
+            ("archive.tar.gz", "archive.tar.gz.enc.png"),
+            ("dir.v2/notes", "dir.v2/notes.enc.png"),
+        ] {
+            assert_eq!(
+                derive_output(Path::new(input), "enc.png"),
+                Path::new(output)
+            );
+        }
+    }
+
+    #[test]
+    fn long_path_prefix() {
+        let short = Path::new(r"C:\images\cover.png");
+        assert_eq!(with_long_path_prefix(short), short);
+
+        let long = format!(r"C:\images\{}.png", "a".repeat(300));
+        assert_eq!(
+            with_long_path_prefix(Path::new(&long)),
+            PathBuf::from(format!(r"\\?\{}", long))
+        );
+
+        let unc = format!(r"\\server\share\{}.png", "a".repeat(300));
+        assert_eq!(
+            with_long_path_prefix(Path::new(&unc)),
+            PathBuf::from(format!(r"\\?\UNC\server\share\{}.png", "a".repeat(300)))
+        );
+
+        let prefixed = PathBuf::from(format!(r"\\?\{}", long));
+        assert_eq!(with_long_path_prefix(&prefixed), prefixed);
+
+        let relative = format!(r"images\{}.png", "a".repeat(300));
+        assert_eq!(
+            with_long_path_prefix(Path::new(&relative)),
+            Path::new(&relative)
+        );
+    }
+
+    #[test]
+    fn collision_detection() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("Cover.png");
+        fs::write(&input, b"cover").unwrap();
+        assert!(collides(&input, &input));
+        assert!(collides(&input, &dir.path().join(".").join("Cover.png")));
+        assert!(!collides(&input, &dir.path().join("cover.enc.png")));
+        assert_eq!(
+            collides(&input, &dir.path().join("COVER.PNG")),
+            is_case_insensitive(dir.path())
+        );
+
+        let linked = dir.path().join("linked.png");
+        fs::hard_link(&input, &linked).unwrap();
+        assert!(collides(&input, &linked));
+        #[cfg(unix)]
+        {
+            let symlink = dir.path().join("symlink.png");
+            std::os::unix::fs::symlink(&input, &symlink).unwrap();
+            assert!(collides(&symlink, &input));
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! Direct PNG reading and writing for the parts `image` doesn't expose, i.e. ancillary chunks
+
+use image::{ColorType, DynamicImage};
+use pngsecret::carrier::Carrier;
+use std::io::{self, BufReader, BufWriter, Cursor, Write};
+use std::path::Path;
+
+use crate::fsguard;
+
+/// tEXt keyword used for the human readable notice written next to the pixel payload
+pub const NOTICE_KEYWORD: &str = "pngsecret-notice";
+
+/// All uncompressed tEXt chunks of a PNG file as keyword/text pairs
+pub fn read_text_chunks(path: &Path) -> io::Result<Vec<(String, String)>> {
+    let decoder = png::Decoder::new(BufReader::new(fsguard::open(path)?));
+    let reader = decoder.read_info().map_err(io::Error::other)?;
+    Ok(reader
+        .info()
+        .uncompressed_latin1_text
+        .iter()
+        .map(|chunk| (chunk.keyword.clone(), chunk.text.clone()))
+        .collect())
+}
+
+/// The notice stored by `--also-chunk-text`, if the file carries one
+pub fn read_notice(path: &Path) -> Option<String> {
+    read_text_chunks(path)
+        .ok()?
+        .into_iter()
+        .find(|(keyword, _)| keyword == NOTICE_KEYWORD)
+        .map(|(_, text)| text)
+}
+
+/// Whether the text can be stored in a tEXt chunk, which is Latin-1 only
+pub fn is_latin1(text: &str) -> bool {
+    text.chars().all(|c| (c as u32) <= 0xFF)
+}
+
+/// Bits per sample of a grayscale PNG stored with fewer than 8, `None` for any other image
+///
+/// `image` expands such samples to 8 bits, which an 8-bit save would keep.
+pub fn packed_gray_depth(bytes: &[u8]) -> Option<u8> {
+    let reader = png::Decoder::new(Cursor::new(bytes)).read_info().ok()?;
+    let info = reader.info();
+    let packed = [png::BitDepth::One, png::BitDepth::Two, png::BitDepth::Four];
+    match info.color_type {
+        png::ColorType::Grayscale if packed.contains(&info.bit_depth) => Some(info.bit_depth as u8),
+        _ => None,
+    }
+}
+
+/// Whether `bytes` is a PNG storing palette indices, which `image` expands to RGB or RGBA
+pub fn is_indexed(bytes: &[u8]) -> bool {
+    png::Decoder::new(Cursor::new(bytes))
+        .read_info()
+        .is_ok_and(|reader| reader.info().color_type == png::ColorType::Indexed)
+}
+
+/// Save an 8-bit buffer as PNG in its own channels with the given tEXt chunks, written after the
+/// header so the pixel data is untouched
+pub fn save_with_text(
+    img: &impl Carrier,
+    path: &Path,
+    texts: &[(String, String)],
+) -> io::Result<()> {
+    let mut staged = fsguard::stage(path)?;
+    let mut sink = BufWriter::new(staged.as_file_mut());
+    write_png(img, &mut sink, texts)?;
+    sink.flush()?;
+    drop(sink);
+    staged.commit()
+}
+
+/// Like [`save_with_text`], at 16 bits per sample if `img` has them
+pub fn save_image_with_text(
+    img: &DynamicImage,
+    path: &Path,
+    texts: &[(String, String)],
+) -> io::Result<()> {
+    match img.color() {
+        ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => {
+            fsguard::write_atomic(path, &encode_16bit(img, texts)?)
+        }
+        _ => save_with_text(img, path, texts),
+    }
+}
+
+/// The PNG bytes [`save_with_text`] would write, for output that doesn't go to a file
+pub fn encode_with_text(img: &impl Carrier, texts: &[(String, String)]) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    write_png(img, &mut bytes, texts)?;
+    Ok(bytes)
+}
+
+/// The PNG bytes of a gray buffer decoded from `depth` bits per sample, stored at that depth again
+///
+/// Decoders expand such a sample by repeating its bits, so the low `depth` bits of the 8-bit sample
+/// are the original one, with whatever payload bits were written into them. Those are kept.
+pub fn encode_packed_gray(
+    img: &impl Carrier,
+    depth: u8,
+    texts: &[(String, String)],
+) -> io::Result<Vec<u8>> {
+    let bit_depth = match depth {
+        1 => png::BitDepth::One,
+        2 => png::BitDepth::Two,
+        4 => png::BitDepth::Four,
+        _ => {
+            return Err(io::Error::other(format!(
+                "no packed depth of {} bits",
+                depth
+            )))
+        }
+    };
+    if img.channels() != 1 {
+        return Err(io::Error::other("only gray images are stored packed"));
+    }
+    let (width, height) = img.dimensions();
+    let per_byte = (8 / depth) as usize;
+    let mask = (1u8 << depth) - 1;
+    let mut packed = Vec::new();
+    for row in img.samples().chunks(width.max(1) as usize) {
+        for samples in row.chunks(per_byte) {
+            let byte = samples.iter().enumerate().fold(0, |byte, (i, sample)| {
+                byte | (sample & mask) << (8 - depth as usize * (i + 1))
+            });
+            packed.push(byte);
+        }
+    }
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, width, height);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(bit_depth);
+    write_chunks(encoder, texts, &packed)?;
+    Ok(bytes)
+}
+
+/// The PNG bytes of a 16-bit image, stored at 16 bits with the given tEXt chunks
+pub fn encode_16bit(img: &DynamicImage, texts: &[(String, String)]) -> io::Result<Vec<u8>> {
+    let (color, samples): (_, &[u16]) = match img {
+        DynamicImage::ImageLuma16(img) => (png::ColorType::Grayscale, img),
+        DynamicImage::ImageLumaA16(img) => (png::ColorType::GrayscaleAlpha, img),
+        DynamicImage::ImageRgb16(img) => (png::ColorType::Rgb, img),
+        DynamicImage::ImageRgba16(img) => (png::ColorType::Rgba, img),
+        other => {
+            return Err(io::Error::other(format!(
+                "{:?} images have no 16-bit samples",
+                other.color()
+            )))
+        }
+    };
+    let data: Vec<u8> = samples
+        .iter()
+        .flat_map(|sample| sample.to_be_bytes())
+        .collect();
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, img.width(), img.height());
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Sixteen);
+    write_chunks(encoder, texts, &data)?;
+    Ok(bytes)
+}
+
+fn write_png<W: Write>(img: &impl Carrier, sink: W, texts: &[(String, String)]) -> io::Result<()> {
+    let (width, height) = img.dimensions();
+    let mut encoder = png::Encoder::new(sink, width, height);
+    encoder.set_color(match img.channels() {
+        1 => png::ColorType::Grayscale,
+        2 => png::ColorType::GrayscaleAlpha,
+        3 => png::ColorType::Rgb,
+        _ => png::ColorType::Rgba,
+    });
+    encoder.set_depth(png::BitDepth::Eight);
+    write_chunks(encoder, texts, img.samples())
+}
+
+/// Write the header, the tEXt chunks and `data` as the image data
+fn write_chunks<W: Write>(
+    mut encoder: png::Encoder<W>,
+    texts: &[(String, String)],
+    data: &[u8],
+) -> io::Result<()> {
+    for (keyword, text) in texts {
+        encoder
+            .add_text_chunk(keyword.clone(), text.clone())
+            .map_err(io::Error::other)?;
+    }
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(data).map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    #[test]
+    fn text_chunks_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.png");
+        let img = RgbaImage::from_fn(3, 2, |x, y| image::Rgba([x as u8, y as u8, 7, 255]));
+        let texts = vec![
+            ("Comment".to_string(), "keep me".to_string()),
+            (
+                NOTICE_KEYWORD.to_string(),
+                "Contact legal@example.com".to_string(),
+            ),
+        ];
+        save_with_text(&img, &path, &texts).unwrap();
+
+        assert_eq!(read_text_chunks(&path).unwrap(), texts);
+        assert_eq!(
+            read_notice(&path).as_deref(),
+            Some("Contact legal@example.com")
+        );
+        assert_eq!(image::open(&path).unwrap().into_rgba8(), img);
+        assert_eq!(
+            encode_with_text(&img, &texts).unwrap(),
+            std::fs::read(&path).unwrap()
+        );
+    }
+
+    #[test]
+    fn packed_gray_keeps_its_depth_and_low_bits() {
+        // 4-bit samples as `image` expands them, with payload bits in the lowest
+        let samples: Vec<u8> = (0..15u8).map(|v| (v * 17) ^ (v & 1)).collect();
+        let img = image::GrayImage::from_raw(5, 3, samples).unwrap();
+        let bytes = encode_packed_gray(&img, 4, &[]).unwrap();
+        assert_eq!(packed_gray_depth(&bytes), Some(4));
+        let decoded = image::load_from_memory(&bytes).unwrap().into_luma8();
+        let low = |img: &image::GrayImage| img.iter().map(|s| s & 0x0F).collect::<Vec<u8>>();
+        assert_eq!(low(&decoded), low(&img));
+        assert_eq!(
+            decoded.iter().map(|s| s >> 4).collect::<Vec<u8>>(),
+            low(&img)
+        );
+        assert_eq!(
+            packed_gray_depth(&encode_with_text(&img, &[]).unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn latin1_check() {
+        assert!(is_latin1("Café"));
+        assert!(!is_latin1("秘密"));
+    }
+}
@@ -0,0 +1,163 @@
+//! Checks run before any pixel is touched so that an encode can't fail at the very end because
+//! of a read-only or full output directory
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{bytesize, fsguard};
+
+/// Free space required by default, relative to the input file size
+const DEFAULT_SPACE_FACTOR: f64 = 1.5;
+
+#[derive(Debug)]
+pub enum PreflightError {
+    OutputDirMissing(PathBuf),
+    OutputDirNotADirectory(PathBuf),
+    CreateDirFailed(PathBuf, io::Error),
+    NotWritable(PathBuf, Option<io::Error>),
+    InsufficientSpace {
+        dir: PathBuf,
+        available: u64,
+        required: u64,
+    },
+}
+
+impl fmt::Display for PreflightError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PreflightError::OutputDirMissing(dir) => write!(
+                f,
+                "output directory {:?} doesn't exist, pass --create-dirs to create it",
+                dir
+            ),
+            PreflightError::OutputDirNotADirectory(dir) => {
+                write!(f, "output directory {:?} is not a directory", dir)
+            }
+            PreflightError::CreateDirFailed(dir, e) => {
+                write!(f, "cannot create output directory {:?}: {}", dir, e)
+            }
+            PreflightError::NotWritable(dir, Some(e)) => {
+                write!(f, "output directory {:?} is not writable: {}", dir, e)
+            }
+            PreflightError::NotWritable(dir, None) => {
+                write!(f, "output directory {:?} is not writable", dir)
+            }
+            PreflightError::InsufficientSpace {
+                dir,
+                available,
+                required,
+            } => write!(
+                f,
+                "output directory {:?} has {} free but at least {} is required",
+                dir,
+                bytesize::format(*available),
+                bytesize::format(*required)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PreflightError {}
+
+/// Estimate how much free space writing an output for `input` needs
+pub fn estimate_required_space(input: &Path) -> u64 {
+    fs::metadata(input)
+        .map(|m| (m.len() as f64 * DEFAULT_SPACE_FACTOR).ceil() as u64)
+        .unwrap_or(0)
+}
+
+/// Make sure the directory `output` will be written to exists, is writable and has at least
+/// `required_space` bytes free
+pub fn check_output(
+    output: &Path,
+    create_dirs: bool,
+    required_space: u64,
+) -> Result<(), PreflightError> {
+    let dir = match output.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    if !dir.exists() {
+        if !create_dirs {
+            return Err(PreflightError::OutputDirMissing(dir));
+        }
+        fsguard::create_dir_all(&dir)
+            .map_err(|e| PreflightError::CreateDirFailed(dir.clone(), e))?;
+    }
+    let metadata =
+        fs::metadata(&dir).map_err(|e| PreflightError::NotWritable(dir.clone(), Some(e)))?;
+    if !metadata.is_dir() {
+        return Err(PreflightError::OutputDirNotADirectory(dir));
+    }
+    // Checked explicitly since privileged users can create the probe file regardless
+    if metadata.permissions().readonly() {
+        return Err(PreflightError::NotWritable(dir, None));
+    }
+    probe_writable(&dir)?;
+
+    if let Ok(available) = fs4::available_space(&dir) {
+        if available < required_space {
+            return Err(PreflightError::InsufficientSpace {
+                dir,
+                available,
+                required: required_space,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Create and delete a temporary file to prove the directory accepts writes
+fn probe_writable(dir: &Path) -> Result<(), PreflightError> {
+    fsguard::temp_file_in(dir, ".pngsecret-probe-")
+        .map_err(|e| PreflightError::NotWritable(dir.to_path_buf(), Some(e)))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_dir_requires_create_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("a/b/out.png");
+        assert!(matches!(
+            check_output(&output, false, 0),
+            Err(PreflightError::OutputDirMissing(_))
+        ));
+        assert!(check_output(&output, true, 0).is_ok());
+        assert!(dir.path().join("a/b").is_dir());
+    }
+
+    #[test]
+    fn probe_file_is_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        check_output(&dir.path().join("out.png"), false, 0).unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn parent_must_be_a_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, b"").unwrap();
+        assert!(matches!(
+            check_output(&file.join("out.png"), true, 0),
+            Err(PreflightError::OutputDirNotADirectory(_))
+                | Err(PreflightError::CreateDirFailed(..))
+        ));
+    }
+
+    #[test]
+    fn insufficient_space() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            check_output(&dir.path().join("out.png"), false, u64::MAX),
+            Err(PreflightError::InsufficientSpace { .. })
+        ));
+    }
+}
@@ -0,0 +1,219 @@
+//! What container an input is, told by its magic bytes before `image` decodes it
+//!
+//! Some inputs would decode into something other than the cover the user had in mind, an
+//! animated WebP into its first frame, or not decode at all without a codec pngsecret is built
+//! without, HEIF and AVIF. They are refused with [`PngSecretError::UnsupportedCoverFormat`] naming
+//! the format. An animated WebP can still give one of its frames with `--frame`.
+
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, DynamicImage};
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::error::PngSecretError;
+
+/// ISO base media brands of HEIF images, HEVC coded or not
+const HEIF_BRANDS: [&[u8; 4]; 10] = [
+    b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"hevm", b"hevs", b"mif1", b"msf1",
+];
+const AVIF_BRANDS: [&[u8; 4]; 2] = [b"avif", b"avis"];
+/// Bit of the VP8X flags set in animated WebP files
+const WEBP_ANIMATION_FLAG: u8 = 0x02;
+
+/// A container refused as a cover
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unsupported {
+    AnimatedWebP { frames: usize },
+    Heif,
+    Avif,
+}
+
+impl Unsupported {
+    pub fn name(self) -> &'static str {
+        match self {
+            Unsupported::AnimatedWebP { .. } => "animated WebP",
+            Unsupported::Heif => "HEIF/HEIC",
+            Unsupported::Avif => "AVIF",
+        }
+    }
+}
+
+/// The refused container `bytes` start with, `None` for anything left to `image`
+pub fn detect(bytes: &[u8]) -> Option<Unsupported> {
+    if let Some(frames) = animated_webp_frames(bytes) {
+        return Some(Unsupported::AnimatedWebP { frames });
+    }
+    let brands = ftyp_brands(bytes)?;
+    if brands.iter().any(|brand| AVIF_BRANDS.contains(brand)) {
+        Some(Unsupported::Avif)
+    } else if brands.iter().any(|brand| HEIF_BRANDS.contains(brand)) {
+        Some(Unsupported::Heif)
+    } else {
+        None
+    }
+}
+
+/// The image in the file `bytes` read from `path`, or frame `frame` of an animated WebP
+pub fn load(
+    bytes: &[u8],
+    path: &Path,
+    frame: Option<usize>,
+) -> Result<DynamicImage, PngSecretError> {
+    let unreadable = || PngSecretError::InputUnreadable(path.to_path_buf());
+    match (detect(bytes), frame) {
+        (Some(Unsupported::AnimatedWebP { frames }), Some(frame)) if frame < frames => {
+            let decoder = WebPDecoder::new(Cursor::new(bytes)).map_err(|_| unreadable())?;
+            let frame = decoder.into_frames().nth(frame).ok_or_else(unreadable)?;
+            Ok(DynamicImage::ImageRgba8(
+                frame.map_err(|_| unreadable())?.into_buffer(),
+            ))
+        }
+        (Some(Unsupported::AnimatedWebP { frames }), Some(frame)) => {
+            Err(PngSecretError::Usage(format!(
+                "--frame {} is past the {} frames of {:?}, the first is 0",
+                frame, frames, path
+            )))
+        }
+        (Some(format), _) => Err(PngSecretError::UnsupportedCoverFormat {
+            path: path.to_path_buf(),
+            format: format.name(),
+            frames: match format {
+                Unsupported::AnimatedWebP { frames } => Some(frames),
+                _ => None,
+            },
+        }),
+        (None, Some(_)) => Err(PngSecretError::Usage(format!(
+            "--frame picks a frame of an animated WebP, {:?} isn't one",
+            path
+        ))),
+        (None, None) => image::load_from_memory(bytes).map_err(|_| unreadable()),
+    }
+}
+
+/// The image at `path` like `image::open`, after refusing the containers [`detect`] finds
+pub fn open(path: &Path) -> Result<DynamicImage, PngSecretError> {
+    let unreadable = || PngSecretError::InputUnreadable(path.to_path_buf());
+    let bytes = std::fs::read(path).map_err(|_| unreadable())?;
+    match detect(&bytes) {
+        // Fails naming the format
+        Some(_) => load(&bytes, path, None),
+        None => image::open(path).map_err(|_| unreadable()),
+    }
+}
+
+/// The number of frames of an animated WebP, `None` for any other file
+fn animated_webp_frames(bytes: &[u8]) -> Option<usize> {
+    if bytes.get(..4)? != b"RIFF" || bytes.get(8..12)? != b"WEBP" {
+        return None;
+    }
+    let mut rest = &bytes[12..];
+    let mut animated = false;
+    let mut frames = 0;
+    while rest.len() >= 8 {
+        let (fourcc, size) = (
+            &rest[..4],
+            u32::from_le_bytes(rest[4..8].try_into().unwrap()),
+        );
+        let data = &rest[8..];
+        match fourcc {
+            b"VP8X" => animated = data.first()? & WEBP_ANIMATION_FLAG != 0,
+            b"ANMF" => frames += 1,
+            _ => {}
+        }
+        // Chunks are padded to an even size
+        let padded = (size as usize).saturating_add(size as usize & 1);
+        rest = data.get(padded..).unwrap_or_default();
+    }
+    animated.then_some(frames)
+}
+
+/// The major and compatible brands of an ISO base media file, `None` for any other file
+fn ftyp_brands(bytes: &[u8]) -> Option<Vec<&[u8; 4]>> {
+    if bytes.get(4..8)? != b"ftyp" {
+        return None;
+    }
+    let size = u32::from_be_bytes(bytes[..4].try_into().unwrap()) as usize;
+    let ftyp = bytes.get(8..size.min(bytes.len()))?;
+    // The minor version after the major brand is no brand
+    let major = ftyp.get(..4)?.try_into().ok()?;
+    let compatible = ftyp.get(8..).unwrap_or_default().chunks_exact(4);
+    Some(
+        std::iter::once(major)
+            .chain(compatible.map(|brand| brand.try_into().unwrap()))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A RIFF file of `chunks`, each a fourcc and its data
+    fn riff(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut body = b"WEBP".to_vec();
+        for (fourcc, data) in chunks {
+            body.extend_from_slice(*fourcc);
+            body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            body.extend_from_slice(data);
+            if data.len() % 2 == 1 {
+                body.push(0);
+            }
+        }
+        [
+            b"RIFF".as_slice(),
+            &(body.len() as u32).to_le_bytes(),
+            &body,
+        ]
+        .concat()
+    }
+
+    fn ftyp(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+        let mut ftyp = vec![0; 4];
+        ftyp.extend_from_slice(b"ftyp");
+        ftyp.extend_from_slice(major);
+        ftyp.extend_from_slice(&[0; 4]);
+        compatible
+            .iter()
+            .for_each(|brand| ftyp.extend_from_slice(*brand));
+        let size = ftyp.len() as u32;
+        ftyp[..4].copy_from_slice(&size.to_be_bytes());
+        ftyp.extend_from_slice(b"\0\0\0\x08meta");
+        ftyp
+    }
+
+    #[test]
+    fn containers_are_told_by_their_magic_bytes() {
+        let vp8x = |flags: u8| [flags, 0, 0, 0, 3, 0, 0, 3, 0, 0];
+        let animated = riff(&[
+            (b"VP8X", &vp8x(WEBP_ANIMATION_FLAG)),
+            (b"ANIM", &[0; 6]),
+            (b"ANMF", &[0; 17]),
+            (b"ANMF", &[0; 16]),
+            (b"ANMF", &[0; 16]),
+        ]);
+        assert_eq!(
+            detect(&animated),
+            Some(Unsupported::AnimatedWebP { frames: 3 })
+        );
+        // Extended but still, and truncated in the middle of a chunk
+        assert_eq!(detect(&riff(&[(b"VP8X", &vp8x(0x10))])), None);
+        assert_eq!(
+            detect(&animated[..animated.len() - 4]),
+            Some(Unsupported::AnimatedWebP { frames: 3 })
+        );
+
+        assert_eq!(
+            detect(&ftyp(b"heic", &[b"mif1", b"heic"])),
+            Some(Unsupported::Heif)
+        );
+        assert_eq!(detect(&ftyp(b"mif1", &[b"miaf"])), Some(Unsupported::Heif));
+        assert_eq!(
+            detect(&ftyp(b"avif", &[b"mif1", b"miaf"])),
+            Some(Unsupported::Avif)
+        );
+        assert_eq!(detect(&ftyp(b"mp42", &[b"isom"])), None);
+        assert_eq!(detect(b"\x89PNG\r\n\x1a\n"), None);
+        assert_eq!(detect(b"RIFF"), None);
+        assert_eq!(detect(b""), None);
+    }
+}
@@ -0,0 +1,83 @@
+//! The progress bar encode draws on stderr while a large payload is embedded
+//!
+//! The bar redraws one line in place, so it is only drawn when stderr is a terminal and
+//! `--silent` isn't given. Payloads the writer embeds before its first report never draw it.
+
+use pngsecret::bytesize;
+use std::io::IsTerminal;
+
+use crate::output::{self, Channel};
+
+/// Cells between the brackets
+const WIDTH: usize = 30;
+
+pub struct Bar {
+    label: &'static str,
+    enabled: bool,
+    drawn: bool,
+}
+
+impl Bar {
+    pub fn new(label: &'static str, silent: bool) -> Self {
+        Bar {
+            label,
+            enabled: !silent && std::io::stderr().is_terminal(),
+            drawn: false,
+        }
+    }
+
+    /// Show that `done` of `total` bytes are through, ending the line once all are
+    pub fn update(&mut self, done: usize, total: usize) {
+        if !self.enabled || (!self.drawn && done >= total) {
+            return;
+        }
+        self.drawn = true;
+        let end = match done >= total {
+            true => "\n",
+            false => "",
+        };
+        let line = render(self.label, done as u64, total as u64);
+        output::write(
+            Channel::Diagnostics,
+            format!("\r{}{}", line, end).as_bytes(),
+        );
+    }
+}
+
+/// The bar for `done` of `total` bytes, e.g. `Embedding [#####   ] 1.0 MiB / 6.0 MiB`
+fn render(label: &str, done: u64, total: u64) -> String {
+    let filled = match total {
+        0 => WIDTH,
+        total => (done.min(total) * WIDTH as u64 / total) as usize,
+    };
+    format!(
+        "{} [{}{}] {} / {}",
+        label,
+        "#".repeat(filled),
+        " ".repeat(WIDTH - filled),
+        bytesize::format(done),
+        bytesize::format(total)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bars_fill_with_the_bytes_done() {
+        assert_eq!(
+            render("Embedding", 1 << 20, 6 << 20),
+            format!(
+                "Embedding [{}{}] 1.0 MiB / 6.0 MiB",
+                "#".repeat(5),
+                " ".repeat(25)
+            )
+        );
+        assert_eq!(
+            render("Embedding", 6, 6),
+            format!("Embedding [{}] 6 B / 6 B", "#".repeat(WIDTH))
+        );
+        assert!(render("Embedding", 0, 0).contains(&"#".repeat(WIDTH)));
+    }
+}
@@ -0,0 +1,301 @@
+//! Build provenance stamped invisibly into release screenshots
+//!
+//! A [`Provenance`] is written in a compact fixed schema and hidden with [`crate::embed`], so it
+//! sits in the usual checksummed frame and any reader of this crate finds it. A stamp may carry
+//! an Ed25519 signature over everything before it, together with the public key it verifies
+//! under; [`read_signed_provenance`] only returns stamps whose signature holds:
+//!
+//! ```text
+//! | magic | flags | timestamp | commit | build id | public key | signature |
+//! |   4   |   1   |     8     | 1 + n  |  1 + m   |   0 / 32   |  0 / 64   |
+//! ```
+//!
+//! The timestamp is big-endian seconds since the Unix epoch, the commit and the build id are
+//! UTF-8 behind a length byte. The layout is versioned by the last magic byte and must not change
+//! under the same version, the tests hold vectors written by the first one.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use image::ImageFormat;
+use std::io::Cursor;
+
+use crate::{carrier, Error};
+
+/// First bytes of a stamp, the last one is the version of the layout
+pub const MAGIC: [u8; 4] = *b"PSP\x01";
+/// Longest commit or build id a stamp holds, in bytes
+pub const MAX_FIELD_BYTES: usize = u8::MAX as usize;
+const SIGNED: u8 = 1;
+const PUBLIC_KEY_BYTES: usize = 32;
+const SIGNATURE_BYTES: usize = 64;
+
+/// Which build produced an image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    /// Usually the full git commit hash
+    pub commit: String,
+    /// The CI run or any other identifier of the build
+    pub build_id: String,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+impl Provenance {
+    /// The stamp of this provenance, signed with `key` if one is given
+    pub fn to_bytes(&self, key: Option<&SigningKey>) -> Result<Vec<u8>, Error> {
+        let mut stamp = MAGIC.to_vec();
+        stamp.push(match key {
+            Some(_) => SIGNED,
+            None => 0,
+        });
+        stamp.extend_from_slice(&self.timestamp.to_be_bytes());
+        for (field, value) in [("commit", &self.commit), ("build id", &self.build_id)] {
+            if value.len() > MAX_FIELD_BYTES {
+                return Err(Error::FieldTooLong {
+                    field,
+                    length: value.len(),
+                });
+            }
+            stamp.push(value.len() as u8);
+            stamp.extend_from_slice(value.as_bytes());
+        }
+        if let Some(key) = key {
+            let signature = key.sign(&stamp);
+            stamp.extend_from_slice(key.verifying_key().as_bytes());
+            stamp.extend_from_slice(&signature.to_bytes());
+        }
+        Ok(stamp)
+    }
+
+    /// The provenance in `stamp` and the key it claims to be signed with, without checking the
+    /// signature, `None` if `stamp` isn't one
+    fn parse(stamp: &[u8]) -> Option<(Provenance, Option<Signed<'_>>)> {
+        let rest = stamp.strip_prefix(&MAGIC)?;
+        let (&flags, rest) = rest.split_first()?;
+        if flags & !SIGNED != 0 {
+            return None;
+        }
+        let (timestamp, rest) = rest.split_first_chunk::<8>()?;
+        let (commit, rest) = field(rest)?;
+        let (build_id, rest) = field(rest)?;
+        let provenance = Provenance {
+            commit,
+            build_id,
+            timestamp: u64::from_be_bytes(*timestamp),
+        };
+        let signed = match (flags & SIGNED != 0, rest.len()) {
+            (false, 0) => None,
+            (true, len) if len == PUBLIC_KEY_BYTES + SIGNATURE_BYTES => {
+                let (key, signature) = rest.split_first_chunk::<PUBLIC_KEY_BYTES>()?;
+                Some(Signed {
+                    message: &stamp[..stamp.len() - rest.len()],
+                    key: VerifyingKey::from_bytes(key).ok()?,
+                    signature: Signature::from_slice(signature).ok()?,
+                })
+            }
+            _ => return None,
+        };
+        Some((provenance, signed))
+    }
+}
+
+/// The signed part of a stamp and the signature over it
+struct Signed<'a> {
+    message: &'a [u8],
+    key: VerifyingKey,
+    signature: Signature,
+}
+
+/// A UTF-8 string behind its length byte and the bytes after it
+fn field(bytes: &[u8]) -> Option<(String, &[u8])> {
+    let (&len, rest) = bytes.split_first()?;
+    let (value, rest) = rest.split_at_checked(len as usize)?;
+    Some((String::from_utf8(value.to_vec()).ok()?, rest))
+}
+
+/// The PNG of `image_bytes`, in any format the `image` crate reads, with `provenance` hidden in
+/// its pixels and signed with `key` if one is given
+///
+/// Images with more than 8 bits per sample are stored with 8, like every other pngsecret output.
+pub fn stamp_provenance(
+    image_bytes: &[u8],
+    provenance: &Provenance,
+    key: Option<&SigningKey>,
+) -> Result<Vec<u8>, Error> {
+    let img = image::load_from_memory(image_bytes).map_err(|_| Error::UnreadableImage)?;
+    let stamped = crate::embed(carrier::to_8bit(img), &provenance.to_bytes(key)?)?;
+    let mut png = Vec::new();
+    stamped
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .expect("an 8-bit image encodes as PNG");
+    Ok(png)
+}
+
+/// The provenance stamped into `image_bytes`, whether or not it is signed
+///
+/// This proves nothing about who stamped the image, see [`read_signed_provenance`] for that.
+pub fn read_provenance(image_bytes: &[u8]) -> Option<Provenance> {
+    Provenance::parse(&read_stamp(image_bytes)?).map(|(provenance, _)| provenance)
+}
+
+/// The provenance stamped into `image_bytes` and the key that signed it, `None` if the stamp is
+/// missing, unsigned or its signature doesn't verify
+///
+/// Anyone can sign a stamp, compare the key with the one releases are signed with.
+pub fn read_signed_provenance(image_bytes: &[u8]) -> Option<(Provenance, VerifyingKey)> {
+    let stamp = read_stamp(image_bytes)?;
+    let (provenance, signed) = Provenance::parse(&stamp)?;
+    let signed = signed?;
+    signed
+        .key
+        .verify(signed.message, &signed.signature)
+        .ok()
+        .map(|()| (provenance, signed.key))
+}
+
+fn read_stamp(image_bytes: &[u8]) -> Option<Vec<u8>> {
+    let img = image::load_from_memory(image_bytes).ok()?;
+    crate::extract(carrier::to_8bit(img)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    fn provenance() -> Provenance {
+        Provenance {
+            commit: "922c3f0d4b1e8a7c6f5e4d3c2b1a0f9e8d7c6b5a".to_string(),
+            build_id: "release-42".to_string(),
+            timestamp: 1_760_000_000,
+        }
+    }
+
+    fn key() -> SigningKey {
+        SigningKey::from_bytes(&[7; 32])
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn screenshot() -> Vec<u8> {
+        let img = RgbImage::from_fn(32, 24, |x, y| image::Rgb([x as u8 * 8, y as u8 * 10, 99]));
+        let mut png = Vec::new();
+        img.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        png
+    }
+
+    /// The unsigned stamp of [`provenance`] as version 1 writes it
+    const UNSIGNED_V1: &str = "50535001000000000068e7780028393232633366306434623165386137633666\
+                               356534643363326231613066396538643763366235610a72656c656173652d34\
+                               32";
+
+    /// A 24x20 RGB PNG stamped by version 1 with [`provenance`], signed with [`key`]
+    const SIGNED_PNG_V1: &str = "89504e470d0a1a0a0000000d494844520000001800000014080200000018d76a\
+                                 d400000281494441547801ede001902449922449128baa99bb47444466666656\
+                                 555555557777777777f7cccccccccccccccccccccccccccccccccccccccccccc\
+                                 cccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccc\
+                                 cccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccc74\
+                                 77777777575755555555666646464484bb9b990acf4c6657777577774fcfcccc\
+                                 cccc4c6285f7dd800db4216f980dd8800dd8800dd8800dd8800db1691668436c\
+                                 e20d6b813761011bd2a6a9da9630b61018840c02cb3c8bc05c61833160aeb0a1\
+                                 7ad3e63259062123010263ae30088141920110062c0154b6852d6164230090b1\
+                                 b84c02c020cb206c04086c21b0506513091bc9180001026c248c000c188111b2\
+                                 2c0c6023b02bdbd8025bc25c660b8c00908c0546d842d84618611b8111954d24\
+                                 03c20006091bc996b125401618811112968c05466055b6b141d8081990b9c220\
+                                 8405b60001c2b605082304062a9b208141c618042000cb0020000446c2480263\
+                                 5936c2ae6c5b46001642060903b22c6319831046d802648300840daada12d808\
+                                 635996319201236459080c129685c09684910c18576fd9461824c920cb32c8d8\
+                                 806519cb1801c60096010346a86a132100611b6183248c41080b64246c4006cb\
+                                 98cb0418576f0bdb200402c4fd24301660d908638c2410c2962c04aa6c0112b6\
+                                 41c62001461804c64216208101f34cb2c000555bb641604b12b68db89fb01120\
+                                 6c0b59083006104602aa3725d908c0189030c220091b0019c00223d958361830\
+                                 82cab6415c6683000b6c092c0458c602c0021b10e20a01aeda143216200c021b\
+                                 490640b6008105204b5808211b3042aadec288cb8c001060649ecd88cb0c4606\
+                                 83110018fe115eaf45873102dfb10000000049454e44ae426082";
+
+    #[test]
+    fn stamps_keep_the_version_1_layout() {
+        let stamp = provenance().to_bytes(None).unwrap();
+        assert_eq!(hex(&stamp), UNSIGNED_V1);
+        let signed = provenance().to_bytes(Some(&key())).unwrap();
+        assert_eq!(signed[..stamp.len()], {
+            let mut flagged = stamp.clone();
+            flagged[MAGIC.len()] = SIGNED;
+            flagged
+        });
+        assert_eq!(signed.len(), stamp.len() + 96);
+
+        let png = unhex(SIGNED_PNG_V1);
+        assert_eq!(read_provenance(&png), Some(provenance()));
+        let (read, signer) = read_signed_provenance(&png).unwrap();
+        assert_eq!(read, provenance());
+        assert_eq!(signer, key().verifying_key());
+    }
+
+    #[test]
+    fn stamps_roundtrip_and_only_valid_signatures_count() {
+        let unsigned = stamp_provenance(&screenshot(), &provenance(), None).unwrap();
+        assert_eq!(read_provenance(&unsigned), Some(provenance()));
+        assert_eq!(read_signed_provenance(&unsigned), None);
+        assert_eq!(
+            image::load_from_memory(&unsigned).unwrap().color(),
+            image::ColorType::Rgb8
+        );
+
+        let signed = stamp_provenance(&screenshot(), &provenance(), Some(&key())).unwrap();
+        assert_eq!(read_signed_provenance(&signed).unwrap().0, provenance());
+
+        // A stamp whose signed fields were changed afterwards, in a valid frame
+        let mut forged = provenance().to_bytes(Some(&key())).unwrap();
+        forged[MAGIC.len() + 1 + 8 + 1] ^= 1;
+        let img = image::load_from_memory(&screenshot()).unwrap();
+        let mut png = Vec::new();
+        crate::embed(img, &forged)
+            .unwrap()
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        assert!(read_provenance(&png).is_some());
+        assert_eq!(read_signed_provenance(&png), None);
+
+        assert_eq!(read_provenance(&screenshot()), None);
+        assert_eq!(
+            stamp_provenance(b"not an image", &provenance(), None),
+            Err(Error::UnreadableImage)
+        );
+        let long = Provenance {
+            build_id: "x".repeat(256),
+            ..provenance()
+        };
+        assert_eq!(
+            long.to_bytes(None),
+            Err(Error::FieldTooLong {
+                field: "build id",
+                length: 256
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_stamps_are_not_read() {
+        let stamp = provenance().to_bytes(Some(&key())).unwrap();
+        assert!(Provenance::parse(&stamp).is_some());
+        for len in 0..stamp.len() {
+            assert!(Provenance::parse(&stamp[..len]).is_none(), "{}", len);
+        }
+        let mut unknown_flag = stamp.clone();
+        unknown_flag[MAGIC.len()] |= 2;
+        assert!(Provenance::parse(&unknown_flag).is_none());
+        let mut trailing = provenance().to_bytes(None).unwrap();
+        trailing.push(0);
+        assert!(Provenance::parse(&trailing).is_none());
+    }
+}
@@ -0,0 +1,249 @@
+//! Signed receipts of what an encode run embedded, for handing over with stamped assets
+//!
+//! A receipt records SHA-256 hashes of the stego file and of the payload, never the payload
+//! itself, together with the embedding parameters. It is signed with an Ed25519 key so that
+//! `receipt verify` can later prove the file is still the one the receipt was issued for.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::Path;
+
+use crate::buildinfo;
+use crate::error::PngSecretError;
+use crate::fsguard;
+use crate::timefmt::{self, TimeFormat};
+
+/// Version of the receipt layout, bumped whenever the signed fields change
+const VERSION: u32 = 1;
+
+/// How the payload was embedded, without anything that helps to extract it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Parameters {
+    pub slot: String,
+    pub permute: String,
+    pub truncated: bool,
+    pub chunk_notice: bool,
+    pub alpha_payload: bool,
+}
+
+/// The signed part of a receipt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Body {
+    pub version: u32,
+    pub tool: String,
+    /// Seconds since the Unix epoch
+    pub created: u64,
+    /// `created` as RFC 3339 in UTC for readers of the JSON, missing in older receipts
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub created_utc: String,
+    pub output_sha256: String,
+    pub payload_sha256: String,
+    pub payload_bytes: usize,
+    pub parameters: Parameters,
+    pub public_key: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Receipt {
+    pub receipt: Body,
+    pub signature: String,
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex<const N: usize>(text: &str) -> Option<[u8; N]> {
+    let text = text.trim();
+    if text.len() != N * 2 || !text.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0; N];
+    for (byte, pair) in bytes.iter_mut().zip(text.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+/// Lowercase hex SHA-256 of `bytes`, as receipts and checksum sidecars record it
+pub(crate) fn sha256(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+fn invalid(reason: impl Into<String>) -> PngSecretError {
+    PngSecretError::ReceiptInvalid(reason.into())
+}
+
+/// Create a new signing key at `path`, which must not exist yet, and return its public key
+///
+/// The file holds the 32 byte secret as hex. On Unix it is only readable by its owner.
+pub fn generate_key(path: &Path) -> Result<VerifyingKey, PngSecretError> {
+    let key = SigningKey::from_bytes(&rand::thread_rng().gen());
+    let context = || format!("Couldn't create the signing key {:?}", path);
+    let mut file = fsguard::create_new(path).map_err(|e| PngSecretError::Io(context(), e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))
+            .map_err(|e| PngSecretError::Io(context(), e))?;
+    }
+    writeln!(file, "{}", hex(key.as_bytes())).map_err(|e| PngSecretError::Io(context(), e))?;
+    Ok(key.verifying_key())
+}
+
+/// Read a signing key written by [`generate_key`]
+pub fn read_signing_key(path: &Path) -> Result<SigningKey, PngSecretError> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| PngSecretError::Io(format!("Couldn't read the signing key {:?}", path), e))?;
+    let secret = unhex::<32>(&text).ok_or_else(|| {
+        PngSecretError::Usage(format!(
+            "The signing key {:?} must hold 64 hex characters, create one with `receipt keygen`",
+            path
+        ))
+    })?;
+    Ok(SigningKey::from_bytes(&secret))
+}
+
+/// The public key as written to receipts and accepted by `receipt verify --public-key`
+pub fn public_key_hex(key: &VerifyingKey) -> String {
+    hex(key.as_bytes())
+}
+
+fn signed_bytes(body: &Body) -> Vec<u8> {
+    serde_json::to_vec(body).expect("receipt body serializes")
+}
+
+/// Sign a receipt for the stego file with the contents `stego` carrying `payload`
+pub fn issue(key: &SigningKey, stego: &[u8], payload: &[u8], parameters: Parameters) -> Receipt {
+    let created = timefmt::now();
+    let body = Body {
+        version: VERSION,
+        tool: buildinfo::TOOL.to_string(),
+        created,
+        created_utc: TimeFormat::Rfc3339Utc.render(created),
+        output_sha256: sha256(stego),
+        payload_sha256: sha256(payload),
+        payload_bytes: payload.len(),
+        parameters,
+        public_key: public_key_hex(&key.verifying_key()),
+    };
+    let signature = key.sign(&signed_bytes(&body));
+    Receipt {
+        receipt: body,
+        signature: hex(&signature.to_bytes()),
+    }
+}
+
+pub fn write(receipt: &Receipt, path: &Path) -> Result<(), PngSecretError> {
+    let json = serde_json::to_string_pretty(receipt).expect("receipt serializes");
+    fsguard::write_atomic(path, format!("{}\n", json).as_bytes())
+        .map_err(|e| PngSecretError::Io(format!("Couldn't write the receipt {:?}", path), e))
+}
+
+/// Check the signature of the receipt at `path` and that `file` still matches it
+///
+/// Without `public_key` any valid signature is accepted, which only proves the receipt wasn't
+/// altered after signing; pass the issuer's key to also prove who signed it.
+pub fn verify(
+    path: &Path,
+    file: &Path,
+    public_key: Option<&str>,
+) -> Result<Receipt, PngSecretError> {
+    let json = std::fs::read(path)
+        .map_err(|e| PngSecretError::Io(format!("Couldn't read the receipt {:?}", path), e))?;
+    let receipt: Receipt =
+        serde_json::from_slice(&json).map_err(|e| invalid(format!("malformed receipt: {}", e)))?;
+    let body = &receipt.receipt;
+    if body.version != VERSION {
+        return Err(invalid(format!("unsupported version {}", body.version)));
+    }
+    if let Some(expected) = public_key {
+        if !expected.trim().eq_ignore_ascii_case(&body.public_key) {
+            return Err(invalid("signed by a different key than --public-key"));
+        }
+    }
+    let key = unhex::<32>(&body.public_key)
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| invalid("malformed public key"))?;
+    let signature =
+        unhex::<64>(&receipt.signature).ok_or_else(|| invalid("malformed signature"))?;
+    key.verify(&signed_bytes(body), &Signature::from_bytes(&signature))
+        .map_err(|_| invalid("the signature doesn't match, the receipt was altered"))?;
+    if !body.created_utc.is_empty()
+        && body.created_utc != TimeFormat::Rfc3339Utc.render(body.created)
+    {
+        return Err(invalid("created_utc doesn't match created"));
+    }
+    let contents =
+        std::fs::read(file).map_err(|_| PngSecretError::InputUnreadable(file.to_path_buf()))?;
+    if sha256(&contents) != body.output_sha256 {
+        return Err(invalid(format!(
+            "{} is not the file the receipt was issued for",
+            file.display()
+        )));
+    }
+    Ok(receipt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameters() -> Parameters {
+        Parameters {
+            slot: "all".to_string(),
+            permute: "none".to_string(),
+            truncated: false,
+            chunk_notice: false,
+            alpha_payload: false,
+        }
+    }
+
+    #[test]
+    fn hex_roundtrip() {
+        assert_eq!(hex(&[0, 0xab, 0x10]), "00ab10");
+        assert_eq!(unhex::<3>("00AB10\n"), Some([0, 0xab, 0x10]));
+        assert_eq!(unhex::<3>("00ab1"), None);
+        assert_eq!(unhex::<2>("zz00"), None);
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_path = dir.path().join("key");
+        let stego = dir.path().join("out.png");
+        let receipt_path = dir.path().join("receipt.json");
+        let public = generate_key(&key_path).unwrap();
+        let key = read_signing_key(&key_path).unwrap();
+        assert_eq!(key.verifying_key(), public);
+
+        std::fs::write(&stego, b"stego bytes").unwrap();
+        let receipt = issue(&key, b"stego bytes", b"payload", parameters());
+        assert_eq!(receipt.receipt.payload_sha256, sha256(b"payload"));
+        assert_eq!(
+            receipt.receipt.created_utc,
+            TimeFormat::Rfc3339Utc.render(receipt.receipt.created)
+        );
+        write(&receipt, &receipt_path).unwrap();
+        let public = public_key_hex(&public);
+        assert_eq!(
+            verify(&receipt_path, &stego, Some(&public)).unwrap(),
+            receipt
+        );
+        assert!(matches!(
+            verify(&receipt_path, &stego, Some(&"00".repeat(32))),
+            Err(PngSecretError::ReceiptInvalid(_))
+        ));
+
+        let mut altered = receipt.clone();
+        altered.receipt.payload_bytes += 1;
+        write(&altered, &receipt_path).unwrap();
+        assert!(matches!(
+            verify(&receipt_path, &stego, None),
+            Err(PngSecretError::ReceiptInvalid(_))
+        ));
+    }
+}
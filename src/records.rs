@@ -0,0 +1,157 @@
+//! Several named payloads in one frame, e.g. a caption next to the full text it sums up
+//!
+//! A record set is [`MAGIC`] and the number of records, followed by the records in order:
+//!
+//! ```text
+//! | magic | count | name length | name | flags | length | data | name length | ...
+//! |   4   |   1   |      1      |  n   |   1   |   4    |  m   |
+//! ```
+//!
+//! Names are UTF-8, lengths big-endian. Each record is compressed on its own before it is packed.
+//! No flags are defined yet, so a record with any set is refused rather than misread. The set
+//! itself is embedded like any payload, in the usual checksummed frame.
+
+use crate::Error;
+
+/// First bytes of a record set, the last one is the version of the layout
+pub const MAGIC: [u8; 4] = *b"PSR\x01";
+/// Longest record name, in bytes
+pub const MAX_NAME_BYTES: usize = u8::MAX as usize;
+/// Most records a set holds
+pub const MAX_RECORDS: usize = u8::MAX as usize;
+
+/// One named payload of a record set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+impl Record {
+    /// A record of `data` as it is
+    pub fn new(name: &str, data: Vec<u8>) -> Self {
+        Record {
+            name: name.to_string(),
+            data,
+        }
+    }
+}
+
+/// Whether `payload` is a record set rather than a single payload
+pub fn is_records(payload: &[u8]) -> bool {
+    payload.len() > MAGIC.len() && payload.starts_with(&MAGIC)
+}
+
+/// The record set of `records`, in order
+///
+/// Names aren't checked for duplicates, [`find`] returns the first record of a name.
+pub fn pack(records: &[Record]) -> Result<Vec<u8>, Error> {
+    if records.len() > MAX_RECORDS {
+        return Err(Error::TooManyRecords {
+            count: records.len(),
+        });
+    }
+    let mut set = MAGIC.to_vec();
+    set.push(records.len() as u8);
+    for record in records {
+        if record.name.len() > MAX_NAME_BYTES {
+            return Err(Error::FieldTooLong {
+                field: "record name",
+                length: record.name.len(),
+            });
+        }
+        let length = u32::try_from(record.data.len()).map_err(|_| Error::PayloadTooLarge {
+            capacity: u32::MAX as usize,
+            requested: record.data.len(),
+        })?;
+        set.push(record.name.len() as u8);
+        set.extend_from_slice(record.name.as_bytes());
+        set.push(0);
+        set.extend_from_slice(&length.to_be_bytes());
+        set.extend_from_slice(&record.data);
+    }
+    Ok(set)
+}
+
+/// The records of a set written by [`pack`]
+///
+/// Fails with [`Error::PayloadCorrupted`] unless `payload` is exactly one record set.
+pub fn unpack(payload: &[u8]) -> Result<Vec<Record>, Error> {
+    let mut rest = payload
+        .strip_prefix(&MAGIC)
+        .ok_or(Error::PayloadCorrupted)?;
+    let count = take(&mut rest, 1)?[0];
+    let mut records = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let name_length = take(&mut rest, 1)?[0] as usize;
+        let name = std::str::from_utf8(take(&mut rest, name_length)?)
+            .map_err(|_| Error::PayloadCorrupted)?
+            .to_string();
+        if take(&mut rest, 1)?[0] != 0 {
+            return Err(Error::PayloadCorrupted);
+        }
+        let length = u32::from_be_bytes(take(&mut rest, 4)?.try_into().unwrap());
+        records.push(Record {
+            name,
+            data: take(&mut rest, length as usize)?.to_vec(),
+        });
+    }
+    match rest.is_empty() {
+        true => Ok(records),
+        false => Err(Error::PayloadCorrupted),
+    }
+}
+
+/// The first record called `name`
+pub fn find<'a>(records: &'a [Record], name: &str) -> Option<&'a Record> {
+    records.iter().find(|record| record.name == name)
+}
+
+fn take<'a>(rest: &mut &'a [u8], length: usize) -> Result<&'a [u8], Error> {
+    if rest.len() < length {
+        return Err(Error::PayloadCorrupted);
+    }
+    let (taken, remaining) = rest.split_at(length);
+    *rest = remaining;
+    Ok(taken)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_sets_roundtrip_and_reject_garbage() {
+        let records = vec![
+            Record::new("caption", b"A sunset".to_vec()),
+            Record::new("secret", vec![0; 300]),
+            Record::new("", Vec::new()),
+        ];
+        let set = pack(&records).unwrap();
+        assert!(is_records(&set));
+        assert_eq!(
+            &set[..MAGIC.len() + 1 + 1 + 7 + 1 + 4],
+            b"PSR\x01\x03\x07caption\x00\x00\x00\x00\x08"
+        );
+        let unpacked = unpack(&set).unwrap();
+        assert_eq!(unpacked, records);
+        assert_eq!(find(&unpacked, "secret").unwrap().data.len(), 300);
+        assert!(find(&unpacked, "missing").is_none());
+
+        assert!(unpack(&set[..set.len() - 1]).is_err());
+        assert!(unpack(&[&set[..], b"x"].concat()).is_err());
+        assert!(unpack(b"hello").is_err());
+        assert!(!is_records(b"PSR\x01"));
+
+        let long = Record::new(&"n".repeat(MAX_NAME_BYTES + 1), Vec::new());
+        assert!(matches!(
+            pack(&[long]),
+            Err(Error::FieldTooLong { length: 256, .. })
+        ));
+        let many = vec![Record::new("a", Vec::new()); MAX_RECORDS + 1];
+        assert!(matches!(
+            pack(&many),
+            Err(Error::TooManyRecords { count: 256 })
+        ));
+    }
+}
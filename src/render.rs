@@ -0,0 +1,180 @@
+//! Terminal rendering of image crops using half-block characters
+//!
+//! Each terminal cell shows two vertically stacked pixels: the upper one as the foreground color
+//! of `▀` and the lower one as the background color. Only truecolor terminals are supported.
+
+use image::{Rgba, RgbaImage};
+use std::str::FromStr;
+
+const UPPER_HALF_BLOCK: char = '▀';
+const RESET: &str = "\x1b[0m";
+const GAP: &str = "  ";
+
+/// A rectangular region of an image, parsed from `x,y,width,height`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for Crop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+        if parts.len() != 4 {
+            return Err(format!("expected x,y,width,height but got {:?}", s));
+        }
+        let mut values = [0u32; 4];
+        for (value, part) in values.iter_mut().zip(parts) {
+            *value = part
+                .parse()
+                .map_err(|_| format!("invalid crop component {:?}", part))?;
+        }
+        if values[2] == 0 || values[3] == 0 {
+            return Err("crop width and height must be positive".to_string());
+        }
+        Ok(Crop {
+            x: values[0],
+            y: values[1],
+            width: values[2],
+            height: values[3],
+        })
+    }
+}
+
+impl Crop {
+    /// Shrink the crop so it lies within an image of the given dimensions
+    fn clamp(&self, width: u32, height: u32) -> Option<Crop> {
+        if self.x >= width || self.y >= height {
+            return None;
+        }
+        Some(Crop {
+            x: self.x,
+            y: self.y,
+            width: self.width.min(width - self.x),
+            height: self.height.min(height - self.y),
+        })
+    }
+}
+
+/// Whether the terminal advertises 24-bit color support through `COLORTERM`
+pub fn supports_truecolor() -> bool {
+    std::env::var("COLORTERM")
+        .map(|v| v == "truecolor" || v == "24bit")
+        .unwrap_or(false)
+}
+
+/// Composite a pixel onto a black background so transparency renders deterministically
+fn to_rgb(pixel: &Rgba<u8>) -> [u8; 3] {
+    let [r, g, b, a] = pixel.0;
+    let blend = |c: u8| ((c as u16 * a as u16 + 127) / 255) as u8;
+    [blend(r), blend(g), blend(b)]
+}
+
+/// Map a pair of vertically stacked pixels to one terminal cell
+fn cell(top: &Rgba<u8>, bottom: Option<&Rgba<u8>>) -> String {
+    let [tr, tg, tb] = to_rgb(top);
+    match bottom {
+        Some(bottom) => {
+            let [br, bg, bb] = to_rgb(bottom);
+            format!(
+                "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m{}",
+                tr, tg, tb, br, bg, bb, UPPER_HALF_BLOCK
+            )
+        }
+        None => format!(
+            "\x1b[38;2;{};{};{}m\x1b[49m{}",
+            tr, tg, tb, UPPER_HALF_BLOCK
+        ),
+    }
+}
+
+/// Render a crop of the image as terminal lines, two pixel rows per line
+fn render_lines(img: &RgbaImage, crop: &Crop) -> Vec<String> {
+    (crop.y..crop.y + crop.height)
+        .step_by(2)
+        .map(|y| {
+            let mut line: String = (crop.x..crop.x + crop.width)
+                .map(|x| {
+                    let bottom = (y + 1 < crop.y + crop.height).then(|| img.get_pixel(x, y + 1));
+                    cell(img.get_pixel(x, y), bottom)
+                })
+                .collect();
+            line.push_str(RESET);
+            line
+        })
+        .collect()
+}
+
+/// Render the same crop of two equally sized images next to each other
+pub fn side_by_side(before: &RgbaImage, after: &RgbaImage, crop: &Crop) -> Option<String> {
+    let crop = crop.clamp(before.width(), before.height())?;
+    let rendered = render_lines(before, &crop)
+        .into_iter()
+        .zip(render_lines(after, &crop))
+        .map(|(left, right)| format!("{}{}{}\n", left, GAP, right))
+        .collect();
+    Some(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crop_parse() {
+        assert_eq!(
+            "1, 2,64,64".parse::<Crop>(),
+            Ok(Crop {
+                x: 1,
+                y: 2,
+                width: 64,
+                height: 64
+            })
+        );
+        assert!("1,2,64".parse::<Crop>().is_err());
+        assert!("1,2,0,64".parse::<Crop>().is_err());
+        assert!("a,2,3,4".parse::<Crop>().is_err());
+    }
+
+    #[test]
+    fn crop_clamp() {
+        let crop: Crop = "8,8,64,64".parse().unwrap();
+        let clamped = crop.clamp(16, 10).unwrap();
+        assert_eq!((clamped.width, clamped.height), (8, 2));
+        assert!(crop.clamp(8, 16).is_none());
+    }
+
+    #[test]
+    fn color_to_rgb_blends_alpha() {
+        assert_eq!(to_rgb(&Rgba([200, 100, 50, 255])), [200, 100, 50]);
+        assert_eq!(to_rgb(&Rgba([200, 100, 50, 0])), [0, 0, 0]);
+        assert_eq!(to_rgb(&Rgba([255, 255, 255, 128])), [128, 128, 128]);
+    }
+
+    #[test]
+    fn cell_uses_top_as_foreground_and_bottom_as_background() {
+        let top = Rgba([1, 2, 3, 255]);
+        let bottom = Rgba([4, 5, 6, 255]);
+        assert_eq!(
+            cell(&top, Some(&bottom)),
+            "\x1b[38;2;1;2;3m\x1b[48;2;4;5;6m▀"
+        );
+        assert_eq!(cell(&top, None), "\x1b[38;2;1;2;3m\x1b[49m▀");
+    }
+
+    #[test]
+    fn side_by_side_dimensions() {
+        let img = RgbaImage::new(4, 3);
+        let crop: Crop = "0,0,4,3".parse().unwrap();
+        let rendered = side_by_side(&img, &img, &crop).unwrap();
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines
+            .iter()
+            .all(|l| l.matches(UPPER_HALF_BLOCK).count() == 8));
+    }
+}
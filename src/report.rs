@@ -0,0 +1,87 @@
+//! The single JSON object `--json` prints on stdout in place of the output path or the payload
+//!
+//! One line per run, whether it succeeded or not, so scripts parse stdout without knowing the
+//! mode. Everything meant for humans stays on stderr as without `--json`. The field names are
+//! stable, `tests/result.schema.json` pins them.
+
+use base64ct::{Base64, Encoding};
+use serde::Serialize;
+use std::path::Path;
+
+use crate::error::PngSecretError;
+use crate::output::{self, Channel};
+
+/// What an encode wrote
+#[derive(Debug, Serialize)]
+pub struct Encoded {
+    pub output: Option<String>,
+    /// Size of the payload as given
+    pub payload_bytes: usize,
+    /// Size of the part of it that was embedded, smaller with `--truncate-to-fit`
+    pub embedded_bytes: usize,
+    pub capacity_bytes: usize,
+}
+
+/// What a decode read
+#[derive(Debug, Serialize)]
+pub struct Decoded {
+    pub payload_base64: String,
+    /// Whether the payload is valid UTF-8 text
+    pub utf8: bool,
+    pub bytes: usize,
+    /// Where `-o` saved the payload
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+}
+
+impl Decoded {
+    pub fn new(payload: &[u8], output: Option<&Path>) -> Self {
+        Decoded {
+            payload_base64: Base64::encode_string(payload),
+            utf8: std::str::from_utf8(payload).is_ok(),
+            bytes: payload.len(),
+            output: output.map(|output| output.to_string_lossy().into_owned()),
+        }
+    }
+}
+
+/// Why a run failed, with the codes of the exit code table
+#[derive(Debug, Serialize)]
+pub struct Failed {
+    pub error: String,
+    pub code: &'static str,
+    pub exit_code: i32,
+}
+
+impl From<&PngSecretError> for Failed {
+    fn from(e: &PngSecretError) -> Self {
+        Failed {
+            error: e.to_string(),
+            code: e.kind().code(),
+            exit_code: e.exit_code(),
+        }
+    }
+}
+
+/// Print `result` as one line of JSON on stdout
+pub fn print(result: &impl Serialize) {
+    let json = serde_json::to_string(result).expect("results serialize");
+    output::line(Channel::Payload, json);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_serialize_to_the_documented_fields() {
+        let decoded = serde_json::to_value(Decoded::new(b"hi\xff", None)).unwrap();
+        assert_eq!(
+            decoded,
+            serde_json::json!({"payload_base64": "aGn/", "utf8": false, "bytes": 3})
+        );
+        let failed = serde_json::to_value(Failed::from(&PngSecretError::NoMessage)).unwrap();
+        assert_eq!(failed["code"], "no_message");
+        assert_eq!(failed["exit_code"], 4);
+    }
+}
@@ -0,0 +1,290 @@
+//! `sanitize` removes every pngsecret trace from an image before publication
+//!
+//! The LSBs are zeroed, randomized or kept, the notice chunk is dropped, and the image is written
+//! again with the same encoder settings every time, so only LSBs and metadata ever change.
+//! Randomized LSBs come from a generator seeded by the rest of the pixel data, which makes the
+//! output deterministic. Afterwards the output is checked with [`detect`] and the command fails if
+//! a trace is left.
+//!
+//! Without a header, only payloads that read as recognizable content in sequential order are
+//! detectable. Binary or permuted payloads can't be told apart from a clean cover, but zeroing or
+//! randomizing the LSBs destroys them just the same.
+
+use pngsecret::carrier::{self, Carrier};
+use rand::Rng;
+use rand_chacha::rand_core::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::Path;
+
+use crate::error::PngSecretError;
+use crate::fsguard;
+use crate::order::{Slot, SubpixelOrder};
+use crate::sniff::{self, ContentType};
+use crate::{pngio, probe, NaiveDecoder, PngSecretReader};
+
+/// Shortest readable message counted as a trace, shorter text shows up in clean covers by chance
+const MIN_TRACE_LEN: usize = 4;
+/// Seeds tried for `--lsb randomize` before giving up on LSBs that happen to read as a message
+const RANDOMIZE_ATTEMPTS: u64 = 8;
+
+/// What happens to the LSBs of every subpixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lsb {
+    Randomize,
+    Zero,
+    Keep,
+}
+
+impl std::str::FromStr for Lsb {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "randomize" => Ok(Lsb::Randomize),
+            "zero" => Ok(Lsb::Zero),
+            "keep" => Ok(Lsb::Keep),
+            _ => Err(format!("unknown LSB treatment {:?}", s)),
+        }
+    }
+}
+
+/// Something in an image that gives away pngsecret
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trace {
+    /// A tEXt chunk with one of our keywords
+    Chunk { keyword: String },
+    /// A message readable from the LSBs of `slot`
+    Message {
+        slot: Slot,
+        bytes: usize,
+        content: ContentType,
+    },
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Trace::Chunk { keyword } => write!(f, "the tEXt chunk {:?}", keyword),
+            Trace::Message {
+                slot,
+                bytes,
+                content,
+            } => write!(
+                f,
+                "a message of {} bytes of {} in the {:?} slot",
+                bytes,
+                content.description(),
+                slot
+            ),
+        }
+    }
+}
+
+/// What `sanitize` found and did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub removed: Vec<Trace>,
+    pub lsb: Lsb,
+    /// Subpixels whose LSB changed
+    pub changed_subpixels: usize,
+    /// tEXt chunks carried over unchanged
+    pub kept_chunks: usize,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.removed.is_empty() {
+            writeln!(f, "No pngsecret traces found")?;
+        }
+        for trace in &self.removed {
+            writeln!(f, "Removed {}", trace)?;
+        }
+        writeln!(
+            f,
+            "LSBs {:?}: {} subpixels changed, {} other tEXt chunks kept, nothing else altered",
+            self.lsb, self.changed_subpixels, self.kept_chunks
+        )
+    }
+}
+
+fn is_ours(keyword: &str) -> bool {
+    keyword == pngio::NOTICE_KEYWORD
+}
+
+/// Every pngsecret trace of an image with the given tEXt chunks
+pub fn detect<C: Carrier + Clone>(img: &C, texts: &[(String, String)]) -> Vec<Trace> {
+    let mut traces: Vec<Trace> = texts
+        .iter()
+        .filter(|(keyword, _)| is_ours(keyword))
+        .map(|(keyword, _)| Trace::Chunk {
+            keyword: keyword.clone(),
+        })
+        .collect();
+    for slot in [Slot::All, Slot::Rgb, Slot::Alpha] {
+        let message = PngSecretReader::new(img.clone(), Box::new(NaiveDecoder::new()))
+            .with_order(SubpixelOrder::Sequential)
+            .with_slot(slot)
+            .with_verify(false)
+            .read_image();
+        let Ok(message) = message else { continue };
+        let content = sniff::sniff(&message);
+        if message.len() >= MIN_TRACE_LEN && content != ContentType::Binary {
+            traces.push(Trace::Message {
+                slot,
+                bytes: message.len(),
+                content,
+            });
+        }
+    }
+    traces
+}
+
+/// Seed for randomized LSBs, derived from everything but the LSBs
+fn seed(img: &impl Carrier, attempt: u64) -> u64 {
+    let (width, height) = img.dimensions();
+    let mut hasher = Sha256::new();
+    hasher.update(width.to_le_bytes());
+    hasher.update(height.to_le_bytes());
+    hasher.update(
+        img.samples()
+            .iter()
+            .map(|sample| sample & !1)
+            .collect::<Vec<u8>>(),
+    );
+    hasher.update(attempt.to_le_bytes());
+    let digest = hasher.finalize();
+    u64::from_le_bytes(digest[..8].try_into().expect("digest is 32 bytes"))
+}
+
+/// Apply `lsb` to a copy of `img`
+fn clean<C: Carrier + Clone>(img: &C, lsb: Lsb, attempt: u64) -> C {
+    let mut cleaned = img.clone();
+    match lsb {
+        Lsb::Keep => {}
+        Lsb::Zero => cleaned
+            .samples_mut()
+            .iter_mut()
+            .for_each(|sample| *sample &= !1),
+        Lsb::Randomize => {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed(img, attempt));
+            for sample in cleaned.samples_mut() {
+                *sample = (*sample & !1) | rng.gen_range(0..=1);
+            }
+        }
+    }
+    cleaned
+}
+
+/// Sanitize the image at `input` into `output`, failing and removing it if a trace survives
+pub fn sanitize(input: &Path, output: &Path, lsb: Lsb) -> Result<Report, PngSecretError> {
+    let (img, wide) = carrier::payload_carrier(probe::open(input)?);
+    let mut texts = pngio::read_text_chunks(input).unwrap_or_default();
+    let removed = detect(&img, &texts);
+    texts.retain(|(keyword, _)| !is_ours(keyword));
+
+    let attempts = match lsb {
+        Lsb::Randomize => RANDOMIZE_ATTEMPTS,
+        Lsb::Zero | Lsb::Keep => 1,
+    };
+    let mut cleaned = clean(&img, lsb, 0);
+    for attempt in 1..attempts {
+        if detect(&cleaned, &texts).is_empty() {
+            break;
+        }
+        cleaned = clean(&img, lsb, attempt);
+    }
+    let cleaned = match wide {
+        Some(wide) => carrier::with_low_bytes(wide, &cleaned),
+        None => cleaned,
+    };
+    pngio::save_image_with_text(&cleaned, output, &texts)
+        .map_err(|_| PngSecretError::SaveFailed(output.to_path_buf()))?;
+
+    let saved =
+        image::open(output).map_err(|_| PngSecretError::InputUnreadable(output.to_path_buf()))?;
+    let saved = carrier::payload_samples(saved);
+    let saved_texts = pngio::read_text_chunks(output).unwrap_or_default();
+    let remaining = detect(&saved, &saved_texts);
+    if !remaining.is_empty() {
+        // Whatever the reason, a file with traces must not be left around for publication
+        let _ = fsguard::remove_file(output);
+        return Err(PngSecretError::TracesRemain(
+            remaining.iter().map(Trace::to_string).collect(),
+        ));
+    }
+    Ok(Report {
+        removed,
+        lsb,
+        changed_subpixels: img
+            .samples()
+            .iter()
+            .zip(saved.samples())
+            .filter(|(a, b)| a != b)
+            .count(),
+        kept_chunks: saved_texts.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NaiveEncoder, PngSecretWriter};
+    use image::RgbaImage;
+
+    fn stego(slot: Slot, payload: &[u8]) -> RgbaImage {
+        let mut cover = RgbaImage::new(32, 32);
+        ChaCha8Rng::seed_from_u64(1).fill(&mut *cover);
+        let mut writer = PngSecretWriter::new(cover, Box::new(NaiveEncoder::new())).with_slot(slot);
+        writer.encoder.encode(payload);
+        writer.embed().unwrap();
+        writer.buffer
+    }
+
+    #[test]
+    fn detects_readable_messages_per_slot() {
+        let traces = detect(&stego(Slot::Alpha, b"alpha secret"), &[]);
+        assert!(traces.contains(&Trace::Message {
+            slot: Slot::Alpha,
+            bytes: 12,
+            content: ContentType::Utf8Text
+        }));
+        let notice = vec![(pngio::NOTICE_KEYWORD.to_string(), "hi".to_string())];
+        assert_eq!(
+            detect(&RgbaImage::new(4, 4), &notice),
+            [Trace::Chunk {
+                keyword: pngio::NOTICE_KEYWORD.to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn randomized_lsbs_are_deterministic_and_keep_the_rest() {
+        let img = stego(Slot::All, b"all secret");
+        let first = clean(&img, Lsb::Randomize, 0);
+        assert_eq!(first, clean(&img, Lsb::Randomize, 0));
+        assert_ne!(first, clean(&img, Lsb::Randomize, 1));
+        assert!(img.iter().zip(first.iter()).all(|(a, b)| a >> 1 == b >> 1));
+        assert!(clean(&img, Lsb::Zero, 0)
+            .iter()
+            .all(|sample| sample % 2 == 0));
+        assert_eq!(clean(&img, Lsb::Keep, 0), img);
+    }
+
+    #[test]
+    fn keeping_lsbs_of_a_stego_image_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("stego.png");
+        stego(Slot::Rgb, b"rgb secret").save(&input).unwrap();
+        let output = dir.path().join("out.png");
+        assert!(matches!(
+            sanitize(&input, &output, Lsb::Keep),
+            Err(PngSecretError::TracesRemain(_))
+        ));
+        assert!(!output.exists());
+        let report = sanitize(&input, &output, Lsb::Zero).unwrap();
+        assert_eq!(report.removed.len(), 1);
+        assert!(detect(&image::open(&output).unwrap().into_rgba8(), &[]).is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+//! One payload spread over several images when it doesn't fit into one
+//!
+//! Each image holds one segment, [`MAGIC`], the id shared by all segments of the payload, the
+//! index of the segment and the number of segments, followed by its part of the payload:
+//!
+//! ```text
+//! | magic | payload id | index | total | data |
+//! |   4   |     8      |   2   |   2   |  n   |
+//! ```
+//!
+//! Numbers are big-endian. A segment is embedded like any payload, compressed and in the usual
+//! checksummed frame, so each image checks out on its own. [`join`] takes the segments in
+//! any order and refuses sets with a segment missing, twice, or from another payload.
+
+use crate::Error;
+
+/// First bytes of a segment, the last one is the version of the layout
+pub const MAGIC: [u8; 4] = *b"PSG\x01";
+/// Bytes a segment adds to its part of the payload
+pub const HEADER_BYTES: usize = MAGIC.len() + 8 + 2 + 2;
+/// Most images one payload is spread over
+pub const MAX_SEGMENTS: usize = u16::MAX as usize;
+
+/// One part of a spread payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    /// Shared by every segment of the payload, random so unrelated images don't mix
+    pub id: u64,
+    pub index: u16,
+    pub total: u16,
+    pub data: Vec<u8>,
+}
+
+impl Segment {
+    /// The bytes to embed for the segment
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_BYTES + self.data.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&self.id.to_be_bytes());
+        bytes.extend_from_slice(&self.index.to_be_bytes());
+        bytes.extend_from_slice(&self.total.to_be_bytes());
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    /// The segment of `payload`, [`Error::PayloadCorrupted`] unless it is one
+    pub fn parse(payload: &[u8]) -> Result<Self, Error> {
+        if !is_segment(payload) {
+            return Err(Error::PayloadCorrupted);
+        }
+        let field = |at: usize, bytes: usize| &payload[MAGIC.len() + at..][..bytes];
+        let segment = Segment {
+            id: u64::from_be_bytes(field(0, 8).try_into().unwrap()),
+            index: u16::from_be_bytes(field(8, 2).try_into().unwrap()),
+            total: u16::from_be_bytes(field(10, 2).try_into().unwrap()),
+            data: payload[HEADER_BYTES..].to_vec(),
+        };
+        match segment.index < segment.total {
+            true => Ok(segment),
+            false => Err(Error::PayloadCorrupted),
+        }
+    }
+}
+
+/// Whether `payload` is a segment rather than a whole payload
+pub fn is_segment(payload: &[u8]) -> bool {
+    payload.len() >= HEADER_BYTES && payload.starts_with(&MAGIC)
+}
+
+/// `payload` cut into one segment per entry of `capacities`, in order, each at most as long as
+/// its capacity with the header
+///
+/// The payload is spread in proportion to the capacities rather than filling the first images,
+/// so no image carries more than its share.
+pub fn split(payload: &[u8], capacities: &[usize], id: u64) -> Result<Vec<Segment>, Error> {
+    let room: Vec<usize> = capacities
+        .iter()
+        .map(|capacity| capacity.saturating_sub(HEADER_BYTES))
+        .collect();
+    let total_room: usize = room.iter().sum();
+    if payload.len() > total_room {
+        return Err(Error::PayloadTooLarge {
+            capacity: total_room,
+            requested: payload.len(),
+        });
+    }
+    let total = u16::try_from(capacities.len()).map_err(|_| Error::TooManySegments {
+        count: capacities.len(),
+    })?;
+    let (mut rest, mut rest_room) = (payload, total_room);
+    let mut segments = Vec::with_capacity(capacities.len());
+    for (index, room) in room.into_iter().enumerate() {
+        // Rounded up so the last images aren't left with more than they hold
+        let share = match rest_room {
+            0 => 0,
+            _ => (rest.len() as u128 * room as u128).div_ceil(rest_room as u128) as usize,
+        };
+        let (data, remaining) = rest.split_at(share.min(room).min(rest.len()));
+        segments.push(Segment {
+            id,
+            index: index as u16,
+            total,
+            data: data.to_vec(),
+        });
+        rest = remaining;
+        rest_room -= room;
+    }
+    Ok(segments)
+}
+
+/// The payload of `segments`, given in any order
+pub fn join(mut segments: Vec<Segment>) -> Result<Vec<u8>, Error> {
+    let Some(first) = segments.first() else {
+        return Ok(Vec::new());
+    };
+    let (id, total) = (first.id, first.total);
+    if segments
+        .iter()
+        .any(|segment| segment.id != id || segment.total != total)
+    {
+        return Err(Error::SegmentsMixed);
+    }
+    segments.sort_by_key(|segment| segment.index);
+    if let Some(pair) = segments
+        .windows(2)
+        .find(|pair| pair[0].index == pair[1].index)
+    {
+        return Err(Error::SegmentDuplicated {
+            index: pair[0].index as usize,
+        });
+    }
+    if let Some(index) =
+        (0..total).find(|&index| segments.get(index as usize).map(|s| s.index) != Some(index))
+    {
+        return Err(Error::SegmentMissing {
+            index: index as usize,
+            total: total as usize,
+        });
+    }
+    Ok(segments
+        .into_iter()
+        .flat_map(|segment| segment.data)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segments_split_in_proportion_and_join_in_any_order() {
+        let payload: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let capacities = [HEADER_BYTES + 100, HEADER_BYTES + 600, HEADER_BYTES + 600];
+        let segments = split(&payload, &capacities, 7).unwrap();
+        let lengths: Vec<usize> = segments.iter().map(|s| s.data.len()).collect();
+        assert_eq!(lengths, [77, 462, 461]);
+        assert!(segments.iter().all(|s| s.id == 7 && s.total == 3));
+
+        let bytes = segments[1].to_bytes();
+        assert_eq!(
+            &bytes[..HEADER_BYTES],
+            b"PSG\x01\0\0\0\0\0\0\0\x07\0\x01\0\x03"
+        );
+        assert_eq!(Segment::parse(&bytes).unwrap(), segments[1]);
+
+        let shuffled = vec![
+            segments[2].clone(),
+            segments[0].clone(),
+            segments[1].clone(),
+        ];
+        assert_eq!(join(shuffled).unwrap(), payload);
+        assert_eq!(
+            join(vec![segments[0].clone(), segments[2].clone()]),
+            Err(Error::SegmentMissing { index: 1, total: 3 })
+        );
+        assert_eq!(
+            join(vec![
+                segments[2].clone(),
+                segments[0].clone(),
+                segments[2].clone()
+            ]),
+            Err(Error::SegmentDuplicated { index: 2 })
+        );
+        let other = split(&payload, &capacities, 8).unwrap();
+        assert_eq!(
+            join(vec![
+                segments[0].clone(),
+                other[1].clone(),
+                segments[2].clone()
+            ]),
+            Err(Error::SegmentsMixed)
+        );
+
+        // Exactly full, and one byte past
+        let full = split(&payload[..700], &capacities[..2], 1).unwrap();
+        assert_eq!(full[0].data.len() + full[1].data.len(), 700);
+        assert!(full
+            .iter()
+            .zip([100, 600])
+            .all(|(s, room)| s.data.len() <= room));
+        assert_eq!(
+            split(&payload[..701], &capacities[..2], 1),
+            Err(Error::PayloadTooLarge {
+                capacity: 700,
+                requested: 701
+            })
+        );
+
+        assert!(Segment::parse(b"PSG\x01").is_err());
+        assert!(Segment::parse(b"PSG\x01\0\0\0\0\0\0\0\x07\0\x03\0\x03").is_err());
+        assert!(!is_segment(b"hello"));
+    }
+}
@@ -0,0 +1,147 @@
+//! Listing the payloads of an image without extracting them
+//!
+//! An image can carry a payload in the rgb slot and another in the alpha slot, each at its own
+//! depth, or one in every subpixel. [`enumerate_slots`] probes each slot at each depth for a frame
+//! header and the first payload bytes, which is enough to tell the codec. Probes are independent,
+//! so a corrupt header only hides its own slot. Only the sequential order can be probed, a
+//! permuted payload needs its seed, and legacy NUL-terminated payloads have no header to find.
+
+use crate::carrier::Carrier;
+use crate::compress::{self, CompressingEncoder};
+use crate::format::{self, FrameHeader};
+use crate::order::{Slot, SubpixelOrder};
+use crate::{read_slot_bytes, NaiveEncoder};
+
+/// Slots probed by [`enumerate_slots`], in the order they are listed
+pub const PROBED: [Slot; 3] = [Slot::All, Slot::Rgb, Slot::Alpha];
+
+/// A payload found by [`enumerate_slots`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotInfo {
+    pub slot: Slot,
+    pub bits: u8,
+    /// Buffer index of the subpixel holding the first payload bit, right after the frame header
+    pub offset: usize,
+    /// Payload length the frame header declares
+    pub length: u64,
+    /// ID of the outermost codec, told by the first payload bytes
+    pub codec: &'static str,
+}
+
+/// Every framed payload of `img` in the sequential order, reading only headers
+pub fn enumerate_slots(img: &impl Carrier) -> Vec<SlotInfo> {
+    let (samples, channels) = (img.samples(), img.channels());
+    PROBED
+        .into_iter()
+        .flat_map(|slot| (1..=format::MAX_BITS).map(move |bits| (slot, bits)))
+        .filter_map(|(slot, bits)| probe(samples, channels, slot, bits))
+        .collect()
+}
+
+fn probe(samples: &[u8], channels: u8, slot: Slot, bits: u8) -> Option<SlotInfo> {
+    let order = SubpixelOrder::Sequential;
+    let head: Vec<u8> = read_slot_bytes(samples, channels, slot, &order, 0, bits)
+        .take(format::MAX_HEADER_BYTES + compress::OVERHEAD_BYTES)
+        .map(|(value, _)| value)
+        .collect();
+    let slot_bytes = format::slot_bytes(slot.subpixels_in(samples.len(), channels) as u64, bits);
+    let header = FrameHeader::parse(&head, slot_bytes)?;
+    // A header describing another slot or depth is a payload seen through the wrong one
+    if let Some(features) = header.features {
+        if (features.slot, features.bits) != (slot, bits) {
+            return None;
+        }
+    }
+    let (length, overhead) = (header.length, header.bytes());
+    let payload = &head[overhead..head.len().min(overhead + length as usize)];
+    let codec = match compress::method(payload) {
+        Some(_) => CompressingEncoder::ID,
+        None => NaiveEncoder::ID,
+    };
+    // The subpixel holding the first bit after the header, which at 3 bits per subpixel also
+    // holds the last header bits
+    let offset = slot
+        .indices_in(&order, samples.len(), channels)
+        .nth(overhead * 8 / bits as usize)
+        .unwrap_or(samples.len());
+    Some(SlotInfo {
+        slot,
+        bits,
+        offset,
+        length,
+        codec,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress::CompressingDecoder;
+    use crate::format::Framing;
+    use crate::{
+        NaiveDecoder, PngSecretDecoder, PngSecretEncoder, PngSecretReader, PngSecretWriter,
+    };
+    use image::RgbaImage;
+
+    fn embed(
+        img: RgbaImage,
+        slot: Slot,
+        encoder: impl PngSecretEncoder + 'static,
+        payload: &[u8],
+    ) -> RgbaImage {
+        let mut writer = PngSecretWriter::new(img, Box::new(encoder)).with_slot(slot);
+        writer.encoder.encode(payload);
+        writer.embed().unwrap();
+        writer.buffer
+    }
+
+    fn read(img: &RgbaImage, info: &SlotInfo, decoder: impl PngSecretDecoder + 'static) -> Vec<u8> {
+        PngSecretReader::new(img.clone(), Box::new(decoder))
+            .with_slot(info.slot)
+            .with_bits(info.bits)
+            .read_image()
+            .unwrap()
+    }
+
+    #[test]
+    fn heterogeneous_slots_are_listed_and_decode_on_their_own() {
+        let naive = || NaiveEncoder::with_framing(Framing::LengthPrefixed);
+        let img = RgbaImage::from_fn(64, 64, |x, y| image::Rgba([x as u8, y as u8, 77, 255]));
+        let text = b"to be or not to be ".repeat(20);
+        let img = embed(
+            img,
+            Slot::Rgb,
+            CompressingEncoder::new(Box::new(naive())),
+            &text,
+        );
+        let note = b"caption";
+        let img = embed(img, Slot::Alpha, naive().with_bits(3), note);
+
+        let slots = enumerate_slots(&img);
+        let summary: Vec<_> = slots.iter().map(|s| (s.slot, s.bits, s.codec)).collect();
+        assert_eq!(
+            summary,
+            [
+                (Slot::Rgb, 1, CompressingEncoder::ID),
+                (Slot::Alpha, 3, NaiveEncoder::ID),
+            ]
+        );
+        // The 104 header bits end in the third rgb subpixel of pixel 34, and in the alpha
+        // subpixel of pixel 34 at 3 bits each
+        assert_eq!(slots[0].offset, 34 * 4 + 2);
+        assert_eq!(slots[1].offset, 34 * 4 + 3);
+        assert_eq!(slots[1].length, note.len() as u64);
+
+        assert_eq!(read(&img, &slots[0], CompressingDecoder::new()), text);
+        assert_eq!(read(&img, &slots[1], NaiveDecoder::new()), note);
+
+        // A header announcing more than the slot holds only hides its own slot
+        let mut corrupt = img.clone();
+        let samples: &mut [u8] = &mut corrupt;
+        let length_bits = Slot::Rgb.indices(&SubpixelOrder::Sequential, samples.len());
+        for subpixel in length_bits.skip(16).take(8) {
+            samples[subpixel] |= 1;
+        }
+        assert_eq!(enumerate_slots(&corrupt), slots[1..]);
+    }
+}
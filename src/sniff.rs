@@ -0,0 +1,125 @@
+//! Guess what an extracted payload contains from its magic number
+//!
+//! Legacy payloads carry no type tag, so decode sniffs the bytes to decide whether to print them
+//! and which file extension to suggest. Legacy payloads end at the first NUL byte, so most binary
+//! formats only survive up to their magic number, which is all the sniffing needs.
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentType {
+    Png,
+    Pdf,
+    Zip,
+    Gzip,
+    Utf8Text,
+    /// UTF-16 text starting with a byte order mark
+    Utf16Text,
+    Binary,
+}
+
+const MAGIC: [(&[u8], ContentType); 4] = [
+    (b"\x89PNG\r\n\x1a\n", ContentType::Png),
+    (b"%PDF-", ContentType::Pdf),
+    (b"PK\x03\x04", ContentType::Zip),
+    (b"\x1f\x8b", ContentType::Gzip),
+];
+
+/// Classify `bytes`, magic numbers take precedence over text since `%PDF-` is valid UTF-8
+pub fn sniff(bytes: &[u8]) -> ContentType {
+    if let Some((_, content)) = MAGIC.iter().find(|(magic, _)| bytes.starts_with(magic)) {
+        return *content;
+    }
+    if bytes.starts_with(b"\xff\xfe") || bytes.starts_with(b"\xfe\xff") {
+        return ContentType::Utf16Text;
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        return ContentType::Utf8Text;
+    }
+    ContentType::Binary
+}
+
+impl ContentType {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ContentType::Png => "png",
+            ContentType::Pdf => "pdf",
+            ContentType::Zip => "zip",
+            ContentType::Gzip => "gz",
+            ContentType::Utf8Text | ContentType::Utf16Text => "txt",
+            ContentType::Binary => "bin",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ContentType::Png => "a PNG image",
+            ContentType::Pdf => "a PDF document",
+            ContentType::Zip => "a ZIP archive",
+            ContentType::Gzip => "gzip compressed data",
+            ContentType::Utf8Text => "UTF-8 text",
+            ContentType::Utf16Text => "UTF-16 text",
+            ContentType::Binary => "binary data",
+        }
+    }
+
+    /// File name to suggest when saving a payload of this type
+    pub fn suggested_name(&self) -> String {
+        format!("payload.{}", self.extension())
+    }
+
+    /// The payload as printable text, `None` for binary content
+    pub fn text(&self, bytes: &[u8]) -> Option<String> {
+        match self {
+            ContentType::Utf8Text => String::from_utf8(bytes.to_vec()).ok(),
+            ContentType::Utf16Text => {
+                let big_endian = bytes.starts_with(b"\xfe\xff");
+                let units: Vec<u16> = bytes[2..]
+                    .chunks(2)
+                    .map(|pair| match (pair, big_endian) {
+                        ([hi, lo], true) | ([lo, hi], false) => u16::from_le_bytes([*lo, *hi]),
+                        _ => char::REPLACEMENT_CHARACTER as u16,
+                    })
+                    .collect();
+                Some(String::from_utf16_lossy(&units))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn magic_numbers() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n"), ContentType::Png);
+        assert_eq!(sniff(b"%PDF-1.7\n"), ContentType::Pdf);
+        assert_eq!(sniff(b"PK\x03\x04\x14"), ContentType::Zip);
+        assert_eq!(sniff(b"\x1f\x8b\x08"), ContentType::Gzip);
+        assert_eq!(sniff("hello wörld".as_bytes()), ContentType::Utf8Text);
+        assert_eq!(sniff(b""), ContentType::Utf8Text);
+        assert_eq!(sniff(b"\xc3\x28\x01"), ContentType::Binary);
+    }
+
+    #[test]
+    fn utf16_with_either_byte_order() {
+        let little = sniff(b"\xff\xfeh\x01");
+        assert_eq!(little, ContentType::Utf16Text);
+        assert_eq!(little.text(b"\xff\xfeh\x01").unwrap(), "\u{0168}");
+        assert_eq!(
+            ContentType::Utf16Text.text(b"\xfe\xff\x01h").unwrap(),
+            "\u{0168}"
+        );
+        assert_eq!(ContentType::Utf16Text.text(b"\xff\xfe").unwrap(), "");
+    }
+
+    #[test]
+    fn suggested_names() {
+        assert_eq!(ContentType::Pdf.suggested_name(), "payload.pdf");
+        assert_eq!(ContentType::Gzip.suggested_name(), "payload.gz");
+        assert_eq!(ContentType::Pdf.text(b"%PDF-"), None);
+    }
+}
@@ -0,0 +1,107 @@
+//! One payload spread over several images, `--input` given more than once
+//!
+//! Encode compresses the payload as asked, cuts it into a [`Segment`] per image in proportion to
+//! their capacity and embeds each through the regular single-image path into `*.enc.png` next to
+//! its image. Decode reads the segment of every image, in any order, puts the payload back
+//! together and opens it like the payload of a single image. A directory among the inputs stands
+//! for the PNG files in it.
+
+use pngsecret::compress;
+use pngsecret::segments::{self, Segment};
+use std::path::{Path, PathBuf};
+
+use crate::error::PngSecretError;
+use crate::output::{self, Channel};
+use crate::summary::{Outcome, Summary};
+use crate::{batch, probe, Method, Opt};
+
+/// Encode or decode the payload spread over every `--input`
+pub fn run(opt: &Opt, summary: &mut Summary) -> Result<(), PngSecretError> {
+    for (given, flag) in [
+        (opt.encode && opt.output.is_some(), "--output"),
+        (opt.encode && opt.legacy, "--legacy"),
+        (opt.method == Some(Method::Chunk), "--method chunk"),
+        (opt.alpha_payload.is_some(), "--alpha-payload"),
+        (opt.truncate_to_fit, "--truncate-to-fit"),
+        (opt.dry_run, "--dry-run"),
+        (opt.inspect, "--inspect"),
+        (opt.list, "--list"),
+        (opt.armor, "--armor"),
+        (opt.dearmor, "--dearmor"),
+        (opt.receipt.is_some(), "--receipt"),
+        (opt.trace_indices.is_some(), "--trace-indices"),
+        (opt.json, "--json"),
+    ] {
+        if given {
+            return Err(PngSecretError::Usage(format!(
+                "{} works on a single image, not a payload spread over several",
+                flag
+            )));
+        }
+    }
+    let mut inputs = Vec::new();
+    for input in &opt.input {
+        match input.is_dir() {
+            true => inputs.extend(batch::list_pngs(input)?),
+            false => inputs.push(input.clone()),
+        }
+    }
+    match opt.encode {
+        true => encode(opt, &inputs, summary),
+        false => decode(opt, &inputs, summary),
+    }
+}
+
+fn encode(opt: &Opt, inputs: &[PathBuf], summary: &mut Summary) -> Result<(), PngSecretError> {
+    let mut payload = crate::encode::encode_inputs(opt)?.payload;
+    // Records are compressed one by one in `record_set`
+    if opt.name.is_empty() && opt.compress {
+        payload = compress::compress(&payload);
+    }
+    let mut item = opt.clone();
+    item.compress = false;
+    let mut capacities = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        let img = probe::open(input)?;
+        capacities.push(crate::encode::capacity(&item, img)?);
+    }
+    let segments = segments::split(&payload, &capacities, rand::random())?;
+    for (i, (input, segment)) in inputs.iter().zip(segments).enumerate() {
+        announce(opt, i, inputs.len(), input);
+        item.input = vec![input.clone()];
+        item.segment = Some(segment.to_bytes());
+        crate::run(&item, summary)?;
+        summary.item(Outcome::Succeeded);
+    }
+    Ok(())
+}
+
+fn decode(opt: &Opt, inputs: &[PathBuf], summary: &mut Summary) -> Result<(), PngSecretError> {
+    let mut item = opt.clone();
+    let mut found = Vec::with_capacity(inputs.len());
+    for (i, input) in inputs.iter().enumerate() {
+        announce(opt, i, inputs.len(), input);
+        item.input = vec![input.clone()];
+        let (png, img) = crate::read_image(&item, summary)?;
+        let (message, _) = crate::decode::read_raw_message(&item, img, &png)?;
+        if !segments::is_segment(&message) {
+            return Err(PngSecretError::Usage(format!(
+                "{} holds a whole payload, not part of one spread over several images",
+                input.display()
+            )));
+        }
+        found.push(Segment::parse(&message)?);
+        summary.item(Outcome::Succeeded);
+    }
+    let payload = crate::decode::open_records(opt, segments::join(found)?)?;
+    crate::decode::deliver_message(opt, payload, summary)
+}
+
+fn announce(opt: &Opt, i: usize, total: usize, input: &Path) {
+    if !opt.silent {
+        output::line(
+            Channel::Diagnostics,
+            format_args!("[{}/{}] {}", i + 1, total, input.display()),
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! `provenance stamp` and `provenance read`, the build provenance of release images
+
+use pngsecret::provenance;
+
+use crate::error::PngSecretError;
+use crate::output::{self, Channel};
+use crate::summary::Summary;
+use crate::{confirm, fsguard, input, paths, receipt, timefmt, Opt, ProvenanceCommand};
+
+/// The first of `variables` that is set and not empty
+fn first_env(variables: &[&str]) -> Option<String> {
+    variables
+        .iter()
+        .filter_map(|variable| std::env::var(variable).ok())
+        .find(|value| !value.is_empty())
+}
+
+/// Stamp the provenance of a build into an image, or print the one stamped into it
+pub fn run(
+    opt: &Opt,
+    cmd: &ProvenanceCommand,
+    summary: &mut Summary,
+) -> Result<(), PngSecretError> {
+    match cmd {
+        ProvenanceCommand::Stamp {
+            input,
+            output,
+            commit,
+            build_id,
+            timestamp,
+            sign_key,
+        } => {
+            let required = |value: &Option<String>, flag: &str, variables: &[&str]| {
+                value
+                    .clone()
+                    .or_else(|| first_env(variables))
+                    .ok_or_else(|| {
+                        PngSecretError::Usage(format!(
+                            "Pass {} or set one of {}",
+                            flag,
+                            variables.join(", ")
+                        ))
+                    })
+            };
+            let timestamp = match timestamp {
+                Some(timestamp) => *timestamp,
+                None => match first_env(&["SOURCE_DATE_EPOCH"]) {
+                    Some(epoch) => epoch.parse().map_err(|_| {
+                        PngSecretError::Usage(format!(
+                            "SOURCE_DATE_EPOCH={:?} is not a number of seconds",
+                            epoch
+                        ))
+                    })?,
+                    None => timefmt::now(),
+                },
+            };
+            let stamp = provenance::Provenance {
+                commit: required(
+                    commit,
+                    "--commit",
+                    &["GITHUB_SHA", "CI_COMMIT_SHA", "GIT_COMMIT"],
+                )?,
+                build_id: required(
+                    build_id,
+                    "--build-id",
+                    &["GITHUB_RUN_ID", "CI_PIPELINE_ID", "BUILD_ID"],
+                )?,
+                timestamp,
+            };
+            let key = sign_key
+                .as_deref()
+                .map(receipt::read_signing_key)
+                .transpose()?;
+            let output = match output {
+                Some(path) => path.to_path_buf(),
+                None => paths::derive_output(input, "stamped.png"),
+            };
+            if paths::collides(input, &output) {
+                return Err(PngSecretError::OutputIsInput(output));
+            }
+            let bytes = input::read_stable(input, opt.modified_retries)?;
+            summary.bytes_in += bytes.len() as u64;
+            let stamped = provenance::stamp_provenance(&bytes, &stamp, key.as_ref()).map_err(
+                |e| match e {
+                    pngsecret::Error::UnreadableImage => {
+                        PngSecretError::InputUnreadable(input.clone())
+                    }
+                    e => e.into(),
+                },
+            )?;
+            let plan = confirm::Plan::new(
+                format!("Stamp {} into {}", input.display(), output.display()),
+                &[&output],
+            );
+            confirm::confirm(&plan, opt.yes)?;
+            fsguard::write_atomic(&output, &stamped)
+                .map_err(|_| PngSecretError::SaveFailed(output.clone()))?;
+            summary.wrote_file(&output);
+            output::path_line(Channel::Payload, &output);
+            Ok(())
+        }
+        ProvenanceCommand::Read { input, public_key } => {
+            let bytes = input::read_stable(input, opt.modified_retries)?;
+            summary.bytes_in += bytes.len() as u64;
+            image::load_from_memory(&bytes)
+                .map_err(|_| PngSecretError::InputUnreadable(input.clone()))?;
+            let stamp = provenance::read_provenance(&bytes).ok_or(PngSecretError::NoMessage)?;
+            let signer = provenance::read_signed_provenance(&bytes)
+                .map(|(_, key)| receipt::public_key_hex(&key));
+            if let Some(expected) = public_key {
+                match &signer {
+                    None => {
+                        return Err(PngSecretError::ProvenanceUntrusted(
+                            "it isn't signed, or its signature doesn't match".to_string(),
+                        ))
+                    }
+                    Some(signer) if !expected.trim().eq_ignore_ascii_case(signer) => {
+                        return Err(PngSecretError::ProvenanceUntrusted(
+                            "signed by a different key than --public-key".to_string(),
+                        ))
+                    }
+                    Some(_) => {}
+                }
+            }
+            output::line(
+                Channel::Payload,
+                format_args!(
+                    "commit: {}\nbuild id: {}\ntime: {}\nsigned by: {}",
+                    stamp.commit,
+                    stamp.build_id,
+                    opt.time_format.render(stamp.timestamp),
+                    signer.as_deref().unwrap_or("nobody")
+                ),
+            );
+            Ok(())
+        }
+    }
+}
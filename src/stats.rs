@@ -0,0 +1,150 @@
+//! Opt-in local usage statistics
+//!
+//! One JSON record is appended per run to the file given by `--stats-file`. Records only carry the
+//! operation, the names of the flags that were used, the duration and the outcome, never paths,
+//! flag values or payload data. Nothing is ever sent anywhere.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::fsguard;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub operation: String,
+    pub flags: Vec<String>,
+    pub duration_ms: u64,
+    pub success: bool,
+}
+
+impl Record {
+    pub fn new(operation: &str, flags: &[&str], duration: Duration, success: bool) -> Self {
+        Record {
+            operation: operation.to_string(),
+            flags: flags.iter().map(|f| f.to_string()).collect(),
+            duration_ms: duration.as_millis() as u64,
+            success,
+        }
+    }
+}
+
+/// Append a record, creating the file and its directory if needed
+pub fn append(path: &Path, record: &Record) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fsguard::create_dir_all(parent)?;
+        }
+    }
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    fsguard::append(path)?.write_all(line.as_bytes())
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct OperationSummary {
+    pub runs: u64,
+    pub failures: u64,
+    pub total_duration_ms: u64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Summary {
+    pub operations: BTreeMap<String, OperationSummary>,
+    pub flags: BTreeMap<String, u64>,
+    /// Lines that couldn't be parsed, e.g. from a crash mid-write
+    pub skipped: u64,
+}
+
+/// Aggregate all records of a stats file
+pub fn summarize(path: &Path) -> io::Result<Summary> {
+    let mut summary = Summary::default();
+    for line in BufReader::new(fsguard::open(path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Ok(record) = serde_json::from_str::<Record>(&line) else {
+            summary.skipped += 1;
+            continue;
+        };
+        let operation = summary.operations.entry(record.operation).or_default();
+        operation.runs += 1;
+        operation.total_duration_ms += record.duration_ms;
+        if !record.success {
+            operation.failures += 1;
+        }
+        for flag in record.flags {
+            *summary.flags.entry(flag).or_default() += 1;
+        }
+    }
+    Ok(summary)
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<12} {:>8} {:>9} {:>12}",
+            "operation", "runs", "failures", "avg ms"
+        )?;
+        for (name, op) in &self.operations {
+            writeln!(
+                f,
+                "{:<12} {:>8} {:>9} {:>12}",
+                name,
+                op.runs,
+                op.failures,
+                op.total_duration_ms / op.runs.max(1)
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{:<24} {:>8}", "flag", "runs")?;
+        for (flag, runs) in &self.flags {
+            writeln!(f, "{:<24} {:>8}", flag, runs)?;
+        }
+        if self.skipped > 0 {
+            writeln!(f, "\n{} unreadable records skipped", self.skipped)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+
+    #[test]
+    fn append_and_summarize() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested/usage.jsonl");
+        let ms = Duration::from_millis;
+        append(
+            &path,
+            &Record::new("encode", &["max-payload"], ms(10), true),
+        )
+        .unwrap();
+        append(&path, &Record::new("encode", &[], ms(30), false)).unwrap();
+        append(&path, &Record::new("decode", &["max-payload"], ms(5), true)).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"truncated\n").unwrap();
+
+        let summary = summarize(&path).unwrap();
+        assert_eq!(
+            summary.operations["encode"],
+            OperationSummary {
+                runs: 2,
+                failures: 1,
+                total_duration_ms: 40
+            }
+        );
+        assert_eq!(summary.operations["decode"].runs, 1);
+        assert_eq!(summary.flags["max-payload"], 2);
+        assert_eq!(summary.skipped, 1);
+        assert!(summary.to_string().contains("encode"));
+    }
+}
@@ -0,0 +1,163 @@
+//! The closing summary every run ends with, whatever the mode
+//!
+//! Scripts driving several modes parse one schema instead of one per mode. Each mode fills a
+//! [`Summary`] as it goes, multi-item modes per item and single-item modes with counts of 1, and
+//! `main` renders it as the last line on stderr and with `--summary-json` as a JSON object. The
+//! field names are stable, `tests/summary.schema.json` pins them.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::timefmt::{self, Timestamp};
+use crate::{bytesize, fsguard};
+
+/// How one item of a run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Succeeded,
+    /// Left out on purpose, e.g. by `cat --skip-missing`
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    /// The subcommand, or `encode` and `decode` for the main mode
+    pub mode: &'static str,
+    /// When the run started
+    pub started: Timestamp,
+    pub processed: usize,
+    pub succeeded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// Bytes of the images and payloads that were read
+    pub bytes_in: u64,
+    /// Bytes of the images and payloads written to files or stdout, reports left out
+    pub bytes_out: u64,
+    /// Number of warnings per id, sorted so the JSON is stable
+    pub warnings: BTreeMap<&'static str, usize>,
+    pub duration_ms: u64,
+}
+
+impl Summary {
+    pub fn new(mode: &'static str) -> Self {
+        Summary {
+            mode,
+            started: Timestamp::new(timefmt::now()),
+            processed: 0,
+            succeeded: 0,
+            skipped: 0,
+            failed: 0,
+            bytes_in: 0,
+            bytes_out: 0,
+            warnings: BTreeMap::new(),
+            duration_ms: 0,
+        }
+    }
+
+    pub fn item(&mut self, outcome: Outcome) {
+        self.processed += 1;
+        match outcome {
+            Outcome::Succeeded => self.succeeded += 1,
+            Outcome::Skipped => self.skipped += 1,
+            Outcome::Failed => self.failed += 1,
+        }
+    }
+
+    pub fn warn(&mut self, id: &'static str) {
+        *self.warnings.entry(id).or_default() += 1;
+    }
+
+    /// Count the size of the file at `path` as read, best effort
+    pub fn read_file(&mut self, path: &Path) {
+        self.bytes_in += file_size(path);
+    }
+
+    /// Count the size of the file at `path` as written, best effort
+    pub fn wrote_file(&mut self, path: &Path) {
+        self.bytes_out += file_size(path);
+    }
+
+    /// Close the run, counting it as one item unless the mode counted its items itself
+    pub fn finish(&mut self, success: bool, duration: Duration) {
+        if self.processed == 0 {
+            self.item(match success {
+                true => Outcome::Succeeded,
+                false => Outcome::Failed,
+            });
+        }
+        self.duration_ms = duration.as_millis() as u64;
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} processed, {} succeeded, {} skipped, {} failed; {} in, {} out; ",
+            self.mode,
+            self.processed,
+            self.succeeded,
+            self.skipped,
+            self.failed,
+            bytesize::format(self.bytes_in),
+            bytesize::format(self.bytes_out)
+        )?;
+        match self.warnings.is_empty() {
+            true => write!(f, "no warnings")?,
+            false => {
+                let warnings: Vec<String> = self
+                    .warnings
+                    .iter()
+                    .map(|(id, count)| format!("{} x{}", id, count))
+                    .collect();
+                write!(f, "warnings {}", warnings.join(", "))?
+            }
+        }
+        write!(f, "; {} ms", self.duration_ms)
+    }
+}
+
+fn file_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map_or(0, |meta| meta.len())
+}
+
+/// Write the summary as a single JSON object to `path`, replacing it
+pub fn write_json(summary: &Summary, path: &Path) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(summary)?;
+    fsguard::write(path, format!("{}\n", json).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_item_modes_count_the_run() {
+        let mut summary = Summary::new("decode");
+        summary.bytes_in = 2048;
+        summary.finish(false, Duration::from_millis(12));
+        assert_eq!((summary.processed, summary.failed), (1, 1));
+        assert_eq!(
+            summary.to_string(),
+            "decode: 1 processed, 0 succeeded, 0 skipped, 1 failed; 2.0 KiB in, 0 B out; no \
+             warnings; 12 ms"
+        );
+
+        let mut summary = Summary::new("cat");
+        summary.item(Outcome::Skipped);
+        summary.item(Outcome::Succeeded);
+        summary.warn("posterized");
+        summary.warn("posterized");
+        summary.finish(true, Duration::ZERO);
+        assert_eq!(
+            (summary.processed, summary.succeeded, summary.skipped),
+            (2, 1, 1)
+        );
+        assert!(summary.to_string().contains("warnings posterized x2"));
+    }
+}
@@ -0,0 +1,348 @@
+//! `sweep` tries every embedding configuration on a cover in memory and compares the results
+//!
+//! The grid covers the options the encoder has: the slot the payload lives in and the subpixel
+//! order. Covers are downscaled to a working copy first unless `--full-size` is given, which
+//! makes the sweep fast but shifts capacity and absolute quality numbers.
+
+use image::imageops::FilterType;
+use image::RgbaImage;
+use serde::Serialize;
+use std::fmt;
+
+use crate::order::{Slot, SubpixelOrder};
+use crate::{NaiveEncoder, PngSecretWriter};
+
+/// Longest side of the working copy when downscaling
+const WORKING_SIDE: u32 = 256;
+/// Side of the blocks SSIM is averaged over
+const SSIM_WINDOW: u32 = 8;
+/// Seed used for the permuted rows, the sweep only needs some fixed permutation
+const SWEEP_SEED: u64 = 0;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Row {
+    pub slot: &'static str,
+    pub order: &'static str,
+    pub capacity_bytes: usize,
+    pub fits: bool,
+    /// Payload size relative to capacity
+    pub utilization: f64,
+    /// `None` when the payload doesn't fit, or for PSNR when the images are identical
+    pub psnr: Option<f64>,
+    pub ssim: Option<f64>,
+    /// Westfeld-Pfitzmann chi-square p-value, close to 1 means LSB embedding is evident
+    pub chi_square_p: Option<f64>,
+    /// Encoded PNG size of the stego image relative to the cover
+    pub size_growth: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SweepReport {
+    pub width: u32,
+    pub height: u32,
+    pub downscaled: bool,
+    pub payload_bytes: usize,
+    pub rows: Vec<Row>,
+}
+
+/// Shrink `cover` so its longest side is at most [`WORKING_SIDE`]
+pub fn working_copy(cover: RgbaImage) -> (RgbaImage, bool) {
+    let (width, height) = cover.dimensions();
+    if width.max(height) <= WORKING_SIDE {
+        return (cover, false);
+    }
+    let scale = WORKING_SIDE as f64 / width.max(height) as f64;
+    let resized = image::imageops::resize(
+        &cover,
+        ((width as f64 * scale).round() as u32).max(1),
+        ((height as f64 * scale).round() as u32).max(1),
+        FilterType::Triangle,
+    );
+    (resized, true)
+}
+
+pub fn sweep(cover: &RgbaImage, payload: &[u8], downscaled: bool) -> SweepReport {
+    let orders = [
+        ("sequential", SubpixelOrder::Sequential),
+        (
+            "blocks",
+            SubpixelOrder::Blocks {
+                block_size: crate::order::DEFAULT_BLOCK_SIZE,
+                seed: SWEEP_SEED,
+            },
+        ),
+    ];
+    let slots = [("all", Slot::All), ("rgb", Slot::Rgb)];
+    let cover_size = png_size(cover);
+    let mut rows = Vec::new();
+    for (slot_name, slot) in slots {
+        for (order_name, order) in orders {
+            let mut writer = PngSecretWriter::new(cover.clone(), Box::new(NaiveEncoder::new()))
+                .with_order(order)
+                .with_slot(slot);
+            let capacity_bytes = writer.capacity();
+            writer.encoder.encode(payload);
+            let fits = writer.embed().is_ok();
+            let stego = fits.then_some(&writer.buffer);
+            rows.push(Row {
+                slot: slot_name,
+                order: order_name,
+                capacity_bytes,
+                fits,
+                utilization: payload.len() as f64 / capacity_bytes.max(1) as f64,
+                psnr: stego.and_then(|stego| psnr(cover, stego)),
+                ssim: stego.map(|stego| ssim(cover, stego)),
+                chi_square_p: stego.map(chi_square_p),
+                size_growth: stego.map(|stego| png_size(stego) as f64 / cover_size as f64),
+            });
+        }
+    }
+    SweepReport {
+        width: cover.width(),
+        height: cover.height(),
+        downscaled,
+        payload_bytes: payload.len(),
+        rows,
+    }
+}
+
+fn png_size(img: &RgbaImage) -> usize {
+    let mut encoded = std::io::Cursor::new(Vec::new());
+    img.write_to(&mut encoded, image::ImageFormat::Png)
+        .expect("encoding to memory can't fail");
+    encoded.into_inner().len()
+}
+
+/// Peak signal to noise ratio over all subpixels in dB, `None` for identical images
+pub fn psnr(a: &RgbaImage, b: &RgbaImage) -> Option<f64> {
+    let squared: f64 = a
+        .iter()
+        .zip(b.iter())
+        .map(|(x, y)| (*x as f64 - *y as f64).powi(2))
+        .sum();
+    let mse = squared / a.len().max(1) as f64;
+    (mse > 0.0).then(|| 10.0 * (255.0 * 255.0 / mse).log10())
+}
+
+fn luma(img: &RgbaImage, x: u32, y: u32) -> f64 {
+    let [r, g, b, _] = img.get_pixel(x, y).0;
+    0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64
+}
+
+/// Mean structural similarity of the luma over non-overlapping windows
+pub fn ssim(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+    let (width, height) = a.dimensions();
+    let mut total = 0.0;
+    let mut windows = 0;
+    for wy in (0..height).step_by(SSIM_WINDOW as usize) {
+        for wx in (0..width).step_by(SSIM_WINDOW as usize) {
+            let points: Vec<(f64, f64)> = (wy..(wy + SSIM_WINDOW).min(height))
+                .flat_map(|y| (wx..(wx + SSIM_WINDOW).min(width)).map(move |x| (x, y)))
+                .map(|(x, y)| (luma(a, x, y), luma(b, x, y)))
+                .collect();
+            let n = points.len() as f64;
+            let mean_a = points.iter().map(|p| p.0).sum::<f64>() / n;
+            let mean_b = points.iter().map(|p| p.1).sum::<f64>() / n;
+            let (mut var_a, mut var_b, mut cov) = (0.0, 0.0, 0.0);
+            for (pa, pb) in &points {
+                var_a += (pa - mean_a).powi(2) / n;
+                var_b += (pb - mean_b).powi(2) / n;
+                cov += (pa - mean_a) * (pb - mean_b) / n;
+            }
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * cov + C2))
+                / ((mean_a.powi(2) + mean_b.powi(2) + C1) * (var_a + var_b + C2));
+            windows += 1;
+        }
+    }
+    total / windows.max(1) as f64
+}
+
+/// Natural log of the gamma function, Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.001_208_650_973_866_179,
+        -0.000_005_395_239_384_953,
+    ];
+    let tmp = x + 5.5;
+    let mut series = 1.000_000_000_190_015;
+    for (i, c) in COEFFICIENTS.iter().enumerate() {
+        series += c / (x + 1.0 + i as f64);
+    }
+    -(tmp - (x + 0.5) * tmp.ln()) + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+/// Regularized lower incomplete gamma function P(s, x)
+fn lower_gamma_regularized(s: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x < s + 1.0 {
+        let mut term = 1.0 / s;
+        let mut sum = term;
+        for n in 1..1000 {
+            term *= x / (s + n as f64);
+            sum += term;
+            if term < sum * 1e-15 {
+                break;
+            }
+        }
+        return (sum.ln() - x + s * x.ln() - ln_gamma(s)).exp().min(1.0);
+    }
+    // Continued fraction for the upper function, converges quickly for large x
+    let mut b = x + 1.0 - s;
+    let mut c = 1.0 / f64::MIN_POSITIVE;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..1000 {
+        let an = -(i as f64) * (i as f64 - s);
+        b += 2.0;
+        d = an * d + b;
+        if d.abs() < f64::MIN_POSITIVE {
+            d = f64::MIN_POSITIVE;
+        }
+        c = b + an / c;
+        if c.abs() < f64::MIN_POSITIVE {
+            c = f64::MIN_POSITIVE;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+    1.0 - (-x + s * x.ln() - ln_gamma(s)).exp() * h
+}
+
+/// Probability that the histogram's pairs of values were equalized by LSB replacement
+///
+/// LSB replacement makes the counts of each pair `2k`/`2k+1` converge, which the chi-square test
+/// of Westfeld and Pfitzmann picks up. Values near 1 mean the image looks embedded.
+pub fn chi_square_p(img: &RgbaImage) -> f64 {
+    let mut histogram = [0u64; 256];
+    for (i, sample) in img.iter().enumerate() {
+        // Alpha is usually constant and says nothing about embedding
+        if i % 4 != 3 {
+            histogram[*sample as usize] += 1;
+        }
+    }
+    let mut statistic = 0.0;
+    let mut categories = 0;
+    for pair in histogram.chunks(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        if expected > 4.0 {
+            statistic += (pair[0] as f64 - expected).powi(2) / expected;
+            categories += 1;
+        }
+    }
+    if categories < 2 {
+        return 0.0;
+    }
+    1.0 - lower_gamma_regularized((categories - 1) as f64 / 2.0, statistic / 2.0)
+}
+
+fn optional(value: Option<f64>, precision: usize) -> String {
+    value.map_or("-".to_string(), |v| format!("{:.*}", precision, v))
+}
+
+impl fmt::Display for SweepReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Sweep of a {} byte payload over a {}x{}{} cover:",
+            self.payload_bytes,
+            self.width,
+            self.height,
+            if self.downscaled {
+                " working copy (downscaled, pass --full-size for absolute numbers)"
+            } else {
+                ""
+            }
+        )?;
+        writeln!(
+            f,
+            "{:<6} {:<10} {:>9} {:<4} {:>6} {:>7} {:>6} {:>6} {:>6}",
+            "slot", "order", "capacity", "fits", "util", "psnr", "ssim", "chi2 p", "growth"
+        )?;
+        for row in &self.rows {
+            writeln!(
+                f,
+                "{:<6} {:<10} {:>9} {:<4} {:>5.1}% {:>7} {:>6} {:>6} {:>6}",
+                row.slot,
+                row.order,
+                row.capacity_bytes,
+                if row.fits { "yes" } else { "no" },
+                row.utilization * 100.0,
+                match (row.fits, row.psnr) {
+                    (true, None) => "inf".to_string(),
+                    (_, psnr) => optional(psnr, 2),
+                },
+                optional(row.ssim, 4),
+                optional(row.chi_square_p, 3),
+                optional(row.size_growth, 3)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pngsecret::testing::{CoverBuilder, Pattern};
+
+    fn noise(width: u32, height: u32) -> RgbaImage {
+        CoverBuilder::new(width, height)
+            .with_pattern(Pattern::Noise)
+            .build()
+    }
+
+    #[test]
+    fn gamma_matches_known_values() {
+        assert!((ln_gamma(5.0) - 24f64.ln()).abs() < 1e-10);
+        // P(1, x) = 1 - e^-x
+        for x in [0.5, 2.0, 10.0] {
+            assert!((lower_gamma_regularized(1.0, x) - (1.0 - (-x).exp())).abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    fn identical_images_score_perfectly() {
+        let img = noise(16, 16);
+        assert_eq!(psnr(&img, &img), None);
+        assert!((ssim(&img, &img) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn working_copy_keeps_the_aspect_ratio() {
+        let (copy, downscaled) = working_copy(RgbaImage::new(1024, 512));
+        assert!(downscaled);
+        assert_eq!(copy.dimensions(), (256, 128));
+        assert!(!working_copy(RgbaImage::new(64, 64)).1);
+    }
+
+    #[test]
+    fn full_embedding_is_more_detectable() {
+        // Smooth cover whose pairs of values are far from equal
+        let cover = RgbaImage::from_fn(64, 64, |x, y| {
+            let v = ((x + y) / 2 * 2) as u8;
+            image::Rgba([v, v, v, 255])
+        });
+        // Fill the whole capacity with bytes that look random and contain no NUL
+        let payload: Vec<u8> = (0..2047u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8 | 1)
+            .collect();
+        let report = sweep(&cover, &payload, false);
+        let row = &report.rows[0];
+        assert!(row.fits);
+        assert!(row.chi_square_p.unwrap() > 0.9, "{:?}", row);
+        assert!(chi_square_p(&cover) < 0.1);
+    }
+}
@@ -0,0 +1,216 @@
+//! Seeded cover images for tests and benchmarks, behind the `test-util` feature
+//!
+//! [`CoverBuilder`] paints its [`Pattern`] over the whole image, then every overlay over its
+//! rectangle, and last fills the alpha channel by its [`Mask`]. The same dimensions, seed, patterns
+//! and mask give the same pixels in every release: the random patterns draw from SplitMix64, kept
+//! here rather than taken from a crate whose streams may change, and `tests/cover_builder.rs` pins
+//! the digests of a few images of every pattern.
+
+use image::{ImageResult, Rgba, RgbaImage};
+use std::path::Path;
+
+/// What an image, or an overlay, is painted with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    /// Every color sample random, LSBs as noisy as a photo's
+    Noise,
+    /// Red rising along x and green along y over a blue of 128, the same for every seed
+    Gradient,
+    /// One color, the kind of region an embedded payload shows in most, the same for every seed
+    Flat([u8; 3]),
+    /// The gradient in black and white by ordered 4x4 dithering, the matrix shifted by the seed
+    Dither,
+}
+
+/// What the alpha channel holds once the patterns are painted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mask {
+    Opaque,
+    Transparent,
+    /// Squares of `cell` pixels, opaque and transparent in turn starting opaque at the origin
+    Checker {
+        cell: u32,
+    },
+    /// Every pixel transparent with a chance of `percent` in 100
+    Holes {
+        percent: u8,
+    },
+}
+
+/// A pattern painted over part of the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Overlay {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    pattern: Pattern,
+}
+
+/// A deterministic cover, a gradient without overlays and opaque until told otherwise
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverBuilder {
+    width: u32,
+    height: u32,
+    seed: u64,
+    pattern: Pattern,
+    overlays: Vec<Overlay>,
+    mask: Mask,
+}
+
+impl CoverBuilder {
+    pub fn new(width: u32, height: u32) -> Self {
+        CoverBuilder {
+            width,
+            height,
+            seed: 0,
+            pattern: Pattern::Gradient,
+            overlays: Vec::new(),
+            mask: Mask::Opaque,
+        }
+    }
+
+    /// Draw the random patterns and masks from `seed` instead of 0
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Paint the whole image with `pattern`
+    pub fn with_pattern(mut self, pattern: Pattern) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    /// Paint `pattern` over the `width` by `height` rectangle at `x`, `y`, clipped to the image
+    ///
+    /// Overlays are painted in the order they were added, a gradient or dither spans its own
+    /// rectangle rather than the image.
+    pub fn with_overlay(
+        mut self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        pattern: Pattern,
+    ) -> Self {
+        self.overlays.push(Overlay {
+            x,
+            y,
+            width,
+            height,
+            pattern,
+        });
+        self
+    }
+
+    /// Fill the alpha channel by `mask` instead of leaving it opaque
+    pub fn with_mask(mut self, mask: Mask) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    pub fn build(&self) -> RgbaImage {
+        let mut img = RgbaImage::new(self.width, self.height);
+        let whole = Overlay {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+            pattern: self.pattern,
+        };
+        // Every layer draws from a stream of its own, so adding an overlay leaves the others be
+        for (layer, overlay) in std::iter::once(&whole).chain(&self.overlays).enumerate() {
+            paint(
+                &mut img,
+                overlay,
+                &mut SplitMix64::new(self.seed, layer as u64),
+            );
+        }
+        let mut rng = SplitMix64::new(self.seed, u64::MAX);
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            pixel[3] = match self.mask {
+                Mask::Opaque => 255,
+                Mask::Transparent => 0,
+                Mask::Checker { cell } => {
+                    let cell = cell.max(1);
+                    match (x / cell + y / cell) % 2 {
+                        0 => 255,
+                        _ => 0,
+                    }
+                }
+                Mask::Holes { percent } => match rng.next() % 100 < percent as u64 {
+                    true => 0,
+                    false => 255,
+                },
+            };
+        }
+        img
+    }
+
+    /// Build the image and save it as a PNG at `path`
+    pub fn save(&self, path: impl AsRef<Path>) -> ImageResult<()> {
+        self.build().save_with_format(path, image::ImageFormat::Png)
+    }
+}
+
+/// Ordered dithering thresholds, scaled by 16 when compared
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+fn paint(img: &mut RgbaImage, overlay: &Overlay, rng: &mut SplitMix64) {
+    let right = overlay.x.saturating_add(overlay.width).min(img.width());
+    let bottom = overlay.y.saturating_add(overlay.height).min(img.height());
+    let (shift_x, shift_y) = match overlay.pattern {
+        Pattern::Dither => (rng.next() % 4, rng.next() % 4),
+        _ => (0, 0),
+    };
+    for y in overlay.y..bottom {
+        for x in overlay.x..right {
+            // Gradients span the overlay, 0 at its first pixel
+            let ramp = |at: u32, start: u32, length: u32| ((at - start) * 256 / length) as u8;
+            let (red, green) = (
+                ramp(x, overlay.x, overlay.width),
+                ramp(y, overlay.y, overlay.height),
+            );
+            let [r, g, b] = match overlay.pattern {
+                Pattern::Noise => {
+                    let [r, g, b, ..] = rng.next().to_le_bytes();
+                    [r, g, b]
+                }
+                Pattern::Gradient => [red, green, 128],
+                Pattern::Flat(color) => color,
+                Pattern::Dither => {
+                    let level = (red as u32 + green as u32) / 2;
+                    let row = BAYER[((y as u64 + shift_y) % 4) as usize];
+                    let threshold = row[((x as u64 + shift_x) % 4) as usize] as u32 * 16 + 8;
+                    let value = match level >= threshold {
+                        true => 255,
+                        false => 0,
+                    };
+                    [value; 3]
+                }
+            };
+            img.put_pixel(x, y, Rgba([r, g, b, 255]));
+        }
+    }
+}
+
+/// The SplitMix64 generator, small and fixed for good
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    const GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+    /// The stream of `layer` for `seed`
+    fn new(seed: u64, layer: u64) -> Self {
+        SplitMix64(seed ^ layer.wrapping_mul(Self::GAMMA).rotate_left(32))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(Self::GAMMA);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
@@ -0,0 +1,195 @@
+//! Rendering of the times the tool records, for receipts and run summaries
+//!
+//! Times are kept as seconds since the Unix epoch and only turned into text when printed, in the
+//! `--time-format` of the run. Machine-readable output never depends on that flag: JSON carries a
+//! [`Timestamp`] with the epoch value and RFC 3339 in UTC, so a reader is never handed local time
+//! it can't tell apart from UTC.
+
+use chrono::format::{Fixed, Item, Numeric, StrftimeItems};
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use serde::Serialize;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How times are printed for humans, `--time-format`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum TimeFormat {
+    /// `2023-11-14T22:13:20Z`
+    #[default]
+    Rfc3339Utc,
+    /// RFC 3339 with the offset of the local time zone
+    Local,
+    /// Seconds since the epoch
+    Unix,
+    /// A strftime pattern rendered in local time, see [`check_pattern`]
+    Custom(String),
+}
+
+impl FromStr for TimeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rfc3339-utc" => Ok(TimeFormat::Rfc3339Utc),
+            "local" => Ok(TimeFormat::Local),
+            "unix" => Ok(TimeFormat::Unix),
+            pattern if pattern.contains('%') => {
+                check_pattern(pattern)?;
+                Ok(TimeFormat::Custom(pattern.to_string()))
+            }
+            _ => Err(format!(
+                "unknown time format {:?}, use rfc3339-utc, local, unix or a strftime pattern",
+                s
+            )),
+        }
+    }
+}
+
+impl TimeFormat {
+    pub fn render(&self, secs: u64) -> String {
+        let Some(utc) = i64::try_from(secs)
+            .ok()
+            .and_then(|secs| DateTime::<Utc>::from_timestamp(secs, 0))
+        else {
+            return secs.to_string();
+        };
+        match self {
+            TimeFormat::Rfc3339Utc => utc.to_rfc3339_opts(SecondsFormat::Secs, true),
+            TimeFormat::Local => utc
+                .with_timezone(&Local)
+                .to_rfc3339_opts(SecondsFormat::Secs, false),
+            TimeFormat::Unix => secs.to_string(),
+            TimeFormat::Custom(pattern) => utc.with_timezone(&Local).format(pattern).to_string(),
+        }
+    }
+}
+
+/// Refuse strftime patterns that don't pin down the instant
+///
+/// A pattern must name the year, the day, the hour and minute and a numeric UTC offset, or use
+/// `%s`, `%+` or RFC 2822 which do all of that. Two-digit years, 12-hour clocks without AM/PM and
+/// zone abbreviations like `IST`, which stand for several offsets, don't count.
+pub fn check_pattern(pattern: &str) -> Result<(), String> {
+    let items: Vec<Item> = StrftimeItems::new(pattern).collect();
+    if items.contains(&Item::Error) {
+        return Err(format!("{:?} isn't a valid strftime pattern", pattern));
+    }
+    let numeric = |wanted: Numeric| {
+        items
+            .iter()
+            .any(|item| matches!(item, Item::Numeric(found, _) if *found == wanted))
+    };
+    let fixed = |wanted: Fixed| items.contains(&Item::Fixed(wanted));
+    if numeric(Numeric::Timestamp) || fixed(Fixed::RFC3339) || fixed(Fixed::RFC2822) {
+        return Ok(());
+    }
+    let year =
+        numeric(Numeric::Year) || (numeric(Numeric::YearDiv100) && numeric(Numeric::YearMod100));
+    let month =
+        numeric(Numeric::Month) || fixed(Fixed::ShortMonthName) || fixed(Fixed::LongMonthName);
+    let day = (month && numeric(Numeric::Day)) || numeric(Numeric::Ordinal);
+    let hour = numeric(Numeric::Hour)
+        || (numeric(Numeric::Hour12) && (fixed(Fixed::LowerAmPm) || fixed(Fixed::UpperAmPm)));
+    let offset = [
+        Fixed::TimezoneOffset,
+        Fixed::TimezoneOffsetZ,
+        Fixed::TimezoneOffsetColon,
+        Fixed::TimezoneOffsetColonZ,
+        Fixed::TimezoneOffsetDoubleColon,
+        Fixed::TimezoneOffsetTripleColon,
+    ]
+    .into_iter()
+    .any(fixed);
+    let missing: Vec<&str> = [
+        (year, "a four-digit year"),
+        (day, "the day"),
+        (hour, "a 24-hour or AM/PM hour"),
+        (numeric(Numeric::Minute), "the minute"),
+        (offset, "a numeric UTC offset"),
+    ]
+    .into_iter()
+    .filter(|(present, _)| !present)
+    .map(|(_, what)| what)
+    .collect();
+    match missing.is_empty() {
+        true => Ok(()),
+        false => Err(format!(
+            "the time format {:?} is ambiguous, it lacks {}",
+            pattern,
+            missing.join(", ")
+        )),
+    }
+}
+
+/// A time in JSON, as epoch seconds and as RFC 3339 in UTC
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Timestamp {
+    pub unix: u64,
+    pub rfc3339: String,
+}
+
+impl Timestamp {
+    pub fn new(secs: u64) -> Self {
+        Timestamp {
+            unix: secs,
+            rfc3339: TimeFormat::Rfc3339Utc.render(secs),
+        }
+    }
+}
+
+/// Seconds since the Unix epoch, 0 on clocks set before it
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPOCH: u64 = 1_700_000_000;
+
+    #[test]
+    fn a_known_epoch_renders_in_every_format() {
+        assert_eq!(TimeFormat::Rfc3339Utc.render(EPOCH), "2023-11-14T22:13:20Z");
+        assert_eq!(TimeFormat::Unix.render(EPOCH), "1700000000");
+        // Whatever the zone of the machine, it is the same instant
+        let local = TimeFormat::Local.render(EPOCH);
+        assert_eq!(
+            DateTime::parse_from_rfc3339(&local).unwrap().timestamp(),
+            EPOCH as i64
+        );
+        let custom: TimeFormat = "%Y-%m-%d %H:%M:%S %z".parse().unwrap();
+        let rendered = custom.render(EPOCH);
+        assert_eq!(
+            DateTime::parse_from_str(&rendered, "%Y-%m-%d %H:%M:%S %z")
+                .unwrap()
+                .timestamp(),
+            EPOCH as i64
+        );
+        assert_eq!(
+            serde_json::to_value(Timestamp::new(EPOCH)).unwrap(),
+            serde_json::json!({"unix": EPOCH, "rfc3339": "2023-11-14T22:13:20Z"})
+        );
+    }
+
+    #[test]
+    fn ambiguous_patterns_are_refused() {
+        for pattern in ["%s", "%+", "%d %b %Y %I:%M %p %:z", "%j/%Y %H:%M%z"] {
+            assert!(check_pattern(pattern).is_ok(), "{}", pattern);
+        }
+        for pattern in [
+            "%Y-%m-%d %H:%M",
+            "%y-%m-%d %H:%M %z",
+            "%Y-%m-%d %I:%M %z",
+            "%Y-%m-%d %H:%M %Z",
+            "%H:%M %z",
+            "%Y %Q",
+        ] {
+            assert!(check_pattern(pattern).is_err(), "{}", pattern);
+        }
+        assert_eq!("rfc3339-utc".parse(), Ok(TimeFormat::Rfc3339Utc));
+        assert!("iso".parse::<TimeFormat>().is_err());
+    }
+}
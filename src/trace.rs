@@ -0,0 +1,125 @@
+//! Traces of the subpixel each payload bit was written to or read from, `--trace-indices`
+//!
+//! A trace is [`MAGIC`] followed by the linear subpixel index of every traced bit as a
+//! big-endian `u64`, bit 0 first, so the bit index is the position in the file. Encode traces
+//! the bits of the framed payload, decode the bits of the slot it reads from. Both stop after
+//! `--trace-bits` bits, and `trace diff` reports where two traces first disagree.
+
+use std::fmt;
+use std::path::Path;
+
+use crate::error::PngSecretError;
+use crate::fsguard;
+
+/// First bytes of a trace file, the last one is the version of the layout
+pub const MAGIC: [u8; 4] = *b"PST\x01";
+const INDEX_BYTES: usize = 8;
+
+/// Write the subpixel indices of a trace to `path`
+pub fn write(path: &Path, indices: &[usize]) -> Result<(), PngSecretError> {
+    let mut bytes = Vec::with_capacity(MAGIC.len() + indices.len() * INDEX_BYTES);
+    bytes.extend_from_slice(&MAGIC);
+    for &index in indices {
+        bytes.extend_from_slice(&(index as u64).to_be_bytes());
+    }
+    fsguard::write_atomic(path, &bytes)
+        .map_err(|e| PngSecretError::Io(format!("Couldn't write the trace {:?}", path), e))
+}
+
+/// The subpixel indices of the trace at `path`
+pub fn read(path: &Path) -> Result<Vec<u64>, PngSecretError> {
+    let bytes = std::fs::read(path).map_err(|_| PngSecretError::InputUnreadable(path.into()))?;
+    let indices = bytes
+        .strip_prefix(&MAGIC)
+        .filter(|indices| indices.len() % INDEX_BYTES == 0)
+        .ok_or_else(|| {
+            PngSecretError::Usage(format!("{:?} is not a --trace-indices file", path))
+        })?;
+    Ok(indices
+        .chunks_exact(INDEX_BYTES)
+        .map(|index| u64::from_be_bytes(index.try_into().unwrap()))
+        .collect())
+}
+
+/// How two traces compare
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Comparison {
+    /// Every bit both traces have went through the same subpixel
+    Same { bits: usize, a: usize, b: usize },
+    /// Bit `bit` went through subpixel `a` in one trace and `b` in the other
+    Diverged { bit: usize, a: u64, b: u64 },
+}
+
+/// Compare two traces bit by bit, one may be longer, e.g. a decode trace of the whole slot
+pub fn compare(a: &[u64], b: &[u64]) -> Comparison {
+    match a.iter().zip(b).position(|(a, b)| a != b) {
+        Some(bit) => Comparison::Diverged {
+            bit,
+            a: a[bit],
+            b: b[bit],
+        },
+        None => Comparison::Same {
+            bits: a.len().min(b.len()),
+            a: a.len(),
+            b: b.len(),
+        },
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Comparison::Same { bits, a, b } => {
+                write!(f, "The traces agree on all {} bits they share", bits)?;
+                match a.cmp(b) {
+                    std::cmp::Ordering::Equal => Ok(()),
+                    _ => write!(f, " (a has {} bits, b has {})", a, b),
+                }
+            }
+            Comparison::Diverged { bit, a, b } => write!(
+                f,
+                "First divergence at bit {} (byte {}, bit {} MSB first): subpixel {} in a, {} in b",
+                bit,
+                bit / 8,
+                bit % 8,
+                a,
+                b
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn traces_roundtrip_and_compare() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.bin");
+        write(&path, &[3, 0, 4_000_000_000]).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 4 + 3 * 8);
+        let trace = read(&path).unwrap();
+        assert_eq!(trace, [3, 0, 4_000_000_000]);
+
+        assert_eq!(
+            compare(&trace, &[3, 0, 7, 9]),
+            Comparison::Diverged {
+                bit: 2,
+                a: 4_000_000_000,
+                b: 7
+            }
+        );
+        assert_eq!(
+            compare(&trace[..2], &[3, 0, 7]),
+            Comparison::Same {
+                bits: 2,
+                a: 2,
+                b: 3
+            }
+        );
+
+        std::fs::write(&path, b"PST\x01\x00").unwrap();
+        assert!(read(&path).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+//! `verify-archive` sweeps a manifest of stego images and reports which still decode
+//!
+//! The sweep only reads, so together with `--read-only` it is safe to run against write-once
+//! media; see [`crate::fsguard`].
+
+use image::ImageFormat;
+use pngsecret::carrier;
+use pngsecret::format::Framing;
+use serde::Deserialize;
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+use crate::error::PngSecretError;
+use crate::OrderOpt;
+use crate::{fsguard, NaiveDecoder, PngSecretReader};
+
+/// The manifest lists images relative to its own directory
+#[derive(Debug, Deserialize)]
+pub struct Manifest {
+    pub images: Vec<Entry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Entry {
+    pub path: PathBuf,
+    /// The message the image must decode to, only checked for presence if missing
+    #[serde(default)]
+    pub payload: Option<String>,
+    /// Decode options like `--permute blocks --seed 42`, see [`DecodeArgs`]
+    #[serde(default)]
+    pub decode_args: Vec<String>,
+}
+
+/// The decode options an [`Entry`] can carry
+#[derive(Debug, StructOpt)]
+struct DecodeArgs {
+    #[structopt(long)]
+    legacy: bool,
+    #[structopt(long)]
+    no_verify: bool,
+    #[structopt(flatten)]
+    order: OrderOpt,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Ok { payload_bytes: usize },
+    Failed(String),
+}
+
+#[derive(Debug)]
+pub struct Report {
+    pub files: Vec<(PathBuf, Status)>,
+    /// Bytes of all images that could be read
+    pub bytes_read: u64,
+}
+
+impl Report {
+    pub fn failed(&self) -> usize {
+        self.files
+            .iter()
+            .filter(|(_, status)| matches!(status, Status::Failed(_)))
+            .count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (path, status) in &self.files {
+            match status {
+                Status::Ok { payload_bytes } => {
+                    writeln!(f, "ok      {} ({} bytes)", path.display(), payload_bytes)?
+                }
+                Status::Failed(reason) => writeln!(f, "FAILED  {}: {}", path.display(), reason)?,
+            }
+        }
+        let failed = self.failed();
+        writeln!(
+            f,
+            "{} checked, {} ok, {} failed",
+            self.files.len(),
+            self.files.len() - failed,
+            failed
+        )
+    }
+}
+
+fn check(path: &Path, entry: &Entry, bytes_read: &mut u64) -> Result<usize, String> {
+    let args =
+        std::iter::once("verify-archive").chain(entry.decode_args.iter().map(String::as_str));
+    let decode_args = DecodeArgs::from_iter_safe(args)
+        .map_err(|e| format!("invalid decode_args: {}", e.message))?;
+    let order = decode_args
+        .order
+        .order()
+        .map_err(|_| "no frame header or message terminator found".to_string())?;
+
+    let mut bytes = Vec::new();
+    fsguard::open(path)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|e| format!("unreadable: {}", e))?;
+    *bytes_read += bytes.len() as u64;
+    // The PNG decoder checks every chunk CRC and the zlib checksum on the way
+    let img = image::load_from_memory_with_format(&bytes, ImageFormat::Png)
+        .map_err(|e| format!("corrupt PNG: {}", e))?;
+    let img = carrier::payload_samples(img);
+    let reader = PngSecretReader::new(img, Box::new(NaiveDecoder::new()))
+        .with_order(order)
+        .with_verify(!decode_args.no_verify);
+    let message = match decode_args.legacy {
+        true => reader.with_framing(Framing::Terminated),
+        false => reader,
+    }
+    .read_image()
+    .map_err(|e| match e {
+        pngsecret::Error::PayloadCorrupted => {
+            "the message doesn't match the checksum of its frame".to_string()
+        }
+        _ => "no frame header found".to_string(),
+    })?;
+    match &entry.payload {
+        Some(expected) if expected.as_bytes() != message => {
+            Err("the message differs from the manifest".to_string())
+        }
+        _ => Ok(message.len()),
+    }
+}
+
+/// Check every image listed in the manifest at `manifest`
+pub fn sweep(manifest: &Path) -> Result<Report, PngSecretError> {
+    let file = fsguard::open(manifest)
+        .map_err(|e| PngSecretError::Io(format!("Couldn't read the manifest {:?}", manifest), e))?;
+    let parsed: Manifest = serde_json::from_reader(std::io::BufReader::new(file))
+        .map_err(|e| PngSecretError::Usage(format!("Invalid manifest {:?}: {}", manifest, e)))?;
+    let base = manifest.parent().unwrap_or(Path::new(""));
+    let mut bytes_read = 0;
+    let files = parsed
+        .images
+        .iter()
+        .map(|entry| {
+            let status = match check(&base.join(&entry.path), entry, &mut bytes_read) {
+                Ok(payload_bytes) => Status::Ok { payload_bytes },
+                Err(reason) => Status::Failed(reason),
+            };
+            (entry.path.clone(), status)
+        })
+        .collect();
+    Ok(Report { files, bytes_read })
+}
@@ -0,0 +1,220 @@
+//! Where a stego image differs from its cover, `diff`
+//!
+//! [`compare`] walks both images channel by channel and [`render`] draws the result: every pixel
+//! with a changed channel in bright red, every other pixel as a dimmed grayscale of the cover.
+//! It shows at a glance how far a payload reaches and whether `--permute` and `--offset` spread
+//! it where they should.
+
+use image::{Rgba, RgbaImage};
+use pngsecret::carrier;
+use std::fmt;
+use std::path::Path;
+
+use crate::error::PngSecretError;
+use crate::output::{self, Channel};
+use crate::summary::Summary;
+use crate::{confirm, paths, pngio, probe, Opt};
+
+/// Color of a pixel with a changed channel
+const MODIFIED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+/// Share of its luma an unchanged pixel keeps
+const DIM: f64 = 0.4;
+
+/// Smallest rectangle holding every modified pixel, bounds included
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+/// How a stego image differs from its cover
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    pub width: u32,
+    pub height: u32,
+    /// Channels whose value changed, alpha included
+    pub channels: usize,
+    /// Pixels with at least one changed channel
+    pub pixels: usize,
+    pub bounds: Option<Bounds>,
+}
+
+impl Difference {
+    /// Share of the pixels touched, in percent
+    pub fn touched_percent(&self) -> f64 {
+        match self.width as usize * self.height as usize {
+            0 => 0.0,
+            total => self.pixels as f64 * 100.0 / total as f64,
+        }
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.width as usize * self.height as usize;
+        writeln!(
+            f,
+            "{} of {} channels modified, in {} of {} pixels ({:.2}% of the image)",
+            self.channels,
+            total * 4,
+            self.pixels,
+            total,
+            self.touched_percent()
+        )?;
+        match self.bounds {
+            Some(Bounds {
+                left,
+                top,
+                right,
+                bottom,
+            }) => writeln!(
+                f,
+                "Modified pixels lie within x {}..={}, y {}..={}",
+                left, right, top, bottom
+            ),
+            None => writeln!(f, "The images are identical"),
+        }
+    }
+}
+
+/// Compare `cover` and `stego` channel by channel, which must have the same dimensions
+pub fn compare(cover: &RgbaImage, stego: &RgbaImage) -> Result<Difference, PngSecretError> {
+    if cover.dimensions() != stego.dimensions() {
+        return Err(PngSecretError::Usage(format!(
+            "The images differ in size, {}x{} and {}x{}",
+            cover.width(),
+            cover.height(),
+            stego.width(),
+            stego.height()
+        )));
+    }
+    let mut difference = Difference {
+        width: cover.width(),
+        height: cover.height(),
+        channels: 0,
+        pixels: 0,
+        bounds: None,
+    };
+    for ((x, y, before), after) in cover.enumerate_pixels().zip(stego.pixels()) {
+        let changed = before
+            .0
+            .iter()
+            .zip(after.0)
+            .filter(|(a, b)| **a != *b)
+            .count();
+        if changed == 0 {
+            continue;
+        }
+        difference.channels += changed;
+        difference.pixels += 1;
+        difference.bounds = Some(match difference.bounds {
+            None => Bounds {
+                left: x,
+                top: y,
+                right: x,
+                bottom: y,
+            },
+            Some(bounds) => Bounds {
+                left: bounds.left.min(x),
+                top: bounds.top.min(y),
+                right: bounds.right.max(x),
+                bottom: bounds.bottom.max(y),
+            },
+        });
+    }
+    Ok(difference)
+}
+
+/// The picture of [`compare`], of images known to have the same dimensions
+pub fn render(cover: &RgbaImage, stego: &RgbaImage) -> RgbaImage {
+    RgbaImage::from_fn(cover.width(), cover.height(), |x, y| {
+        let before = cover.get_pixel(x, y);
+        if before != stego.get_pixel(x, y) {
+            return MODIFIED;
+        }
+        let [r, g, b, _] = before.0;
+        let luma = 0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64;
+        let gray = (luma * DIM).round() as u8;
+        Rgba([gray, gray, gray, 255])
+    })
+}
+
+/// Compare a stego image with its cover, drawing the modified pixels into `out` if given
+pub fn diff(
+    opt: &Opt,
+    cover: &Path,
+    stego: &Path,
+    out: Option<&Path>,
+    summary: &mut Summary,
+) -> Result<(), PngSecretError> {
+    let load =
+        |path: &Path| probe::open(path).map(|img| carrier::payload_samples(img).into_rgba8());
+    let (before, after) = (load(cover)?, load(stego)?);
+    summary.read_file(cover);
+    summary.read_file(stego);
+    let difference = compare(&before, &after)?;
+    if let Some(out) = out {
+        if paths::collides(cover, out) || paths::collides(stego, out) {
+            return Err(PngSecretError::OutputIsInput(out.to_path_buf()));
+        }
+        let plan = confirm::Plan::new(
+            format!(
+                "Draw the changes from {} to {} into {}",
+                cover.display(),
+                stego.display(),
+                out.display()
+            ),
+            &[out],
+        );
+        confirm::confirm(&plan, opt.yes)?;
+        pngio::save_with_text(&render(&before, &after), out, &[])
+            .map_err(|_| PngSecretError::SaveFailed(out.to_path_buf()))?;
+        summary.wrote_file(out);
+    }
+    output::write(Channel::Payload, difference.to_string().as_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pngsecret::format::Framing;
+
+    #[test]
+    fn modified_channels_are_the_payload_bits_that_differed() {
+        let cover = RgbaImage::from_fn(16, 16, |x, y| {
+            Rgba([(x * 16 + y) as u8, (y * 16) as u8, (x ^ y) as u8, 255])
+        });
+        let payload = b"counted bit by bit";
+        let stego = pngsecret::embed(cover.clone(), payload).unwrap();
+
+        let framed = Framing::LengthPrefixed.frame(payload);
+        let differing = cover
+            .as_raw()
+            .iter()
+            .enumerate()
+            .take(framed.len() * 8)
+            .filter(|(i, sample)| (framed[i / 8] >> (7 - i % 8)) & 1 != *sample & 1)
+            .count();
+        let difference = compare(&cover, &stego).unwrap();
+        assert_eq!(difference.channels, differing);
+        assert!(difference.pixels <= differing);
+
+        // Sequential embedding starts at the first pixel and fills whole rows
+        let last = (framed.len() * 8 - 1) / 4;
+        let bounds = difference.bounds.unwrap();
+        assert_eq!((bounds.left, bounds.top, bounds.right), (0, 0, 15));
+        assert!(bounds.bottom <= last as u32 / 16);
+
+        let picture = render(&cover, &stego);
+        let red = picture.pixels().filter(|pixel| **pixel == MODIFIED).count();
+        assert_eq!(red, difference.pixels);
+        assert_eq!(picture.get_pixel(15, 15), &Rgba([87, 87, 87, 255]));
+
+        let identical = compare(&cover, &cover).unwrap();
+        assert_eq!((identical.channels, identical.bounds), (0, None));
+        assert!(compare(&cover, &RgbaImage::new(16, 15)).is_err());
+    }
+}
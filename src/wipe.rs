@@ -0,0 +1,165 @@
+//! `wipe` removes one kind of payload from an image and leaves everything else as it was
+
+use pngsecret::carrier::{self, Carrier};
+use pngsecret::order::{Slot, SubpixelOrder};
+use pngsecret::{bytes_to_chunks, bytesize, Location};
+use rand::Rng;
+use std::path::{Path, PathBuf};
+
+use crate::decode::pinned_reader;
+use crate::error::PngSecretError;
+use crate::output::{self, Channel};
+use crate::{confirm, paths, pngio, probe, Backend, Opt};
+
+/// Remove one kind of payload from an image while leaving the other intact, returning the path
+/// of the result
+pub fn wipe(
+    opt: &Opt,
+    input: &Path,
+    output: Option<&Path>,
+    backend: Backend,
+    order: SubpixelOrder,
+    slot: Option<Slot>,
+    offset: Option<usize>,
+) -> Result<PathBuf, PngSecretError> {
+    let (mut img, wide) = carrier::payload_carrier(probe::open(input)?);
+    let mut texts = pngio::read_text_chunks(input).unwrap_or_default();
+    let wiped = match backend {
+        Backend::Pixel => {
+            let wiped = wipe_pixel_payload(&mut img, order, slot, offset, opt.bits)?;
+            format!("a pixel payload of {}", bytesize::format(wiped as u64))
+        }
+        Backend::Chunk => {
+            let before = texts.len();
+            texts.retain(|(keyword, _)| keyword != pngio::NOTICE_KEYWORD);
+            if texts.len() == before {
+                return Err(PngSecretError::NoMessage);
+            }
+            "the chunk notice".to_string()
+        }
+    };
+    let output = match output {
+        Some(path) => path.to_path_buf(),
+        None => paths::derive_output(input, "wiped.png"),
+    };
+    if paths::collides(input, &output) {
+        return Err(PngSecretError::OutputIsInput(output));
+    }
+    let plan = confirm::Plan::new(
+        format!(
+            "Wipe {} from {} into {}",
+            wiped,
+            input.display(),
+            output.display()
+        ),
+        &[&output],
+    );
+    confirm::confirm(&plan, opt.yes)?;
+    if !opt.silent && backend == Backend::Pixel {
+        output::line(Channel::Diagnostics, format_args!("Wiped {}", wiped));
+    }
+    let img = match wide {
+        Some(wide) => carrier::with_low_bytes(wide, &img),
+        None => img,
+    };
+    pngio::save_image_with_text(&img, &output, &texts)
+        .map_err(|_| PngSecretError::SaveFailed(output.clone()))?;
+    if !opt.silent {
+        output::path_line(Channel::Payload, &output);
+    }
+    Ok(output)
+}
+
+/// Overwrite the LSBs carrying the payload and its framing with random non-zero bytes, so the
+/// reader can neither find the old payload nor stop early on a fake terminator or header
+///
+/// The slot, offset and depth not given are those the frame header describes.
+fn wipe_pixel_payload<C: Carrier + Clone>(
+    img: &mut C,
+    order: SubpixelOrder,
+    slot: Option<Slot>,
+    offset: Option<usize>,
+    bits: Option<u8>,
+) -> Result<usize, PngSecretError> {
+    let mut reader = pinned_reader(img.clone(), order, slot, offset, bits).with_verify(false);
+    let payload = reader.read_image()?;
+    let Location { slot, bits, offset } = reader.location();
+    let overhead = reader.framing().overhead_bytes() as usize;
+    let mut rng = rand::thread_rng();
+    let noise: Vec<u8> = (0..payload.len() + overhead)
+        .map(|_| rng.gen_range(1..=255))
+        .collect();
+    let mask = (1 << bits) - 1;
+    let channels = img.channels();
+    let samples = img.samples_mut();
+    for (index, chunk) in slot
+        .indices_from(&order, samples.len(), channels, offset)
+        .zip(bytes_to_chunks(&noise, bits))
+    {
+        samples[index] = (samples[index] & !mask) | chunk;
+    }
+    Ok(payload.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+    use pngsecret::format::Framing;
+    use pngsecret::{
+        NaiveDecoder, NaiveEncoder, PngSecretEncoder, PngSecretReader, PngSecretWriter,
+    };
+
+    #[test]
+    fn alpha_and_rgb_slots_are_independent() {
+        let read = |img: &RgbaImage, slot| {
+            PngSecretReader::new(img.clone(), Box::new(NaiveDecoder::new()))
+                .with_slot(slot)
+                .with_framing(Framing::Terminated)
+                .read_image()
+                .unwrap()
+        };
+        let mut writer =
+            PngSecretWriter::new(RgbaImage::new(16, 16), Box::new(NaiveEncoder::new()))
+                .with_slot(Slot::Rgb);
+        assert_eq!(
+            (writer.capacity(), writer.capacity_in(Slot::Alpha)),
+            (95, 31)
+        );
+        writer.encoder.encode(b"color payload");
+        writer.embed().unwrap();
+        let mut alpha = NaiveEncoder::new();
+        alpha.encode(b"alpha payload");
+        writer.embed_text(Slot::Alpha, alpha.text()).unwrap();
+
+        let mut stego = writer.buffer;
+        assert_eq!(read(&stego, Slot::Rgb), b"color payload");
+        assert_eq!(read(&stego, Slot::Alpha), b"alpha payload");
+
+        let rgb_before: Vec<u8> = Slot::Rgb
+            .indices(&SubpixelOrder::Sequential, stego.len())
+            .map(|i| stego.as_raw()[i])
+            .collect();
+        wipe_pixel_payload(
+            &mut stego,
+            SubpixelOrder::Sequential,
+            Some(Slot::Alpha),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_ne!(
+            PngSecretReader::new(stego.clone(), Box::new(NaiveDecoder::new()))
+                .with_slot(Slot::Alpha)
+                .read_image()
+                .ok(),
+            Some(b"alpha payload".to_vec())
+        );
+        let rgb_after: Vec<u8> = Slot::Rgb
+            .indices(&SubpixelOrder::Sequential, stego.len())
+            .map(|i| stego.as_raw()[i])
+            .collect();
+        assert_eq!(rgb_before, rgb_after);
+        assert_eq!(read(&stego, Slot::Rgb), b"color payload");
+    }
+}
@@ -0,0 +1,308 @@
+//! `wizard` asks for the options of a single encode or decode and prints the equivalent command
+//! line before running it, so that users pick up the flags along the way
+//!
+//! Every question goes through [`Prompt`] so the interaction can be scripted in tests.
+
+use image::ImageDecoder;
+use std::ffi::OsString;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::format::{self, Framing};
+use crate::{bytesize, paths};
+
+const DEFAULT_TEXT: &str = "Hello World";
+
+/// A source of answers for the wizard
+pub trait Prompt {
+    /// Ask `question` and return the answer, `None` if the user cancelled
+    ///
+    /// An empty answer selects `default` when there is one.
+    fn ask(&mut self, question: &str, default: Option<&str>) -> io::Result<Option<String>>;
+    /// Show information that doesn't need an answer
+    fn say(&mut self, message: &str);
+}
+
+/// Asks on stderr and reads answers from stdin, end of input cancels
+pub struct Terminal;
+
+impl Prompt for Terminal {
+    fn ask(&mut self, question: &str, default: Option<&str>) -> io::Result<Option<String>> {
+        let mut stderr = io::stderr().lock();
+        match default {
+            Some(default) => write!(stderr, "{} [{}]: ", question, default)?,
+            None => write!(stderr, "{}: ", question)?,
+        }
+        stderr.flush()?;
+        let mut answer = String::new();
+        if io::stdin().lock().read_line(&mut answer)? == 0 {
+            writeln!(stderr)?;
+            return Ok(None);
+        }
+        let answer = answer.trim_end_matches(['\r', '\n']);
+        Ok(Some(match default {
+            Some(default) if answer.is_empty() => default.to_string(),
+            _ => answer.to_string(),
+        }))
+    }
+
+    fn say(&mut self, message: &str) {
+        eprintln!("{}", message);
+    }
+}
+
+/// Ask until `accept` turns an answer into a value, `None` if the user cancelled
+fn ask_until<T>(
+    prompt: &mut dyn Prompt,
+    question: &str,
+    default: Option<&str>,
+    mut accept: impl FnMut(&str) -> Result<T, String>,
+) -> io::Result<Option<T>> {
+    loop {
+        let Some(answer) = prompt.ask(question, default)? else {
+            return Ok(None);
+        };
+        match accept(&answer) {
+            Ok(value) => return Ok(Some(value)),
+            Err(problem) => prompt.say(&problem),
+        }
+    }
+}
+
+/// Run the interview and return the resolved command line, `None` if the user cancelled
+pub fn resolve(prompt: &mut dyn Prompt) -> io::Result<Option<Vec<OsString>>> {
+    prompt.say("Press Ctrl-D at any question to cancel.");
+    let Some(hide) = ask_until(
+        prompt,
+        "Hide or reveal a message? (hide/reveal)",
+        Some("hide"),
+        |answer| match answer.trim().to_ascii_lowercase().as_str() {
+            "hide" | "h" => Ok(true),
+            "reveal" | "r" => Ok(false),
+            _ => Err("Please answer hide or reveal.".to_string()),
+        },
+    )?
+    else {
+        return Ok(None);
+    };
+
+    let mut capacity = 0;
+    let Some(input) = ask_until(prompt, "Which image?", None, |answer| {
+        if answer.is_empty() {
+            return Err("Please enter the path of a PNG image.".to_string());
+        }
+        // Only the header is read, so even huge images answer right away
+        let decoder = image::ImageReader::open(answer)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(image::ImageError::from)
+            .and_then(|reader| reader.into_decoder())
+            .map_err(|e| format!("Couldn't open {:?}: {}", answer, e))?;
+        let (width, height) = decoder.dimensions();
+        let subpixels = width as u64 * height as u64 * decoder.color_type().channel_count() as u64;
+        capacity = format::slot_capacity(subpixels, Framing::default(), format::DEFAULT_BITS);
+        Ok(PathBuf::from(answer))
+    })?
+    else {
+        return Ok(None);
+    };
+
+    let mut args: Vec<OsString> = vec!["pngsecret".into()];
+    if !hide {
+        args.extend(["-i".into(), input.into_os_string()]);
+        return Ok(Some(args));
+    }
+    prompt.say(&format!(
+        "This image can hide up to {}.",
+        bytesize::format(capacity)
+    ));
+
+    let Some(text) = ask_until(prompt, "Message to hide", Some(DEFAULT_TEXT), |answer| {
+        if answer.len() as u64 > capacity {
+            return Err(format!(
+                "That message is {} but the image only fits {}, please shorten it.",
+                bytesize::format(answer.len() as u64),
+                bytesize::format(capacity)
+            ));
+        }
+        Ok(answer.to_string())
+    })?
+    else {
+        return Ok(None);
+    };
+
+    let default_output = paths::derive_output(&input, "enc.png");
+    let default_output = default_output.to_string_lossy();
+    let Some(output) = ask_until(prompt, "Where to save?", Some(&default_output), |answer| {
+        if Path::new(answer) == input {
+            return Err("That would overwrite the original image, pick another path.".to_string());
+        }
+        Ok(PathBuf::from(answer))
+    })?
+    else {
+        return Ok(None);
+    };
+
+    args.extend([
+        "-e".into(),
+        "-i".into(),
+        input.into_os_string(),
+        "-t".into(),
+        text.into(),
+        "-o".into(),
+        output.into_os_string(),
+    ]);
+    Ok(Some(args))
+}
+
+/// Render arguments as a command line that can be pasted into a POSIX shell
+pub fn command_line(args: &[OsString]) -> String {
+    let quote = |arg: &OsString| {
+        let arg = arg.to_string_lossy();
+        let plain = !arg.is_empty()
+            && arg
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+        if plain {
+            arg.into_owned()
+        } else {
+            format!("'{}'", arg.replace('\'', r"'\''"))
+        }
+    };
+    args.iter().map(quote).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+    use std::collections::VecDeque;
+
+    /// Answers questions from a script, `None` entries cancel
+    struct Script {
+        answers: VecDeque<Option<String>>,
+        said: Vec<String>,
+    }
+
+    impl Script {
+        fn new(answers: &[Option<&str>]) -> Self {
+            Script {
+                answers: answers.iter().map(|a| a.map(str::to_string)).collect(),
+                said: Vec::new(),
+            }
+        }
+    }
+
+    impl Prompt for Script {
+        fn ask(&mut self, _question: &str, default: Option<&str>) -> io::Result<Option<String>> {
+            let answer = self.answers.pop_front().expect("script ran out of answers");
+            Ok(answer.map(|answer| match default {
+                Some(default) if answer.is_empty() => default.to_string(),
+                _ => answer.to_string(),
+            }))
+        }
+
+        fn say(&mut self, message: &str) {
+            self.said.push(message.to_string());
+        }
+    }
+
+    fn cover(dir: &Path) -> String {
+        let path = dir.join("cover.png");
+        RgbaImage::new(8, 8).save(&path).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn strings(args: Vec<OsString>) -> Vec<String> {
+        args.into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn hide_with_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let cover = cover(dir.path());
+        let cover = cover.as_str();
+        let mut script = Script::new(&[Some(""), Some(cover), Some(""), Some("")]);
+        let args = strings(resolve(&mut script).unwrap().unwrap());
+        let output = dir.path().join("cover.enc.png");
+        assert_eq!(
+            args,
+            [
+                "pngsecret",
+                "-e",
+                "-i",
+                cover,
+                "-t",
+                DEFAULT_TEXT,
+                "-o",
+                output.to_str().unwrap()
+            ]
+        );
+        assert!(script.said.iter().any(|s| s.contains("up to 19 B")));
+    }
+
+    #[test]
+    fn invalid_answers_are_asked_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let cover = cover(dir.path());
+        let cover = cover.as_str();
+        let mut script = Script::new(&[
+            Some("maybe"),
+            Some("hide"),
+            Some("missing.png"),
+            Some(cover),
+            Some("this message is far longer than thirty one bytes"),
+            Some("short"),
+            Some(cover),
+            Some("out.png"),
+        ]);
+        let args = strings(resolve(&mut script).unwrap().unwrap());
+        assert_eq!(
+            args,
+            [
+                "pngsecret",
+                "-e",
+                "-i",
+                cover,
+                "-t",
+                "short",
+                "-o",
+                "out.png"
+            ]
+        );
+        assert_eq!(script.said.len(), 6);
+    }
+
+    #[test]
+    fn reveal_only_needs_the_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let cover = cover(dir.path());
+        let cover = cover.as_str();
+        let mut script = Script::new(&[Some("reveal"), Some(cover)]);
+        let args = strings(resolve(&mut script).unwrap().unwrap());
+        assert_eq!(args, ["pngsecret", "-i", cover]);
+    }
+
+    #[test]
+    fn cancel_at_any_question() {
+        let dir = tempfile::tempdir().unwrap();
+        let cover = cover(dir.path());
+        let cover = cover.as_str();
+        assert_eq!(resolve(&mut Script::new(&[None])).unwrap(), None);
+        let mut script = Script::new(&[Some("hide"), Some(cover), Some("hi"), None]);
+        assert_eq!(resolve(&mut script).unwrap(), None);
+    }
+
+    #[test]
+    fn command_line_quotes_only_when_needed() {
+        let args: Vec<OsString> = ["pngsecret", "-t", "it's here", "-o", "out.png", ""]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        assert_eq!(
+            command_line(&args),
+            r"pngsecret -t 'it'\''s here' -o out.png ''"
+        );
+    }
+}
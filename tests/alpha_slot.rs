@@ -0,0 +1,127 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+use std::fs;
+
+#[test]
+fn alpha_payload_roundtrips_next_to_the_main_payload() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let small = dir.path().join("small.bin");
+    fs::write(&small, b"in the alpha channel").unwrap();
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+
+    let encoded = pngsecret(&[
+        "-e",
+        "--text",
+        "in the colors",
+        "--alpha-payload",
+        small.to_str().unwrap(),
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego,
+    ]);
+    assert!(encoded.status.success());
+    let diagnostics = String::from_utf8(encoded.stderr).unwrap();
+    assert!(
+        diagnostics.contains("Capacity per slot: rgb 371 B, alpha 115 B"),
+        "{}",
+        diagnostics
+    );
+
+    let decode = |slot: &str, input: &str| {
+        let out = pngsecret(&["-s", "--slot", slot, "-i", input]);
+        String::from_utf8(out.stdout).unwrap()
+    };
+    assert_eq!(decode("rgb", stego), "in the colors");
+    assert_eq!(decode("alpha", stego), "in the alpha channel");
+
+    let wiped = dir.path().join("wiped.png");
+    let wiped = wiped.to_str().unwrap();
+    let out = pngsecret(&[
+        "-s",
+        "wipe",
+        "--backend",
+        "pixel",
+        "--slot",
+        "alpha",
+        "-i",
+        stego,
+        "-o",
+        wiped,
+    ]);
+    assert!(out.status.success());
+    assert_eq!(decode("rgb", wiped), "in the colors");
+    assert_ne!(decode("alpha", wiped), "in the alpha channel");
+}
+
+#[test]
+fn main_payload_cannot_share_the_alpha_slot() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let small = dir.path().join("small.bin");
+    fs::write(&small, b"alpha").unwrap();
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--slot",
+        "alpha",
+        "--alpha-payload",
+        small.to_str().unwrap(),
+        "-i",
+        cover.to_str().unwrap(),
+    ]);
+    assert_eq!(out.status.code(), Some(1));
+}
+
+#[test]
+fn skip_alpha_leaves_every_alpha_byte_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = dir.path().join("cover.png");
+    // Opaque, transparent and in between, each would show a changed LSB
+    let original = image::RgbaImage::from_fn(32, 32, |x, y| {
+        image::Rgba([
+            x as u8 * 8,
+            y as u8 * 8,
+            128,
+            [255, 0, 128][(x % 3) as usize],
+        ])
+    });
+    original.save(&cover).unwrap();
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    let encode = |text: &str, extra: &[&str]| {
+        let mut args = vec!["-s", "-e", "--text", text, "--skip-alpha"];
+        args.extend(extra);
+        args.extend(["-i", cover.to_str().unwrap(), "-o", stego]);
+        pngsecret(&args)
+    };
+
+    // 3072 color subpixels hold 384 bytes, 371 after the frame header
+    let text = "a".repeat(371);
+    let out = encode(&text, &[]);
+    assert!(out.status.success(), "{:?}", out);
+    let saved = image::open(stego).unwrap().into_rgba8();
+    assert!(saved
+        .pixels()
+        .zip(original.pixels())
+        .all(|(after, before)| after[3] == before[3]));
+    assert_ne!(saved, original);
+    assert_eq!(
+        encode(&format!("{}a", text), &["-y"]).status.code(),
+        Some(3)
+    );
+
+    let out = pngsecret(&["-s", "--skip-alpha", "-i", stego]);
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), text);
+    let out = pngsecret(&["-s", "--slot", "rgb", "-i", stego]);
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), text);
+    // The frame header says which channels hold the payload
+    let out = pngsecret(&["-s", "-i", stego]);
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), text);
+
+    let out = encode("x", &["--slot", "alpha"]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn decode_stdin(args: &[&str], stdin: &[u8]) -> std::process::Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_pngsecret"))
+        .args(args)
+        .env_remove("PNGSECRET_STATS_FILE")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn armored_output_pipes_into_dearmor() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let encoded = pngsecret(&[
+        "-s",
+        "-e",
+        "--armor",
+        "--text",
+        "piped",
+        "-i",
+        cover.to_str().unwrap(),
+    ]);
+    assert!(encoded.status.success(), "{:?}", encoded);
+    let armored = String::from_utf8(encoded.stdout.clone()).unwrap();
+    assert!(armored.starts_with("-----BEGIN PNGSECRET IMAGE-----\n"));
+    assert!(armored.lines().all(|line| line.len() <= 64));
+    // Without -o nothing is written next to the cover
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+    let decoded = decode_stdin(&["-s", "--dearmor"], &encoded.stdout);
+    assert!(decoded.status.success(), "{:?}", decoded);
+    assert_eq!(decoded.stdout, b"piped");
+
+    let mut lines: Vec<&str> = armored.lines().collect();
+    lines.remove(3);
+    let truncated = lines.join("\n");
+    let decoded = decode_stdin(&["-s", "--dearmor", "-i", "-"], truncated.as_bytes());
+    assert_eq!(decoded.status.code(), Some(2));
+    let stderr = String::from_utf8(decoded.stderr).unwrap();
+    assert!(stderr.contains("line"), "{}", stderr);
+}
+
+#[test]
+fn armor_with_output_also_saves_the_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let armored = dir.path().join("stego.asc");
+    let encoded = pngsecret(&[
+        "-e",
+        "--armor",
+        "--text",
+        "both",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego.to_str().unwrap(),
+    ]);
+    assert!(encoded.status.success(), "{:?}", encoded);
+    std::fs::write(&armored, &encoded.stdout).unwrap();
+
+    let from_file = pngsecret(&["-s", "-i", stego.to_str().unwrap()]);
+    let from_armor = pngsecret(&["-s", "--dearmor", "-i", armored.to_str().unwrap()]);
+    assert_eq!(from_file.stdout, b"both");
+    assert_eq!(from_armor.stdout, from_file.stdout);
+}
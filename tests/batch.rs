@@ -0,0 +1,70 @@
+mod common;
+
+use common::pngsecret;
+use pngsecret::testing::{CoverBuilder, Pattern};
+use std::fs;
+use std::path::Path;
+
+fn names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn directories_are_processed_image_by_image() {
+    let dir = tempfile::tempdir().unwrap();
+    let covers = dir.path().join("covers");
+    fs::create_dir(&covers).unwrap();
+    for (i, name) in ["a.png", "b.png", "c.png"].iter().enumerate() {
+        CoverBuilder::new(16, 16)
+            .with_pattern(Pattern::Noise)
+            .with_seed(i as u64)
+            .save(covers.join(name))
+            .unwrap();
+    }
+    fs::write(covers.join("broken.png"), b"not a png at all").unwrap();
+    fs::write(covers.join("README.txt"), b"left alone").unwrap();
+    let stegos = dir.path().join("stegos");
+    let (covers, stegos) = (covers.to_str().unwrap(), stegos.to_str().unwrap());
+
+    let out = pngsecret(&["-e", "--text", "(c) archive", "-i", covers, "-o", stegos]);
+    assert_eq!(out.status.code(), Some(15), "{:?}", out);
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("[4/4] "), "{}", stderr);
+    assert!(
+        stderr.contains("broken.png: The file") && stderr.contains("couldn't be correctly read"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("1 of 4 images failed"), "{}", stderr);
+    assert!(
+        stderr.contains("Summary: encode: 4 processed, 3 succeeded, 0 skipped, 1 failed"),
+        "{}",
+        stderr
+    );
+    assert_eq!(names(Path::new(stegos)), ["a.png", "b.png", "c.png"]);
+
+    let messages = dir.path().join("messages");
+    let out = pngsecret(&["-s", "-i", stegos, "-o", messages.to_str().unwrap()]);
+    assert!(out.status.success(), "{:?}", out);
+    // Silent runs report neither progress nor the summary
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        !stderr.contains("[1/3]") && !stderr.contains("Summary:"),
+        "{}",
+        stderr
+    );
+    assert_eq!(names(&messages), ["a.payload", "b.payload", "c.payload"]);
+    for name in names(&messages) {
+        assert_eq!(fs::read(messages.join(name)).unwrap(), b"(c) archive");
+    }
+
+    let out = pngsecret(&["-s", "-i", stegos]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+    let out = pngsecret(&["-s", "-i", stegos, "-o", stegos]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+}
@@ -0,0 +1,107 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+
+#[test]
+fn files_roundtrip_byte_for_byte() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let cover = cover.to_str().unwrap();
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    let dumped = dir.path().join("dumped.bin");
+    let dumped = dumped.to_str().unwrap();
+
+    // Not UTF-8, with NUL bytes anywhere since the frame header carries the length
+    let binary: Vec<u8> = (0..=255).collect();
+    let nuls = vec![0; 40];
+    let empty: Vec<u8> = Vec::new();
+    for payload in [binary, nuls, empty] {
+        let file = dir.path().join("payload.bin");
+        std::fs::write(&file, &payload).unwrap();
+        let file = file.to_str().unwrap();
+        let out = pngsecret(&["-s", "-y", "-e", "--file", file, "-i", cover, "-o", stego]);
+        assert!(out.status.success(), "{:?}", out);
+        let out = pngsecret(&["-s", "-y", "-i", stego, "-o", dumped]);
+        assert!(out.status.success(), "{:?}", out);
+        assert_eq!(std::fs::read(dumped).unwrap(), payload);
+    }
+}
+
+#[test]
+fn unfit_files_fail_before_writing() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let cover = cover.to_str().unwrap();
+    let stego = dir.path().join("stego.png");
+    let encode = |payload: &[u8], extra: &[&str]| {
+        let file = dir.path().join("payload.bin");
+        std::fs::write(&file, payload).unwrap();
+        let mut args = vec![
+            "-s",
+            "-e",
+            "--file",
+            file.to_str().unwrap(),
+            "-i",
+            cover,
+            "-o",
+            stego.to_str().unwrap(),
+        ];
+        args.extend(extra);
+        pngsecret(&args)
+    };
+
+    let out = encode(&[b'x'; 1000], &[]);
+    assert_eq!(out.status.code(), Some(3));
+    assert!(!stego.exists());
+
+    // The legacy format ends the message at the first NUL byte
+    let out = encode(b"ab\0cd", &["--legacy"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("offset 2"));
+    assert!(!stego.exists());
+
+    let out = encode(b"abc", &["--text", "also"]);
+    assert!(!out.status.success());
+    assert!(!stego.exists());
+}
+
+#[test]
+fn decode_writes_into_new_directories_and_keeps_existing_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let cover = cover.to_str().unwrap();
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+
+    for (name, payload) in [
+        ("text.txt", &b"plain text"[..]),
+        ("raw.bin", &[0xff, 0, 0xfe]),
+    ] {
+        let file = dir.path().join("payload.bin");
+        std::fs::write(&file, payload).unwrap();
+        let file = file.to_str().unwrap();
+        let out = pngsecret(&["-s", "-y", "-e", "--file", file, "-i", cover, "-o", stego]);
+        assert!(out.status.success(), "{:?}", out);
+
+        let output = dir.path().join("messages").join("nested").join(name);
+        let out = pngsecret(&["-i", stego, "-o", output.to_str().unwrap()]);
+        assert!(out.status.success(), "{:?}", out);
+        assert_eq!(std::fs::read(&output).unwrap(), payload);
+        assert_eq!(out.stdout, format!("{}\n", output.display()).as_bytes());
+        let stderr = String::from_utf8(out.stderr).unwrap();
+        assert!(
+            stderr.contains(&format!("Saved {} B", payload.len())),
+            "{}",
+            stderr
+        );
+
+        std::fs::write(&output, b"keep me").unwrap();
+        let out = pngsecret(&["-s", "-i", stego, "-o", output.to_str().unwrap()]);
+        assert_eq!(out.status.code(), Some(10), "{:?}", out);
+        assert_eq!(std::fs::read(&output).unwrap(), b"keep me");
+        let out = pngsecret(&["-s", "--force", "-i", stego, "-o", output.to_str().unwrap()]);
+        assert!(out.status.success(), "{:?}", out);
+        assert_eq!(std::fs::read(&output).unwrap(), payload);
+    }
+}
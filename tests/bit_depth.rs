@@ -0,0 +1,49 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+
+#[test]
+fn deeper_embedding_roundtrips_at_the_depth_its_header_describes() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let cover = cover.to_str().unwrap();
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    let dumped = dir.path().join("dumped.bin");
+    let dumped = dumped.to_str().unwrap();
+    // 32x32 RGBA holds 499 bytes one bit deep, 1523 three bits deep
+    let payload: Vec<u8> = (0..1500).map(|i| (i * 7) as u8).collect();
+    let file = dir.path().join("payload.bin");
+    std::fs::write(&file, &payload).unwrap();
+    let file = file.to_str().unwrap();
+    let encode = |extra: &[&str]| {
+        let mut args = vec!["-s", "-y", "-e", "--file", file, "-i", cover, "-o", stego];
+        args.extend(extra);
+        pngsecret(&args)
+    };
+
+    assert_eq!(encode(&[]).status.code(), Some(3));
+    let out = encode(&["--bits", "3"]);
+    assert!(out.status.success(), "{:?}", out);
+
+    let out = pngsecret(&["-s", "-y", "--bits", "3", "-i", stego, "-o", dumped]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(std::fs::read(dumped).unwrap(), payload);
+
+    std::fs::remove_file(dumped).unwrap();
+    let out = pngsecret(&["-s", "-y", "-i", stego, "-o", dumped]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(std::fs::read(dumped).unwrap(), payload);
+
+    // A depth given is taken at its word, a header at another one tells what went wrong
+    let out = pngsecret(&["-s", "-y", "--bits", "2", "-i", stego, "-o", dumped]);
+    assert_eq!(out.status.code(), Some(4), "{:?}", out);
+    assert!(
+        String::from_utf8_lossy(&out.stderr).contains("decode with --bits 3"),
+        "{:?}",
+        out
+    );
+
+    let out = pngsecret(&["-s", "--bits", "5", "-i", stego]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+}
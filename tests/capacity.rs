@@ -0,0 +1,45 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+
+#[test]
+fn the_printed_limit_is_exactly_what_fits() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let cover = cover.to_str().unwrap();
+    let encode = |text: &str, output: &str| {
+        let output = dir.path().join(output);
+        let out = pngsecret(&[
+            "-e",
+            "--text",
+            text,
+            "-i",
+            cover,
+            "-o",
+            output.to_str().unwrap(),
+        ]);
+        (out, output)
+    };
+
+    let (out, _) = encode("probe", "probe.png");
+    assert!(out.status.success(), "{:?}", out);
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    let limit: usize = stderr
+        .split("message length limit ")
+        .nth(1)
+        .and_then(|rest| rest.lines().next())
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or_else(|| panic!("{}", stderr));
+    // 32x32 RGBA at one bit per subpixel, less the frame header
+    assert_eq!(limit, 32 * 32 * 4 / 8 - 13);
+
+    let fits = "f".repeat(limit);
+    let (out, stego) = encode(&fits, "fits.png");
+    assert!(out.status.success(), "{:?}", out);
+    let out = pngsecret(&["-s", "-i", stego.to_str().unwrap()]);
+    assert_eq!(out.stdout, fits.as_bytes());
+
+    let (out, stego) = encode(&"f".repeat(limit + 1), "too_large.png");
+    assert_eq!(out.status.code(), Some(3), "{:?}", out);
+    assert!(!stego.exists());
+}
@@ -0,0 +1,393 @@
+mod common;
+
+use common::pngsecret;
+use image::{DynamicImage, GrayAlphaImage, GrayImage, RgbImage};
+use pngsecret::testing::CoverBuilder;
+
+#[test]
+fn covers_keep_their_color_type() {
+    let dir = tempfile::tempdir().unwrap();
+    let covers = [
+        DynamicImage::ImageRgb8(RgbImage::from_fn(32, 32, |x, y| {
+            image::Rgb([x as u8 * 8, y as u8 * 8, 128])
+        })),
+        DynamicImage::ImageRgba8(CoverBuilder::new(32, 32).build()),
+        DynamicImage::ImageLuma8(GrayImage::from_fn(32, 32, |x, y| {
+            image::Luma([(x + y) as u8 * 4])
+        })),
+        DynamicImage::ImageLumaA8(GrayAlphaImage::from_fn(32, 32, |x, y| {
+            image::LumaA([x as u8 * 8, 255 - y as u8])
+        })),
+    ];
+    for cover in covers {
+        let color = cover.color();
+        let path = dir.path().join(format!("{:?}.png", color));
+        let stego = dir.path().join(format!("{:?}.enc.png", color));
+        cover.save(&path).unwrap();
+
+        let out = pngsecret(&[
+            "-s",
+            "-e",
+            "--text",
+            "same channels in and out",
+            "-i",
+            path.to_str().unwrap(),
+            "-o",
+            stego.to_str().unwrap(),
+        ]);
+        assert!(out.status.success(), "{:?}: {:?}", color, out);
+        let saved = image::open(&stego).unwrap();
+        assert_eq!(saved.color(), color);
+        // Matching moves a sample up or down by one, whatever that does to its higher bits
+        let moved_by_one = cover
+            .as_bytes()
+            .iter()
+            .zip(saved.as_bytes())
+            .all(|(before, after)| before.abs_diff(*after) <= 1);
+        assert!(moved_by_one, "{:?}", color);
+
+        let out = pngsecret(&["-s", "-i", stego.to_str().unwrap()]);
+        assert!(out.status.success(), "{:?}: {:?}", color, out);
+        assert_eq!(out.stdout, b"same channels in and out", "{:?}", color);
+    }
+}
+
+#[test]
+fn wiped_covers_keep_their_color_type() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = dir.path().join("rgb.png");
+    let stego = dir.path().join("rgb.enc.png");
+    let wiped = dir.path().join("rgb.wiped.png");
+    RgbImage::from_fn(32, 32, |x, y| image::Rgb([x as u8 * 8, y as u8 * 8, 128]))
+        .save(&cover)
+        .unwrap();
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--text",
+        "wiped from three channels",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+
+    let out = pngsecret(&[
+        "-s",
+        "wipe",
+        "--backend",
+        "pixel",
+        "-i",
+        stego.to_str().unwrap(),
+        "-o",
+        wiped.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    let saved = image::open(&wiped).unwrap();
+    assert_eq!(saved.color(), image::ColorType::Rgb8);
+    let stego = image::open(&stego).unwrap();
+    let high_bits_kept = stego
+        .as_bytes()
+        .iter()
+        .zip(saved.as_bytes())
+        .all(|(before, after)| before >> 1 == after >> 1);
+    assert!(high_bits_kept);
+
+    let out = pngsecret(&["-s", "-i", wiped.to_str().unwrap()]);
+    assert!(!out.status.success(), "{:?}", out);
+    assert!(out.stdout.is_empty());
+}
+
+#[test]
+fn gray_covers_have_no_alpha_slot() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = dir.path().join("gray.png");
+    GrayImage::new(32, 32).save(&cover).unwrap();
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--text",
+        "x",
+        "--slot",
+        "alpha",
+        "-i",
+        cover.to_str().unwrap(),
+        "--dry-run",
+    ]);
+    assert_eq!(out.status.code(), Some(3), "{:?}", out);
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with("alpha slot: carrier 0 B"));
+
+    // 1024 gray samples hold 128 bytes, 115 after the frame header
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--text",
+        &"x".repeat(115),
+        "-i",
+        cover.to_str().unwrap(),
+        "--dry-run",
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    assert!(String::from_utf8_lossy(&out.stdout).ends_with("headroom 0 B\n"));
+}
+
+/// A `width` x `height` gray PNG with `depth` bits per sample, from samples in 0..2^depth
+fn write_packed_gray(path: &std::path::Path, depth: png::BitDepth, width: u32, height: u32) {
+    let bits = depth as u32;
+    let mut data = Vec::new();
+    for y in 0..height {
+        let mut row = vec![0u8; (width * bits).div_ceil(8) as usize];
+        for x in 0..width {
+            let sample = ((x * 7 + y * 3) % (1 << bits)) as u8;
+            let offset = x * bits;
+            row[(offset / 8) as usize] |= sample << (8 - bits - offset % 8);
+        }
+        data.extend(row);
+    }
+    let mut encoder = png::Encoder::new(std::fs::File::create(path).unwrap(), width, height);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(depth);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(&data).unwrap();
+}
+
+fn png_depth(path: &std::path::Path) -> png::BitDepth {
+    let decoder = png::Decoder::new(std::fs::File::open(path).unwrap());
+    decoder.read_info().unwrap().info().bit_depth
+}
+
+#[test]
+fn four_bit_gray_covers_stay_four_bit() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = dir.path().join("scan.png");
+    let stego = dir.path().join("scan.enc.png");
+    write_packed_gray(&cover, png::BitDepth::Four, 37, 40);
+    for bits in ["1", "2"] {
+        let out = pngsecret(&[
+            "-s",
+            "-e",
+            "--yes",
+            "--bits",
+            bits,
+            "--text",
+            "filed under 1907",
+            "-i",
+            cover.to_str().unwrap(),
+            "-o",
+            stego.to_str().unwrap(),
+        ]);
+        assert!(out.status.success(), "{:?}", out);
+        assert_eq!(png_depth(&stego), png::BitDepth::Four);
+        assert!(std::fs::metadata(&stego).unwrap().len() < 37 * 40 / 2 + 200);
+
+        let out = pngsecret(&["-s", "--bits", bits, "-i", stego.to_str().unwrap()]);
+        assert!(out.status.success(), "{:?}", out);
+        assert_eq!(out.stdout, b"filed under 1907");
+    }
+}
+
+#[test]
+fn one_and_two_bit_gray_covers_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    for (depth, name) in [(png::BitDepth::One, "1"), (png::BitDepth::Two, "2")] {
+        let cover = dir.path().join(format!("{}bit.png", name));
+        write_packed_gray(&cover, depth, 64, 64);
+        for dry_run in [false, true] {
+            let mut args = vec!["-s", "-e", "--text", "x", "-i", cover.to_str().unwrap()];
+            if dry_run {
+                args.push("--dry-run");
+            }
+            let out = pngsecret(&args);
+            assert_eq!(out.status.code(), Some(7), "{:?}", out);
+            let stderr = String::from_utf8(out.stderr).unwrap();
+            assert!(
+                stderr.starts_with(&format!("The cover has {} bit(s) per gray sample", name)),
+                "{}",
+                stderr
+            );
+        }
+    }
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+}
+
+#[test]
+fn lossless_formats_are_kept_and_lossy_outputs_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = RgbImage::from_fn(32, 32, |x, y| image::Rgb([x as u8 * 8, y as u8 * 8, 77]));
+    for (extension, format) in [
+        ("bmp", image::ImageFormat::Bmp),
+        ("tif", image::ImageFormat::Tiff),
+        ("webp", image::ImageFormat::WebP),
+    ] {
+        let path = dir.path().join(format!("release.v2.{}", extension));
+        cover.save(&path).unwrap();
+        let text = "any lossless container";
+        let out = pngsecret(&["-s", "-e", "--text", text, "-i", path.to_str().unwrap()]);
+        assert!(out.status.success(), "{}: {:?}", extension, out);
+        let stego = dir.path().join(format!("release.v2.enc.{}", extension));
+        let bytes = std::fs::read(&stego).unwrap();
+        assert_eq!(image::guess_format(&bytes).unwrap(), format);
+
+        let out = pngsecret(&["-s", "-i", stego.to_str().unwrap()]);
+        assert_eq!(out.stdout, b"any lossless container", "{}", extension);
+    }
+
+    let jpeg = dir.path().join("stego.jpg");
+    let out = pngsecret(&[
+        "-e",
+        "--text",
+        "lost",
+        "-i",
+        dir.path().join("missing.png").to_str().unwrap(),
+        "-o",
+        jpeg.to_str().unwrap(),
+    ]);
+    // Refused before the input is even read
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("lossy"));
+    assert!(!jpeg.exists());
+
+    let gray = dir.path().join("gray.png");
+    GrayImage::new(32, 32).save(&gray).unwrap();
+    let bmp = dir.path().join("gray.bmp");
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--text",
+        "moved",
+        "-i",
+        gray.to_str().unwrap(),
+        "-o",
+        bmp.to_str().unwrap(),
+    ]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+    assert!(!bmp.exists());
+}
+
+#[test]
+fn panoramas_wider_than_the_output_holds_are_refused_before_embedding() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = dir.path().join("panorama.png");
+    RgbImage::new(16_385, 2).save(&cover).unwrap();
+    let stego = dir.path().join("panorama.webp");
+    let encode = |extra: &[&str]| {
+        let mut args = vec!["-s", "-e", "--text", "wide", "-i", cover.to_str().unwrap()];
+        args.extend_from_slice(&["-o", stego.to_str().unwrap()]);
+        args.extend_from_slice(extra);
+        pngsecret(&args)
+    };
+    for extra in [&[][..], &["--dry-run"]] {
+        let out = encode(extra);
+        assert_eq!(out.status.code(), Some(1), "{:?}: {:?}", extra, out);
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(
+            stderr.contains("16385x2 cover doesn't fit into WebP"),
+            "{}",
+            stderr
+        );
+        assert!(stderr.contains("16384 pixels per side"), "{}", stderr);
+        assert!(!stego.exists());
+    }
+}
+
+#[test]
+fn sixteen_bit_covers_stay_sixteen_bit() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = dir.path().join("scan16.png");
+    let stego = dir.path().join("scan16.enc.png");
+    let original = image::ImageBuffer::from_fn(32, 32, |x, y| {
+        image::Rgb([x as u16 * 2047, y as u16 * 1999, 0x8000 | (x * y) as u16])
+    });
+    DynamicImage::ImageRgb16(original.clone())
+        .save(&cover)
+        .unwrap();
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--text",
+        "every one of the 16 bits kept",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(png_depth(&stego), png::BitDepth::Sixteen);
+    let saved = image::open(&stego).unwrap();
+    let saved = saved.as_rgb16().unwrap();
+    assert_ne!(saved, &original);
+    assert!(original
+        .iter()
+        .zip(saved.iter())
+        .all(|(before, after)| before.abs_diff(*after) <= 1));
+
+    let out = pngsecret(&["-s", "-i", stego.to_str().unwrap()]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(out.stdout, b"every one of the 16 bits kept");
+
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "-y",
+        "--force-8bit",
+        "--text",
+        "down to 8",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(png_depth(&stego), png::BitDepth::Eight);
+    let out = pngsecret(&["-s", "-i", stego.to_str().unwrap()]);
+    assert_eq!(out.stdout, b"down to 8", "{:?}", out);
+}
+
+#[test]
+fn palette_covers_need_force_8bit() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = dir.path().join("palette.png");
+    let mut encoder = png::Encoder::new(std::fs::File::create(&cover).unwrap(), 32, 32);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(
+        (0..=255u8)
+            .flat_map(|i| [i, 255 - i, i / 2])
+            .collect::<Vec<u8>>(),
+    );
+    let mut writer = encoder.write_header().unwrap();
+    writer
+        .write_image_data(&(0..32 * 32).map(|i| (i * 7) as u8).collect::<Vec<u8>>())
+        .unwrap();
+    drop(writer);
+
+    let stego = dir.path().join("palette.enc.png");
+    let encode = |extra: &[&str]| {
+        let mut args = vec![
+            "-s",
+            "-e",
+            "--text",
+            "was indexed",
+            "-i",
+            cover.to_str().unwrap(),
+        ];
+        args.extend_from_slice(extra);
+        args.extend(["-o", stego.to_str().unwrap()]);
+        pngsecret(&args)
+    };
+    for extra in [&[][..], &["--dry-run"]] {
+        let out = encode(extra);
+        assert_eq!(out.status.code(), Some(7), "{:?}", out);
+        let stderr = String::from_utf8(out.stderr).unwrap();
+        assert!(stderr.starts_with("The cover has a palette"), "{}", stderr);
+        assert!(stderr.contains("--force-8bit"), "{}", stderr);
+    }
+    assert!(!stego.exists());
+
+    let out = encode(&["--force-8bit"]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(image::open(&stego).unwrap().color(), image::ColorType::Rgb8);
+    let out = pngsecret(&["-s", "-i", stego.to_str().unwrap()]);
+    assert_eq!(out.stdout, b"was indexed", "{:?}", out);
+}
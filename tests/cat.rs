@@ -0,0 +1,92 @@
+mod common;
+
+use common::{pngsecret, write_noise_cover};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const ORIGINAL: &str = "first fragment of the file,\nthe middle one\tand a tail.\n";
+
+/// Embed `text` into a copy of the noise cover saved as `name`
+fn stego(dir: &Path, name: &str, text: &str) -> PathBuf {
+    let cover = write_noise_cover(dir, 32, 32);
+    let output = dir.join(name);
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        output.to_str().unwrap(),
+        "--text",
+        text,
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    output
+}
+
+fn fragments(dir: &Path) -> Vec<PathBuf> {
+    let (first, rest) = ORIGINAL.split_at(20);
+    let (middle, last) = rest.split_at(15);
+    vec![
+        stego(dir, "1.png", first),
+        stego(dir, "2.png", middle),
+        stego(dir, "3.png", last),
+    ]
+}
+
+fn cat(args: &[&Path], extra: &[&str]) -> std::process::Output {
+    let mut all = vec!["-s", "cat"];
+    all.extend(extra);
+    all.extend(args.iter().map(|p| p.to_str().unwrap()));
+    pngsecret(&all)
+}
+
+#[test]
+fn fragments_concatenate_in_argument_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let parts = fragments(dir.path());
+    let out = cat(&[&parts[0], &parts[1], &parts[2]], &[]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(out.stdout, ORIGINAL.as_bytes());
+    assert!(
+        out.stderr.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+
+    let out = cat(&[&parts[2], &parts[0]], &[]);
+    let (first, _) = ORIGINAL.split_at(20);
+    assert_eq!(out.stdout, [&ORIGINAL[35..], first].concat().as_bytes());
+}
+
+#[test]
+fn missing_message_fails_unless_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    let parts = fragments(dir.path());
+    let empty = dir.path().join("empty.png");
+    image::RgbaImage::from_pixel(8, 8, image::Rgba([1, 1, 1, 1]))
+        .save(&empty)
+        .unwrap();
+    let unreadable = dir.path().join("missing.png");
+
+    let out = cat(&[&parts[0], &empty, &parts[1], &parts[2]], &[]);
+    assert_eq!(out.status.code(), Some(4));
+    assert_eq!(out.stdout, ORIGINAL.as_bytes());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("empty.png: "), "{}", stderr);
+    assert!(stderr.contains("1 of 4 images"), "{}", stderr);
+
+    let out = cat(
+        &[&parts[0], &empty, &parts[1], &parts[2]],
+        &["--skip-missing"],
+    );
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(out.stdout, ORIGINAL.as_bytes());
+
+    // Skipping only covers images without a message, not unreadable files
+    let out = cat(&[&parts[0], &unreadable], &["--skip-missing"]);
+    assert_eq!(out.status.code(), Some(4));
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("missing.png: "), "{}", stderr);
+    assert!(fs::metadata(&unreadable).is_err());
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use common::{pngsecret, write_noise_cover};
+use std::fs;
+
+#[test]
+fn sidecars_check_the_file_and_the_payload() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_noise_cover(dir.path(), 32, 32);
+    let output = dir.path().join("out.enc.png");
+    let output = output.to_str().unwrap();
+    let sidecar = dir.path().join("out.enc.png.psum");
+    let sidecar = sidecar.to_str().unwrap();
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--text",
+        "archived",
+        "--write-checksum",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        output,
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    let toml = fs::read_to_string(sidecar).unwrap();
+    assert!(toml.contains("format_version = 1"), "{}", toml);
+    assert!(!toml.contains("archived"), "{}", toml);
+
+    let out = pngsecret(&["verify", "--checksum", sidecar]);
+    assert!(out.status.success(), "{:?}", out);
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with("Checksum OK: "));
+    let out = pngsecret(&["verify", "--checksum", sidecar, "--decode"]);
+    assert!(out.status.success(), "{:?}", out);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("payload included"));
+
+    let mut modified = fs::read(output).unwrap();
+    let last = modified.len() - 1;
+    modified[last] ^= 1;
+    fs::write(output, modified).unwrap();
+    let out = pngsecret(&["verify", "--checksum", sidecar]);
+    assert_eq!(out.status.code(), Some(5), "{:?}", out);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("not the file"));
+
+    fs::remove_file(sidecar).unwrap();
+    let out = pngsecret(&["verify", "--checksum", sidecar]);
+    assert_eq!(out.status.code(), Some(8), "{:?}", out);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("checksum sidecar"));
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+use std::path::Path;
+
+/// The data of the `kind` chunks of the PNG at `path`, walked without decoding the image
+fn chunk_data(path: &Path, kind: &[u8; 4]) -> Vec<u8> {
+    let png = std::fs::read(path).unwrap();
+    let mut rest = &png[8..];
+    let mut data = Vec::new();
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        if &rest[4..8] == kind {
+            data.extend_from_slice(&rest[8..8 + length]);
+        }
+        rest = &rest[12 + length..];
+    }
+    data
+}
+
+#[test]
+fn chunk_payloads_roundtrip_and_leave_the_pixels_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let out = pngsecret(&[
+        "-s",
+        "-y",
+        "-e",
+        "--method",
+        "chunk",
+        "--compress",
+        "--text",
+        "kept out of the pixels",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+
+    assert_eq!(chunk_data(&stego, b"IDAT"), chunk_data(&cover, b"IDAT"));
+    assert!(!chunk_data(&stego, b"stEg").is_empty());
+    assert_eq!(
+        image::open(&stego).unwrap().into_rgba8(),
+        image::open(&cover).unwrap().into_rgba8()
+    );
+
+    // Decode finds the chunk without being told
+    let mut args = vec!["-s", "-i", stego.to_str().unwrap()];
+    let out = pngsecret(&args);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(out.stdout, b"kept out of the pixels");
+
+    args.extend(["--method", "lsb"]);
+    let out = pngsecret(&args);
+    assert_eq!(out.status.code(), Some(4), "{:?}", out);
+}
+
+#[test]
+fn info_lists_the_chunk_and_pixel_covers_fall_back() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--method",
+        "chunk",
+        "--text",
+        "abc",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego,
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    let out = pngsecret(&["info", "-i", stego]);
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "-  chunk stEg, 16 B\n"
+    );
+
+    let out = pngsecret(&["-s", "-i", cover.to_str().unwrap(), "--method", "chunk"]);
+    assert_eq!(out.status.code(), Some(4), "{:?}", out);
+
+    // Pixel payloads decode as before
+    let pixels = dir.path().join("pixels.png");
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--text",
+        "abc",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        pixels.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    let out = pngsecret(&["-s", "-i", pixels.to_str().unwrap()]);
+    assert_eq!(out.stdout, b"abc", "{:?}", out);
+
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--method",
+        "chunk",
+        "--text",
+        "abc",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        dir.path().join("stego.bmp").to_str().unwrap(),
+    ]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+/// Run pngsecret with a stdout whose reader already went away, as after `| head -c 0`
+fn to_closed_stdout(args: &[&str]) -> Output {
+    let (reader, writer) = std::io::pipe().unwrap();
+    drop(reader);
+    Command::new(env!("CARGO_BIN_EXE_pngsecret"))
+        .args(args)
+        .env_remove("PNGSECRET_STATS_FILE")
+        .stdout(writer)
+        .stderr(Stdio::piped())
+        .output()
+        .unwrap()
+}
+
+fn stego(dir: &Path, name: &str, payload: &[u8]) -> PathBuf {
+    let cover = write_cover(dir);
+    let file = dir.join(format!("{}.bin", name));
+    std::fs::write(&file, payload).unwrap();
+    let output = dir.join(name);
+    let out = pngsecret(&[
+        "-s",
+        "-y",
+        "-e",
+        "--file",
+        file.to_str().unwrap(),
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        output.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    output
+}
+
+fn quiet(out: &Output) -> bool {
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    !stderr.contains("panicked") && !stderr.contains("Broken pipe")
+}
+
+#[test]
+fn decode_into_a_closed_pipe_succeeds_quietly() {
+    let dir = tempfile::tempdir().unwrap();
+    let stego = stego(dir.path(), "stego.png", &[b'x'; 499]);
+    let stego = stego.to_str().unwrap();
+
+    let out = to_closed_stdout(&["-s", "--format", "raw", "-i", stego]);
+    assert_eq!(out.status.code(), Some(0), "{:?}", out);
+    assert!(quiet(&out), "{:?}", out);
+
+    let out = to_closed_stdout(&["-s", "--strict", "--format", "raw", "-i", stego]);
+    assert_eq!(out.status.code(), Some(12), "{:?}", out);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("cut short"));
+}
+
+#[test]
+fn cat_stops_at_a_closed_pipe() {
+    let dir = tempfile::tempdir().unwrap();
+    let parts: Vec<PathBuf> = (0..3)
+        .map(|i| stego(dir.path(), &format!("{}.png", i), b"fragment"))
+        .collect();
+    let mut args = vec!["-s", "cat"];
+    args.extend(parts.iter().map(|p| p.to_str().unwrap()));
+
+    let out = to_closed_stdout(&args);
+    assert_eq!(out.status.code(), Some(0), "{:?}", out);
+    assert!(quiet(&out), "{:?}", out);
+
+    args.insert(1, "--strict");
+    let out = to_closed_stdout(&args);
+    assert_eq!(out.status.code(), Some(12), "{:?}", out);
+}
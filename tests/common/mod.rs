@@ -0,0 +1,77 @@
+//! Helpers shared by the integration tests
+
+#![allow(dead_code)]
+
+use pngsecret::testing::{CoverBuilder, Pattern};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+pub fn pngsecret(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pngsecret"))
+        .args(args)
+        .env_remove("PNGSECRET_STATS_FILE")
+        .output()
+        .expect("failed to run pngsecret")
+}
+
+/// Write a small gradient cover into `dir` and return its path
+pub fn write_cover(dir: &Path) -> PathBuf {
+    let path = dir.join("cover.png");
+    CoverBuilder::new(32, 32).save(&path).unwrap();
+    path
+}
+
+/// Write a deterministic noise cover into `dir` and return its path
+pub fn write_noise_cover(dir: &Path, width: u32, height: u32) -> PathBuf {
+    let path = dir.join("noise.png");
+    CoverBuilder::new(width, height)
+        .with_pattern(Pattern::Noise)
+        .save(&path)
+        .unwrap();
+    path
+}
+
+/// Check `value` against the subset of JSON Schema that the `*.schema.json` files use
+pub fn validate(schema: &Value, value: &Value, at: &str) -> Result<(), String> {
+    let fail = |what: &str| Err(format!("{}: {} in {}", at, what, value));
+    let type_ok = match schema["type"].as_str() {
+        Some("object") => value.is_object(),
+        Some("string") => value.is_string(),
+        Some("integer") => value.is_u64() || value.is_i64(),
+        Some("boolean") => value.is_boolean(),
+        Some(other) => return fail(&format!("unsupported type {}", other)),
+        None => true,
+    };
+    if !type_ok {
+        return fail(&format!("not of type {}", schema["type"]));
+    }
+    if let Some(allowed) = schema["enum"].as_array() {
+        if !allowed.contains(value) {
+            return fail("not one of the enum values");
+        }
+    }
+    if let (Some(minimum), Some(number)) = (schema["minimum"].as_i64(), value.as_i64()) {
+        if number < minimum {
+            return fail(&format!("below the minimum {}", minimum));
+        }
+    }
+    let Some(object) = value.as_object() else {
+        return Ok(());
+    };
+    for required in schema["required"].as_array().into_iter().flatten() {
+        if !object.contains_key(required.as_str().unwrap()) {
+            return fail(&format!("missing {}", required));
+        }
+    }
+    for (key, field) in object {
+        let at = format!("{}.{}", at, key);
+        match (&schema["properties"][key], &schema["additionalProperties"]) {
+            (Value::Object(property), _) => validate(&Value::Object(property.clone()), field, &at)?,
+            (_, Value::Bool(false)) => return Err(format!("{}: not in the schema", at)),
+            (_, additional @ Value::Object(_)) => validate(additional, field, &at)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
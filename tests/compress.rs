@@ -0,0 +1,30 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+
+#[test]
+fn compressed_messages_fit_past_the_raw_capacity() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let cover = cover.to_str().unwrap();
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    // 32x32 RGBA holds 506 bytes one bit deep
+    let text = "All work and no play makes Jack a dull boy. ".repeat(40);
+    assert!(text.len() > 1500);
+    let encode = |extra: &[&str]| {
+        let mut args = vec!["-s", "-y", "-e", "--text", &text, "-i", cover, "-o", stego];
+        args.extend(extra);
+        pngsecret(&args)
+    };
+
+    assert_eq!(encode(&[]).status.code(), Some(3));
+    let out = encode(&["--compress"]);
+    assert!(out.status.success(), "{:?}", out);
+
+    let out = pngsecret(&["-s", "-i", stego]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(out.stdout, text.as_bytes());
+
+    assert_eq!(encode(&["--compress", "--legacy"]).status.code(), Some(1));
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+
+#[test]
+fn flipped_payload_bits_fail_the_checksum() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--text",
+        "every bit counts",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+
+    // The 13 byte frame header takes the first 104 subpixels, the payload follows
+    let mut img = image::open(&stego).unwrap().into_rgba8();
+    img.as_mut()[104 + 21] ^= 1;
+    let flipped = dir.path().join("flipped.png");
+    img.save(&flipped).unwrap();
+
+    let out = pngsecret(&["-s", "-i", flipped.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(14), "{:?}", out);
+    assert!(out.stdout.is_empty());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        stderr.starts_with("The payload is corrupted or this is not a pngsecret image"),
+        "{}",
+        stderr
+    );
+
+    let out = pngsecret(&[
+        "-s",
+        "--no-verify",
+        "--raw",
+        "-i",
+        flipped.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(out.stdout.len(), "every bit counts".len());
+    assert_ne!(out.stdout, b"every bit counts");
+}
+
+#[test]
+fn legacy_images_need_no_verify_or_legacy() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--legacy",
+        "--text",
+        "from an older version",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego,
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+
+    // Without a frame header there is nothing to verify, so nothing is read
+    assert_eq!(pngsecret(&["-s", "-i", stego]).status.code(), Some(4));
+    for flag in ["--no-verify", "--legacy"] {
+        let out = pngsecret(&["-s", flag, "-i", stego]);
+        assert!(out.status.success(), "{}: {:?}", flag, out);
+        assert_eq!(out.stdout, b"from an older version", "{}", flag);
+    }
+    let out = pngsecret(&["-s", "-e", "--no-verify", "-i", stego]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+}
+
+#[test]
+fn frames_of_a_newer_version_are_refused_with_a_clear_message() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--text",
+        "from the future",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+
+    // The second magic byte 0xA1 becomes 0xA2, the first version past this one
+    let mut img = image::open(&stego).unwrap().into_rgba8();
+    img.as_mut()[14] |= 1;
+    img.as_mut()[15] &= !1;
+    let newer = dir.path().join("newer.png");
+    img.save(&newer).unwrap();
+
+    let out = pngsecret(&["-s", "-i", newer.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+    assert!(out.stdout.is_empty());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        stderr.contains("produced by a newer pngsecret"),
+        "{}",
+        stderr
+    );
+}
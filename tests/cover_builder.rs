@@ -0,0 +1,111 @@
+use pngsecret::testing::{CoverBuilder, Mask, Pattern};
+use sha2::{Digest, Sha256};
+
+fn digest(cover: &CoverBuilder) -> String {
+    Sha256::digest(cover.build().as_raw())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Changing any of these digests breaks the tests of every crate built on the covers, a pattern
+/// that must change gets a new variant instead
+#[test]
+fn covers_are_stable_across_releases() {
+    let noise = CoverBuilder::new(16, 16).with_pattern(Pattern::Noise);
+    let dither = CoverBuilder::new(16, 16).with_pattern(Pattern::Dither);
+    let cases = [
+        (
+            noise.clone(),
+            "1404ac688bfad41db5f0ae3b87e884f2aefb3b833e62afdd034a781e758bad19",
+        ),
+        (
+            noise.clone().with_seed(7),
+            "313b2a06c68638316c57473fbe535908e5bf8bc84230fa8bb8383f19befc5367",
+        ),
+        (
+            CoverBuilder::new(31, 17).with_pattern(Pattern::Noise),
+            "469f6022f885e744b53be6955af144633262b18a8ce534cb439ba9b054095d2e",
+        ),
+        (
+            CoverBuilder::new(16, 16),
+            "789a0a2cba97f193f858638bb8d980e85d62d39a2a681e5b4c29585cd19046a8",
+        ),
+        (
+            CoverBuilder::new(31, 17),
+            "26b9c0e8303558c00819b523a58bf8a8a893919cae32ac59eb87a0f692003df2",
+        ),
+        (
+            CoverBuilder::new(16, 16).with_pattern(Pattern::Flat([12, 34, 56])),
+            "46650a034752fc448c9e1fafc4b67fc6540e7565c87b8c6aa4921853d36f1979",
+        ),
+        (
+            dither.clone(),
+            "48fd505e34028a345b2c3265bfa0b10bab3a3db86d0da69e514e9ff15e333801",
+        ),
+        (
+            dither.with_seed(5),
+            "056400f419c71ff8925fa2a9e66633963133f75005d6d3ed3510156dcaf9046b",
+        ),
+        (
+            CoverBuilder::new(16, 16).with_mask(Mask::Checker { cell: 4 }),
+            "6bdc0232cf0dd8d2dd41e6f2ae82998dfdef37c26d0d40456f6ad64f99f3dd27",
+        ),
+        (
+            CoverBuilder::new(16, 16).with_mask(Mask::Holes { percent: 30 }),
+            "a125d6ce55a7128d1275e7b46c90fc7ad67a06c92a6ff9dd0de834cab36427a1",
+        ),
+        (
+            CoverBuilder::new(16, 16).with_mask(Mask::Transparent),
+            "228e4e85eb14bd3d5e2be6df435c239d9806f518c44fd445b3db171a93222045",
+        ),
+        (
+            noise
+                .with_seed(3)
+                .with_overlay(4, 4, 8, 8, Pattern::Flat([0, 0, 0]))
+                .with_overlay(10, 0, 20, 6, Pattern::Gradient)
+                .with_mask(Mask::Holes { percent: 10 }),
+            "57aefa82b76d7228398f6589479dbef62eb3765f4fdc36594b97a3a0e9ac8337",
+        ),
+    ];
+    for (i, (cover, expected)) in cases.iter().enumerate() {
+        assert_eq!(&digest(cover), expected, "case {}", i);
+    }
+}
+
+#[test]
+fn patterns_are_what_they_say() {
+    // The gradient is the cover the CLI tests have always used
+    let gradient = CoverBuilder::new(32, 32).build();
+    assert_eq!(gradient.get_pixel(3, 5).0, [24, 40, 128, 255]);
+    assert!(gradient
+        .pixels()
+        .all(|p| p.0[..3].iter().all(|s| s & 1 == 0)));
+
+    let noise = CoverBuilder::new(32, 32).with_pattern(Pattern::Noise);
+    assert_eq!(noise.build(), noise.build());
+    assert_ne!(noise.build(), noise.clone().with_seed(1).build());
+    let noise = noise.build();
+    let odd = noise
+        .pixels()
+        .flat_map(|p| &p.0[..3])
+        .filter(|s| *s & 1 == 1)
+        .count();
+    assert!((1300..=1800).contains(&odd), "{}", odd);
+
+    let dither = CoverBuilder::new(32, 32)
+        .with_pattern(Pattern::Dither)
+        .build();
+    assert!(dither
+        .pixels()
+        .all(|p| matches!(p.0, [0, 0, 0, 255] | [255, 255, 255, 255])));
+
+    // Overlays are clipped, masks only touch alpha
+    let composite = CoverBuilder::new(8, 8)
+        .with_overlay(6, 6, 10, 10, Pattern::Flat([1, 2, 3]))
+        .with_mask(Mask::Checker { cell: 2 })
+        .build();
+    assert_eq!(composite.get_pixel(7, 7).0, [1, 2, 3, 255]);
+    assert_eq!(composite.get_pixel(5, 7).0[..3], [160, 224, 128]);
+    assert_eq!(composite.get_pixel(2, 0).0[3], 0);
+}
@@ -0,0 +1,164 @@
+mod common;
+
+use common::pngsecret;
+use image::codecs::webp::WebPEncoder;
+use pngsecret::testing::{CoverBuilder, Pattern};
+
+/// The lossless VP8L chunk of a still WebP of `img`, fourcc and size included
+fn vp8l_chunk(img: &image::RgbaImage) -> Vec<u8> {
+    let mut still = Vec::new();
+    WebPEncoder::new_lossless(&mut still)
+        .encode(
+            img,
+            img.width(),
+            img.height(),
+            image::ExtendedColorType::Rgba8,
+        )
+        .unwrap();
+    assert_eq!(&still[12..16], b"VP8L");
+    still[12..].to_vec()
+}
+
+fn chunk(fourcc: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = [fourcc.as_slice(), &(data.len() as u32).to_le_bytes(), data].concat();
+    if data.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+/// An animated WebP of `frames`, all as large as the first
+fn animated_webp(frames: &[image::RgbaImage]) -> Vec<u8> {
+    let (width, height) = frames[0].dimensions();
+    let le24 = |value: u32| value.to_le_bytes()[..3].to_vec();
+    let mut vp8x = vec![0x02 | 0x10, 0, 0, 0];
+    vp8x.extend(le24(width - 1));
+    vp8x.extend(le24(height - 1));
+    let mut body = b"WEBP".to_vec();
+    body.extend(chunk(b"VP8X", &vp8x));
+    body.extend(chunk(b"ANIM", &[0, 0, 0, 0, 0, 0]));
+    for frame in frames {
+        let mut anmf = [
+            le24(0),
+            le24(0),
+            le24(width - 1),
+            le24(height - 1),
+            le24(100),
+        ]
+        .concat();
+        anmf.push(0);
+        anmf.extend(vp8l_chunk(frame));
+        body.extend(chunk(b"ANMF", &anmf));
+    }
+    [
+        b"RIFF".as_slice(),
+        &(body.len() as u32).to_le_bytes(),
+        &body,
+    ]
+    .concat()
+}
+
+/// An ISO base media file that only gets as far as its `ftyp` box
+fn ftyp(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+    let size = 16 + 4 * compatible.len() as u32;
+    let mut file = [&size.to_be_bytes(), b"ftyp", major.as_slice(), &[0; 4]].concat();
+    compatible
+        .iter()
+        .for_each(|brand| file.extend_from_slice(*brand));
+    file
+}
+
+#[test]
+fn heif_and_avif_covers_are_refused_by_name() {
+    let dir = tempfile::tempdir().unwrap();
+    for (name, bytes, format) in [
+        (
+            "photo.heic",
+            ftyp(b"heic", &[b"mif1", b"heic"]),
+            "HEIF/HEIC",
+        ),
+        ("photo.jpg", ftyp(b"mif1", &[b"heic"]), "HEIF/HEIC"),
+        ("photo.avif", ftyp(b"avif", &[b"mif1", b"miaf"]), "AVIF"),
+    ] {
+        let path = dir.path().join(name);
+        std::fs::write(&path, bytes).unwrap();
+        for args in [
+            vec!["-s", "-e", "--text", "x", "-i", path.to_str().unwrap()],
+            vec!["-s", "-i", path.to_str().unwrap()],
+            vec!["-s", "doctor", "-i", path.to_str().unwrap()],
+        ] {
+            let out = pngsecret(&args);
+            assert_eq!(out.status.code(), Some(2), "{:?}", out);
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            assert!(
+                stderr.contains(&format!(
+                    "is in the {} format, which pngsecret doesn't take as a cover; convert it \
+                     losslessly to PNG first\n",
+                    format
+                )),
+                "{}",
+                stderr
+            );
+        }
+    }
+}
+
+#[test]
+fn animated_webp_covers_need_a_frame() {
+    let dir = tempfile::tempdir().unwrap();
+    let frames: Vec<_> = (0..2)
+        .map(|seed| {
+            CoverBuilder::new(32, 32)
+                .with_pattern(Pattern::Noise)
+                .with_seed(seed)
+                .build()
+        })
+        .collect();
+    let cover = dir.path().join("animated.webp");
+    std::fs::write(&cover, animated_webp(&frames)).unwrap();
+    let cover = cover.to_str().unwrap();
+
+    let out = pngsecret(&["-s", "-e", "--text", "x", "-i", cover]);
+    assert_eq!(out.status.code(), Some(2), "{:?}", out);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains(
+            "is in the animated WebP format, which pngsecret doesn't take as a cover; convert it \
+             losslessly to PNG first, or pick one of its 2 frames with --frame 0 to 1\n"
+        ),
+        "{}",
+        stderr
+    );
+    let out = pngsecret(&["-s", "-e", "--text", "x", "--frame", "2", "-i", cover]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("past the 2 frames"));
+
+    let stego = dir.path().join("frame.png");
+    let stego = stego.to_str().unwrap();
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--text",
+        "second frame",
+        "--frame",
+        "1",
+        "-i",
+        cover,
+        "-o",
+        stego,
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    let saved = image::open(stego).unwrap().into_rgba8();
+    let changed = saved
+        .iter()
+        .zip(frames[1].iter())
+        .filter(|(a, b)| a != b)
+        .count();
+    assert!(changed < 200, "{}", changed);
+    let out = pngsecret(&["-s", "-i", stego]);
+    assert_eq!(out.stdout, b"second frame", "{:?}", out);
+
+    // A still image has no frame to pick
+    let out = pngsecret(&["-s", "--frame", "0", "-i", stego]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+}
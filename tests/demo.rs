@@ -0,0 +1,29 @@
+mod common;
+
+use common::pngsecret;
+
+#[test]
+fn demo_round_trips_and_prints_every_command() {
+    let out = pngsecret(&["demo", "--keep"]);
+    assert_eq!(out.status.code(), Some(0), "{:?}", out);
+    assert_eq!(
+        out.stdout,
+        b"Demo passed: the payload came back byte for byte\n"
+    );
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("[5/5] Compare"), "{}", stderr);
+    assert_eq!(stderr.matches("  $ pngsecret ").count(), 2, "{}", stderr);
+    let kept = stderr
+        .lines()
+        .find_map(|line| line.strip_prefix("Kept the demo files in "))
+        .unwrap();
+    let kept = std::path::Path::new(kept);
+    for name in ["cover.png", "payload.txt", "stego.png", "decoded.txt"] {
+        assert!(kept.join(name).is_file(), "{}", name);
+    }
+    std::fs::remove_dir_all(kept).unwrap();
+
+    let out = pngsecret(&["-s", "demo"]);
+    assert_eq!(out.status.code(), Some(0), "{:?}", out);
+    assert!(!String::from_utf8_lossy(&out.stderr).contains("[1/5]"));
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use common::{pngsecret, write_noise_cover};
+use std::path::Path;
+
+/// Embed a short text into `cover` with `extra`, LSB replacement so every change is a flipped LSB
+fn embed(cover: &Path, stego: &Path, extra: &[&str]) {
+    let mut args = vec![
+        "-s",
+        "-y",
+        "-e",
+        "--embedding",
+        "replace",
+        "--text",
+        "where did the bits go",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego.to_str().unwrap(),
+    ];
+    args.extend_from_slice(extra);
+    let out = pngsecret(&args);
+    assert!(out.status.success(), "{:?}", out);
+}
+
+#[test]
+fn diff_counts_and_draws_the_flipped_bits() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_noise_cover(dir.path(), 32, 32);
+    let stego = dir.path().join("stego.png");
+    let drawing = dir.path().join("diff.png");
+    embed(&cover, &stego, &[]);
+
+    let before = image::open(&cover).unwrap().into_rgba8();
+    let after = image::open(&stego).unwrap().into_rgba8();
+    let flipped = before
+        .as_raw()
+        .iter()
+        .zip(after.as_raw())
+        .filter(|(a, b)| (*a ^ *b) & 1 == 1)
+        .count();
+    let pixels = before
+        .pixels()
+        .zip(after.pixels())
+        .filter(|(a, b)| a != b)
+        .count();
+
+    let out = pngsecret(&[
+        "diff",
+        cover.to_str().unwrap(),
+        stego.to_str().unwrap(),
+        "--out",
+        drawing.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(
+        stdout.starts_with(&format!(
+            "{} of 4096 channels modified, in {} of 1024 pixels",
+            flipped, pixels
+        )),
+        "{}",
+        stdout
+    );
+    // Sequential embedding starts in the top left corner
+    assert!(stdout.contains("within x 0..=31, y 0..="), "{}", stdout);
+
+    let drawing = image::open(&drawing).unwrap().into_rgba8();
+    let red = drawing
+        .pixels()
+        .filter(|pixel| pixel.0 == [255, 0, 0, 255])
+        .count();
+    assert_eq!(red, pixels);
+    assert_eq!(
+        drawing.get_pixel(31, 31).0[0],
+        drawing.get_pixel(31, 31).0[2]
+    );
+}
+
+#[test]
+fn diff_shows_permuted_payloads_spread_out() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_noise_cover(dir.path(), 32, 32);
+    let stego = dir.path().join("stego.png");
+    embed(&cover, &stego, &["--permute", "subpixels", "--seed", "5"]);
+
+    let out = pngsecret(&["diff", cover.to_str().unwrap(), stego.to_str().unwrap()]);
+    assert!(out.status.success(), "{:?}", out);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let bottom: u32 = stdout
+        .split("y 0..=")
+        .nth(1)
+        .and_then(|rest| rest.trim().parse().ok())
+        .unwrap_or_else(|| panic!("{}", stdout));
+    assert!(bottom > 16, "{}", stdout);
+}
+
+#[test]
+fn diff_refuses_images_of_different_sizes() {
+    let dir = tempfile::tempdir().unwrap();
+    let small = write_noise_cover(dir.path(), 16, 16);
+    let other = dir.path().join("small.png");
+    std::fs::rename(&small, &other).unwrap();
+    let large = write_noise_cover(dir.path(), 32, 32);
+
+    let out = pngsecret(&["diff", other.to_str().unwrap(), large.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+    assert!(out.stdout.is_empty());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("16x16 and 32x32"), "{}", stderr);
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use common::{pngsecret, write_cover, write_noise_cover};
+
+fn doctor(path: &str) -> String {
+    let output = pngsecret(&["-s", "doctor", "-i", path]);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn clean_image_looks_untouched() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let report = doctor(cover.to_str().unwrap());
+    assert!(
+        report.contains("looks like an untouched cover"),
+        "{}",
+        report
+    );
+    assert!(report.contains("\"found\": false"));
+}
+
+#[test]
+fn stego_image_is_reported_decodable() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    pngsecret(&[
+        "-s",
+        "-e",
+        "--text",
+        "hello doctor",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego,
+    ]);
+    let report = doctor(stego);
+    assert!(
+        report.contains("A message of 12 bytes was found"),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn resized_stego_image_is_suspected() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_noise_cover(dir.path(), 64, 64);
+    let stego = dir.path().join("stego.png");
+    // Replacing draws no random bits, so the resized LSBs are the same on every run
+    pngsecret(&[
+        "-s",
+        "-e",
+        "--embedding",
+        "replace",
+        "--text",
+        "this payload will not survive resizing",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego.to_str().unwrap(),
+    ]);
+    let resized = dir.path().join("resized.png");
+    image::open(&stego)
+        .unwrap()
+        .resize_exact(48, 48, image::imageops::FilterType::Triangle)
+        .into_rgba8()
+        .save(&resized)
+        .unwrap();
+    let report = doctor(resized.to_str().unwrap());
+    assert!(report.contains("resized"), "{}", report);
+}
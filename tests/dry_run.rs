@@ -0,0 +1,40 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+
+#[test]
+fn dry_runs_report_the_fit_without_writing() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let cover = cover.to_str().unwrap();
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    let run = |text: &str, extra: &[&str]| {
+        let mut args = vec!["-s", "-e", "--text", text, "-i", cover, "-o", stego];
+        args.extend(extra);
+        pngsecret(&args)
+    };
+
+    let out = run("hello", &["--dry-run"]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "all slot: carrier 512 B, encoded payload 18 B (5 B payload, 13 B overhead), headroom \
+         494 B\n"
+    );
+    assert!(!std::path::Path::new(stego).exists());
+
+    // 32x32 RGBA holds 499 payload bytes, and the dry run agrees with the real write
+    let fits = "x".repeat(499);
+    let too_large = "x".repeat(500);
+    let out = run(&fits, &["--dry-run"]);
+    assert!(String::from_utf8_lossy(&out.stdout).ends_with("headroom 0 B\n"));
+    assert!(out.status.success(), "{:?}", out);
+    let out = run(&too_large, &["--dry-run"]);
+    assert_eq!(out.status.code(), Some(3), "{:?}", out);
+    assert!(String::from_utf8_lossy(&out.stdout).ends_with("1 B over capacity\n"));
+    assert!(!std::path::Path::new(stego).exists());
+
+    assert!(run(&fits, &[]).status.success());
+    assert_eq!(run(&too_large, &["-y"]).status.code(), Some(3));
+}
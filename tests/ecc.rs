@@ -0,0 +1,85 @@
+mod common;
+
+use common::{pngsecret, write_noise_cover};
+use rand::seq::index::sample;
+use rand::SeedableRng;
+use std::path::{Path, PathBuf};
+
+/// The corrected header, then the 1010 framed bytes of the text in 4 blocks of 223 data bytes
+/// and one of 118, each with 32 parity bytes
+const ENCODED_BYTES: usize = 15 + 4 * 255 + 118 + 32;
+
+/// A 1000 byte text embedded with `--ecc 32`, which repairs 16 bytes per block
+fn embed(dir: &Path) -> (String, PathBuf) {
+    let cover = write_noise_cover(dir, 64, 64);
+    let stego = dir.join("stego.png");
+    let text: String = (0..1000).map(|i| (b'a' + (i % 26) as u8) as char).collect();
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--ecc",
+        "32",
+        "--text",
+        &text,
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    (text, stego)
+}
+
+/// Flip the LSB of the subpixels `subpixels` picks from those holding the encoded bytes
+fn flip(stego: &Path, subpixels: impl FnOnce(&mut rand_chacha::ChaCha8Rng) -> Vec<usize>) {
+    let mut img = image::open(stego).unwrap().into_rgba8();
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(7);
+    for subpixel in subpixels(&mut rng) {
+        img.as_mut()[subpixel] ^= 1;
+    }
+    img.save(stego).unwrap();
+}
+
+#[test]
+fn flipped_bits_are_corrected() {
+    for flipped in [1, 12, 30] {
+        let dir = tempfile::tempdir().unwrap();
+        let (text, stego) = embed(dir.path());
+        flip(&stego, |rng| {
+            sample(rng, ENCODED_BYTES * 8, flipped).into_vec()
+        });
+
+        let out = pngsecret(&["-i", stego.to_str().unwrap()]);
+        assert!(out.status.success(), "{} flipped: {:?}", flipped, out);
+        assert_eq!(out.stdout, text.as_bytes(), "{} flipped", flipped);
+        // Bits flipped in the same byte, or in the header, count once or not at all
+        let stderr = String::from_utf8(out.stderr).unwrap();
+        let repaired: usize = stderr
+            .split("Error correction repaired ")
+            .nth(1)
+            .and_then(|rest| rest.split(' ').next())
+            .and_then(|count| count.parse().ok())
+            .unwrap_or_else(|| panic!("{} flipped: {}", flipped, stderr));
+        assert!(repaired <= flipped && repaired * 2 >= flipped, "{}", stderr);
+    }
+}
+
+#[test]
+fn damage_past_the_parity_is_uncorrectable() {
+    let dir = tempfile::tempdir().unwrap();
+    let (_, stego) = embed(dir.path());
+    // One bit in each of 17 bytes of the first block
+    flip(&stego, |_| {
+        (0..17).map(|byte| (15 + byte * 3) * 8).collect()
+    });
+
+    let out = pngsecret(&["-s", "-i", stego.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(14), "{:?}", out);
+    assert!(out.stdout.is_empty());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        stderr.starts_with("The message is uncorrectable: block 0"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::{pngsecret, write_noise_cover};
+use std::path::Path;
+
+const MESSAGE: &str = "moved by one either way, not overwritten";
+
+/// The stego image of `cover` embedded with `extra`, and its subpixels less those of `cover`
+fn embed(cover: &Path, stego: &Path, extra: &[&str]) -> (Vec<u8>, Vec<i16>) {
+    let mut args = vec![
+        "-s",
+        "-y",
+        "-e",
+        "--text",
+        MESSAGE,
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego.to_str().unwrap(),
+    ];
+    args.extend_from_slice(extra);
+    let out = pngsecret(&args);
+    assert!(out.status.success(), "{:?}", out);
+
+    let out = pngsecret(&["-s", "-i", stego.to_str().unwrap()]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(out.stdout, MESSAGE.as_bytes());
+
+    let before = image::open(cover).unwrap().into_rgba8().into_raw();
+    let after = image::open(stego).unwrap().into_rgba8().into_raw();
+    let deltas = before
+        .iter()
+        .zip(&after)
+        .map(|(before, after)| *after as i16 - *before as i16)
+        .collect();
+    (after, deltas)
+}
+
+#[test]
+fn matching_moves_both_ways_and_replace_keeps_the_high_bits() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_noise_cover(dir.path(), 32, 32);
+    let stego = dir.path().join("stego.png");
+
+    let (_, deltas) = embed(&cover, &stego, &["--seed", "5"]);
+    assert!(deltas.contains(&1) && deltas.contains(&-1), "{:?}", deltas);
+    assert!(deltas.iter().all(|delta| delta.abs() <= 1));
+
+    let before = image::open(&cover).unwrap().into_rgba8().into_raw();
+    let (after, _) = embed(&cover, &stego, &["--embedding", "replace"]);
+    assert!(before.iter().zip(&after).all(|(a, b)| a >> 1 == b >> 1));
+}
+
+#[test]
+fn seeded_matching_repeats_itself() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_noise_cover(dir.path(), 32, 32);
+    let stego = dir.path().join("stego.png");
+    let (first, _) = embed(&cover, &stego, &["--seed", "11"]);
+    let (again, _) = embed(&cover, &stego, &["--seed", "11"]);
+    assert_eq!(first, again);
+    let (other, _) = embed(&cover, &stego, &["--seed", "12"]);
+    assert_ne!(first, other);
+}
@@ -0,0 +1,103 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{pngsecret, write_noise_cover};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+fn stego(dir: &Path, name: &str, text: &str) -> String {
+    let cover = write_noise_cover(dir, 32, 32);
+    let output = dir.join(name);
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        output.to_str().unwrap(),
+        "--text",
+        text,
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    output.to_str().unwrap().to_string()
+}
+
+/// A hook copying the payload into `copies` under the source's file name, logging each call
+fn copy_script(dir: &Path, copies: &Path) -> PathBuf {
+    let script = dir.join("copy.sh");
+    fs::write(
+        &script,
+        format!(
+            "#!/bin/sh\necho \"$1\" >> '{copies}/calls'\ncp \"$1\" '{copies}'/\"$(basename \"$2\")\".payload\n",
+            copies = copies.display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    script
+}
+
+#[test]
+fn hook_runs_once_per_extracted_image() {
+    let dir = tempfile::tempdir().unwrap();
+    let copies = dir.path().join("copies");
+    fs::create_dir(&copies).unwrap();
+    let script = copy_script(dir.path(), &copies);
+    let script = script.to_str().unwrap();
+    let first = stego(dir.path(), "first.png", "first payload");
+    let second = stego(dir.path(), "second.png", "second payload");
+
+    let shell = format!("{} {{payload_path}} {{source}}", script);
+    let out = pngsecret(&["-s", "-i", &first, "--exec-on-success", &shell]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(out.stdout, b"first payload");
+    let argv = serde_json::to_string(&[script, "{payload_path}", "{source}"]).unwrap();
+    let out = pngsecret(&["-s", "-i", &second, "--exec-on-success", &argv]);
+    assert!(out.status.success(), "{:?}", out);
+
+    assert_eq!(
+        fs::read(copies.join("first.png.payload")).unwrap(),
+        b"first payload"
+    );
+    assert_eq!(
+        fs::read(copies.join("second.png.payload")).unwrap(),
+        b"second payload"
+    );
+    let calls = fs::read_to_string(copies.join("calls")).unwrap();
+    assert_eq!(calls.lines().count(), 2);
+    for temp in calls.lines() {
+        assert!(!Path::new(temp).exists(), "{} was left behind", temp);
+    }
+}
+
+#[test]
+fn keep_temp_and_failing_hooks() {
+    let dir = tempfile::tempdir().unwrap();
+    let copies = dir.path().join("copies");
+    fs::create_dir(&copies).unwrap();
+    let script = copy_script(dir.path(), &copies);
+    let input = stego(dir.path(), "kept.png", "kept payload");
+
+    let hook = format!("{} {{payload_path}} {{source}}", script.display());
+    let out = pngsecret(&[
+        "-s",
+        "-i",
+        &input,
+        "--exec-on-success",
+        &hook,
+        "--keep-temp",
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    let calls = fs::read_to_string(copies.join("calls")).unwrap();
+    let kept = Path::new(calls.trim());
+    assert_eq!(fs::read(kept).unwrap(), b"kept payload");
+    assert_eq!(fs::metadata(kept).unwrap().permissions().mode() & 0o077, 0);
+    fs::remove_file(kept).unwrap();
+
+    let out = pngsecret(&["-s", "-i", &input, "--exec-on-success", "exit 3"]);
+    assert_eq!(out.status.code(), Some(11));
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("exited with status 3"), "{}", stderr);
+}
@@ -0,0 +1,208 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+use image::RgbaImage;
+
+#[test]
+fn exit_codes_follow_the_documented_table() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let cover = cover.to_str().unwrap();
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+
+    let status = |args: &[&str]| pngsecret(args).status.code();
+    assert_eq!(status(&["-s", "-e", "-i", cover, "-o", stego]), Some(0));
+    assert_eq!(status(&["-s", "-i", stego]), Some(0));
+    assert_eq!(status(&["-s", "-i", "missing.png"]), Some(2));
+    let long = "x".repeat(1000);
+    assert_eq!(
+        status(&["-s", "-e", "--text", &long, "-i", cover, "-o", stego]),
+        Some(3)
+    );
+    assert_eq!(
+        status(&[
+            "-s",
+            "-e",
+            "--text",
+            "Hello World",
+            "--max-payload",
+            "1B",
+            "-i",
+            cover,
+            "-o",
+            stego
+        ]),
+        Some(3)
+    );
+    assert_eq!(
+        status(&[
+            "-s",
+            "-e",
+            "--max-payload",
+            "1.5K",
+            "-i",
+            cover,
+            "-o",
+            stego
+        ]),
+        Some(1)
+    );
+    let gated = dir.path().join("gated.png");
+    let gated = gated.to_str().unwrap();
+    assert_eq!(
+        status(&[
+            "-s",
+            "-e",
+            "--max-detectability",
+            "0.01",
+            "-i",
+            cover,
+            "-o",
+            gated
+        ]),
+        Some(7)
+    );
+    assert!(!std::path::Path::new(gated).exists());
+    assert_eq!(
+        status(&[
+            "-s",
+            "-e",
+            "--max-detectability",
+            "0.5",
+            "-i",
+            cover,
+            "-o",
+            gated
+        ]),
+        Some(0)
+    );
+
+    let noisy = dir.path().join("noisy.png");
+    RgbaImage::from_pixel(8, 8, image::Rgba([255, 255, 255, 255]))
+        .save(&noisy)
+        .unwrap();
+    assert_eq!(status(&["-s", "-i", noisy.to_str().unwrap()]), Some(4));
+}
+
+#[test]
+fn help_lists_exit_codes() {
+    let help = String::from_utf8(pngsecret(&["--help"]).stdout).unwrap();
+    assert!(help.contains("EXIT CODES"));
+    assert!(help.contains("no_message"));
+}
+
+#[test]
+fn help_keeps_the_about_text_of_every_command() {
+    for (args, about) in [
+        (
+            &["--help"][..],
+            "A simple tool to embed secret bytes to png images",
+        ),
+        (
+            &["wipe", "--help"],
+            "Remove the pixel payload or the chunk notice from an image",
+        ),
+        (
+            &["cat", "--help"],
+            "Write the payloads of several images to stdout back to back",
+        ),
+    ] {
+        let help = String::from_utf8(pngsecret(args).stdout).unwrap();
+        assert!(help.contains(about), "{}", help);
+    }
+}
+
+#[test]
+fn wizard_refuses_without_a_terminal() {
+    let out = pngsecret(&["wizard"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(out.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--help"));
+}
+
+#[test]
+fn replacing_outputs_needs_yes_without_a_terminal() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let cover = cover.to_str().unwrap();
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    let encode = |text: &str, extra: &[&str]| {
+        let mut args = vec!["-s", "-e", "--text", text, "-i", cover, "-o", stego];
+        args.extend(extra);
+        pngsecret(&args)
+    };
+
+    assert!(encode("first", &[]).status.success());
+    let before = std::fs::read(stego).unwrap();
+    let out = encode("second", &[]);
+    assert_eq!(out.status.code(), Some(10));
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("without keeping a backup"), "{}", stderr);
+    assert!(stderr.contains("--yes"), "{}", stderr);
+    assert_eq!(std::fs::read(stego).unwrap(), before);
+
+    assert!(encode("second", &["--yes"]).status.success());
+    let out = pngsecret(&["-s", "-i", stego]);
+    assert_eq!(out.stdout, b"second");
+
+    let out = pngsecret(&["-s", "wipe", "--backend", "pixel", "-i", stego, "-o", stego]);
+    assert_eq!(out.status.code(), Some(1), "the output is the input");
+    let wiped = dir.path().join("wiped.png");
+    std::fs::write(&wiped, b"keep me").unwrap();
+    let wiped = wiped.to_str().unwrap();
+    let out = pngsecret(&["-s", "wipe", "--backend", "pixel", "-i", stego, "-o", wiped]);
+    assert_eq!(out.status.code(), Some(10));
+    assert_eq!(std::fs::read(wiped).unwrap(), b"keep me");
+    let out = pngsecret(&[
+        "-s",
+        "-y",
+        "wipe",
+        "--backend",
+        "pixel",
+        "-i",
+        stego,
+        "-o",
+        wiped,
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+}
+
+#[test]
+fn outputs_linked_to_the_input_are_refused_even_with_force() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let before = std::fs::read(&cover).unwrap();
+    let linked = dir.path().join("linked.png");
+    std::fs::hard_link(&cover, &linked).unwrap();
+    let mut outputs = vec![linked];
+    #[cfg(unix)]
+    {
+        let symlink = dir.path().join("symlink.png");
+        std::os::unix::fs::symlink(&cover, &symlink).unwrap();
+        outputs.push(symlink);
+    }
+    for output in &outputs {
+        let out = pngsecret(&[
+            "-s",
+            "-e",
+            "--force",
+            "--text",
+            "abc",
+            "-i",
+            cover.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+        ]);
+        assert_eq!(out.status.code(), Some(1), "{:?}", out);
+        assert_eq!(std::fs::read(&cover).unwrap(), before);
+    }
+
+    // Without an extension that names an image, the whole name is kept
+    let archive = dir.path().join("archive.tar.gz");
+    std::fs::copy(&cover, &archive).unwrap();
+    let out = pngsecret(&["-s", "-e", "--text", "abc", "-i", archive.to_str().unwrap()]);
+    assert!(out.status.success(), "{:?}", out);
+    assert!(dir.path().join("archive.tar.gz.enc.png").exists());
+}
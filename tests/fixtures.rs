@@ -0,0 +1,49 @@
+mod common;
+
+use common::pngsecret;
+use serde_json::Value;
+use std::fs;
+
+#[test]
+fn generated_catalog_decodes_through_the_cli() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = pngsecret(&["-s", "gen-fixtures", dir.path().to_str().unwrap()]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+
+    let mut sidecars = 0;
+    for entry in fs::read_dir(dir.path()).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|e| e != "json") {
+            continue;
+        }
+        sidecars += 1;
+        let sidecar: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        let stego = dir.path().join(sidecar["stego"].as_str().unwrap());
+        assert!(dir.path().join(sidecar["cover"].as_str().unwrap()).exists());
+        let mut args = vec!["-s", "-i", stego.to_str().unwrap()];
+        args.extend(
+            sidecar["decode_args"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|a| a.as_str().unwrap()),
+        );
+        let decoded = pngsecret(&args);
+        assert!(decoded.status.success(), "{:?}", path);
+        let expected = sidecar["payload"].as_str().unwrap();
+        assert_eq!(String::from_utf8(decoded.stdout).unwrap(), expected);
+    }
+    assert!(sidecars >= 5);
+}
+
+#[test]
+fn gen_fixtures_is_hidden_from_help() {
+    let help = pngsecret(&["--help"]);
+    assert!(!String::from_utf8(help.stdout)
+        .unwrap()
+        .contains("gen-fixtures"));
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+
+const MESSAGE: &str = "rendered covers keep their lowest bits at zero";
+
+#[test]
+fn flat_lsb_covers_are_warned_about_and_padded_to_capacity() {
+    let dir = tempfile::tempdir().unwrap();
+    // Every LSB of the gradient is zero
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let args = [
+        "-y",
+        "-e",
+        "--text",
+        MESSAGE,
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego.to_str().unwrap(),
+    ];
+    let out = pngsecret(&args);
+    assert!(out.status.success(), "{:?}", out);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("100% of the lowest color bits of the cover are 0"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("--pad-to-capacity"), "{}", stderr);
+
+    let padded = ["--pad-to-capacity", "--embedding", "replace"];
+    let out = pngsecret(&[&args[..], &padded[..]].concat());
+    assert!(out.status.success(), "{:?}", out);
+    let after = image::open(&stego).unwrap().into_rgba8();
+    let ones = after.iter().filter(|sample| *sample & 1 == 1).count();
+    assert!(
+        (after.len() * 2 / 5..after.len() * 3 / 5).contains(&ones),
+        "{} of {}",
+        ones,
+        after.len()
+    );
+    let out = pngsecret(&["-s", "-i", stego.to_str().unwrap()]);
+    assert_eq!(out.stdout, MESSAGE.as_bytes());
+
+    // Decoded as legacy, the zero LSBs of the clean cover are no message rather than an empty one
+    let out = pngsecret(&["-s", "--legacy", "-i", cover.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(4), "{:?}", out);
+    let empty = ["-s", "-y", "-e", "--legacy", "--text", ""];
+    let out = pngsecret(&[&empty[..], &args[4..]].concat());
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+use std::process::{Command, Output};
+
+fn with_injection(spec: &str, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pngsecret"))
+        .args(args)
+        .env("PNGSECRET_INJECT_FAILURE", spec)
+        .env_remove("PNGSECRET_STATS_FILE")
+        .output()
+        .unwrap()
+}
+
+/// Exit code and error line, without the parenthesized sizes injected errors carry as zeros
+#[cfg(feature = "failure-injection")]
+fn shape(out: &Output) -> (Option<i32>, String) {
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    let mut line = String::new();
+    let mut depth = 0;
+    for c in stderr.lines().next().unwrap_or_default().chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            c if depth == 0 => line.push(c),
+            _ => {}
+        }
+    }
+    (out.status.code(), line)
+}
+
+#[cfg(feature = "failure-injection")]
+#[test]
+fn injected_failures_look_like_organic_ones() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let cover = cover.to_str().unwrap();
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    let encode =
+        |text: &str| ["-s", "-e", "--text", text, "-i", cover, "-o", stego].map(String::from);
+
+    let (too_long, short) = (encode(&"x".repeat(4000)), encode("short"));
+    let organic = pngsecret(&too_long.each_ref().map(String::as_str));
+    let injected = with_injection("1:capacity_exceeded", &short.each_ref().map(String::as_str));
+    assert_eq!(organic.status.code(), Some(3), "{:?}", organic);
+    assert_eq!(shape(&injected), shape(&organic));
+}
+
+#[cfg(feature = "failure-injection")]
+#[test]
+fn only_the_nth_operation_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let mut parts = Vec::new();
+    for (i, text) in ["one ", "two ", "three"].iter().enumerate() {
+        let part = dir.path().join(format!("part{}.png", i));
+        let out = pngsecret(&[
+            "-s",
+            "-e",
+            "--text",
+            text,
+            "-i",
+            cover.to_str().unwrap(),
+            "-o",
+            part.to_str().unwrap(),
+        ]);
+        assert!(out.status.success(), "{:?}", out);
+        parts.push(part.to_str().unwrap().to_string());
+    }
+    let mut args = vec!["cat"];
+    args.extend(parts.iter().map(String::as_str));
+
+    let out = with_injection("2:io", &args);
+    assert_eq!(out.stdout, b"one three");
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("injected failure"));
+
+    let out = with_injection("4:io", &args);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(out.stdout, b"one two three");
+
+    let out = with_injection("two:io", &args);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("must be <n>:<code>"));
+}
+
+#[cfg(not(feature = "failure-injection"))]
+#[test]
+fn default_builds_ignore_the_variable() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    let out = with_injection(
+        "1:io",
+        &[
+            "-s",
+            "-e",
+            "--text",
+            "hi",
+            "-i",
+            cover.to_str().unwrap(),
+            "-o",
+            stego,
+        ],
+    );
+    assert!(out.status.success(), "{:?}", out);
+    let out = with_injection("1:io", &["-s", "-i", stego]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(out.stdout, b"hi");
+
+    let out = pngsecret(&["--version", "-v"]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("features: none\n"));
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+use std::path::Path;
+
+fn embed(cover: &Path, stego: &Path, extra: &[&str]) {
+    let mut args = vec![
+        "-s",
+        "-y",
+        "-e",
+        "--text",
+        "never printed",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego.to_str().unwrap(),
+    ];
+    args.extend(extra);
+    let out = pngsecret(&args);
+    assert!(out.status.success(), "{:?}", out);
+}
+
+#[test]
+fn genuine_payloads_are_described_not_printed() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let path = stego.to_str().unwrap();
+
+    embed(&cover, &stego, &[]);
+    let out = pngsecret(&["-s", "--inspect", "-i", path]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        format!(
+            "{}: payload bytes=13 method=lsb bits=1 compressed=no utf8=yes\n",
+            path
+        )
+    );
+
+    embed(&cover, &stego, &["--bits", "2", "--compress"]);
+    let out = pngsecret(&["-s", "--inspect", "-i", path]);
+    assert!(out.status.success(), "{:?}", out);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(
+        stdout.ends_with("method=lsb bits=2 compressed=yes utf8=yes\n"),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("never printed"));
+
+    embed(&cover, &stego, &["--method", "chunk"]);
+    let out = pngsecret(&["-s", "--inspect", "-i", path]);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(
+        stdout.contains("bytes=13 method=chunk bits=- "),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn clean_and_damaged_images_have_no_payload() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let out = pngsecret(&["-s", "--inspect", "-i", cover.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(4), "{:?}", out);
+    assert!(out.stdout.is_empty());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.starts_with("No payload detected"), "{}", stderr);
+
+    // One bit of the CRC in the frame header
+    let stego = dir.path().join("stego.png");
+    embed(&cover, &stego, &[]);
+    let mut img = image::open(&stego).unwrap().into_rgba8();
+    img.as_mut()[50] ^= 1;
+    img.save(&stego).unwrap();
+    let out = pngsecret(&["-s", "--inspect", "-i", stego.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(14), "{:?}", out);
+    assert!(out.stdout.is_empty());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.starts_with("No payload detected"), "{}", stderr);
+}
+
+#[test]
+fn directories_are_scanned_without_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let images = dir.path().join("images");
+    std::fs::create_dir(&images).unwrap();
+    let cover = write_cover(&images);
+    embed(&cover, &images.join("stego.png"), &[]);
+
+    let out = pngsecret(&["-s", "--inspect", "-i", images.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(15), "{:?}", out);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 1, "{}", stdout);
+    assert!(stdout.contains("stego.png: payload bytes=13"), "{}", stdout);
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::{pngsecret, validate, write_cover};
+use image::RgbaImage;
+use serde_json::Value;
+use std::process::Output;
+
+/// The single JSON line on stdout, checked against the `outcome` schema
+fn result_of(out: &Output, outcome: &str) -> Value {
+    let stdout = String::from_utf8(out.stdout.clone()).unwrap();
+    assert_eq!(stdout.lines().count(), 1, "{:?}", out);
+    let result: Value = serde_json::from_str(&stdout).unwrap();
+    let schema: Value = serde_json::from_str(include_str!("result.schema.json")).unwrap();
+    validate(&schema["$defs"][outcome], &result, outcome).unwrap();
+    result
+}
+
+#[test]
+fn encode_and_decode_print_one_json_object() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    let out = pngsecret(&[
+        "--json",
+        "-e",
+        "--text",
+        "for scripts",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego,
+    ]);
+    assert_eq!(out.status.code(), Some(0), "{:?}", out);
+    let encoded = result_of(&out, "encoded");
+    assert_eq!(encoded["output"], stego);
+    assert_eq!(encoded["payload_bytes"], 11);
+    assert_eq!(encoded["embedded_bytes"], 11);
+    // 32x32 RGBA at one bit, minus the frame header
+    assert_eq!(encoded["capacity_bytes"], 499);
+
+    let out = pngsecret(&["--json", "-i", stego]);
+    assert_eq!(out.status.code(), Some(0), "{:?}", out);
+    let decoded = result_of(&out, "decoded");
+    assert_eq!(decoded["payload_base64"], "Zm9yIHNjcmlwdHM=");
+    assert_eq!(decoded["utf8"], true);
+    assert_eq!(decoded["bytes"], 11);
+
+    let saved = dir.path().join("saved.txt");
+    let out = pngsecret(&["--json", "-i", stego, "-o", saved.to_str().unwrap()]);
+    assert_eq!(
+        result_of(&out, "decoded")["output"],
+        saved.to_str().unwrap()
+    );
+    assert_eq!(std::fs::read(&saved).unwrap(), b"for scripts");
+}
+
+#[test]
+fn failures_print_their_code_as_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let blank = dir.path().join("blank.png");
+    RgbaImage::new(16, 16).save(&blank).unwrap();
+    let out = pngsecret(&["--json", "-i", blank.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(4), "{:?}", out);
+    let failed = result_of(&out, "failed");
+    assert_eq!(failed["code"], "no_message");
+    assert_eq!(failed["exit_code"], 4);
+    assert!(!String::from_utf8_lossy(&out.stderr).is_empty());
+
+    let out = pngsecret(&["--json", "-i", "missing.png"]);
+    assert_eq!(out.status.code(), Some(2), "{:?}", out);
+    assert_eq!(result_of(&out, "failed")["code"], "input_unreadable");
+}
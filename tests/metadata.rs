@@ -0,0 +1,90 @@
+mod common;
+
+use common::{pngsecret, write_noise_cover};
+use flate2::write::ZlibEncoder;
+use std::io::Write;
+use std::path::Path;
+
+/// The chunks of the PNG at `path` as kind and data, walked without decoding the image
+fn chunks(path: &Path) -> Vec<([u8; 4], Vec<u8>)> {
+    let png = std::fs::read(path).unwrap();
+    let mut rest = &png[8..];
+    let mut chunks = Vec::new();
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        chunks.push((rest[4..8].try_into().unwrap(), rest[8..8 + length].to_vec()));
+        rest = &rest[12 + length..];
+    }
+    chunks
+}
+
+fn chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(data);
+    [
+        &(data.len() as u32).to_be_bytes()[..],
+        kind,
+        data,
+        &hasher.finalize().to_be_bytes(),
+    ]
+    .concat()
+}
+
+#[test]
+fn cover_metadata_survives_the_embedding() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_noise_cover(dir.path(), 32, 32);
+    let mut profile = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    profile.write_all(&[7; 128]).unwrap();
+    let icc = [&b"sRGB-ish\0\0"[..], &profile.finish().unwrap()].concat();
+    let comment = b"Comment\0taken at dawn".to_vec();
+    let phys = [0, 0, 0x0B, 0x13, 0, 0, 0x0B, 0x13, 1];
+
+    // Signature and IHDR, then the metadata, then the rest of the file
+    let png = std::fs::read(&cover).unwrap();
+    let (head, rest) = png.split_at(8 + 25);
+    let metadata = [
+        chunk(b"iCCP", &icc),
+        chunk(b"pHYs", &phys),
+        chunk(b"tEXt", &comment),
+        chunk(b"bKGD", &[0, 1, 0, 2, 0, 3]),
+    ]
+    .concat();
+    std::fs::write(&cover, [head, &metadata, rest].concat()).unwrap();
+
+    let stego = dir.path().join("stego.png");
+    let out = pngsecret(&[
+        "-e",
+        "-s",
+        "--text",
+        "metadata and all",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        stderr.contains("Warning: left out the bKGD chunk of the cover"),
+        "{}",
+        stderr
+    );
+
+    let kept = chunks(&stego);
+    for (kind, data) in [(b"iCCP", &icc[..]), (b"pHYs", &phys), (b"tEXt", &comment)] {
+        assert!(
+            kept.iter().any(|(k, d)| k == kind && d == data),
+            "{}",
+            String::from_utf8_lossy(kind)
+        );
+    }
+    assert!(kept.iter().all(|(kind, _)| kind != b"bKGD"));
+    let idat = kept.iter().position(|(kind, _)| kind == b"IDAT").unwrap();
+    assert!(kept[..idat].iter().any(|(kind, _)| kind == b"iCCP"));
+
+    let out = pngsecret(&["-s", "-i", stego.to_str().unwrap()]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(out.stdout, b"metadata and all");
+}
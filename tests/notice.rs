@@ -0,0 +1,81 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+
+const NOTICE: &str = "Contact legal@example.com";
+
+#[test]
+fn pixel_payload_and_notice_coexist_and_wipe_independently() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    pngsecret(&[
+        "-s",
+        "-e",
+        "--text",
+        "machine payload",
+        "--also-chunk-text",
+        NOTICE,
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego,
+    ]);
+
+    let decoded = pngsecret(&["-i", stego]);
+    assert_eq!(decoded.stdout, b"machine payload");
+    assert!(String::from_utf8_lossy(&decoded.stderr).contains(NOTICE));
+
+    let no_notice = dir.path().join("no_notice.png");
+    let no_notice = no_notice.to_str().unwrap();
+    pngsecret(&[
+        "-s",
+        "wipe",
+        "--backend",
+        "chunk",
+        "-i",
+        stego,
+        "-o",
+        no_notice,
+    ]);
+    let decoded = pngsecret(&["-i", no_notice]);
+    assert_eq!(decoded.stdout, b"machine payload");
+    assert!(!String::from_utf8_lossy(&decoded.stderr).contains(NOTICE));
+
+    let no_payload = dir.path().join("no_payload.png");
+    let no_payload = no_payload.to_str().unwrap();
+    pngsecret(&[
+        "-s",
+        "wipe",
+        "--backend",
+        "pixel",
+        "-i",
+        stego,
+        "-o",
+        no_payload,
+    ]);
+    let decoded = pngsecret(&["-i", no_payload]);
+    assert_eq!(decoded.stdout, b"");
+    let stderr = String::from_utf8_lossy(&decoded.stderr);
+    assert!(stderr.contains(NOTICE));
+    assert!(stderr.contains("doesn't have embedded message"));
+}
+
+#[test]
+fn notice_must_be_latin1() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let output = pngsecret(&[
+        "-e",
+        "--also-chunk-text",
+        "秘密",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego.to_str().unwrap(),
+    ]);
+    assert!(!stego.exists());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Latin-1"));
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use common::{pngsecret, write_noise_cover};
+
+const MESSAGE: &str = "starts wherever --offset says, not at the first pixel";
+
+#[test]
+fn offsets_roundtrip_and_wrap_around() {
+    let dir = tempfile::tempdir().unwrap();
+    // 4096 subpixels, the framed message takes 528 of them
+    let cover = write_noise_cover(dir.path(), 32, 32);
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    for offset in ["0", "1", "3900"] {
+        let out = pngsecret(&[
+            "-s",
+            "-y",
+            "-e",
+            "--text",
+            MESSAGE,
+            "--offset",
+            offset,
+            "-i",
+            cover.to_str().unwrap(),
+            "-o",
+            stego,
+        ]);
+        assert!(out.status.success(), "{:?}", out);
+
+        let out = pngsecret(&["-s", "--offset", offset, "-i", stego]);
+        assert!(out.status.success(), "{}: {:?}", offset, out);
+        assert_eq!(out.stdout, MESSAGE.as_bytes());
+        // Without one, the frame header is looked for at every offset
+        let out = pngsecret(&["-s", "-i", stego]);
+        assert!(out.status.success(), "{}: {:?}", offset, out);
+        assert_eq!(out.stdout, MESSAGE.as_bytes());
+
+        let wrong = if offset == "0" { "8" } else { "0" };
+        let out = pngsecret(&["-s", "--offset", wrong, "-i", stego]);
+        assert!(matches!(out.status.code(), Some(4 | 14)), "{:?}", out);
+        assert!(out.stdout.is_empty(), "{}", offset);
+    }
+}
+
+#[test]
+fn offsets_past_the_slot_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_noise_cover(dir.path(), 32, 32);
+    let stego = dir.path().join("stego.png");
+    for slot in [&["--slot", "all"][..], &["--slot", "rgb"]] {
+        let past = if slot[1] == "all" { "4096" } else { "3072" };
+        let mut args = vec!["-s", "-e", "--text", "abc", "--offset", past];
+        args.extend_from_slice(slot);
+        args.extend(["-i", cover.to_str().unwrap(), "-o", stego.to_str().unwrap()]);
+        let out = pngsecret(&args);
+        assert_eq!(out.status.code(), Some(1), "{:?}", out);
+        let stderr = String::from_utf8(out.stderr).unwrap();
+        assert!(stderr.contains("offset"), "{}", stderr);
+        assert!(!stego.exists());
+    }
+    let out = pngsecret(&["-s", "--offset", "4096", "-i", cover.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+}
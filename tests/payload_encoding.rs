@@ -0,0 +1,110 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+
+#[test]
+fn base64_text_embeds_binary_and_decodes_as_hex() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    // 00 ff 10 00 61 fe, NULs and bytes that aren't UTF-8
+    let out = pngsecret(&[
+        "-s",
+        "-y",
+        "-e",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego,
+        "--payload-encoding",
+        "base64",
+        "--text",
+        "AP8QAGH+",
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+
+    let out = pngsecret(&["-s", "-i", stego, "--payload-encoding", "hex"]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(out.stdout, b"00ff100061fe\n");
+    let out = pngsecret(&["-s", "-i", stego, "--payload-encoding", "base64"]);
+    assert_eq!(out.stdout, b"AP8QAGH+\n", "{:?}", out);
+    let out = pngsecret(&["-s", "-i", stego, "--raw"]);
+    assert_eq!(out.stdout, b"\x00\xff\x10\x00a\xfe", "{:?}", out);
+}
+
+#[test]
+fn invalid_text_is_refused_at_its_position() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    for (encoding, text, position) in [("base64", "AP8Q-GH+", 4), ("hex", "00ff1", 5)] {
+        let out = pngsecret(&[
+            "-s",
+            "-y",
+            "-e",
+            "-i",
+            cover.to_str().unwrap(),
+            "-o",
+            stego.to_str().unwrap(),
+            "--payload-encoding",
+            encoding,
+            "--text",
+            text,
+        ]);
+        assert_eq!(out.status.code(), Some(1), "{:?}", out);
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(
+            stderr.contains(&format!("valid {} at position {}", encoding, position)),
+            "{}",
+            stderr
+        );
+        assert!(!stego.exists());
+    }
+}
+
+#[test]
+fn escaped_text_embeds_control_bytes_and_decode_escapes_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    let out = pngsecret(&[
+        "-s",
+        "-y",
+        "-e",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego,
+        "--escape",
+        "--text",
+        r"one\ntwo\t\x1b[2J",
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+
+    let out = pngsecret(&["-s", "-i", stego, "--raw"]);
+    assert_eq!(out.stdout, b"one\ntwo\t\x1b[2J", "{:?}", out);
+    let out = pngsecret(&["-s", "-i", stego, "--format", "text"]);
+    assert_eq!(out.stdout, b"one\\ntwo\\t\\x1b[2J\n", "{:?}", out);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("pass --raw"), "{}", stderr);
+    let out = pngsecret(&["-s", "-i", stego, "--escape"]);
+    assert_eq!(out.stdout, b"one\\ntwo\\t\\x1b[2J\n", "{:?}", out);
+
+    let out = pngsecret(&[
+        "-s",
+        "-y",
+        "-e",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego,
+        "--escape",
+        "--text",
+        r"bad\q",
+    ]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("escape at position 3"), "{}", stderr);
+}
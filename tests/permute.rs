@@ -0,0 +1,93 @@
+mod common;
+
+use common::{pngsecret, write_noise_cover};
+
+const MESSAGE: &str = "scattered over the whole cover, not packed at the top";
+
+#[test]
+fn subpixel_permutation_roundtrips_only_with_its_seed() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_noise_cover(dir.path(), 32, 32);
+    let encode = |name: &str, keying: &[&str]| {
+        let stego = dir.path().join(name);
+        let mut args = vec!["-s", "-e", "--text", MESSAGE, "--permute", "subpixels"];
+        args.extend_from_slice(keying);
+        args.extend_from_slice(&["-i", cover.to_str().unwrap(), "-o", stego.to_str().unwrap()]);
+        let out = pngsecret(&args);
+        assert!(out.status.success(), "{:?}", out);
+        stego.to_str().unwrap().to_string()
+    };
+    let decode = |stego: &str, keying: &[&str]| {
+        let mut args = vec!["-s", "--permute", "subpixels", "-i", stego];
+        args.extend_from_slice(keying);
+        pngsecret(&args)
+    };
+
+    let seeded = encode("seeded.png", &["--seed", "1234"]);
+    let out = decode(&seeded, &["--seed", "1234"]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(out.stdout, MESSAGE.as_bytes());
+    for wrong in [&["--seed", "1235"][..], &["--key", "1234"]] {
+        let out = decode(&seeded, wrong);
+        assert!(matches!(out.status.code(), Some(4 | 14)), "{:?}", out);
+        assert!(out.stdout.is_empty(), "{:?}", wrong);
+    }
+    let out = pngsecret(&["-s", "-i", &seeded]);
+    assert!(out.stdout.is_empty(), "{:?}", out);
+
+    let keyed = encode("keyed.png", &["--key", "correct horse"]);
+    let out = decode(&keyed, &["--key", "correct horse"]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(out.stdout, MESSAGE.as_bytes());
+    let out = decode(&keyed, &["--key", "correct horsf"]);
+    assert!(out.stdout.is_empty(), "{:?}", out);
+
+    let out = decode(&seeded, &[]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+    let out = decode(&seeded, &["--seed", "1", "--key", "1"]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+}
+
+#[test]
+fn the_top_of_the_image_is_not_where_the_payload_is() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_noise_cover(dir.path(), 64, 64);
+    let encode = |name: &str, permute: &str| {
+        let stego = dir.path().join(name);
+        let out = pngsecret(&[
+            "-s",
+            "-e",
+            "--text",
+            MESSAGE,
+            "--permute",
+            permute,
+            "--seed",
+            "7",
+            "-i",
+            cover.to_str().unwrap(),
+            "-o",
+            stego.to_str().unwrap(),
+        ]);
+        assert!(out.status.success(), "{:?}", out);
+        image::open(stego).unwrap().into_rgba8().into_raw()
+    };
+    let cover = image::open(&cover).unwrap().into_rgba8().into_raw();
+    let changed = |stego: &[u8]| -> Vec<usize> {
+        cover
+            .iter()
+            .zip(stego)
+            .enumerate()
+            .filter(|(_, (before, after))| before != after)
+            .map(|(i, _)| i)
+            .collect()
+    };
+
+    // The frame and the message take this many subpixels from the start when sequential
+    let bits = (MESSAGE.len() + 13) * 8;
+    let sequential = changed(&encode("sequential.png", "none"));
+    assert!(sequential.iter().all(|&i| i < bits));
+    let shuffled = changed(&encode("shuffled.png", "subpixels"));
+    let at_the_top = shuffled.iter().filter(|&&i| i < bits).count();
+    assert!(at_the_top < sequential.len() / 4, "{}", at_the_top);
+    assert!(shuffled.iter().any(|&i| i >= cover.len() * 3 / 4));
+}
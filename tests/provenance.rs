@@ -0,0 +1,123 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+use std::process::{Command, Output};
+
+const CI_VARIABLES: [&str; 7] = [
+    "GITHUB_SHA",
+    "CI_COMMIT_SHA",
+    "GIT_COMMIT",
+    "GITHUB_RUN_ID",
+    "CI_PIPELINE_ID",
+    "BUILD_ID",
+    "SOURCE_DATE_EPOCH",
+];
+
+fn with_env(env: &[(&str, &str)], args: &[&str]) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_pngsecret"));
+    for variable in CI_VARIABLES {
+        command.env_remove(variable);
+    }
+    command
+        .args(args)
+        .envs(env.iter().copied())
+        .env_remove("PNGSECRET_STATS_FILE")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn stamps_take_the_build_from_the_ci_environment() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let cover = cover.to_str().unwrap();
+    let stamped = dir.path().join("cover.stamped.png");
+
+    let out = with_env(&[], &["provenance", "stamp", "-i", cover]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("GITHUB_SHA"));
+
+    let env = [
+        ("GITHUB_SHA", "0123456789abcdef0123456789abcdef01234567"),
+        ("GITHUB_RUN_ID", "9001"),
+        ("SOURCE_DATE_EPOCH", "1700000000"),
+    ];
+    let out = with_env(&env, &["provenance", "stamp", "-i", cover]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(out.stdout, format!("{}\n", stamped.display()).as_bytes());
+
+    let out = pngsecret(&[
+        "--time-format",
+        "rfc3339-utc",
+        "provenance",
+        "read",
+        "-i",
+        stamped.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "commit: 0123456789abcdef0123456789abcdef01234567\nbuild id: 9001\n\
+         time: 2023-11-14T22:13:20Z\nsigned by: nobody\n"
+    );
+    let out = pngsecret(&["provenance", "read", "-i", cover]);
+    assert_eq!(out.status.code(), Some(4), "{:?}", out);
+}
+
+#[test]
+fn signed_stamps_are_checked_against_the_public_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let key = dir.path().join("release.key");
+    let out = pngsecret(&["receipt", "keygen", key.to_str().unwrap()]);
+    assert!(out.status.success(), "{:?}", out);
+    let public_key = String::from_utf8(out.stdout).unwrap().trim().to_string();
+
+    let stamp = |name: &str, sign: bool| {
+        let output = dir.path().join(name);
+        let mut args = vec![
+            "provenance",
+            "stamp",
+            "--commit",
+            "abc123",
+            "--build-id",
+            "nightly",
+            "-i",
+            cover.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+        ];
+        if sign {
+            args.extend_from_slice(&["--sign-key", key.to_str().unwrap()]);
+        }
+        let out = with_env(&[], &args);
+        assert!(out.status.success(), "{:?}", out);
+        output.to_str().unwrap().to_string()
+    };
+    let read = |stamped: &str, public_key: &str| {
+        pngsecret(&[
+            "provenance",
+            "read",
+            "-i",
+            stamped,
+            "--public-key",
+            public_key,
+        ])
+    };
+
+    let signed = stamp("signed.png", true);
+    let out = read(&signed, &public_key);
+    assert!(out.status.success(), "{:?}", out);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(
+        stdout.contains("commit: abc123\n") && stdout.ends_with(&format!("by: {}\n", public_key)),
+        "{}",
+        stdout
+    );
+    let out = read(&signed, &"0".repeat(64));
+    assert_eq!(out.status.code(), Some(5), "{:?}", out);
+
+    let unsigned = stamp("unsigned.png", false);
+    let out = read(&unsigned, &public_key);
+    assert_eq!(out.status.code(), Some(5), "{:?}", out);
+}
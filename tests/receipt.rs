@@ -0,0 +1,98 @@
+mod common;
+
+use common::{pngsecret, write_noise_cover};
+use std::fs;
+
+const PAYLOAD: &str = "stamped for the customer";
+
+#[test]
+fn receipt_verifies_until_the_file_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = dir.path().join("key");
+    let out = pngsecret(&["receipt", "keygen", key.to_str().unwrap()]);
+    assert!(out.status.success(), "{:?}", out);
+    let public = String::from_utf8(out.stdout).unwrap().trim().to_string();
+    assert_eq!(public.len(), 64);
+
+    let cover = write_noise_cover(dir.path(), 32, 32);
+    let output = dir.path().join("out.png");
+    let receipt = dir.path().join("receipt.json");
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        output.to_str().unwrap(),
+        "--text",
+        PAYLOAD,
+        "--receipt",
+        receipt.to_str().unwrap(),
+        "--sign-key",
+        key.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    let json = fs::read_to_string(&receipt).unwrap();
+    assert!(!json.contains(PAYLOAD), "{}", json);
+    assert!(json.contains(&public), "{}", json);
+
+    let verify = |extra: &[&str]| {
+        let mut args = vec![
+            "receipt",
+            "verify",
+            receipt.to_str().unwrap(),
+            "--file",
+            output.to_str().unwrap(),
+        ];
+        args.extend(extra);
+        pngsecret(&args)
+    };
+    let out = verify(&["--public-key", &public]);
+    assert!(out.status.success(), "{:?}", out);
+    assert!(String::from_utf8(out.stdout)
+        .unwrap()
+        .starts_with("Receipt OK"));
+
+    let body: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let created = body["receipt"]["created"].as_u64().unwrap();
+    assert!(body["receipt"]["created_utc"]
+        .as_str()
+        .unwrap()
+        .ends_with('Z'));
+    let out = pngsecret(&[
+        "--time-format",
+        "unix",
+        "receipt",
+        "verify",
+        receipt.to_str().unwrap(),
+        "--file",
+        output.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(
+        stdout.ends_with(&format!(" at {}\n", created)),
+        "{}",
+        stdout
+    );
+    let out = pngsecret(&[
+        "--time-format",
+        "%d.%m. %H:%M",
+        "receipt",
+        "verify",
+        receipt.to_str().unwrap(),
+        "--file",
+        output.to_str().unwrap(),
+    ]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("ambiguous"));
+
+    let mut stego = fs::read(&output).unwrap();
+    let last = stego.len() - 1;
+    stego[last] ^= 1;
+    fs::write(&output, stego).unwrap();
+    let out = verify(&[]);
+    assert_eq!(out.status.code(), Some(5));
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("is not the file"), "{}", stderr);
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use common::{pngsecret, write_noise_cover};
+
+#[test]
+fn named_records_roundtrip_and_list() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_noise_cover(dir.path(), 64, 64);
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    let body = "x".repeat(700);
+    let out = pngsecret(&[
+        "-s",
+        "-y",
+        "-e",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego,
+        "--text",
+        "A sunset",
+        "--name",
+        "caption",
+        "--text",
+        "",
+        "--name",
+        "empty",
+        "--text",
+        &body,
+        "--name",
+        "body",
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+
+    let out = pngsecret(&["-s", "-i", stego, "--list"]);
+    assert!(out.status.success(), "{:?}", out);
+    let listed = String::from_utf8(out.stdout).unwrap();
+    let lines: Vec<&str> = listed.lines().collect();
+    assert_eq!(
+        lines,
+        ["caption\t8 bytes", "empty\t0 bytes", "body\t700 bytes"]
+    );
+
+    let out = pngsecret(&["-s", "-i", stego, "--name", "caption"]);
+    assert_eq!(out.stdout, b"A sunset", "{:?}", out);
+    let out = pngsecret(&["-s", "-i", stego, "--name", "empty"]);
+    assert!(out.status.success(), "{:?}", out);
+    assert!(out.stdout.is_empty());
+    let out = pngsecret(&["-s", "-i", stego, "--name", "body"]);
+    assert_eq!(out.stdout, body.as_bytes(), "{:?}", out);
+    let out = pngsecret(&["-s", "-i", stego, "--name", "missing"]);
+    assert_eq!(out.status.code(), Some(4), "{:?}", out);
+    let out = pngsecret(&["-s", "-i", stego]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("caption, empty, body"));
+}
+
+#[test]
+fn records_need_a_name_each() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_noise_cover(dir.path(), 32, 32);
+    let stego = dir.path().join("stego.png");
+    let encode = |extra: &[&str]| {
+        let mut args = vec!["-s", "-y", "-e", "-i", cover.to_str().unwrap()];
+        args.extend_from_slice(&["-o", stego.to_str().unwrap()]);
+        args.extend_from_slice(extra);
+        pngsecret(&args)
+    };
+    for extra in [
+        &["--text", "a", "--text", "b"][..],
+        &["--text", "a", "--text", "b", "--name", "a"],
+        &["--text", "a", "--text", "b", "--name", "n", "--name", "n"],
+        &["--text", "a", "--name", "n", "--legacy"],
+    ] {
+        let out = encode(extra);
+        assert_eq!(out.status.code(), Some(1), "{:?}: {:?}", extra, out);
+        assert!(!stego.exists());
+    }
+
+    let out = encode(&["--text", "single"]);
+    assert!(out.status.success(), "{:?}", out);
+    let stego = stego.to_str().unwrap();
+    let out = pngsecret(&["-s", "-i", stego, "--list"]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+    let out = pngsecret(&["-s", "-i", stego, "--name", "single"]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+}
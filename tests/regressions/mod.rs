@@ -0,0 +1,138 @@
+//! Regression corpus for the reader, replayed by a single test
+//!
+//! Each case is either a raw LSB bitstream or a whole PNG file, both hex encoded so the corpus
+//! stays reviewable in diffs. Bitstreams are laid onto the subpixels of a synthetic image, any
+//! subpixel past the end of the stream gets its LSB set so it can't act as a terminator.
+//! Use [`regression_constant`] to turn a new crashing input into a case.
+
+use super::*;
+use image::RgbaImage;
+use pngsecret::byte_to_8bits;
+
+enum Input {
+    Bitstream(&'static str),
+    Png(&'static str),
+}
+
+#[derive(Debug, PartialEq)]
+enum Expected {
+    Payload(&'static [u8]),
+    NoMessage,
+}
+
+struct Case {
+    name: &'static str,
+    input: Input,
+    expected: Expected,
+}
+
+const CASES: &[Case] = &[
+    Case {
+        name: "declared_length_all_ones",
+        input: Input::Bitstream("ffffffff"),
+        expected: Expected::NoMessage,
+    },
+    Case {
+        name: "zero_length_payload_trailing_garbage",
+        input: Input::Bitstream("00deadbeef"),
+        expected: Expected::NoMessage,
+    },
+    Case {
+        name: "legacy_first_eight_lsbs_zero",
+        input: Input::Bitstream("00"),
+        expected: Expected::NoMessage,
+    },
+    Case {
+        name: "png_single_char_payload",
+        input: Input::Png(
+            "89504e470d0a1a0a0000000d4948445200000004000000010806000000f93c0fcd000000104944415478da\
+             6310101400010809040008a901030e411ffe0000000049454e44ae426082",
+        ),
+        expected: Expected::Payload(b"A"),
+    },
+];
+
+fn from_hex(hex: &str) -> Vec<u8> {
+    assert!(hex.len().is_multiple_of(2), "odd hex length");
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("invalid hex"))
+        .collect()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn bitstream_image(bytes: &[u8]) -> RgbaImage {
+    let subpixels = bytes.len() * 8 + 4;
+    let width = subpixels.div_ceil(4) as u32;
+    let mut img = RgbaImage::from_pixel(width, 1, image::Rgba([1, 1, 1, 1]));
+    for (subpixel, bit) in img.iter_mut().zip(bytes.iter().flat_map(byte_to_8bits)) {
+        *subpixel = 0x10 | bit;
+    }
+    img
+}
+
+/// Read without checksum verification, which would turn most cases into a missing message before
+/// the parsing they pin down is reached
+fn replay(img: RgbaImage) -> Result<Vec<u8>, pngsecret::Error> {
+    PngSecretReader::new(img, Box::new(NaiveDecoder::new()))
+        .with_verify(false)
+        .read_image()
+}
+
+fn load(input: &Input) -> RgbaImage {
+    match input {
+        Input::Bitstream(hex) => bitstream_image(&from_hex(hex)),
+        Input::Png(hex) => image::load_from_memory(&from_hex(hex))
+            .expect("corpus PNG must decode")
+            .into_rgba8(),
+    }
+}
+
+impl Expected {
+    fn matches(&self, actual: &Result<Vec<u8>, pngsecret::Error>) -> bool {
+        match (self, actual) {
+            (Expected::Payload(expected), Ok(payload)) => expected == payload,
+            (Expected::NoMessage, Err(pngsecret::Error::NoMessage { .. })) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Render a ready-to-paste corpus entry for a bitstream that misbehaved
+pub fn regression_constant(name: &str, bitstream: &[u8]) -> String {
+    let expected = match replay(bitstream_image(bitstream)) {
+        Ok(payload) => format!("Expected::Payload(&{:?})", payload),
+        Err(_) => "Expected::NoMessage".to_string(),
+    };
+    format!(
+        "    Case {{\n        name: {:?},\n        input: Input::Bitstream({:?}),\n        expected: {},\n    }},\n",
+        name,
+        to_hex(bitstream),
+        expected
+    )
+}
+
+#[test]
+fn replay_corpus() {
+    for case in CASES {
+        let actual = replay(load(&case.input));
+        assert!(
+            case.expected.matches(&actual),
+            "case {}: expected {:?}, got {:?}",
+            case.name,
+            case.expected,
+            actual
+        );
+    }
+}
+
+#[test]
+fn regression_constant_is_pasteable() {
+    assert_eq!(
+        regression_constant("hi", b"hi\0"),
+        "    Case {\n        name: \"hi\",\n        input: Input::Bitstream(\"686900\"),\n        expected: Expected::Payload(&[104, 105]),\n    },\n"
+    );
+}
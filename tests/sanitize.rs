@@ -0,0 +1,94 @@
+mod common;
+
+use common::{pngsecret, write_noise_cover};
+use std::fs;
+
+#[test]
+fn every_backend_is_removed() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_noise_cover(dir.path(), 32, 32);
+    let alpha = dir.path().join("alpha.bin");
+    fs::write(&alpha, b"hidden in the alpha channel").unwrap();
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--text",
+        "hidden in the colors",
+        "--alpha-payload",
+        alpha.to_str().unwrap(),
+        "--also-chunk-text",
+        "Property of Example Corp",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego,
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+
+    for lsb in ["randomize", "zero"] {
+        let clean = dir.path().join(format!("{}.png", lsb));
+        let clean = clean.to_str().unwrap();
+        let out = pngsecret(&["sanitize", "-i", stego, "-o", clean, "--lsb", lsb]);
+        assert!(out.status.success(), "{:?}", out);
+        assert_eq!(
+            String::from_utf8(out.stdout).unwrap(),
+            format!("{}\n", clean)
+        );
+        let report = String::from_utf8(out.stderr).unwrap();
+        assert!(report.contains("Removed the tEXt chunk"), "{}", report);
+        assert!(report.contains("in the Rgb slot"), "{}", report);
+        assert!(report.contains("in the Alpha slot"), "{}", report);
+
+        for slot in ["all", "rgb", "alpha"] {
+            let out = pngsecret(&["--format", "raw", "--slot", slot, "-i", clean]);
+            let stdout = String::from_utf8_lossy(&out.stdout);
+            assert!(!stdout.contains("hidden"), "{}: {}", slot, stdout);
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            assert!(!stderr.contains("Notice"), "{}", stderr);
+        }
+        let stego_pixels = image::open(stego).unwrap().into_rgba8();
+        let clean_pixels = image::open(clean).unwrap().into_rgba8();
+        assert!(stego_pixels
+            .iter()
+            .zip(clean_pixels.iter())
+            .all(|(a, b)| a >> 1 == b >> 1));
+    }
+}
+
+#[test]
+fn keeping_the_lsbs_of_a_stego_image_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_noise_cover(dir.path(), 32, 32);
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--text",
+        "still here",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego,
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+
+    let clean = dir.path().join("clean.png");
+    let out = pngsecret(&[
+        "-s",
+        "sanitize",
+        "--lsb",
+        "keep",
+        "-i",
+        stego,
+        "-o",
+        clean.to_str().unwrap(),
+    ]);
+    assert_eq!(out.status.code(), Some(5));
+    assert!(String::from_utf8(out.stderr)
+        .unwrap()
+        .contains("Traces remain"));
+    assert!(!clean.exists());
+}
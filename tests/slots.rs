@@ -0,0 +1,73 @@
+mod common;
+
+use common::{pngsecret, write_noise_cover};
+
+#[test]
+fn info_lists_every_slot_and_decode_indexes_into_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_noise_cover(dir.path(), 32, 32);
+    let first = dir.path().join("first.png");
+    let first = first.to_str().unwrap();
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    let repeated = "ab".repeat(300);
+
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--slot",
+        "alpha",
+        "--bits",
+        "2",
+        "--compress",
+        "--text",
+        &repeated,
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        first,
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--slot",
+        "rgb",
+        "--text",
+        "in rgb",
+        "--also-chunk-text",
+        "see the pixels",
+        "-i",
+        first,
+        "-o",
+        stego,
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+
+    let out = pngsecret(&["-s", "info", "-i", stego]);
+    assert!(out.status.success(), "{:?}", out);
+    let listed = String::from_utf8(out.stdout).unwrap();
+    let lines: Vec<&str> = listed.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", listed);
+    assert_eq!(
+        lines[0],
+        "#0 pixel rgb, 1 bit, 6 B at subpixel 138, codec naive"
+    );
+    assert!(
+        lines[1].starts_with("#1 pixel alpha, 2 bits, "),
+        "{}",
+        listed
+    );
+    assert!(lines[1].ends_with("codec deflate"), "{}", listed);
+    assert_eq!(lines[2], "-  chunk tEXt pngsecret-notice, 14 B");
+
+    let out = pngsecret(&["-s", "--slot", "0", "-i", stego]);
+    assert_eq!(out.stdout, b"in rgb", "{:?}", out);
+    let out = pngsecret(&["-s", "--slot", "1", "-i", stego]);
+    assert_eq!(out.stdout, repeated.as_bytes(), "{:?}", out);
+
+    let out = pngsecret(&["-s", "--slot", "2", "-i", stego]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+    let out = pngsecret(&["-s", "-e", "--slot", "0", "--text", "x", "-i", stego]);
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use common::{pngsecret, write_noise_cover};
+use std::path::{Path, PathBuf};
+
+/// Three 64x64 covers, each with its own noise
+fn covers(dir: &Path) -> Vec<PathBuf> {
+    ["a", "b", "c"]
+        .iter()
+        .map(|name| {
+            let cover = dir.join(format!("{}.png", name));
+            std::fs::rename(write_noise_cover(dir, 64, 64), &cover).unwrap();
+            cover
+        })
+        .collect()
+}
+
+/// `args` followed by `-i` and each of `inputs`
+fn with_inputs<'a>(args: &[&'a str], inputs: &'a [PathBuf]) -> Vec<&'a str> {
+    let mut args = args.to_vec();
+    for input in inputs {
+        args.extend(["-i", input.to_str().unwrap()]);
+    }
+    args
+}
+
+#[test]
+fn payloads_spread_over_three_images_come_back_in_any_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let covers = covers(dir.path());
+    // 30 KB of random letters, which deflate to more than any one of the images holds
+    let mut state: u32 = 0x9e37_79b9;
+    let text: String = (0..30_000)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (b'a' + (state % 26) as u8) as char
+        })
+        .collect();
+    let payload = text.as_bytes();
+    let file = dir.path().join("payload.txt");
+    std::fs::write(&file, payload).unwrap();
+
+    let out = pngsecret(&with_inputs(
+        &[
+            "-s",
+            "-e",
+            "--compress",
+            "--bits",
+            "4",
+            "--file",
+            file.to_str().unwrap(),
+        ],
+        &covers,
+    ));
+    assert!(out.status.success(), "{:?}", out);
+    let stegos: Vec<PathBuf> = ["c", "a", "b"]
+        .iter()
+        .map(|name| dir.path().join(format!("{}.enc.png", name)))
+        .collect();
+
+    let decoded = dir.path().join("decoded.txt");
+    let args = ["-s", "--bits", "4", "-o"];
+    let mut decode = with_inputs(&args, &stegos);
+    decode.insert(args.len(), decoded.to_str().unwrap());
+    let out = pngsecret(&decode);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(std::fs::read(&decoded).unwrap(), payload);
+
+    // Every image holds a part, none of them the whole
+    for stego in &stegos {
+        let out = pngsecret(&["-s", "--bits", "4", "-i", stego.to_str().unwrap()]);
+        assert_eq!(out.status.code(), Some(1), "{:?}", out);
+        let stderr = String::from_utf8(out.stderr).unwrap();
+        assert!(stderr.contains("is missing"), "{}", stderr);
+    }
+}
+
+#[test]
+fn missing_and_duplicated_segments_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let covers = covers(dir.path());
+    let text = "x".repeat(3000);
+    let out = pngsecret(&with_inputs(&["-s", "-e", "--text", &text], &covers));
+    assert!(out.status.success(), "{:?}", out);
+    let stegos: Vec<PathBuf> = ["a", "b", "c"]
+        .iter()
+        .map(|name| dir.path().join(format!("{}.enc.png", name)))
+        .collect();
+
+    let out = pngsecret(&with_inputs(&["-s"], &stegos));
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(out.stdout, text.as_bytes());
+
+    let out = pngsecret(&with_inputs(
+        &["-s"],
+        &[stegos[0].clone(), stegos[2].clone()],
+    ));
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+    assert!(out.stdout.is_empty());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("Segment 2 of 3 is missing"), "{}", stderr);
+
+    let twice = [stegos[1].clone(), stegos[0].clone(), stegos[1].clone()];
+    let out = pngsecret(&with_inputs(&["-s"], &twice));
+    assert_eq!(out.status.code(), Some(1), "{:?}", out);
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        stderr.contains("Segment 2 was given more than once"),
+        "{}",
+        stderr
+    );
+
+    // Too much for all three
+    let text = "x".repeat(7000);
+    let out = pngsecret(&with_inputs(&["-s", "-e", "-y", "--text", &text], &covers));
+    assert_eq!(out.status.code(), Some(3), "{:?}", out);
+}
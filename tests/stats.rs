@@ -0,0 +1,81 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+
+#[test]
+fn summarize_counts_runs() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let cover = cover.to_str().unwrap();
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    let stats = dir.path().join("usage.jsonl");
+    let stats = stats.to_str().unwrap();
+
+    pngsecret(&[
+        "-s",
+        "--stats-file",
+        stats,
+        "-e",
+        "--text",
+        "Hello World",
+        "-i",
+        cover,
+        "-o",
+        stego,
+    ]);
+    pngsecret(&[
+        "-s",
+        "--stats-file",
+        stats,
+        "-e",
+        "--text",
+        "Hello World",
+        "-i",
+        cover,
+        "-o",
+        stego,
+        "--max-payload",
+        "1B",
+    ]);
+    pngsecret(&["-s", "--stats-file", stats, "-i", stego]);
+    pngsecret(&["-s", "--stats-file", stats, "-i", "missing.png"]);
+
+    let recorded = std::fs::read_to_string(stats).unwrap();
+    assert_eq!(recorded.lines().count(), 4);
+    assert!(!recorded.contains("cover.png") && !recorded.contains("Hello World"));
+
+    let output = pngsecret(&["--stats-file", stats, "stats", "summarize"]);
+    let summary = String::from_utf8(output.stdout).unwrap();
+    let row = |name: &str| -> Vec<String> {
+        summary
+            .lines()
+            .find(|l| l.split_whitespace().next() == Some(name))
+            .unwrap_or_else(|| panic!("no {} row in {}", name, summary))
+            .split_whitespace()
+            .map(str::to_string)
+            .collect()
+    };
+    assert_eq!(row("encode")[1..3], ["2", "1"]);
+    assert_eq!(row("decode")[1..3], ["2", "1"]);
+    assert_eq!(row("max-payload")[1], "1");
+    assert_eq!(row("silent")[1], "4");
+}
+
+#[test]
+fn unwritable_stats_file_does_not_fail_the_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let output = pngsecret(&[
+        "--stats-file",
+        dir.path().to_str().unwrap(),
+        "-e",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego.to_str().unwrap(),
+    ]);
+    assert!(stego.exists());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Couldn't write statistics"));
+}
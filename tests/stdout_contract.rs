@@ -0,0 +1,116 @@
+//! stdout must only carry the payload (decode) or the output path (encode), everything else is
+//! expected on stderr
+
+mod common;
+
+use common::{pngsecret, write_cover};
+use image::RgbaImage;
+
+#[test]
+fn encode_prints_only_output_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let output = pngsecret(&[
+        "-e",
+        "--text",
+        "secret",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego.to_str().unwrap(),
+    ]);
+    assert_eq!(output.stdout, format!("{}\n", stego.display()).into_bytes());
+    assert!(!output.stderr.is_empty());
+    // Debug builds once dumped the parsed options, secrets and all
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("Opt {"), "{}", stderr);
+}
+
+#[test]
+fn encode_silent_prints_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let output = pngsecret(&[
+        "-s",
+        "-e",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego.to_str().unwrap(),
+    ]);
+    assert_eq!(output.stdout, b"");
+    assert!(stego.exists());
+}
+
+#[test]
+fn decode_prints_only_payload() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    pngsecret(&[
+        "-s",
+        "-e",
+        "--text",
+        "hidden words",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego,
+    ]);
+
+    assert_eq!(pngsecret(&["-i", stego]).stdout, b"hidden words");
+    assert_eq!(pngsecret(&["-s", "-i", stego]).stdout, b"hidden words");
+}
+
+#[test]
+fn failures_keep_stdout_empty() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing = dir.path().join("missing.png");
+    let output = pngsecret(&["-i", missing.to_str().unwrap()]);
+    assert_eq!(output.stdout, b"");
+    assert!(!output.stderr.is_empty());
+
+    // A cover full of set LSBs never yields a terminator
+    let noisy = dir.path().join("noisy.png");
+    RgbaImage::from_pixel(8, 8, image::Rgba([255, 255, 255, 255]))
+        .save(&noisy)
+        .unwrap();
+    let output = pngsecret(&["-i", noisy.to_str().unwrap()]);
+    assert_eq!(output.stdout, b"");
+    assert!(!output.stderr.is_empty());
+}
+
+#[test]
+fn binary_payloads_pipe_through_stdin_and_stdout() {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    let payload: Vec<u8> = (0..=255).collect();
+
+    let mut encode = Command::new(env!("CARGO_BIN_EXE_pngsecret"))
+        .args(["-s", "-e", "-i", cover.to_str().unwrap(), "-o", stego])
+        .env_remove("PNGSECRET_STATS_FILE")
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap();
+    encode.stdin.take().unwrap().write_all(&payload).unwrap();
+    assert!(encode.wait().unwrap().success());
+
+    for extra in [&[][..], &["--raw"]] {
+        let mut args = vec!["-i", stego];
+        args.extend(extra);
+        let output = pngsecret(&args);
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(output.stdout, payload);
+    }
+    let output = pngsecret(&["--format", "auto", "-i", stego]);
+    assert_eq!(output.status.code(), Some(6), "{:?}", output);
+    assert_eq!(output.stdout, b"");
+}
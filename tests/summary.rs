@@ -0,0 +1,194 @@
+mod common;
+
+use common::{pngsecret, validate, write_cover, write_noise_cover};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+
+/// Run pngsecret with `--summary-json`, returning the summary after checking it against the schema
+fn summary_of(dir: &Path, args: &[&str]) -> Value {
+    let path = dir.join("summary.json");
+    let _ = fs::remove_file(&path);
+    let mut all = vec!["--summary-json", path.to_str().unwrap()];
+    all.extend(args);
+    let out = pngsecret(&all);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    let last_line = stderr.lines().last().unwrap_or_default();
+    assert!(last_line.starts_with("Summary: "), "{:?}", out);
+
+    let summary: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+    let schema = include_str!("summary.schema.json");
+    validate(&serde_json::from_str(schema).unwrap(), &summary, "summary").unwrap();
+    assert_eq!(
+        summary["processed"],
+        summary["succeeded"].as_u64().unwrap()
+            + summary["skipped"].as_u64().unwrap()
+            + summary["failed"].as_u64().unwrap()
+    );
+    let started = &summary["started"];
+    let rfc3339 = started["rfc3339"].as_str().unwrap();
+    assert!(rfc3339.ends_with('Z'), "{}", rfc3339);
+    assert_eq!(
+        chrono::DateTime::parse_from_rfc3339(rfc3339)
+            .unwrap()
+            .timestamp(),
+        started["unix"].as_i64().unwrap()
+    );
+    assert!(
+        last_line.starts_with(&format!("Summary: {}: ", summary["mode"].as_str().unwrap())),
+        "{}",
+        last_line
+    );
+    summary
+}
+
+#[test]
+fn every_mode_ends_with_the_same_summary() {
+    let dir = tempfile::tempdir().unwrap();
+    let d = dir.path();
+    let cover = write_cover(d);
+    let cover = cover.to_str().unwrap();
+    let noise = write_noise_cover(d, 32, 32);
+    let noise = noise.to_str().unwrap();
+    let stego = d.join("stego.png");
+    let stego = stego.to_str().unwrap();
+    let payload = d.join("payload.bin");
+    fs::write(&payload, [b'x'; 600]).unwrap();
+    let payload = payload.to_str().unwrap();
+
+    // The JSON stays in UTC whatever the humans get to read
+    let encode = summary_of(
+        d,
+        &[
+            "-y",
+            "--time-format",
+            "local",
+            "-e",
+            "--text",
+            "hello",
+            "-i",
+            cover,
+            "-o",
+            stego,
+        ],
+    );
+    assert_eq!(encode["mode"], "encode");
+    assert_eq!(
+        (encode["processed"].as_u64(), encode["succeeded"].as_u64()),
+        (Some(1), Some(1))
+    );
+    assert_eq!(encode["bytes_in"], fs::metadata(cover).unwrap().len());
+    assert_eq!(encode["bytes_out"], fs::metadata(stego).unwrap().len());
+
+    let trace = d.join("trace.bin");
+    fs::write(&trace, b"PST\x01").unwrap();
+    let trace = trace.to_str().unwrap();
+    let diff = summary_of(d, &["trace", "diff", trace, trace]);
+    assert_eq!(diff["mode"], "trace");
+
+    let truncated = d.join("truncated.png");
+    let truncated_path = truncated.to_str().unwrap();
+    let truncated = summary_of(
+        d,
+        &[
+            "-y",
+            "-e",
+            "--truncate-to-fit",
+            "--file",
+            payload,
+            "-i",
+            noise,
+            "-o",
+            truncated_path,
+        ],
+    );
+    assert_eq!(truncated["warnings"], json!({ "truncated": 1 }));
+    let decode_truncated = summary_of(d, &["-i", truncated_path]);
+    assert_eq!(decode_truncated["warnings"], json!({ "truncated": 1 }));
+
+    let decode = summary_of(d, &["-i", stego]);
+    assert_eq!(decode["mode"], "decode");
+    assert_eq!(decode["bytes_out"], 5);
+
+    let missing = summary_of(d, &["-i", noise]);
+    assert_eq!(
+        (missing["succeeded"].as_u64(), missing["failed"].as_u64()),
+        (Some(0), Some(1))
+    );
+
+    let cat = summary_of(d, &["cat", "--skip-missing", stego, noise, stego]);
+    assert_eq!(cat["mode"], "cat");
+    let counts = ["processed", "succeeded", "skipped", "failed"].map(|k| cat[k].as_u64().unwrap());
+    assert_eq!(counts, [3, 2, 1, 0]);
+    assert_eq!(cat["bytes_out"], 10);
+
+    let manifest = d.join("manifest.json");
+    let images = json!({ "images": [
+        { "path": "stego.png", "payload": "hello" },
+        { "path": "noise.png" },
+    ]});
+    fs::write(&manifest, images.to_string()).unwrap();
+    let verify = summary_of(
+        d,
+        &["verify-archive", "--manifest", manifest.to_str().unwrap()],
+    );
+    let counts = ["processed", "succeeded", "failed"].map(|k| verify[k].as_u64().unwrap());
+    assert_eq!(counts, [2, 1, 1]);
+
+    let sanitized = d.join("sanitized.png");
+    let sanitize = summary_of(
+        d,
+        &[
+            "-y",
+            "sanitize",
+            "-i",
+            stego,
+            "-o",
+            sanitized.to_str().unwrap(),
+        ],
+    );
+    assert_eq!(
+        sanitize["bytes_out"],
+        fs::metadata(&sanitized).unwrap().len()
+    );
+
+    let wiped = d.join("wiped.png");
+    let wipe = summary_of(
+        d,
+        &[
+            "-y",
+            "wipe",
+            "-i",
+            stego,
+            "-o",
+            wiped.to_str().unwrap(),
+            "--backend",
+            "pixel",
+        ],
+    );
+    assert_eq!(wipe["mode"], "wipe");
+
+    let doctor = summary_of(d, &["doctor", "-i", stego]);
+    assert_eq!(doctor["mode"], "doctor");
+
+    let sweep = summary_of(d, &["sweep", "-i", cover, "--payload", payload]);
+    assert_eq!(sweep["bytes_in"], fs::metadata(cover).unwrap().len() + 600);
+}
+
+#[test]
+fn silent_runs_still_write_the_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let path = dir.path().join("summary.json");
+    let out = pngsecret(&[
+        "-s",
+        "--summary-json",
+        path.to_str().unwrap(),
+        "doctor",
+        "-i",
+        cover.to_str().unwrap(),
+    ]);
+    assert!(out.stderr.is_empty(), "{:?}", out);
+    let summary: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+    assert_eq!(summary["mode"], "doctor");
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+use serde_json::Value;
+use std::fs;
+
+#[test]
+fn sweep_reports_one_row_per_configuration() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let payload = dir.path().join("payload.bin");
+    fs::write(&payload, "p".repeat(300)).unwrap();
+
+    let out = pngsecret(&[
+        "-s",
+        "sweep",
+        "-i",
+        cover.to_str().unwrap(),
+        "--payload",
+        payload.to_str().unwrap(),
+    ]);
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let (table, json) = stdout.split_once("\nJSON:\n").unwrap();
+    assert_eq!(table.lines().count(), 2 + 4, "{}", table);
+
+    let report: Value = serde_json::from_str(json).unwrap();
+    assert_eq!(report["downscaled"], false);
+    let rows = report["rows"].as_array().unwrap();
+    assert_eq!(rows.len(), 4);
+    let row = |slot: &str, order: &str| {
+        rows.iter()
+            .find(|r| r["slot"] == slot && r["order"] == order)
+            .unwrap()
+    };
+    // 32x32 holds 511 bytes in all four channels but only 383 in rgb
+    assert_eq!(row("all", "sequential")["capacity_bytes"], 511);
+    assert_eq!(row("rgb", "blocks")["capacity_bytes"], 383);
+    for r in rows {
+        assert_eq!(r["fits"], true);
+        assert!(r["psnr"].as_f64().unwrap() > 40.0);
+        assert!(r["ssim"].as_f64().unwrap() > 0.9);
+    }
+    let utilization = |r: &Value| r["utilization"].as_f64().unwrap();
+    assert!(utilization(row("rgb", "sequential")) > utilization(row("all", "sequential")));
+}
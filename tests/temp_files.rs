@@ -0,0 +1,110 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{pngsecret, write_cover};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn entries(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+fn with_tmpdir(tmpdir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pngsecret"))
+        .args(args)
+        .env("TMPDIR", tmpdir)
+        .env_remove("PNGSECRET_STATS_FILE")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn saves_leave_no_temp_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let out_dir = dir.path().join("out");
+    fs::create_dir(&out_dir).unwrap();
+    let encode = |output: &Path| {
+        pngsecret(&[
+            "-s",
+            "-e",
+            "--yes",
+            "--text",
+            "staged",
+            "-i",
+            cover.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+        ])
+    };
+
+    let stego = out_dir.join("stego.png");
+    let out = encode(&stego);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(entries(&out_dir), ["stego.png"]);
+
+    // The rename over a directory fails after the PNG was written in full
+    let blocked = out_dir.join("blocked.png");
+    fs::create_dir(&blocked).unwrap();
+    fs::write(blocked.join("keep"), b"").unwrap();
+    let out = encode(&blocked);
+    assert_eq!(out.status.code(), Some(8), "{:?}", out);
+    assert_eq!(entries(&out_dir), ["blocked.png", "stego.png"]);
+
+    let message = out_dir.join("message.txt");
+    let out = pngsecret(&[
+        "-s",
+        "-i",
+        stego.to_str().unwrap(),
+        "-o",
+        message.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(fs::read(&message).unwrap(), b"staged");
+    assert_eq!(
+        entries(&out_dir),
+        ["blocked.png", "message.txt", "stego.png"]
+    );
+}
+
+#[test]
+fn hooks_leave_no_temp_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let out = pngsecret(&[
+        "-s",
+        "-e",
+        "--text",
+        "hooked",
+        "-i",
+        cover.to_str().unwrap(),
+        "-o",
+        stego.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{:?}", out);
+
+    let tmpdir = dir.path().join("tmp");
+    fs::create_dir(&tmpdir).unwrap();
+    for (hook, code) in [("test -s {payload_path}", 0), ("exit 3", 11)] {
+        let out = with_tmpdir(
+            &tmpdir,
+            &[
+                "-s",
+                "-i",
+                stego.to_str().unwrap(),
+                "--exec-on-success",
+                hook,
+            ],
+        );
+        assert_eq!(out.status.code(), Some(code), "{}: {:?}", hook, out);
+        assert!(entries(&tmpdir).is_empty(), "{}", hook);
+    }
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use common::{pngsecret, write_noise_cover};
+use std::path::Path;
+
+fn indices(path: &Path) -> Vec<u64> {
+    let bytes = std::fs::read(path).unwrap();
+    assert_eq!(&bytes[..4], b"PST\x01");
+    bytes[4..]
+        .chunks_exact(8)
+        .map(|index| u64::from_be_bytes(index.try_into().unwrap()))
+        .collect()
+}
+
+#[test]
+fn decode_traces_match_the_encode_trace() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_noise_cover(dir.path(), 24, 24);
+    let stego = dir.path().join("stego.png");
+    let stego = stego.to_str().unwrap();
+    let layouts: [&[&str]; 3] = [
+        &["--permute", "blocks", "--block-size", "5", "--seed", "3"],
+        &["--permute", "subpixels", "--key", "k", "--slot", "rgb"],
+        &[
+            "--permute",
+            "blocks",
+            "--block-size",
+            "2",
+            "--seed",
+            "9",
+            "--skip-alpha",
+            "--bits",
+            "2",
+        ],
+    ];
+    for layout in layouts {
+        let encoded = dir.path().join("encoded.bin");
+        let decoded = dir.path().join("decoded.bin");
+        let mut args = vec!["-s", "-y", "-e", "--text", "interop", "-i"];
+        args.extend_from_slice(&[cover.to_str().unwrap(), "-o", stego, "--trace-indices"]);
+        args.push(encoded.to_str().unwrap());
+        args.extend_from_slice(layout);
+        let out = pngsecret(&args);
+        assert!(out.status.success(), "{:?}: {:?}", layout, out);
+        assert_eq!(indices(&encoded).len(), (13 + 7) * 8, "{:?}", layout);
+
+        let mut args = vec![
+            "-s",
+            "-i",
+            stego,
+            "--trace-indices",
+            decoded.to_str().unwrap(),
+        ];
+        args.extend_from_slice(&["--trace-bits", "200"]);
+        args.extend_from_slice(layout);
+        let out = pngsecret(&args);
+        assert_eq!(out.stdout, b"interop", "{:?}: {:?}", layout, out);
+        assert_eq!(indices(&decoded).len(), 200);
+        assert_eq!(indices(&decoded)[..160], indices(&encoded));
+
+        let out = pngsecret(&[
+            "trace",
+            "diff",
+            encoded.to_str().unwrap(),
+            decoded.to_str().unwrap(),
+        ]);
+        assert!(out.status.success(), "{:?}: {:?}", layout, out);
+        assert_eq!(
+            out.stdout,
+            b"The traces agree on all 160 bits they share (a has 160 bits, b has 200)\n"
+        );
+    }
+
+    // Reading with the wrong seed walks other subpixels from the first bit on
+    let encoded = dir.path().join("encoded.bin");
+    let decoded = dir.path().join("decoded.bin");
+    let out = pngsecret(&[
+        "-s",
+        "-i",
+        stego,
+        "--permute",
+        "blocks",
+        "--block-size",
+        "2",
+        "--seed",
+        "10",
+        "--skip-alpha",
+        "--bits",
+        "2",
+        "--trace-indices",
+        decoded.to_str().unwrap(),
+    ]);
+    assert!(!out.status.success());
+    let out = pngsecret(&[
+        "trace",
+        "diff",
+        encoded.to_str().unwrap(),
+        decoded.to_str().unwrap(),
+    ]);
+    assert_eq!(out.status.code(), Some(5), "{:?}", out);
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("First divergence at bit "), "{}", stderr);
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use common::{pngsecret, write_cover};
+
+#[test]
+fn verbosity_levels_add_detail_and_silent_removes_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let cover = write_cover(dir.path());
+    let stego = dir.path().join("stego.png");
+    let encode = |level: &[&str]| {
+        let mut args = level.to_vec();
+        args.extend_from_slice(&[
+            "-e",
+            "--yes",
+            "--text",
+            "levels",
+            "-i",
+            cover.to_str().unwrap(),
+            "-o",
+            stego.to_str().unwrap(),
+        ]);
+        let out = pngsecret(&args);
+        assert!(out.status.success(), "{:?}", out);
+        String::from_utf8(out.stderr).unwrap()
+    };
+
+    let silent = encode(&["-s"]);
+    assert!(!silent.contains("Image width"), "{}", silent);
+    let plain = encode(&[]);
+    assert!(plain.contains("message length limit"), "{}", plain);
+    assert!(!plain.contains("embedded"), "{}", plain);
+    let detailed = encode(&["-vv"]);
+    assert!(detailed.contains("embedded"), "{}", detailed);
+
+    let out = pngsecret(&["-vv", "-i", stego.to_str().unwrap()]);
+    assert_eq!(out.stdout, b"levels");
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        stderr.contains("Image width") && stderr.contains("extracted"),
+        "{}",
+        stderr
+    );
+}
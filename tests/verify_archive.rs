@@ -0,0 +1,109 @@
+mod common;
+
+use common::pngsecret;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+
+/// Generate the fixture catalog into `dir` and return manifest entries for all of it
+fn healthy_entries(dir: &Path) -> Vec<Value> {
+    let out = pngsecret(&["-s", "gen-fixtures", dir.to_str().unwrap()]);
+    assert!(out.status.success());
+    let mut entries: Vec<Value> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "json"))
+        .map(|path| {
+            let sidecar: Value = serde_json::from_slice(&fs::read(path).unwrap()).unwrap();
+            json!({
+                "path": sidecar["stego"],
+                "payload": sidecar["payload"],
+                "decode_args": sidecar["decode_args"],
+            })
+        })
+        .collect();
+    entries.sort_by_key(|entry| entry["path"].to_string());
+    entries
+}
+
+fn listing(dir: &Path) -> Vec<(String, Vec<u8>)> {
+    let mut files: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            (name, fs::read(&path).unwrap())
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn healthy_archive_passes() {
+    let dir = tempfile::tempdir().unwrap();
+    let entries = healthy_entries(dir.path());
+    let manifest = dir.path().join("sets.json");
+    fs::write(&manifest, json!({ "images": entries }).to_string()).unwrap();
+
+    let out = pngsecret(&[
+        "verify-archive",
+        "--manifest",
+        manifest.to_str().unwrap(),
+        "--read-only",
+    ]);
+    let report = String::from_utf8(out.stdout).unwrap();
+    assert_eq!(out.status.code(), Some(0), "{}", report);
+    assert!(
+        report.ends_with("7 checked, 7 ok, 0 failed\n"),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn damaged_images_fail_the_sweep_without_writes() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut entries = healthy_entries(dir.path());
+
+    let mut corrupted = fs::read(dir.path().join("legacy-gradient.png")).unwrap();
+    let middle = corrupted.len() / 2;
+    corrupted[middle] ^= 0x40;
+    fs::write(dir.path().join("corrupted.png"), corrupted).unwrap();
+    entries.push(json!({ "path": "corrupted.png" }));
+    entries.push(json!({
+        "path": "legacy-gradient.png",
+        "payload": "something else",
+        "decode_args": ["--legacy"],
+    }));
+    entries.push(json!({ "path": "missing.png" }));
+    entries.push(json!({ "path": "permuted-blocks.png", "payload": "scattered" }));
+
+    let manifest = dir.path().join("sets.json");
+    fs::write(&manifest, json!({ "images": entries }).to_string()).unwrap();
+    let before = listing(dir.path());
+
+    let out = pngsecret(&[
+        "verify-archive",
+        "--manifest",
+        manifest.to_str().unwrap(),
+        "--read-only",
+    ]);
+    let report = String::from_utf8(out.stdout).unwrap();
+    assert_eq!(out.status.code(), Some(5), "{}", report);
+    assert!(
+        report.contains("FAILED  corrupted.png: corrupt PNG"),
+        "{}",
+        report
+    );
+    assert!(report.contains("FAILED  legacy-gradient.png: the message differs"));
+    assert!(report.contains("FAILED  missing.png: unreadable"));
+    // Without its decode_args the permuted image doesn't decode
+    assert!(report.contains("FAILED  permuted-blocks.png"));
+    assert!(
+        report.ends_with("11 checked, 7 ok, 4 failed\n"),
+        "{}",
+        report
+    );
+    assert_eq!(listing(dir.path()), before);
+}
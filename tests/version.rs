@@ -0,0 +1,33 @@
+mod common;
+
+use common::pngsecret;
+
+#[test]
+fn plain_version_is_one_line() {
+    let out = pngsecret(&["--version"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        format!("pngsecret {}\n", env!("CARGO_PKG_VERSION"))
+    );
+}
+
+#[test]
+fn verbose_version_lists_support_as_json() {
+    let out = pngsecret(&["--version", "-v"]);
+    assert!(out.status.success(), "{:?}", out);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let (text, json) = stdout.split_once("JSON:\n").unwrap();
+    assert!(text.contains("codecs: naive"), "{}", text);
+    let info: serde_json::Value = serde_json::from_str(json).unwrap();
+    assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    assert!(!info["git_describe"].as_str().unwrap().is_empty());
+    assert_eq!(info["formats"], serde_json::json!(["framed", "legacy"]));
+    assert_eq!(info["codecs"], serde_json::json!(["naive"]));
+    assert_eq!(info["backends"], serde_json::json!(["pixel", "chunk"]));
+    let features: &[&str] = match cfg!(feature = "failure-injection") {
+        true => &["failure-injection"],
+        false => &[],
+    };
+    assert_eq!(info["features"], serde_json::json!(features));
+}